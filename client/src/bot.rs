@@ -0,0 +1,249 @@
+//! Headless players for load testing a server, run with `--bot <n>` in place of the game window.
+//!
+//! Each bot joins through the same network task as the game, as the username followed by its
+//! number, then walks in a random direction that changes every [`MOVE_INTERVAL`] and now and
+//! then shoots somewhere. Every [`REPORT_INTERVAL`] a line sums up how many bots are connected,
+//! how many could not connect or lost their connection, and the round trips of the pings the
+//! network tasks sent since the last line. The bots leave the server on Ctrl-C.
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use common::{
+    message::{ClientMessage, ServerMessage},
+    time::monotonic_secs,
+    vec::Vec2,
+    world::combat::ProjectileKind,
+};
+use rand::Rng;
+use tokio::{
+    runtime::Runtime,
+    sync::{mpsc::unbounded_channel, oneshot, watch},
+    time,
+};
+
+use crate::client::{Client, Login, Route};
+
+/// Time between two changes of direction
+const MOVE_INTERVAL: Duration = Duration::from_millis(250);
+/// Chance of shooting on each change of direction
+const SHOT_CHANCE: f64 = 0.3;
+/// Time between two bots connecting, so a server is not hit by all of them at once
+const CONNECT_SPACING: Duration = Duration::from_millis(20);
+/// Time between two summaries
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the bots get to tell the server they are leaving
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+/// How the bots are doing
+#[derive(Default)]
+struct Stats {
+    connected: usize,
+    /// Bots that never joined
+    failed: usize,
+    /// Bots whose connection ended after they joined
+    dropped: usize,
+    /// Round trips in seconds since the last summary
+    rtts: Vec<f64>,
+}
+
+/// Round trips over an interval, in seconds
+#[derive(Debug, PartialEq)]
+struct RttSummary {
+    min: f64,
+    mean: f64,
+    p95: f64,
+    max: f64,
+}
+impl RttSummary {
+    fn of(rtts: &mut [f64]) -> Option<Self> {
+        if rtts.is_empty() {
+            return None;
+        }
+        rtts.sort_unstable_by(f64::total_cmp);
+        let p95 = ((rtts.len() as f64 * 0.95).ceil() as usize).clamp(1, rtts.len()) - 1;
+        Some(Self {
+            min: rtts[0],
+            mean: rtts.iter().sum::<f64>() / rtts.len() as f64,
+            p95: rtts[p95],
+            max: rtts[rtts.len() - 1],
+        })
+    }
+}
+impl Display for RttSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |secs: f64| secs * 1000.0;
+        write!(
+            f,
+            "rtt min {:.1} ms, mean {:.1} ms, p95 {:.1} ms, max {:.1} ms",
+            ms(self.min),
+            ms(self.mean),
+            ms(self.p95),
+            ms(self.max)
+        )
+    }
+}
+
+/// Runs `count` bots against the server at `address` until Ctrl-C, reporting how they do.
+pub fn run(
+    runtime: Runtime,
+    count: usize,
+    address: String,
+    route: Route,
+    login: Login,
+) -> Result<()> {
+    runtime.block_on(async move {
+        let stats = Arc::new(Mutex::new(Stats::default()));
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        println!("Starting {count} bots against {address}");
+
+        let mut bots = Vec::with_capacity(count);
+        let mut report = time::interval(REPORT_INTERVAL);
+        report.tick().await;
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        let mut spawn = time::interval(CONNECT_SPACING);
+        loop {
+            tokio::select! {
+                _ = &mut ctrl_c => break,
+                _ = spawn.tick(), if bots.len() < count => {
+                    let index = bots.len() + 1;
+                    let login = Login {
+                        username: format!("{}{index}", login.username),
+                        ..login.clone()
+                    };
+                    bots.push(tokio::spawn(bot(
+                        index,
+                        address.clone(),
+                        route.clone(),
+                        login,
+                        stats.clone(),
+                        shutdown_rx.clone(),
+                    )));
+                }
+                _ = report.tick() => println!("{}", summary(&stats, count)),
+            }
+        }
+
+        println!("Stopping the bots");
+        let _ = shutdown.send(true);
+        for bot in bots {
+            let _ = bot.await;
+        }
+        println!("{}", summary(&stats, count));
+        Ok(())
+    })
+}
+
+/// One line on how the bots are doing, taking the round trips since the last one
+fn summary(stats: &Mutex<Stats>, count: usize) -> String {
+    let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+    let rtt = RttSummary::of(&mut stats.rtts)
+        .map_or_else(|| String::from("no round trips"), |rtt| rtt.to_string());
+    stats.rtts.clear();
+    format!(
+        "{}/{count} bots connected, {} could not connect, {} dropped, {rtt}",
+        stats.connected, stats.failed, stats.dropped
+    )
+}
+
+/// Plays as one bot until its connection ends or `shutdown` turns true
+async fn bot(
+    index: usize,
+    address: String,
+    route: Route,
+    login: Login,
+    stats: Arc<Mutex<Stats>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let (runtime_tx, mut server_rx) = unbounded_channel();
+    let (server_tx, runtime_rx) = unbounded_channel();
+    let (quit, quit_rx) = oneshot::channel();
+    let mut network = tokio::spawn(Client::run(
+        address, route, login, runtime_tx, runtime_rx, quit_rx,
+    ));
+
+    let mut joined = false;
+    let mut seq = 0;
+    let mut moves = time::interval(MOVE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => {
+                let _ = quit.send(());
+                let _ = time::timeout(SHUTDOWN_GRACE, &mut network).await;
+                return;
+            }
+            ended = &mut network => {
+                let reason = match ended {
+                    Ok(Ok(())) => String::from("the server closed the connection"),
+                    Ok(Err(e)) => e.to_string(),
+                    Err(e) => e.to_string(),
+                };
+                eprintln!("Bot {index}: {reason}");
+                update(&stats, |stats| if joined {
+                    stats.connected -= 1;
+                    stats.dropped += 1;
+                } else {
+                    stats.failed += 1;
+                });
+                return;
+            }
+            Some(msg) = server_rx.recv() => match msg {
+                // Sent again when a broken connection is resumed
+                ServerMessage::ConnectionAccepted { .. } if !joined => {
+                    joined = true;
+                    update(&stats, |stats| stats.connected += 1);
+                }
+                ServerMessage::Pong { client_time, .. } => {
+                    let rtt = monotonic_secs() - client_time;
+                    update(&stats, |stats| stats.rtts.push(rtt));
+                }
+                _ => {}
+            },
+            _ = moves.tick(), if joined => {
+                let mut rng = rand::rng();
+                seq += 1;
+                let _ = server_tx.send(ClientMessage::MoveInput { seq, dir: random_dir(&mut rng) });
+                if rng.random_bool(SHOT_CHANCE) {
+                    let _ = server_tx.send(ClientMessage::NotifyShot {
+                        dir: random_dir(&mut rng),
+                        kind: ProjectileKind::default(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn update(stats: &Mutex<Stats>, change: impl FnOnce(&mut Stats)) {
+    change(&mut stats.lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+/// Direction of unit length picked at random
+fn random_dir(rng: &mut impl Rng) -> Vec2 {
+    let angle = rng.random_range(0.0..std::f32::consts::TAU);
+    Vec2 {
+        x: angle.cos(),
+        y: angle.sin(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_are_summed_up() {
+        assert_eq!(RttSummary::of(&mut []), None);
+
+        let mut rtts: Vec<f64> = (1..=20).rev().map(|ms| ms as f64 / 1000.0).collect();
+        let summary = RttSummary::of(&mut rtts).unwrap();
+        assert_eq!(summary.min, 0.001);
+        assert_eq!(summary.max, 0.02);
+        assert_eq!(summary.p95, 0.019);
+        assert!((summary.mean - 0.0105).abs() < 1e-9);
+    }
+}
@@ -1,5 +1,168 @@
+//! Where the world is seen from, and conversions between world units and window pixels.
+//!
+//! At a zoom of 1 the window is two world units tall, centered on the camera, and the width
+//! follows the window's aspect ratio so the world is never stretched.
 use common::vec::Vec2;
 
+/// Closest and farthest the camera can be zoomed
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 4.0;
+/// How quickly the camera catches up with its target, higher being snappier
+const SMOOTHING: f32 = 10.0;
+/// Farthest the view moves from the camera position at full shake, in world units
+const MAX_SHAKE: f32 = 0.05;
+/// Shake lost per second
+const SHAKE_DECAY: f32 = 2.0;
+
 pub struct Camera {
     pub pos: Vec2,
+    /// Magnification, 2 shows half as much of the world as 1
+    pub zoom: f32,
+    /// Point the camera moves towards
+    pub target: Vec2,
+    /// Strength of the screen shake, from 0 to 1
+    trauma: f32,
+    /// Displacement of the view for the current frame, from the shake
+    shake_offset: Vec2,
+}
+impl Camera {
+    pub fn new(pos: Vec2) -> Self {
+        Self {
+            pos,
+            zoom: 1.0,
+            target: pos,
+            trauma: 0.0,
+            shake_offset: Vec2::ZERO,
+        }
+    }
+
+    /// Shakes the view, `amount` adding up to at most 1 for the strongest shake.
+    pub fn shake(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount.max(0.0)).min(1.0);
+    }
+
+    /// Eases the camera towards its target over `dt` seconds, independently of the frame rate.
+    pub fn update(&mut self, dt: f32) {
+        let t = 1.0 - (-SMOOTHING * dt.max(0.0)).exp();
+        self.pos += (self.target - self.pos) * t;
+
+        self.trauma = (self.trauma - SHAKE_DECAY * dt.max(0.0)).max(0.0);
+        // Squared so small shakes stay subtle
+        let strength = MAX_SHAKE * self.trauma * self.trauma;
+        self.shake_offset = (Vec2::random() * 2.0 - Vec2::ONE) * strength;
+    }
+
+    /// Where the view is centered this frame, the camera position moved by the shake
+    fn center(&self) -> Vec2 {
+        self.pos + self.shake_offset
+    }
+
+    /// Multiplies the zoom by `factor`, within the allowed range.
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Normalized device coordinates per world unit along each axis
+    fn scale(&self, screen: Vec2) -> Vec2 {
+        let aspect = screen.y.max(1.0) / screen.x.max(1.0);
+        Vec2 {
+            x: self.zoom * aspect,
+            y: self.zoom,
+        }
+    }
+
+    /// Column-major matrix taking world positions to normalized device coordinates
+    pub fn view_matrix(&self, screen: Vec2) -> [f32; 16] {
+        let scale = self.scale(screen);
+        let center = self.center();
+        #[rustfmt::skip]
+        let matrix = [
+            scale.x, 0.0, 0.0, 0.0,
+            0.0, scale.y, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -center.x * scale.x, -center.y * scale.y, 0.0, 1.0,
+        ];
+        matrix
+    }
+
+    /// Window pixel, from the top left corner, where a world position is drawn
+    pub fn world_to_screen(&self, world: Vec2, screen: Vec2) -> Vec2 {
+        let scale = self.scale(screen);
+        let center = self.center();
+        let ndc = Vec2 {
+            x: (world.x - center.x) * scale.x,
+            y: (world.y - center.y) * scale.y,
+        };
+        Vec2 {
+            x: (ndc.x + 1.0) / 2.0 * screen.x,
+            y: (1.0 - ndc.y) / 2.0 * screen.y,
+        }
+    }
+
+    /// World position under a window pixel, from the top left corner
+    pub fn screen_to_world(&self, pixel: Vec2, screen: Vec2) -> Vec2 {
+        let scale = self.scale(screen);
+        let ndc = Vec2 {
+            x: pixel.x / screen.x.max(1.0) * 2.0 - 1.0,
+            y: 1.0 - pixel.y / screen.y.max(1.0) * 2.0,
+        };
+        let center = self.center();
+        Vec2 {
+            x: center.x + ndc.x / scale.x,
+            y: center.y + ndc.y / scale.y,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Vec2 = Vec2 { x: 800.0, y: 600.0 };
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let mut camera = Camera::new(Vec2 { x: 1.0, y: -2.0 });
+        camera.zoom_by(2.0);
+
+        // The camera position is drawn in the middle of the window
+        let middle = Vec2 { x: 400.0, y: 300.0 };
+        assert!(close(camera.world_to_screen(camera.pos, SCREEN), middle));
+        // At a zoom of 2 the window is one world unit tall
+        let top = camera.screen_to_world(Vec2 { x: 400.0, y: 0.0 }, SCREEN);
+        assert!(close(top, Vec2 { x: 1.0, y: -1.5 }));
+
+        let point = Vec2 { x: 1.3, y: -2.2 };
+        let pixel = camera.world_to_screen(point, SCREEN);
+        assert!(close(camera.screen_to_world(pixel, SCREEN), point));
+    }
+
+    #[test]
+    fn follows_the_target_smoothly() {
+        let mut camera = Camera::new(Vec2::ZERO);
+        camera.target = Vec2 { x: 1.0, y: 0.0 };
+        camera.update(0.05);
+        assert!(camera.pos.x > 0.0 && camera.pos.x < 1.0);
+        for _ in 0..100 {
+            camera.update(0.05);
+        }
+        assert!(close(camera.pos, camera.target));
+
+        camera.zoom_by(100.0);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+    }
+
+    #[test]
+    fn shaking_wears_off() {
+        let mut camera = Camera::new(Vec2::ZERO);
+        camera.shake(1.0);
+        camera.update(0.1);
+        assert!(camera.center().length() <= MAX_SHAKE * 2.0_f32.sqrt());
+        camera.update(1.0);
+        assert_eq!(camera.center(), camera.pos);
+    }
 }
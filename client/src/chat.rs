@@ -0,0 +1,73 @@
+//! Chat state kept by the client: the lines received from the server and the text being typed.
+use std::collections::VecDeque;
+
+use common::message::MAX_CHAT_LENGTH;
+
+/// Number of received lines kept for the overlay
+const MAX_LINES: usize = 8;
+/// Seconds a line stays visible while the input box is closed
+const LINE_LIFETIME: f64 = 10.0;
+
+/// A single received chat line
+pub struct ChatLine {
+    pub sender: String,
+    pub text: String,
+    /// Local time the line arrived, used to fade old lines out
+    pub received: f64,
+}
+
+/// Chat log and input box toggled by Enter
+#[derive(Default)]
+pub struct Chat {
+    pub lines: VecDeque<ChatLine>,
+    pub input: String,
+    pub open: bool,
+}
+impl Chat {
+    pub fn push(&mut self, sender: String, text: String) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(ChatLine {
+            sender,
+            text,
+            received: miniquad::date::now(),
+        });
+    }
+
+    /// Opens the input box, or closes it and returns the typed text if there is any.
+    pub fn toggle(&mut self) -> Option<String> {
+        if !self.open {
+            self.open = true;
+            return None;
+        }
+        self.open = false;
+        let text = std::mem::take(&mut self.input);
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    /// Closes the input box and throws away the typed text.
+    pub fn cancel(&mut self) {
+        self.open = false;
+        self.input.clear();
+    }
+
+    pub fn type_char(&mut self, character: char) {
+        if self.open && !character.is_control() && self.input.chars().count() < MAX_CHAT_LENGTH {
+            self.input.push(character);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    /// Lines that should currently be drawn, oldest first
+    pub fn visible_lines(&self) -> impl Iterator<Item = &ChatLine> {
+        let now = miniquad::date::now();
+        self.lines
+            .iter()
+            .filter(move |line| self.open || now - line.received < LINE_LIFETIME)
+    }
+}
@@ -1,11 +1,25 @@
+use std::path::PathBuf;
+
 use clap::Parser;
-use common::details;
+use common::{color::Color, details, message::Transport};
+
+use crate::{haptics::Effect, input, record};
 /// Command-line arguments for the server application.
 #[derive(Parser, Debug)]
 #[command(name = "Client")]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    pub address: String,
+    #[arg(required_unless_present_any = ["replay", "single_player"])]
+    pub address: Option<String>,
+
+    /// Runs this many headless bots against the server at `address` instead of the game, for load
+    /// testing. They join as the username followed by their number
+    #[arg(long, value_name = "N", conflicts_with_all = ["single_player", "replay", "record"])]
+    pub bot: Option<usize>,
+
+    /// Plays alone on a server run inside the client, instead of joining one at `address`
+    #[arg(long, conflicts_with_all = ["address", "replay"])]
+    pub single_player: bool,
 
     #[arg(long, default_value = details::DEFAULT_USERNAME)]
     pub username: String,
@@ -13,6 +27,93 @@ pub struct Cli {
     #[arg(long, default_value = None)]
     pub password: Option<String>,
 
+    /// Color to ask the server for, like `#ff8800`, otherwise the server picks one
+    #[arg(long, value_parser = Color::from_hex)]
+    pub color: Option<Color>,
+
+    /// Lobby to play in on servers that host several, their first one otherwise
+    #[arg(long, value_name = "NAME", conflicts_with = "single_player")]
+    pub lobby: Option<String>,
+
     #[arg(long)]
     pub metal: bool,
+
+    /// Protocol to reach the server with, `tcp` or `udp`, it has to match the server's
+    #[arg(long, default_value_t = Transport::Tcp)]
+    pub transport: Transport,
+
+    /// Encrypts the connection, for servers run with `--tls-cert`
+    #[arg(long, conflicts_with = "single_player")]
+    pub tls: bool,
+
+    /// PEM file of the certificates to trust with `--tls`, the server's own or one that signed it
+    #[arg(long, value_name = "FILE", default_value = "ca.pem")]
+    pub tls_ca: PathBuf,
+
+    /// Directory of sprite images, named by sprite id like `3.png`
+    #[arg(long, value_name = "DIR", default_value = "sprites")]
+    pub sprites: PathBuf,
+
+    /// Directory of GLSL shaders that debug builds draw with and compile again when they change,
+    /// see `render/shader.rs` for the file names
+    #[arg(long, value_name = "DIR", default_value = "shaders")]
+    pub shaders: PathBuf,
+
+    /// File the control profiles and key bindings are kept in
+    #[arg(long, value_name = "FILE", default_value = "controls.toml")]
+    pub controls: PathBuf,
+
+    /// TOML file of templates to word server notices with, English otherwise
+    #[arg(long, value_name = "FILE")]
+    pub locale: Option<PathBuf>,
+
+    /// TOML file of the music stems to play on each map and mode
+    #[arg(long, value_name = "FILE", default_value = "music.toml")]
+    pub music: PathBuf,
+
+    /// Turns the music off
+    #[arg(long)]
+    pub no_music: bool,
+
+    /// TOML file of the sizes, colors and HUD layout to draw with
+    #[arg(long, value_name = "FILE", default_value = "style.toml")]
+    pub style: PathBuf,
+
+    /// Discord application to show the server, map and mode on the player's Discord profile
+    /// with, only used by clients built with the `discord` feature
+    #[arg(long, value_name = "ID")]
+    pub discord_app_id: Option<String>,
+
+    /// Turns off controller rumble for an effect, can be given several times
+    #[arg(long, value_name = "EFFECT")]
+    pub no_rumble: Vec<Effect>,
+
+    /// Share of the controller stick's range around its center that does not move the player,
+    /// from 0 to 0.9
+    #[arg(long, default_value_t = input::DEFAULT_DEAD_ZONE, value_parser = input::parse_dead_zone)]
+    pub dead_zone: f32,
+
+    /// Fixed interpolation delay for remote players in milliseconds, picked automatically when omitted
+    #[arg(long)]
+    pub interp_delay: Option<u32>,
+
+    /// Saves every message received from the server to a replay file
+    #[arg(long, value_name = "FILE")]
+    pub save_replay: Option<PathBuf>,
+
+    /// Watches a replay file instead of connecting to a server
+    #[arg(long, value_name = "FILE", conflicts_with = "save_replay")]
+    pub replay: Option<PathBuf>,
+
+    /// Records video frames as numbered PNGs in a directory, or as raw RGBA on stdout with `-`
+    #[arg(long, value_name = "DIR")]
+    pub record: Option<String>,
+
+    /// Frames per second of the recording
+    #[arg(long, default_value_t = 30.0)]
+    pub record_fps: f64,
+
+    /// Size of the recorded frames
+    #[arg(long, default_value = "1280x720", value_parser = record::parse_size)]
+    pub record_size: (u32, u32),
 }
@@ -1,99 +1,455 @@
-use anyhow::Result;
-use common::message::{ClientMessage, ServerMessage};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpStream, ToSocketAddrs};
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use common::{
+    color::Color,
+    message::{
+        ClientMessage, Message, MessageStream, ServerMessage, Transport,
+        auth::{Handshake, Signer},
+        frame::Compression,
+        tls,
+        udp::UdpConnection,
+    },
+    time,
+    version::PROTOCOL_VERSION,
+};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use tokio_rustls::TlsConnector;
+
+use crate::cli::Cli;
+
+/// Time between two pings timing the round trip to the server
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// How long after the connection broke the session is tried to be resumed, servers keep it for a
+/// minute unless told otherwise
+const RESUME_WINDOW: Duration = Duration::from_secs(30);
+/// Time between two attempts at resuming the session
+const RESUME_RETRY: Duration = Duration::from_secs(1);
+
+/// How to reach the server
+#[derive(Clone)]
+pub struct Route {
+    pub transport: Transport,
+    /// Encrypts TCP connections, the server's certificate has to be one it trusts
+    pub tls: Option<TlsConnector>,
+}
+impl Route {
+    pub fn from_cli(cli: &Cli) -> Result<Self> {
+        Ok(Self {
+            transport: cli.transport,
+            tls: cli.tls.then(|| tls::connector(&cli.tls_ca)).transpose()?,
+        })
+    }
+}
+
+/// Link to the server over one of the supported transports
+enum Link {
+    /// TCP, encrypted or not
+    Stream {
+        stream: Box<dyn MessageStream>,
+        /// Bytes received from the server that have not been decoded yet
+        read_buf: Vec<u8>,
+    },
+    Udp(Box<UdpConnection>),
+}
+
+/// Connection to the server
+struct Connection {
+    link: Link,
+    /// Signs every message once the server agreed on a key, see
+    /// [`auth`](common::message::auth)
+    signer: Option<Signer>,
+}
+impl Connection {
+    async fn open(addr: &str, route: &Route) -> Result<Self> {
+        let link = match (route.transport, &route.tls) {
+            (Transport::Tcp, None) => {
+                let stream = TcpStream::connect(addr).await?;
+                eprintln!("Connected to {}", stream.peer_addr()?);
+                Link::stream(stream)
+            }
+            (Transport::Tcp, Some(connector)) => {
+                let name = tls::server_name(addr)?;
+                let stream = TcpStream::connect(addr).await?;
+                let peer = stream.peer_addr()?;
+                let stream = connector
+                    .connect(name, stream)
+                    .await
+                    .map_err(|e| anyhow!("TLS handshake with {peer} failed: {e}"))?;
+                eprintln!("Connected to {peer} over TLS");
+                Link::stream(stream)
+            }
+            (Transport::Udp, Some(_)) => bail!("Only TCP connections can use TLS"),
+            (Transport::Udp, None) => {
+                let connection = UdpConnection::connect(addr).await?;
+                eprintln!("Connecting to {} over UDP", connection.peer_addr());
+                Link::Udp(Box::new(connection))
+            }
+        };
+        Ok(Connection { link, signer: None })
+    }
+
+    async fn send(&mut self, msg: &ClientMessage) -> Result<()> {
+        let mut frame = msg.encode()?;
+        if let Some(signer) = &mut self.signer {
+            frame = signer.sign(frame)?;
+        }
+        match &mut self.link {
+            Link::Stream { stream, .. } => {
+                stream.write_all(&frame).await?;
+                // TLS holds on to what it encrypted until flushed
+                stream.flush().await?;
+                Ok(())
+            }
+            Link::Udp(connection) => connection.send_frame(frame, msg.delivery()).await,
+        }
+    }
+
+    /// Says goodbye to the server and waits until it went out: over TCP everything written is
+    /// sent before the stream is shut down, ending TLS first, over UDP the server has to
+    /// acknowledge it.
+    async fn disconnect(&mut self) -> Result<()> {
+        self.send(&ClientMessage::Disconnect).await?;
+        match &mut self.link {
+            Link::Stream { stream, .. } => stream.shutdown().await?,
+            Link::Udp(connection) => connection.flush().await?,
+        }
+        Ok(())
+    }
+
+    /// Waits for the next complete message, returning `None` if the server closed the connection.
+    /// Cancelling it does not lose any message.
+    async fn recv(&mut self) -> Result<Option<ServerMessage>> {
+        match &mut self.link {
+            Link::Stream { stream, read_buf } => {
+                ServerMessage::read_from_stream(stream, read_buf).await
+            }
+            Link::Udp(connection) => connection.recv().await,
+        }
+    }
+
+    /// Share of the datagrams from the server lost since the last call, nothing is lost over TCP
+    fn take_loss(&mut self) -> f32 {
+        match &mut self.link {
+            Link::Stream { .. } => 0.0,
+            Link::Udp(connection) => connection.take_loss().unwrap_or(0.0),
+        }
+    }
+}
+impl Link {
+    fn stream(stream: impl MessageStream + 'static) -> Self {
+        Link::Stream {
+            stream: Box::new(stream),
+            read_buf: Vec::new(),
+        }
+    }
+}
+
+/// Where the client stands with the server
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    Connecting,
+    /// Waiting for a free slot, at the given place in the queue
+    Queued(u32),
+    Connected,
+    /// Moving to another server the previous one sent us to
+    Transferring,
+    /// The connection failed or was closed, for the given reason
+    Lost(String),
+}
+impl ConnectionStatus {
+    /// Text shown in place of the game while not playing
+    pub fn message(&self) -> Option<String> {
+        match self {
+            ConnectionStatus::Connecting => Some(String::from("Connecting...")),
+            ConnectionStatus::Queued(position) => Some(format!(
+                "Server is full, you are number {position} in the queue"
+            )),
+            ConnectionStatus::Connected => None,
+            ConnectionStatus::Transferring => Some(String::from("Transferring...")),
+            ConnectionStatus::Lost(reason) => Some(format!("Disconnected: {reason}")),
+        }
+    }
+}
+
+/// Who to join the server as
+#[derive(Clone)]
+pub struct Login {
+    pub username: String,
+    pub password: String,
+    /// Color to ask for, the server picks one otherwise
+    pub color: Option<Color>,
+    /// Lobby to play in, the server's first one otherwise
+    pub lobby: Option<String>,
+}
+
+/// How a connection to a server came to an end, other than by breaking
+enum Ending {
+    /// The game is closing and the server was told
+    Quit,
+    /// The server ended the connection on purpose
+    Disconnected,
+    /// The server will not have us back
+    Banned,
+    /// The server sent us to `address`, with `token` as the password
+    Redirect { address: String, token: String },
+}
 
 pub struct Client {
-    stream: TcpStream,
+    connection: Connection,
     runtime_tx: UnboundedSender<ServerMessage>,
     runtime_rx: UnboundedReceiver<ClientMessage>,
+    /// Round trip timed by the last pong, in seconds
+    last_rtt: Option<f64>,
 }
 
 impl Client {
-    // Connect to the server at the given address
-    pub async fn connect<T: ToSocketAddrs>(
-        addr: T,
-        username: String,
-        password: String,
-        runtime_tx: UnboundedSender<ServerMessage>,
-        runtime_rx: UnboundedReceiver<ClientMessage>,
-    ) -> anyhow::Result<(u64, Self)> {
-        let mut stream = TcpStream::connect(addr).await?;
-        println!("Connected to {}", stream.peer_addr()?);
-
-        ClientMessage::Connect(username, password)
-            .write_to_tcp_stream(&mut stream)
+    /// Connects to the server and relays messages until the connection ends, which is what the
+    /// network task of the game runs. When the server sends us elsewhere, the runtime is told
+    /// through the redirect message and the task moves on to the new server. When the connection
+    /// breaks, the task connects again for up to [`RESUME_WINDOW`] to resume the session and play
+    /// on as the same player.
+    ///
+    /// Once `quit` fires or its sender is dropped, the server is told we are leaving ahead of
+    /// anything still waiting to be sent, and the task ends.
+    pub async fn run(
+        mut addr: String,
+        route: Route,
+        mut login: Login,
+        mut runtime_tx: UnboundedSender<ServerMessage>,
+        mut runtime_rx: UnboundedReceiver<ClientMessage>,
+        mut quit: oneshot::Receiver<()>,
+    ) -> Result<()> {
+        // Session to resume and when the connection that had it broke
+        let mut resume: Option<(String, Instant)> = None;
+        loop {
+            let session = resume.as_ref().map(|(session, _)| session.as_str());
+            let join = Self::join(&addr, &route, &login, session, &runtime_tx);
+            // Not accepted yet, so there is no one to say goodbye to
+            let joined = tokio::select! {
+                biased;
+                _ = &mut quit => return Ok(()),
+                joined = join => joined,
+            };
+            let (connection, session) = match joined {
+                Ok(joined) => joined,
+                // The server may not be reachable again yet
+                Err(e)
+                    if resume
+                        .as_ref()
+                        .is_some_and(|(_, lost)| lost.elapsed() < RESUME_WINDOW) =>
+                {
+                    eprintln!("Could not resume the session yet: {e}");
+                    tokio::select! {
+                        biased;
+                        _ = &mut quit => return Ok(()),
+                        _ = tokio::time::sleep(RESUME_RETRY) => continue,
+                    }
+                }
+                Err(e) => return Err(e),
+            };
+
+            let mut client = Client {
+                connection,
+                runtime_tx,
+                runtime_rx,
+                last_rtt: None,
+            };
+            let ending = client.listen(&mut quit).await;
+            Client {
+                runtime_tx,
+                runtime_rx,
+                ..
+            } = client;
+            match ending {
+                Ok(Ending::Quit) => return Ok(()),
+                Ok(Ending::Disconnected) => bail!("Disconnected by the server"),
+                Ok(Ending::Banned) => bail!("Banned from this server"),
+                Ok(Ending::Redirect { address, token }) => {
+                    eprintln!("Redirected to {address}");
+                    runtime_tx
+                        .send(ServerMessage::Redirect {
+                            address: address.clone(),
+                            token: token.clone(),
+                        })
+                        .ok();
+                    (addr, login.password) = (address, token);
+                    resume = None;
+                }
+                Err(e) => {
+                    eprintln!("{e}, resuming the session");
+                    resume = Some((session, Instant::now()));
+                }
+            }
+        }
+    }
+
+    /// Joins the server at `addr`, resuming `session` if there is one, and waits in the join
+    /// queue if it is full. Returns the connection and the session the server gave, the acceptance
+    /// and queue updates are forwarded to the runtime.
+    async fn join(
+        addr: &str,
+        route: &Route,
+        login: &Login,
+        session: Option<&str>,
+        runtime_tx: &UnboundedSender<ServerMessage>,
+    ) -> Result<(Connection, String)> {
+        let mut connection = Connection::open(addr, route).await?;
+        connection
+            .send(&ClientMessage::Hello(PROTOCOL_VERSION))
+            .await?;
+        if let Some(color) = login.color {
+            connection.send(&ClientMessage::PreferColor(color)).await?;
+        }
+        connection
+            .send(&ClientMessage::AcceptCompression(Compression::Lz4))
+            .await?;
+        // A resumed session goes back to its own lobby
+        if let Some(lobby) = &login.lobby {
+            connection
+                .send(&ClientMessage::SelectLobby(lobby.clone()))
+                .await?;
+        }
+        // Servers that know about signing answer once they let us in
+        let handshake = Handshake::new()?;
+        connection
+            .send(&ClientMessage::ProposeSigning(handshake.public_key()))
             .await?;
+        let mut handshake = Some(handshake);
+        let connect = ClientMessage::Connect(login.username.clone(), login.password.clone());
+        match session {
+            Some(session) => {
+                connection
+                    .send(&ClientMessage::Resume(session.to_string()))
+                    .await?
+            }
+            None => connection.send(&connect).await?,
+        }
 
-        let mut buffer = [0; 1024];
-        match ServerMessage::read_from_tcp_stream(&mut stream, &mut buffer).await? {
-            ServerMessage::ConnectionAccepted(id) => Ok((
-                id,
-                Self {
-                    stream,
-                    runtime_tx,
-                    runtime_rx,
-                },
-            )),
-            _ => {
-                println!("Error");
-                Err(anyhow::anyhow!("Error"))
+        // Anything the server sends right after accepting us stays buffered for `listen`
+        loop {
+            match connection.recv().await? {
+                Some(ServerMessage::ConnectionAccepted {
+                    id,
+                    username,
+                    session,
+                }) => {
+                    runtime_tx
+                        .send(ServerMessage::ConnectionAccepted {
+                            id,
+                            username,
+                            session: session.clone(),
+                        })
+                        .ok();
+                    return Ok((connection, session));
+                }
+                Some(ServerMessage::SigningAgreed(public_key)) => {
+                    if let Some(handshake) = handshake.take() {
+                        connection.signer = Some(Signer::new(handshake.finish(&public_key)?));
+                    }
+                }
+                Some(msg @ ServerMessage::QueuePosition(_)) => {
+                    runtime_tx.send(msg).ok();
+                }
+                Some(ServerMessage::SessionExpired) => {
+                    eprintln!("The session is over, joining afresh");
+                    connection.send(&connect).await?;
+                }
+                Some(ServerMessage::ServerFull) => bail!("Server is full"),
+                Some(ServerMessage::PasswordFailed) => bail!("Wrong password"),
+                Some(ServerMessage::Banned) => bail!("Banned from this server"),
+                Some(ServerMessage::IncompatibleVersion(version)) => bail!(
+                    "Server runs version {version} of the protocol and this game {PROTOCOL_VERSION}, \
+                    update through the launcher"
+                ),
+                Some(ServerMessage::Ping) => connection.send(&ClientMessage::Pong).await?,
+                // Over UDP a snapshot can overtake the acceptance, it is outdated soon anyway
+                Some(ServerMessage::UpdateEntities { .. }) if route.transport == Transport::Udp => {
+                }
+                Some(_) => {
+                    eprintln!("Error");
+                    return Err(anyhow::anyhow!("Error"));
+                }
+                None => return Err(anyhow::anyhow!("Server did not answer")),
             }
         }
     }
 
     // Send a client message to the server
     pub async fn send_message(&mut self, msg: ClientMessage) -> anyhow::Result<()> {
-        let encoded = msg.encode()?;
-        self.stream.write_all(&encoded).await?;
-        Ok(())
+        self.connection.send(&msg).await
     }
 
-    pub async fn listen(&mut self) -> Result<()> {
-        let mut read_buf = [0u8; 4096];
-        let mut read_pos = 0;
+    /// Seconds the last ping took to come back, once one did. Pongs are also handed to the
+    /// runtime, which times them with [`time::monotonic_secs`] the same way
+    pub fn last_rtt(&self) -> Option<f64> {
+        self.last_rtt
+    }
 
+    /// Relays messages until the connection ends or `quit` fires, returning how it ended. A
+    /// connection that broke, or that the server closed without a word, is an error.
+    async fn listen(&mut self, quit: &mut oneshot::Receiver<()>) -> Result<Ending> {
+        let mut ping = tokio::time::interval(PING_INTERVAL);
         loop {
             tokio::select! {
-                // 1) Read from the server
-                read_res = self.stream.read(&mut read_buf[read_pos..]) => {
-                    let n = read_res?;
-                    if n == 0 {
-                        println!("Server closed connection");
-                        break;
+                // Checked first, so leaving does not wait behind queued messages
+                biased;
+
+                // 0) The game is closing
+                _ = &mut *quit => {
+                    if let Err(e) = self.connection.disconnect().await {
+                        eprintln!("Could not disconnect cleanly: {e}");
                     }
-                    read_pos += n;
-
-                    // Try to decode as many ServerMessages as possible from buffer
-                    let mut offset = 0;
-                    while offset < read_pos {
-                        match ServerMessage::decode(&read_buf[offset..read_pos]) {
-                            Ok((msg, len)) => {
-                                self.runtime_tx.send(msg).ok(); // Ignore send errors (runtime dropped)
-                                offset += len;
-                            }
-                            Err(_) => {
-                                // Incomplete message? Wait for more bytes
-                                break;
-                            }
+                    return Ok(Ending::Quit);
+                }
+
+                // 1) Read from the server
+                msg = self.connection.recv() => {
+                    let Some(msg) = msg? else {
+                        match self.last_rtt() {
+                            Some(rtt) => bail!("Server closed connection, the last round trip took {:.0} ms", rtt * 1000.0),
+                            None => bail!("Server closed connection"),
                         }
+                    };
+                    if msg == ServerMessage::Ping {
+                        // Answered right away, the server only wants to know we are alive
+                        self.send_message(ClientMessage::Pong).await?;
+                        continue;
                     }
-
-                    // Remove consumed bytes from buffer by shifting remaining to start
-                    if offset > 0 {
-                        read_buf.copy_within(offset..read_pos, 0);
-                        read_pos -= offset;
+                    match msg {
+                        ServerMessage::Pong { client_time, .. } => {
+                            self.last_rtt = Some(time::monotonic_secs() - client_time);
+                        }
+                        ServerMessage::Disconnect => return Ok(Ending::Disconnected),
+                        ServerMessage::Banned => return Ok(Ending::Banned),
+                        ServerMessage::Redirect { address, token } => {
+                            return Ok(Ending::Redirect { address, token });
+                        }
+                        _ => {}
                     }
+                    self.runtime_tx.send(msg).ok(); // Ignore send errors (runtime dropped)
                 }
 
                 // 2) Receive outgoing messages from runtime and send to server
-                Some(msg) = self.runtime_rx.recv() => {
+                Some(mut msg) = self.runtime_rx.recv() => {
+                    // The runtime knows its frame rate, only the connection what got lost
+                    if let ClientMessage::QualityReport { snapshot_loss, .. } = &mut msg {
+                        *snapshot_loss = self.connection.take_loss();
+                    }
                     self.send_message(msg).await?;
                 }
+
+                // 3) Time the round trip
+                _ = ping.tick() => {
+                    let client_time = time::monotonic_secs();
+                    self.send_message(ClientMessage::Ping { client_time }).await?;
+                }
             }
         }
-
-        Ok(())
     }
 }
@@ -0,0 +1,512 @@
+//! Control profiles: which key does what, kept in a file so rebinding lasts across runs.
+//!
+//! A few profiles come built in, and the controls file can change them or add more. Players pick
+//! a profile and rebind keys from the controls menu, opened with Escape. Keys used by fixed
+//! shortcuts, such as Enter for chat, cannot be bound.
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Result, anyhow, bail};
+use miniquad::KeyCode;
+use serde::{Deserialize, Serialize};
+
+use crate::render::menu::Menu;
+
+/// Something the player can do with a key
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Shoot,
+    Weapon1,
+    Weapon2,
+    Weapon3,
+    Weapon4,
+    /// Shows the leaderboard while held
+    Scoreboard,
+    /// Reloads the selected weapon
+    Reload,
+}
+impl Action {
+    pub const ALL: [Action; 11] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Shoot,
+        Action::Weapon1,
+        Action::Weapon2,
+        Action::Weapon3,
+        Action::Weapon4,
+        Action::Scoreboard,
+        Action::Reload,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Shoot => "Shoot",
+            Action::Weapon1 => "Weapon 1",
+            Action::Weapon2 => "Weapon 2",
+            Action::Weapon3 => "Weapon 3",
+            Action::Weapon4 => "Weapon 4",
+            Action::Scoreboard => "Scoreboard",
+            Action::Reload => "Reload",
+        }
+    }
+
+    /// Index of the weapon the action selects, if it selects one
+    pub fn weapon(self) -> Option<usize> {
+        match self {
+            Action::Weapon1 => Some(0),
+            Action::Weapon2 => Some(1),
+            Action::Weapon3 => Some(2),
+            Action::Weapon4 => Some(3),
+            _ => None,
+        }
+    }
+}
+
+/// Keys taken by fixed shortcuts: chat, the controls menu and the debug overlay
+const RESERVED_KEYS: [KeyCode; 6] = [
+    KeyCode::Enter,
+    KeyCode::Escape,
+    KeyCode::F3,
+    KeyCode::LeftBracket,
+    KeyCode::RightBracket,
+    KeyCode::Backslash,
+];
+
+/// Keys that can be bound, named in the controls file as they are spelled here
+const KEYS: [KeyCode; 79] = [
+    KeyCode::Space,
+    KeyCode::Apostrophe,
+    KeyCode::Comma,
+    KeyCode::Minus,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Semicolon,
+    KeyCode::Equal,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::GraveAccent,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Right,
+    KeyCode::Left,
+    KeyCode::Down,
+    KeyCode::Up,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::CapsLock,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+    KeyCode::KpEnter,
+    KeyCode::LeftShift,
+    KeyCode::LeftControl,
+    KeyCode::LeftAlt,
+    KeyCode::RightShift,
+    KeyCode::RightControl,
+    KeyCode::RightAlt,
+];
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{key:?}")
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    KEYS.into_iter()
+        .find(|key| key_name(*key).eq_ignore_ascii_case(name))
+}
+
+/// Keys bound to actions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    bindings: BTreeMap<Action, KeyCode>,
+}
+impl Profile {
+    fn new(bindings: &[(Action, KeyCode)]) -> Self {
+        Self {
+            bindings: bindings.iter().copied().collect(),
+        }
+    }
+
+    fn built_in() -> Vec<(String, Profile)> {
+        use Action::*;
+        use KeyCode::*;
+        let weapons =
+            |keys: [KeyCode; 4]| [Weapon1, Weapon2, Weapon3, Weapon4].into_iter().zip(keys);
+        let profile = |moves: [KeyCode; 4], shoot, weapon_keys, scoreboard, reload| {
+            let mut bindings = vec![
+                (MoveUp, moves[0]),
+                (MoveDown, moves[1]),
+                (MoveLeft, moves[2]),
+                (MoveRight, moves[3]),
+                (Shoot, shoot),
+                (Scoreboard, scoreboard),
+                (Reload, reload),
+            ];
+            bindings.extend(weapons(weapon_keys));
+            Profile::new(&bindings)
+        };
+        vec![
+            (
+                String::from("wasd"),
+                profile([W, S, A, D], Space, [Key1, Key2, Key3, Key4], Tab, R),
+            ),
+            (
+                String::from("arrows"),
+                profile(
+                    [Up, Down, Left, Right],
+                    RightControl,
+                    [Key1, Key2, Key3, Key4],
+                    Tab,
+                    RightShift,
+                ),
+            ),
+            (
+                String::from("left-handed"),
+                profile([I, K, J, L], Space, [Key7, Key8, Key9, Key0], P, U),
+            ),
+        ]
+    }
+
+    pub fn key(&self, action: Action) -> Option<KeyCode> {
+        self.bindings.get(&action).copied()
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| **bound == key)
+            .map(|(action, _)| *action)
+    }
+
+    /// Binds `key` to `action`, unless the key is reserved or already does something else.
+    pub fn rebind(&mut self, action: Action, key: KeyCode) -> Result<()> {
+        if RESERVED_KEYS.contains(&key) || !KEYS.contains(&key) {
+            bail!("{} cannot be bound", key_name(key));
+        }
+        if let Some(other) = self.action(key).filter(|other| *other != action) {
+            bail!("{} is already bound to {}", key_name(key), other.label());
+        }
+        self.bindings.insert(action, key);
+        Ok(())
+    }
+}
+
+/// What the controls file holds
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ControlsFile {
+    /// Name of the profile in use
+    profile: Option<String>,
+    /// Key names by action, by profile name
+    profiles: BTreeMap<String, BTreeMap<Action, String>>,
+}
+
+/// Every profile and the one in use
+pub struct Controls {
+    profiles: Vec<(String, Profile)>,
+    active: usize,
+    /// File changes are saved to
+    path: Option<PathBuf>,
+}
+impl Controls {
+    /// Loads the controls file on top of the built-in profiles, which are used alone if the file
+    /// does not exist yet.
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let mut controls = Self {
+            profiles: Profile::built_in(),
+            active: 0,
+            path,
+        };
+        let file: ControlsFile = match &controls.path {
+            Some(path) if path.exists() => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Failed to read controls {}: {e}", path.display()))?;
+                toml::from_str(&text)
+                    .map_err(|e| anyhow!("Invalid controls in {}: {e}", path.display()))?
+            }
+            _ => ControlsFile::default(),
+        };
+
+        for (name, keys) in file.profiles {
+            let mut profile = Profile::default();
+            for (action, key_name) in keys {
+                let key = parse_key(&key_name).ok_or_else(|| {
+                    anyhow!("Unknown key `{key_name}` in the controls profile `{name}`")
+                })?;
+                profile
+                    .rebind(action, key)
+                    .map_err(|e| anyhow!("In the controls profile `{name}`: {e}"))?;
+            }
+            match controls
+                .profiles
+                .iter_mut()
+                .find(|(other, _)| *other == name)
+            {
+                Some((_, existing)) => *existing = profile,
+                None => controls.profiles.push((name, profile)),
+            }
+        }
+        if let Some(name) = file.profile {
+            controls.active = controls
+                .profiles
+                .iter()
+                .position(|(other, _)| *other == name)
+                .ok_or_else(|| anyhow!("No controls profile named `{name}`"))?;
+        }
+        Ok(controls)
+    }
+
+    /// Writes every profile and the one in use to the controls file.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = ControlsFile {
+            profile: Some(self.name().to_string()),
+            profiles: self
+                .profiles
+                .iter()
+                .map(|(name, profile)| {
+                    let keys = profile
+                        .bindings
+                        .iter()
+                        .map(|(action, key)| (*action, key_name(*key)))
+                        .collect();
+                    (name.clone(), keys)
+                })
+                .collect(),
+        };
+        std::fs::write(path, toml::to_string(&file)?)
+            .map_err(|e| anyhow!("Failed to write controls {}: {e}", path.display()))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.profiles[self.active].0
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profiles[self.active].1
+    }
+
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.profiles[self.active].1
+    }
+
+    /// Switches to the profile `step` places after the current one, wrapping around.
+    pub fn cycle(&mut self, step: isize) {
+        let count = self.profiles.len() as isize;
+        self.active = (self.active as isize + step).rem_euclid(count) as usize;
+    }
+}
+
+/// What the controls menu did with a key press
+#[derive(Debug, PartialEq)]
+pub enum MenuEvent {
+    /// Nothing the rest of the game needs to know about
+    None,
+    /// The bindings or the profile in use changed
+    Changed,
+    Closed,
+}
+
+/// Controls menu: pick a profile with Left and Right, an action with Up and Down, and press Enter
+/// to bind the next key pressed to it
+#[derive(Default)]
+pub struct ControlsMenu {
+    selected: usize,
+    /// Whether the next key pressed gets bound to the selected action
+    capturing: bool,
+    /// Result of the last rebind, such as a conflict
+    notice: Option<String>,
+}
+impl ControlsMenu {
+    pub fn key_down(&mut self, key: KeyCode, controls: &mut Controls) -> MenuEvent {
+        if self.capturing {
+            self.capturing = false;
+            if key == KeyCode::Escape {
+                self.notice = None;
+                return MenuEvent::None;
+            }
+            let action = Action::ALL[self.selected];
+            return match controls.profile_mut().rebind(action, key) {
+                Ok(()) => {
+                    self.notice = Some(format!("{} bound to {}", action.label(), key_name(key)));
+                    MenuEvent::Changed
+                }
+                Err(e) => {
+                    self.notice = Some(e.to_string());
+                    MenuEvent::None
+                }
+            };
+        }
+
+        match key {
+            KeyCode::Escape => return MenuEvent::Closed,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(Action::ALL.len() - 1),
+            KeyCode::Left | KeyCode::Right => {
+                controls.cycle(if key == KeyCode::Left { -1 } else { 1 });
+                self.notice = None;
+                return MenuEvent::Changed;
+            }
+            KeyCode::Enter => {
+                self.capturing = true;
+                self.notice = Some(format!(
+                    "Press a key for {}, Escape to cancel",
+                    Action::ALL[self.selected].label()
+                ));
+            }
+            _ => {}
+        }
+        MenuEvent::None
+    }
+
+    /// What to draw for the menu
+    pub fn view(&self, controls: &Controls) -> Menu {
+        let profile = controls.profile();
+        Menu {
+            title: format!("CONTROLS  < {} >", controls.name()),
+            rows: Action::ALL
+                .iter()
+                .map(|action| {
+                    let key = profile.key(*action).map_or(String::from("-"), key_name);
+                    (action.label().to_string(), key)
+                })
+                .collect(),
+            selected: self.selected,
+            footer: self.notice.clone().unwrap_or_else(|| {
+                String::from("Left/Right profile, Up/Down action, Enter rebind, Escape close")
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicts_are_refused() {
+        let mut profile = Profile::built_in().remove(0).1;
+        assert!(profile.rebind(Action::Shoot, KeyCode::W).is_err());
+        assert!(profile.rebind(Action::Shoot, KeyCode::Enter).is_err());
+        assert_eq!(profile.key(Action::Shoot), Some(KeyCode::Space));
+
+        // Binding an action to the key it already has is fine
+        assert!(profile.rebind(Action::Shoot, KeyCode::Space).is_ok());
+        assert!(profile.rebind(Action::Shoot, KeyCode::F).is_ok());
+        assert_eq!(profile.action(KeyCode::F), Some(Action::Shoot));
+        assert_eq!(profile.action(KeyCode::Space), None);
+    }
+
+    #[test]
+    fn menu_rebinds_the_next_key() {
+        let mut controls = Controls::load(None).unwrap();
+        let mut menu = ControlsMenu::default();
+        // Down to Move down, then rebind it
+        menu.key_down(KeyCode::Down, &mut controls);
+        assert_eq!(
+            menu.key_down(KeyCode::Enter, &mut controls),
+            MenuEvent::None
+        );
+        assert_eq!(menu.key_down(KeyCode::X, &mut controls), MenuEvent::Changed);
+        assert_eq!(controls.profile().key(Action::MoveDown), Some(KeyCode::X));
+
+        // Taken keys are refused with a notice
+        menu.key_down(KeyCode::Enter, &mut controls);
+        assert_eq!(menu.key_down(KeyCode::W, &mut controls), MenuEvent::None);
+        assert!(menu.view(&controls).footer.contains("Move up"));
+
+        menu.key_down(KeyCode::Right, &mut controls);
+        assert_eq!(controls.name(), "arrows");
+        assert_eq!(
+            menu.key_down(KeyCode::Escape, &mut controls),
+            MenuEvent::Closed
+        );
+    }
+
+    #[test]
+    fn saved_controls_load_back() {
+        let path = std::env::temp_dir().join(format!("controls-{}.toml", std::process::id()));
+        let mut controls = Controls::load(Some(path.clone())).unwrap();
+        controls.cycle(-1);
+        controls
+            .profile_mut()
+            .rebind(Action::Shoot, KeyCode::H)
+            .unwrap();
+        controls.save().unwrap();
+
+        let loaded = Controls::load(Some(path.clone())).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.name(), "left-handed");
+        assert_eq!(loaded.profile(), controls.profile());
+    }
+
+    #[test]
+    fn every_key_has_a_name_that_parses_back() {
+        for key in KEYS {
+            assert_eq!(parse_key(&key_name(key)), Some(key));
+        }
+        assert_eq!(parse_key("space"), Some(KeyCode::Space));
+        assert_eq!(parse_key("Hyper"), None);
+    }
+}
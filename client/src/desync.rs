@@ -0,0 +1,179 @@
+//! Detection of client prediction drifting away from the server for good.
+//!
+//! After every local step the client keeps a [`checksum`] of its predicted player, keyed by the
+//! input it was following and how many steps into that input it was. Snapshots say the same of the
+//! server's copy, so the two can be compared at the same point. A mismatch on its own is an
+//! ordinary correction, such as another player shoving us, and reconciling fixes it. Only when
+//! every snapshot disagrees for [`DESYNC_STREAK`] in a row is a [`DesyncReport`] logged, as the
+//! two simulations then disagree about how the player moves.
+use std::{collections::VecDeque, fmt};
+
+use common::{
+    vec::Vec2,
+    world::{
+        checksum,
+        entities::{Entities, PUSH_REACH, Player},
+    },
+};
+
+/// Snapshots in a row that have to disagree with the prediction before it counts as a desync
+pub const DESYNC_STREAK: u32 = 10;
+/// Predicted steps remembered, a few seconds of play
+const HISTORY_LENGTH: usize = 512;
+
+struct Predicted {
+    seq: u64,
+    ticks: u32,
+    hash: u64,
+    pos: Vec2,
+    vel: Vec2,
+}
+
+/// What was known when prediction and the server kept disagreeing
+pub struct DesyncReport {
+    /// Server tick of the snapshot that made the streak long enough
+    pub tick: u64,
+    pub id: u64,
+    pub input_seq: u64,
+    pub predicted: (Vec2, Vec2),
+    pub server: (Vec2, Vec2),
+    /// Players close enough to push the local one, who may be part of the cause
+    pub nearby: Vec<u64>,
+}
+impl fmt::Display for DesyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ((predicted_pos, predicted_vel), (server_pos, server_vel)) =
+            (self.predicted, self.server);
+        write!(
+            f,
+            "Prediction desync at tick {} on player {} following input {}: predicted {:?} \
+             moving {:?}, server has {:?} moving {:?}, nearby players {:?}",
+            self.tick,
+            self.id,
+            self.input_seq,
+            predicted_pos,
+            predicted_vel,
+            server_pos,
+            server_vel,
+            self.nearby
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct DesyncDetector {
+    history: VecDeque<Predicted>,
+    /// Snapshots in a row whose player did not match the prediction
+    streak: u32,
+}
+impl DesyncDetector {
+    /// Remembers the local player as predicted after a step
+    pub fn record(&mut self, player: &Player) {
+        if let Some(last) = self.history.back_mut()
+            && (last.seq, last.ticks) == (player.last_input_seq, player.input_ticks)
+        {
+            // Predicted again after a correction, the newest prediction is the one to check
+            self.history.pop_back();
+        }
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(Predicted {
+            seq: player.last_input_seq,
+            ticks: player.input_ticks,
+            hash: checksum::player(player),
+            pos: player.pos,
+            vel: player.vel,
+        });
+    }
+
+    /// Compares the player `id` of the snapshot of `tick` with what was predicted at the same
+    /// point. Returns a report on the snapshot that makes the streak of mismatches reach
+    /// [`DESYNC_STREAK`].
+    pub fn check(
+        &mut self,
+        tick: u64,
+        id: u64,
+        entities: &Entities,
+        player_radius: f32,
+    ) -> Option<DesyncReport> {
+        let server = entities.players.get(&id)?;
+        // Predictions of inputs the server has moved past can no longer be compared
+        while self
+            .history
+            .front()
+            .is_some_and(|predicted| predicted.seq < server.last_input_seq)
+        {
+            self.history.pop_front();
+        }
+        // The dead do not move and what was never predicted cannot disagree
+        let predicted = self.history.iter().find(|predicted| {
+            (predicted.seq, predicted.ticks) == (server.last_input_seq, server.input_ticks)
+        })?;
+        if !server.is_alive() || predicted.hash == checksum::player(server) {
+            self.streak = 0;
+            return None;
+        }
+        self.streak += 1;
+        if self.streak != DESYNC_STREAK {
+            return None;
+        }
+        let reach = PUSH_REACH * player_radius;
+        let mut nearby: Vec<_> = entities
+            .players
+            .iter()
+            .filter(|(other, player)| **other != id && (player.pos - server.pos).length() <= reach)
+            .map(|(other, _)| *other)
+            .collect();
+        nearby.sort_unstable();
+        Some(DesyncReport {
+            tick,
+            id,
+            input_seq: server.last_input_seq,
+            predicted: (predicted.pos, predicted.vel),
+            server: (server.pos, server.vel),
+            nearby,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        physics::PhysicsConfig,
+        world::{entities::Entities, environment::Environment},
+    };
+    use test_utils::world::player;
+
+    use super::*;
+
+    #[test]
+    fn only_lasting_mismatches_are_reported() {
+        let (physics, environment) = (PhysicsConfig::default(), Environment::default());
+        let mut detector = DesyncDetector::default();
+        let mut predicted = player("a", Vec2::ZERO);
+        predicted.vel = Vec2 { x: 1.0, y: 0.0 };
+        let mut entities = Entities {
+            players: [(1, predicted.clone()), (2, player("b", Vec2::ZERO))].into(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        };
+
+        let mut reports = Vec::new();
+        for tick in 0..DESYNC_STREAK as u64 * 2 {
+            predicted.update(0.1, &physics, &environment);
+            detector.record(&predicted);
+            // The server agrees at first, then keeps the player a little behind
+            let server = entities.players.get_mut(&1).unwrap();
+            server.update(0.1, &physics, &environment);
+            if tick >= 3 {
+                server.pos.x -= 0.01;
+            }
+            reports.extend(detector.check(tick, 1, &entities, physics.player_radius));
+        }
+
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].tick, 3 + DESYNC_STREAK as u64 - 1);
+        assert_eq!(reports[0].nearby, Vec::<u64>::new());
+    }
+}
@@ -0,0 +1,140 @@
+//! Short-lived visuals that only exist on the client, such as explosion blasts, particles and
+//! damage numbers.
+use common::{color::Color, vec::Vec2};
+
+/// Seconds a blast ring takes to grow to its full size
+const BLAST_TIME: f32 = 0.3;
+/// Particles thrown out by every explosion
+const PARTICLES_PER_EXPLOSION: usize = 24;
+/// Seconds a particle lives at most
+const PARTICLE_TIME: f32 = 0.6;
+/// Fraction of velocity particles lose per second
+const PARTICLE_DRAG: f32 = 3.0;
+/// Seconds a damage number floats above the player that took it
+const NUMBER_TIME: f32 = 0.8;
+/// Distance a damage number rises over its life, in world units
+const NUMBER_RISE: f32 = 0.1;
+const HEALTH_NUMBER_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.85,
+    b: 0.3,
+};
+const ARMOR_NUMBER_COLOR: Color = Color {
+    r: 0.3,
+    g: 0.6,
+    b: 1.0,
+};
+
+struct Blast {
+    pos: Vec2,
+    radius: f32,
+    age: f32,
+}
+
+struct Particle {
+    pos: Vec2,
+    vel: Vec2,
+    ttl: f32,
+}
+
+struct DamageNumber {
+    pos: Vec2,
+    text: String,
+    color: Color,
+    age: f32,
+}
+
+#[derive(Default)]
+pub struct Effects {
+    blasts: Vec<Blast>,
+    particles: Vec<Particle>,
+    numbers: Vec<DamageNumber>,
+}
+impl Effects {
+    /// Shows an explosion reaching `radius` around `pos`.
+    pub fn explosion(&mut self, pos: Vec2, radius: f32) {
+        self.blasts.push(Blast {
+            pos,
+            radius,
+            age: 0.0,
+        });
+        for _ in 0..PARTICLES_PER_EXPLOSION {
+            let random = Vec2::random();
+            // Random direction, with speeds that carry the fastest particles to the blast's edge
+            let angle = random.x * std::f32::consts::TAU;
+            let speed = radius * PARTICLE_DRAG * (0.3 + 0.7 * random.y);
+            self.particles.push(Particle {
+                pos,
+                vel: Vec2 {
+                    x: angle.cos(),
+                    y: angle.sin(),
+                } * speed,
+                ttl: PARTICLE_TIME * (0.5 + 0.5 * random.y),
+            });
+        }
+    }
+
+    /// Shows the damage a player at `pos` took, armor and health apart.
+    pub fn damage(&mut self, pos: Vec2, armor: f32, health: f32) {
+        let parts = [(health, HEALTH_NUMBER_COLOR), (armor, ARMOR_NUMBER_COLOR)];
+        let mut pos = pos;
+        for (amount, color) in parts {
+            if amount < 0.5 {
+                continue;
+            }
+            self.numbers.push(DamageNumber {
+                pos,
+                text: format!("{amount:.0}"),
+                color,
+                age: 0.0,
+            });
+            // The armor part sits next to the health part
+            pos.x += 0.06;
+        }
+    }
+
+    /// Advances every effect by `dt` seconds, dropping the finished ones.
+    pub fn update(&mut self, dt: f32) {
+        for blast in &mut self.blasts {
+            blast.age += dt;
+        }
+        self.blasts.retain(|blast| blast.age < BLAST_TIME);
+
+        for particle in &mut self.particles {
+            particle.pos += particle.vel * dt;
+            particle.vel *= (1.0 - PARTICLE_DRAG * dt).max(0.0);
+            particle.ttl -= dt;
+        }
+        self.particles.retain(|particle| particle.ttl > 0.0);
+
+        for number in &mut self.numbers {
+            number.age += dt;
+        }
+        self.numbers.retain(|number| number.age < NUMBER_TIME);
+    }
+
+    /// World position, text and color of every damage number
+    pub fn numbers(&self) -> impl Iterator<Item = (Vec2, &str, Color)> + '_ {
+        self.numbers.iter().map(|number| {
+            let rise = Vec2 {
+                x: 0.0,
+                y: NUMBER_RISE * number.age / NUMBER_TIME,
+            };
+            (number.pos + rise, number.text.as_str(), number.color)
+        })
+    }
+
+    /// Center and current radius of every blast ring
+    pub fn blasts(&self) -> impl Iterator<Item = (Vec2, f32)> + '_ {
+        self.blasts
+            .iter()
+            .map(|blast| (blast.pos, blast.radius * blast.age / BLAST_TIME))
+    }
+
+    /// Position and size of every particle, shrinking as they fade out
+    pub fn particles(&self) -> impl Iterator<Item = (Vec2, f32)> + '_ {
+        self.particles
+            .iter()
+            .map(|particle| (particle.pos, 0.012 * particle.ttl / PARTICLE_TIME))
+    }
+}
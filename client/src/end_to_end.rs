@@ -0,0 +1,196 @@
+//! End to end tests: a server started in-process on a free port and several [`Client`]s joining
+//! it through the network task the game runs, so a change to either side of the protocol that
+//! breaks the other shows up here.
+use anyhow::Result;
+use clap::Parser;
+use common::{
+    message::{ClientMessage, ServerMessage, Transport},
+    vec::Vec2,
+};
+use server::{Server, cli::ServerConfig};
+use test_utils::driver::EXPECT_TIMEOUT;
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        oneshot,
+    },
+    task::JoinHandle,
+    time,
+};
+
+use crate::client::{Client, Login, Route};
+
+/// A client as the game sees it: what the network task hands over and what it sends on
+struct Player {
+    id: u64,
+    from_server: UnboundedReceiver<ServerMessage>,
+    to_server: UnboundedSender<ClientMessage>,
+    quit: Option<oneshot::Sender<()>>,
+    network: JoinHandle<Result<()>>,
+}
+impl Player {
+    async fn join(addr: String, transport: Transport, username: &str) -> Self {
+        let (runtime_tx, from_server) = unbounded_channel();
+        let (to_server, runtime_rx) = unbounded_channel();
+        let (quit, quit_rx) = oneshot::channel();
+        let route = Route {
+            transport,
+            tls: None,
+        };
+        let login = Login {
+            username: String::from(username),
+            password: String::new(),
+            color: None,
+            lobby: None,
+        };
+        let network = tokio::spawn(Client::run(
+            addr, route, login, runtime_tx, runtime_rx, quit_rx,
+        ));
+        let mut player = Self {
+            id: 0,
+            from_server,
+            to_server,
+            quit: Some(quit),
+            network,
+        };
+        let accepted = player
+            .expect("the server accepts us", |msg| {
+                matches!(msg, ServerMessage::ConnectionAccepted { .. })
+            })
+            .await;
+        if let ServerMessage::ConnectionAccepted { id, .. } = accepted {
+            player.id = id;
+        }
+        player
+    }
+
+    fn send(&self, msg: ClientMessage) {
+        self.to_server.send(msg).expect("the network task runs");
+    }
+
+    /// Reads messages until one matches, panicking with the description if none does in time
+    async fn expect(
+        &mut self,
+        description: &str,
+        matches: impl Fn(&ServerMessage) -> bool,
+    ) -> ServerMessage {
+        let wait = async {
+            while let Some(msg) = self.from_server.recv().await {
+                if matches(&msg) {
+                    return Some(msg);
+                }
+            }
+            None
+        };
+        match time::timeout(EXPECT_TIMEOUT, wait).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => panic!("The connection ended before {description}"),
+            Err(_) => panic!("Timed out waiting until {description}"),
+        }
+    }
+
+    /// Where the snapshots put player `id`, once one has it
+    async fn position_of(&mut self, id: u64) -> Vec2 {
+        let snapshot = self
+            .expect("a snapshot has the player", |msg| {
+                matches!(msg, ServerMessage::UpdateEntities { entities, .. }
+                    if entities.players.contains_key(&id))
+            })
+            .await;
+        match snapshot {
+            ServerMessage::UpdateEntities { entities, .. } => entities.players[&id].pos,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Tells the server we are leaving and waits for the network task to end
+    async fn leave(mut self) {
+        if let Some(quit) = self.quit.take() {
+            let _ = quit.send(());
+        }
+        let ended = time::timeout(EXPECT_TIMEOUT, self.network).await;
+        ended
+            .expect("the network task ends")
+            .expect("the network task does not panic")
+            .expect("the network task leaves cleanly");
+    }
+}
+
+/// Three players join, one moves and chats while the others watch, then it leaves
+async fn play(transport: Transport) {
+    let transport_name = transport.to_string();
+    let config = ServerConfig::parse_from(["server", "--transport", &transport_name]);
+    let (addr, server) = Server::run_in_background(config).await.unwrap();
+    let addr = addr.to_string();
+
+    let mut mover = Player::join(addr.clone(), transport, "mover").await;
+    let mut watchers = Vec::new();
+    for username in ["first", "second"] {
+        let watcher = Player::join(addr.clone(), transport, username).await;
+        let id = watcher.id;
+        mover
+            .expect("the mover hears of the watcher joining", |msg| {
+                matches!(msg, ServerMessage::PlayerJoined { id: joined, .. } if *joined == id)
+            })
+            .await;
+        watchers.push(watcher);
+    }
+
+    // Moving shows up in the snapshots everyone gets
+    let id = mover.id;
+    let start = watchers[0].position_of(id).await;
+    mover.send(ClientMessage::MoveInput {
+        seq: 1,
+        dir: Vec2 { x: 1.0, y: 0.0 },
+    });
+    for watcher in &mut watchers {
+        watcher
+            .expect("the mover moved right", |msg| {
+                matches!(msg, ServerMessage::UpdateEntities { entities, .. }
+                if entities.players.get(&id).is_some_and(|player| {
+                    player.last_input_seq == 1 && player.pos.x > start.x
+                }))
+            })
+            .await;
+    }
+
+    mover.send(ClientMessage::Chat(String::from("hello")));
+    for watcher in &mut watchers {
+        watcher
+            .expect("the chat is relayed", |msg| {
+                matches!(msg, ServerMessage::ChatBroadcast { sender_id, text, .. }
+                    if *sender_id == id && text == "hello")
+            })
+            .await;
+    }
+
+    mover.leave().await;
+    for watcher in &mut watchers {
+        watcher
+            .expect(
+                "the mover is gone",
+                |msg| matches!(msg, ServerMessage::PlayerLeft { id: left, .. } if *left == id),
+            )
+            .await;
+        watcher
+            .expect("snapshots leave the mover out", |msg| {
+                matches!(msg, ServerMessage::UpdateEntities { entities, .. }
+                    if !entities.players.contains_key(&id))
+            })
+            .await;
+    }
+    for watcher in watchers {
+        watcher.leave().await;
+    }
+    server.abort();
+}
+
+#[tokio::test]
+async fn players_join_move_chat_and_leave_over_tcp() {
+    play(Transport::Tcp).await;
+}
+
+#[tokio::test]
+async fn players_join_move_chat_and_leave_over_udp() {
+    play(Transport::Udp).await;
+}
@@ -0,0 +1,139 @@
+//! Ghost of the local player's fastest lap in time trials, raced against on the following laps.
+//!
+//! While a lap runs, the local player's position is recorded by server tick since the lap
+//! started. When the server reports the lap completed faster than any before, the recording
+//! becomes the ghost, which every later lap plays back from its own start. Dying or leaving
+//! throws the running recording away, as the server ends the lap too.
+use common::vec::Vec2;
+
+/// Most positions recorded in a lap, the ghost of a longer lap stops where the recording does
+const MAX_SAMPLES: usize = 60 * 60 * 10;
+
+/// Positions by ticks since the start of a lap, in order
+type Path = Vec<(u64, Vec2)>;
+
+#[derive(Default)]
+pub struct Ghost {
+    /// Tick the running lap started at and the path of the local player through it so far
+    lap: Option<(u64, Path)>,
+    /// Time of the fastest lap in seconds, and its path
+    best: Option<(f32, Path)>,
+}
+impl Ghost {
+    /// Starts recording a lap the server started at `tick`
+    pub fn start_lap(&mut self, tick: u64) {
+        self.lap = Some((tick, Vec::new()));
+    }
+
+    /// Ends the running lap, completed in `time` seconds, keeping it as the ghost if it is the
+    /// fastest yet
+    pub fn complete_lap(&mut self, time: f32) {
+        let Some((_, path)) = self.lap.take() else {
+            return;
+        };
+        if self.best.as_ref().is_none_or(|(best, _)| time < *best) {
+            self.best = Some((time, path));
+        }
+    }
+
+    /// Throws the running lap away
+    pub fn abandon_lap(&mut self) {
+        self.lap = None;
+    }
+
+    /// Forgets everything, as when leaving the server
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Notes where the local player is at `tick`
+    pub fn record(&mut self, tick: u64, pos: Vec2) {
+        let Some((start, path)) = &mut self.lap else {
+            return;
+        };
+        let Some(offset) = tick.checked_sub(*start) else {
+            return;
+        };
+        // Frames are drawn faster than ticks go by, the first position of a tick is kept
+        if path.len() < MAX_SAMPLES && path.last().is_none_or(|(last, _)| offset > *last) {
+            path.push((offset, pos));
+        }
+    }
+
+    /// Where the ghost is at `tick`, between the recorded positions around it. Nowhere when no
+    /// lap runs, there is no ghost yet, or the ghost already finished.
+    pub fn position(&self, tick: u64) -> Option<Vec2> {
+        let (start, _) = self.lap.as_ref()?;
+        let (_, path) = self.best.as_ref()?;
+        let offset = tick.checked_sub(*start)?;
+        let after = path.partition_point(|(at, _)| *at < offset);
+        let &(to_tick, to) = path.get(after)?;
+        let Some(&(from_tick, from)) = after.checked_sub(1).and_then(|i| path.get(i)) else {
+            return Some(to);
+        };
+        let t = (offset - from_tick) as f32 / (to_tick - from_tick) as f32;
+        Some(from + (to - from) * t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32) -> Vec2 {
+        Vec2 { x, y: 0.0 }
+    }
+
+    /// Records a lap starting at `start`, moving one unit per tick for `ticks` ticks
+    fn drive(ghost: &mut Ghost, start: u64, ticks: u64) {
+        ghost.start_lap(start);
+        for offset in 0..=ticks {
+            ghost.record(start + offset, at(offset as f32));
+        }
+    }
+
+    #[test]
+    fn the_fastest_lap_is_played_back_from_each_lap_start() {
+        let mut ghost = Ghost::default();
+        drive(&mut ghost, 100, 10);
+        // No ghost during the first lap
+        assert_eq!(ghost.position(105), None);
+        ghost.complete_lap(10.0);
+
+        ghost.start_lap(200);
+        assert_eq!(ghost.position(200), Some(at(0.0)));
+        assert_eq!(ghost.position(204), Some(at(4.0)));
+        // Finished by then
+        assert_eq!(ghost.position(211), None);
+    }
+
+    #[test]
+    fn slower_laps_do_not_replace_the_ghost() {
+        let mut ghost = Ghost::default();
+        drive(&mut ghost, 0, 10);
+        ghost.complete_lap(10.0);
+        ghost.start_lap(20);
+        for offset in 0..=20 {
+            ghost.record(20 + offset, at(offset as f32 / 2.0));
+        }
+        ghost.complete_lap(20.0);
+
+        ghost.start_lap(50);
+        assert_eq!(ghost.position(56), Some(at(6.0)));
+    }
+
+    #[test]
+    fn positions_between_recorded_ticks_are_blended() {
+        let mut ghost = Ghost::default();
+        ghost.start_lap(0);
+        ghost.record(0, at(0.0));
+        ghost.record(4, at(2.0));
+        ghost.complete_lap(1.0);
+        ghost.start_lap(10);
+        assert_eq!(ghost.position(12), Some(at(1.0)));
+
+        // Abandoned laps leave no ghost to race
+        ghost.abandon_lap();
+        assert_eq!(ghost.position(12), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Controller rumble for what happens to the local player, and the controller's stick.
+//!
+//! Game events are turned into a [`Rumble`] here, scaled by how much they matter, and handed to
+//! whatever [`Controller`] is in use. There is no gamepad backend yet, so [`NoController`] takes
+//! every rumble and does nothing with it; a backend only has to implement [`Controller`] for
+//! taking damage, firing and dying to be felt. Each effect can be turned off with `--no-rumble`.
+use clap::ValueEnum;
+use common::{vec::Vec2, world::combat::ProjectileKind};
+
+/// Weakest rumble worth playing, small hits are raised to it so they can still be felt
+const MIN_STRENGTH: f32 = 0.15;
+/// Seconds a rumble at full strength lasts, weaker ones are shorter
+const DAMAGE_DURATION: f32 = 0.35;
+const FIRING_STRENGTH: f32 = 0.2;
+/// Explosives kick harder than the other weapons
+const EXPLOSIVE_FIRING_STRENGTH: f32 = 0.5;
+const FIRING_DURATION: f32 = 0.08;
+const DEATH_STRENGTH: f32 = 1.0;
+const DEATH_DURATION: f32 = 0.8;
+
+/// Kinds of events that rumble, each can be turned off on its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Effect {
+    Damage,
+    Firing,
+    Death,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rumble {
+    pub effect: Effect,
+    /// From 0 for nothing to 1 for as hard as the controller can
+    pub strength: f32,
+    /// Seconds
+    pub duration: f32,
+}
+impl Rumble {
+    /// The local player lost `armor` and `health`, out of `max_health`
+    pub fn damage(armor: f32, health: f32, max_health: f32) -> Self {
+        let strength = ((armor + health) / max_health.max(f32::EPSILON)).clamp(MIN_STRENGTH, 1.0);
+        Self {
+            effect: Effect::Damage,
+            strength,
+            duration: DAMAGE_DURATION * strength,
+        }
+    }
+
+    pub fn firing(kind: ProjectileKind) -> Self {
+        let strength = match kind {
+            ProjectileKind::Explosive => EXPLOSIVE_FIRING_STRENGTH,
+            _ => FIRING_STRENGTH,
+        };
+        Self {
+            effect: Effect::Firing,
+            strength,
+            duration: FIRING_DURATION,
+        }
+    }
+
+    pub fn death() -> Self {
+        Self {
+            effect: Effect::Death,
+            strength: DEATH_STRENGTH,
+            duration: DEATH_DURATION,
+        }
+    }
+}
+
+/// A gamepad that can rumble
+pub trait Controller {
+    fn rumble(&mut self, rumble: Rumble);
+
+    /// Where the stick that moves the player points, each axis from -1 to 1. Centered for
+    /// controllers without one
+    fn stick(&self) -> Vec2 {
+        Vec2::ZERO
+    }
+}
+
+/// Stands in while no gamepad is connected
+pub struct NoController;
+impl Controller for NoController {
+    fn rumble(&mut self, _rumble: Rumble) {}
+}
+
+/// Which effects rumble, all of them unless turned off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RumbleSettings {
+    pub damage: bool,
+    pub firing: bool,
+    pub death: bool,
+}
+impl RumbleSettings {
+    /// Every effect but those in `disabled`
+    pub fn without(disabled: &[Effect]) -> Self {
+        Self {
+            damage: !disabled.contains(&Effect::Damage),
+            firing: !disabled.contains(&Effect::Firing),
+            death: !disabled.contains(&Effect::Death),
+        }
+    }
+
+    pub fn enabled(&self, effect: Effect) -> bool {
+        match effect {
+            Effect::Damage => self.damage,
+            Effect::Firing => self.firing,
+            Effect::Death => self.death,
+        }
+    }
+}
+
+pub struct Haptics {
+    controller: Box<dyn Controller>,
+}
+impl Default for Haptics {
+    fn default() -> Self {
+        Self::new(Box::new(NoController))
+    }
+}
+impl Haptics {
+    pub fn new(controller: Box<dyn Controller>) -> Self {
+        Self { controller }
+    }
+
+    /// Where the controller's stick points, see [`Controller::stick`]
+    pub fn stick(&self) -> Vec2 {
+        self.controller.stick()
+    }
+
+    /// Plays `rumble` unless its effect is turned off in `settings`
+    pub fn play(&mut self, settings: &RumbleSettings, rumble: Rumble) {
+        if settings.enabled(rumble.effect) {
+            self.controller.rumble(rumble);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    struct Recording(Rc<RefCell<Vec<Rumble>>>);
+    impl Controller for Recording {
+        fn rumble(&mut self, rumble: Rumble) {
+            self.0.borrow_mut().push(rumble);
+        }
+    }
+
+    #[test]
+    fn rumbles_follow_events_and_toggles() {
+        let played = Rc::new(RefCell::new(Vec::new()));
+        let mut haptics = Haptics::new(Box::new(Recording(played.clone())));
+        let settings = RumbleSettings::without(&[Effect::Firing]);
+
+        haptics.play(&settings, Rumble::damage(10.0, 40.0, 100.0));
+        haptics.play(&settings, Rumble::damage(0.0, 1.0, 100.0));
+        haptics.play(&settings, Rumble::firing(ProjectileKind::Explosive));
+        haptics.play(&settings, Rumble::death());
+
+        let played = played.borrow();
+        let effects: Vec<_> = played.iter().map(|rumble| rumble.effect).collect();
+        assert_eq!(effects, [Effect::Damage, Effect::Damage, Effect::Death]);
+        // Harder hits rumble harder and longer, the smallest still noticeably
+        assert_eq!(played[0].strength, 0.5);
+        assert!(played[0].duration > played[1].duration);
+        assert_eq!(played[1].strength, MIN_STRENGTH);
+    }
+}
@@ -0,0 +1,99 @@
+//! Heads-up display state: the frame rate, the round trip to the server and the flash of a shot
+//! with an empty weapon.
+//!
+//! Round trips come from the pongs the network task passes on, each echoing the time its ping
+//! was sent at.
+use std::collections::VecDeque;
+
+/// Seconds of frames the frame rate is worked out over
+const FPS_WINDOW: f64 = 1.0;
+/// Weight of a new sample in the smoothed round trip, as TCP does it
+const RTT_SMOOTHING: f64 = 0.125;
+/// Seconds the ammo counter flashes after trying to fire an empty weapon
+const DRY_FIRE_TIME: f64 = 0.3;
+
+#[derive(Default)]
+pub struct Hud {
+    /// When the frames of the last second were drawn
+    frames: VecDeque<f64>,
+    /// Smoothed round trip in seconds, once there has been a sample
+    rtt: Option<f64>,
+    /// When an empty weapon was last fired
+    dry_fire: Option<f64>,
+}
+impl Hud {
+    pub fn frame(&mut self, now: f64) {
+        self.frames.push_back(now);
+        while self
+            .frames
+            .front()
+            .is_some_and(|time| now - time > FPS_WINDOW)
+        {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Frames per second over the last second
+    pub fn fps(&self) -> f64 {
+        match (self.frames.front(), self.frames.back()) {
+            (Some(first), Some(last)) if last > first => {
+                (self.frames.len() - 1) as f64 / (last - first)
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub fn add_rtt_sample(&mut self, rtt: f64) {
+        let rtt = rtt.max(0.0);
+        self.rtt = Some(match self.rtt {
+            Some(smoothed) => smoothed + RTT_SMOOTHING * (rtt - smoothed),
+            None => rtt,
+        });
+    }
+
+    /// Smoothed round trip to the server in seconds
+    pub fn rtt(&self) -> Option<f64> {
+        self.rtt
+    }
+
+    pub fn dry_fire(&mut self, now: f64) {
+        self.dry_fire = Some(now);
+    }
+
+    /// Whether the ammo counter is still flashing from a dry shot
+    pub fn dry_firing(&self, now: f64) -> bool {
+        self.dry_fire.is_some_and(|time| now - time < DRY_FIRE_TIME)
+    }
+
+    /// Forgets the connection, for when we move to another server
+    pub fn reset_connection(&mut self) {
+        self.rtt = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fps_counts_frames_in_the_last_second() {
+        let mut hud = Hud::default();
+        for frame in 0..=120 {
+            hud.frame(frame as f64 / 60.0);
+        }
+        assert!((hud.fps() - 60.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn round_trip_is_smoothed_from_the_first_sample() {
+        let mut hud = Hud::default();
+        assert_eq!(hud.rtt(), None);
+        hud.add_rtt_sample(0.1);
+        assert_eq!(hud.rtt(), Some(0.1));
+        // One slow pong only moves it part of the way
+        hud.add_rtt_sample(0.9);
+        assert!((hud.rtt().unwrap() - 0.2).abs() < 1e-9);
+        hud.reset_connection();
+        assert_eq!(hud.rtt(), None);
+    }
+}
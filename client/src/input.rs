@@ -0,0 +1,261 @@
+//! Keyboard and stick state for movement, sampled every frame instead of acting on single key
+//! events.
+//!
+//! Keys are turned into [`Action`]s by the control profile before they get here. Holding several
+//! keys combines them, so up and right together move diagonally and letting go of one of them
+//! keeps the other going.
+//!
+//! While no movement key is held, the controller's stick moves the player instead, slower the
+//! less it is pushed. Pushes within the dead zone count as none, so a stick resting slightly off
+//! center does not creep, and the rest of the range is stretched to start from zero.
+//!
+//! Changes are coalesced into at most one movement update per simulation tick, the rate the server
+//! steps players at anyway, so key events do not each cost a message. Keys pressed during a tick
+//! count for it even if they were let go of before it ended, so a tap quicker than a tick still
+//! moves the player for one.
+use std::collections::HashSet;
+
+use anyhow::{Result, ensure};
+
+use common::{details::TICK_RATE, vec::Vec2};
+
+/// Share of the stick's range ignored around its center unless set otherwise
+pub const DEFAULT_DEAD_ZONE: f32 = 0.15;
+/// Largest dead zone, a larger one would leave the stick next to no range
+pub const MAX_DEAD_ZONE: f32 = 0.9;
+
+use crate::controls::Action;
+
+pub struct InputState {
+    held: HashSet<Action>,
+    /// Keys pressed since the last update went out, held or not
+    pressed: HashSet<Action>,
+    /// Where the stick points, as the controller reads it
+    stick: Vec2,
+    /// Share of the stick's range around its center that counts as not pushed
+    dead_zone: f32,
+    /// Movement the server last heard about
+    sent: Vec2,
+    /// When `sent` went out
+    last_send: Option<f64>,
+    /// Shortest time between two updates, one tick of the server's simulation, in seconds
+    interval: f64,
+}
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            stick: Vec2::ZERO,
+            dead_zone: DEFAULT_DEAD_ZONE,
+            sent: Vec2::ZERO,
+            last_send: None,
+            interval: 1.0 / TICK_RATE,
+        }
+    }
+}
+impl InputState {
+    /// Ignores pushes of the stick within `dead_zone` of its center
+    pub fn with_dead_zone(dead_zone: f32) -> Self {
+        Self {
+            dead_zone,
+            ..Self::default()
+        }
+    }
+
+    pub fn press(&mut self, action: Action) {
+        self.held.insert(action);
+        self.pressed.insert(action);
+    }
+
+    pub fn release(&mut self, action: Action) {
+        self.held.remove(&action);
+    }
+
+    /// Lets go of every key, such as when the chat box takes the keyboard or the bindings change.
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.pressed.clear();
+    }
+
+    /// Sends updates once every `interval` seconds at most, the server's tick
+    pub fn set_interval(&mut self, interval: f64) {
+        self.interval = interval;
+    }
+
+    /// Takes where the stick points this frame, each axis from -1 to 1
+    pub fn set_stick(&mut self, stick: Vec2) {
+        self.stick = stick;
+    }
+
+    /// Movement for the coming update: the held keys and those pressed since the last one, or
+    /// the stick when no key is
+    fn movement(&self) -> Vec2 {
+        match direction(|action| self.held.contains(&action) || self.pressed.contains(&action)) {
+            Vec2::ZERO => analog(self.stick, self.dead_zone),
+            keys => keys,
+        }
+    }
+
+    /// Movement to send at `now`, if it changed since the last one sent and a tick passed since.
+    pub fn due(&self, now: f64) -> Option<Vec2> {
+        let movement = self.movement();
+        let throttled = self
+            .last_send
+            .is_some_and(|last| now - last < self.interval);
+        (movement != self.sent && !throttled).then_some(movement)
+    }
+
+    /// Remembers that `movement` reached the server at `now`, starting the next tick's presses.
+    pub fn mark_sent(&mut self, movement: Vec2, now: f64) {
+        self.sent = movement;
+        self.last_send = Some(now);
+        self.pressed.clear();
+    }
+
+    /// Forgets what was sent, so the held keys go out again on the next frame. Taps in between are
+    /// dropped.
+    pub fn resend(&mut self) {
+        self.sent = Vec2::ZERO;
+        self.last_send = None;
+        self.pressed.clear();
+    }
+}
+
+/// Direction of the movement keys `down` tells are down, of length 1 or zero when they cancel out
+fn direction(down: impl Fn(Action) -> bool) -> Vec2 {
+    let axis = |negative, positive| down(positive) as i8 as f32 - down(negative) as i8 as f32;
+    let dir = Vec2 {
+        x: axis(Action::MoveLeft, Action::MoveRight),
+        y: axis(Action::MoveDown, Action::MoveUp),
+    };
+    match dir.length() {
+        0.0 => Vec2::ZERO,
+        length => dir / length,
+    }
+}
+
+/// Reads a dead zone given on the command line
+pub fn parse_dead_zone(text: &str) -> Result<f32> {
+    let dead_zone: f32 = text.trim().parse()?;
+    ensure!(
+        (0.0..=MAX_DEAD_ZONE).contains(&dead_zone),
+        "The dead zone must be from 0 to {MAX_DEAD_ZONE}, got {dead_zone}"
+    );
+    Ok(dead_zone)
+}
+
+/// Movement of a stick pointing at `stick`, of at most unit length and zero within `dead_zone`
+fn analog(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if !length.is_finite() || length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    let push = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick * (push / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends whatever is due at `now` like the game loop does, returning it
+    fn send(input: &mut InputState, now: f64) -> Option<Vec2> {
+        let movement = input.due(now)?;
+        input.mark_sent(movement, now);
+        Some(movement)
+    }
+
+    #[test]
+    fn held_keys_combine() {
+        let mut input = InputState::default();
+        input.set_interval(0.05);
+        input.press(Action::MoveUp);
+        input.press(Action::MoveRight);
+        let diagonal = send(&mut input, 0.0).unwrap();
+        assert!((diagonal.length() - 1.0).abs() < 1e-6);
+        assert!(diagonal.x > 0.0 && diagonal.y > 0.0);
+
+        // Letting go of one key keeps the other going
+        input.release(Action::MoveUp);
+        assert_eq!(send(&mut input, 0.05), Some(Vec2 { x: 1.0, y: 0.0 }));
+
+        // Opposite keys cancel out
+        input.press(Action::MoveLeft);
+        assert_eq!(send(&mut input, 0.1), Some(Vec2::ZERO));
+    }
+
+    #[test]
+    fn changes_are_sent_once_a_tick() {
+        let mut input = InputState::default();
+        input.set_interval(0.05);
+        assert_eq!(input.due(0.0), None);
+
+        input.press(Action::MoveUp);
+        assert_eq!(send(&mut input, 0.0), Some(Vec2 { x: 0.0, y: 1.0 }));
+        input.release(Action::MoveUp);
+        input.press(Action::MoveRight);
+        assert_eq!(input.due(0.01), None);
+
+        // Everything that happened during the tick goes out as one update
+        input.release(Action::MoveRight);
+        input.press(Action::MoveDown);
+        let merged = send(&mut input, 0.05).unwrap();
+        assert!(merged.x > 0.0 && merged.y < 0.0);
+        assert_eq!(send(&mut input, 0.1), Some(Vec2 { x: 0.0, y: -1.0 }));
+        assert_eq!(send(&mut input, 0.2), None);
+    }
+
+    #[test]
+    fn stick_moves_slower_when_pushed_less() {
+        let mut input = InputState::with_dead_zone(0.2);
+        input.set_interval(0.05);
+
+        // Resting slightly off center
+        input.set_stick(Vec2 { x: 0.1, y: -0.1 });
+        assert_eq!(input.due(0.0), None);
+
+        input.set_stick(Vec2 { x: 0.6, y: 0.0 });
+        let half = send(&mut input, 0.0).unwrap();
+        assert!((half.x - 0.5).abs() < 1e-6 && half.y == 0.0);
+
+        // Corners of a square stick are no faster than a full push
+        input.set_stick(Vec2 { x: 1.0, y: 1.0 });
+        let corner = send(&mut input, 0.05).unwrap();
+        assert!((corner.length() - 1.0).abs() < 1e-6);
+
+        // Keys take over from the stick
+        input.press(Action::MoveLeft);
+        assert_eq!(send(&mut input, 0.1), Some(Vec2 { x: -1.0, y: 0.0 }));
+    }
+
+    #[test]
+    fn taps_within_a_tick_are_not_lost() {
+        let mut input = InputState::default();
+        input.set_interval(0.05);
+        let mut sent = Vec::new();
+        // Each key tapped between two frames, frames coming every 10 ms
+        for (i, action) in [Action::MoveLeft, Action::MoveUp, Action::MoveRight]
+            .into_iter()
+            .enumerate()
+        {
+            let frame = i as f64 * 0.1;
+            input.press(action);
+            input.release(action);
+            for step in 0..10 {
+                sent.extend(send(&mut input, frame + step as f64 * 0.01));
+            }
+        }
+        assert_eq!(
+            sent,
+            vec![
+                Vec2 { x: -1.0, y: 0.0 },
+                Vec2::ZERO,
+                Vec2 { x: 0.0, y: 1.0 },
+                Vec2::ZERO,
+                Vec2 { x: 1.0, y: 0.0 },
+                Vec2::ZERO,
+            ]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Interpolation of remote entities.
+//!
+//! Snapshots from the server arrive at an uneven pace, so remote entities are drawn slightly in
+//! the past. [`SnapshotBuffer`] keeps recent snapshots keyed by server tick and blends between the
+//! two surrounding the render time, extrapolating for a short while when snapshots are late.
+//! [`DelayEstimator`] compares snapshot arrival times with the server times they were sent at and
+//! works out how large that delay needs to be to hide the measured jitter, unless the user picked
+//! a fixed delay. The snapshots go back a few seconds, so the kill cam can replay the lead up to a
+//! death from them, see [`killcam`](crate::killcam).
+use std::collections::{BTreeMap, HashMap};
+
+use common::{details::TICK_RATE, vec::Vec2, world::entities::Player};
+
+use crate::{
+    killcam::KILL_CAM_LENGTH,
+    settings::{InterpolationDelay, MAX_INTERPOLATION_DELAY},
+};
+
+/// Weight given to each new sample in the running averages
+const SMOOTHING: f64 = 0.1;
+/// Delay used in auto mode before any snapshots have been measured, in seconds
+const DEFAULT_DELAY: f64 = 0.1;
+/// Number of standard deviations of jitter covered by the auto delay
+const JITTER_MARGIN: f64 = 3.0;
+
+/// Longest time remote entities keep moving past the newest snapshot, in seconds
+const MAX_EXTRAPOLATION: f64 = 0.25;
+/// Snapshots older than this behind the newest one are discarded, in seconds
+const HISTORY: f64 = KILL_CAM_LENGTH + 1.0;
+/// Rate at which the clock offset estimate is allowed to grow, per snapshot
+const CLOCK_DRIFT: f64 = 0.01;
+
+/// Remote entity state received in a single snapshot
+#[derive(Clone)]
+struct Snapshot {
+    players: HashMap<u64, Player>,
+}
+
+/// Recent snapshots keyed by the server tick they were taken at
+pub struct SnapshotBuffer {
+    snapshots: BTreeMap<u64, Snapshot>,
+    /// Estimate of local time minus server time, in seconds
+    clock_offset: Option<f64>,
+    /// Simulation steps per second on the server
+    tick_rate: f64,
+}
+impl Default for SnapshotBuffer {
+    fn default() -> Self {
+        Self {
+            snapshots: BTreeMap::new(),
+            clock_offset: None,
+            tick_rate: TICK_RATE,
+        }
+    }
+}
+impl SnapshotBuffer {
+    /// Server time of a tick, in seconds
+    pub fn tick_time(&self, tick: u64) -> f64 {
+        tick as f64 / self.tick_rate
+    }
+
+    /// The server steps `tick_rate` times per second. Snapshots kept so far are timed by the old
+    /// rate, so they are dropped when it changes.
+    pub fn set_tick_rate(&mut self, tick_rate: f64) {
+        if tick_rate != self.tick_rate {
+            *self = Self {
+                tick_rate,
+                ..Self::default()
+            };
+        }
+    }
+
+    /// Stores the players of a snapshot taken at `tick` that arrived at local time `now`.
+    pub fn insert(&mut self, tick: u64, players: HashMap<u64, Player>, now: f64) {
+        // Track the fastest observed delivery, slowly letting the estimate grow to follow clock drift
+        let sample = now - self.tick_time(tick);
+        self.clock_offset = Some(match self.clock_offset {
+            Some(offset) if sample > offset => offset + CLOCK_DRIFT * (sample - offset),
+            _ => sample,
+        });
+        self.snapshots.insert(tick, Snapshot { players });
+
+        // Keep one snapshot older than the history window to interpolate from
+        let oldest_needed = self.tick_time(tick) - HISTORY;
+        while self.snapshots.len() > 2 {
+            let mut ticks = self.snapshots.keys();
+            let (first, second) = (*ticks.next().unwrap(), *ticks.next().unwrap());
+            if self.tick_time(second) > oldest_needed {
+                break;
+            }
+            self.snapshots.remove(&first);
+        }
+    }
+
+    /// Position of player `id` as it should be drawn at local time `now`, `delay` seconds in the past.
+    pub fn sample(&self, id: u64, now: f64, delay: f64) -> Option<Vec2> {
+        self.sample_at(id, now - self.clock_offset? - delay)
+    }
+
+    /// Position of player `id` at server time `render_time`, in seconds
+    pub fn sample_at(&self, id: u64, render_time: f64) -> Option<Vec2> {
+        let before = self.snapshots.iter().rev().find(|(tick, snapshot)| {
+            self.tick_time(**tick) <= render_time && snapshot.players.contains_key(&id)
+        });
+        let after = self.snapshots.iter().find(|(tick, snapshot)| {
+            self.tick_time(**tick) > render_time && snapshot.players.contains_key(&id)
+        });
+
+        match (before, after) {
+            (Some((from_tick, from)), Some((to_tick, to))) => {
+                let from_time = self.tick_time(*from_tick);
+                let t = (render_time - from_time) / (self.tick_time(*to_tick) - from_time);
+                let (from, to) = (&from.players[&id], &to.players[&id]);
+                Some(from.pos + (to.pos - from.pos) * t as f32)
+            }
+            // Snapshots are late, keep the player moving along its last known velocity for a while
+            (Some((tick, snapshot)), None) => {
+                let player = &snapshot.players[&id];
+                let ahead = (render_time - self.tick_time(*tick)).min(MAX_EXTRAPOLATION);
+                Some(player.pos + player.vel * ahead as f32)
+            }
+            (None, Some((_, snapshot))) => Some(snapshot.players[&id].pos),
+            (None, None) => None,
+        }
+    }
+
+    /// Every player of the snapshot last taken by server time `render_time`, placed where they
+    /// were at that time
+    pub fn players_at(&self, render_time: f64) -> HashMap<u64, Player> {
+        let Some((_, snapshot)) = self
+            .snapshots
+            .iter()
+            .rev()
+            .find(|(tick, _)| self.tick_time(**tick) <= render_time)
+            .or_else(|| self.snapshots.first_key_value())
+        else {
+            return HashMap::new();
+        };
+        let mut players = snapshot.players.clone();
+        for (id, player) in &mut players {
+            if let Some(pos) = self.sample_at(*id, render_time) {
+                player.pos = pos;
+            }
+        }
+        players
+    }
+
+    /// Server time of the oldest snapshot kept, in seconds
+    pub fn oldest_time(&self) -> Option<f64> {
+        let (tick, _) = self.snapshots.first_key_value()?;
+        Some(self.tick_time(*tick))
+    }
+
+    /// Copy of the snapshots taken up to `tick`, which later snapshots do not push out
+    pub fn until(&self, tick: u64) -> Self {
+        Self {
+            snapshots: self
+                .snapshots
+                .range(..=tick)
+                .map(|(tick, snapshot)| (*tick, snapshot.clone()))
+                .collect(),
+            clock_offset: self.clock_offset,
+            tick_rate: self.tick_rate,
+        }
+    }
+}
+
+/// Measures the interval between snapshots and the jitter of their delivery
+#[derive(Default)]
+pub struct DelayEstimator {
+    /// Local and server time of the last snapshot
+    last_arrival: Option<(f64, f64)>,
+    /// Running average of the server time between snapshots, in seconds
+    interval: Option<f64>,
+    /// Running average of the squared change in delivery time between snapshots
+    variance: f64,
+}
+impl DelayEstimator {
+    /// Records that a snapshot sent at server time `sent` arrived at local time `now`, in seconds.
+    pub fn record_arrival(&mut self, now: f64, sent: f64) {
+        if let Some((last_now, last_sent)) = self.last_arrival.replace((now, sent)) {
+            let gap = sent - last_sent;
+            // Only the network adds jitter, a late server tick delays sending and arrival alike
+            let deviation = (now - last_now) - gap;
+            self.variance += SMOOTHING * (deviation * deviation - self.variance);
+            self.interval = Some(match self.interval {
+                Some(interval) => interval + SMOOTHING * (gap - interval),
+                None => gap,
+            });
+        }
+    }
+
+    /// Average time between snapshots, in seconds
+    pub fn interval(&self) -> Option<f64> {
+        self.interval
+    }
+
+    /// Standard deviation of the change in delivery time between snapshots, in seconds
+    pub fn jitter(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// Delay that should be applied with the given setting, in seconds.
+    pub fn effective_delay(&self, setting: InterpolationDelay) -> f64 {
+        match setting {
+            InterpolationDelay::Manual(delay) => delay,
+            InterpolationDelay::Auto => match self.interval {
+                // Keep two snapshots buffered and leave room for late ones
+                Some(interval) => {
+                    (2.0 * interval + JITTER_MARGIN * self.jitter()).min(MAX_INTERPOLATION_DELAY)
+                }
+                None => DEFAULT_DELAY,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_hiccups_are_not_jitter() {
+        let mut estimator = DelayEstimator::default();
+        // A steady trip across the network, with the server sending at an uneven pace
+        let sent = [0.0, 0.05, 0.1, 0.2, 0.25, 0.4, 0.45];
+        for time in sent {
+            estimator.record_arrival(time + 0.03, time);
+        }
+        assert!(estimator.jitter() < 1e-9);
+
+        // The same pace with deliveries taking between 30 and 70 ms
+        let mut estimator = DelayEstimator::default();
+        for (i, time) in sent.into_iter().enumerate() {
+            let trip = if i % 2 == 0 { 0.03 } else { 0.07 };
+            estimator.record_arrival(time + trip, time);
+        }
+        assert!(estimator.jitter() > 0.01);
+    }
+}
@@ -0,0 +1,131 @@
+//! Slow-motion replay of the last seconds before the local player died, seen from the killer.
+//!
+//! The recent snapshots kept for interpolation already hold everything needed. When the local
+//! player is killed by someone else, the snapshots up to the death are copied out of the
+//! [`SnapshotBuffer`] so newer ones cannot push them out, and played back slower than they
+//! happened with the camera on the killer. As much is replayed as fits in the wait to respawn, at
+//! most [`KILL_CAM_LENGTH`]. The replay ends when it reaches the death, when the player respawns,
+//! or when skipped.
+use std::collections::HashMap;
+
+use common::{vec::Vec2, world::entities::Player};
+
+use crate::interpolation::SnapshotBuffer;
+
+/// Most seconds of play before the death that are replayed
+pub const KILL_CAM_LENGTH: f64 = 3.0;
+/// Speed of the replay, as a share of real time
+const SLOW_MOTION: f64 = 0.5;
+
+pub struct KillCam {
+    pub killer: u64,
+    snapshots: SnapshotBuffer,
+    /// Server time shown, in seconds
+    time: f64,
+    /// Server time of the death, where the replay ends
+    end: f64,
+    /// Whether a snapshot has shown the local player dead yet, the death message can come first
+    seen_dead: bool,
+    /// Whether the local player came back alive after that, which ends the replay
+    respawned: bool,
+}
+impl KillCam {
+    /// Replay of the snapshots in `snapshots` leading up to `death_tick`, made to end within
+    /// `respawn_delay` seconds. `None` when there is nothing to replay.
+    pub fn start(
+        snapshots: &SnapshotBuffer,
+        killer: u64,
+        death_tick: u64,
+        respawn_delay: f64,
+    ) -> Option<Self> {
+        let snapshots = snapshots.until(death_tick);
+        let end = snapshots.tick_time(death_tick);
+        let length = KILL_CAM_LENGTH.min(respawn_delay * SLOW_MOTION);
+        let start = (end - length).max(snapshots.oldest_time()?);
+        (start < end).then_some(Self {
+            killer,
+            snapshots,
+            time: start,
+            end,
+            seen_dead: false,
+            respawned: false,
+        })
+    }
+
+    /// Moves the replay along by `dt` seconds of real time, `alive` telling whether the local
+    /// player is alive by now
+    pub fn update(&mut self, dt: f64, alive: bool) {
+        self.time = (self.time + dt * SLOW_MOTION).min(self.end);
+        self.seen_dead |= !alive;
+        self.respawned |= self.seen_dead && alive;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.time >= self.end || self.respawned
+    }
+
+    /// Players as they were at the moment shown
+    pub fn players(&self) -> HashMap<u64, Player> {
+        self.snapshots.players_at(self.time)
+    }
+
+    /// Where the killer was at the moment shown, for the camera to follow
+    pub fn killer_pos(&self) -> Option<Vec2> {
+        self.snapshots.sample_at(self.killer, self.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::details::TICK_RATE;
+    use test_utils::world::player;
+
+    use super::*;
+
+    #[test]
+    fn replays_up_to_the_death_in_slow_motion() {
+        let mut snapshots = SnapshotBuffer::default();
+        // The killer walks right one unit a tick, for longer than the replay lasts
+        let ticks = (KILL_CAM_LENGTH * TICK_RATE) as u64 * 2;
+        for tick in 0..=ticks {
+            let killer = player(
+                "killer",
+                Vec2 {
+                    x: tick as f32,
+                    y: 0.0,
+                },
+            );
+            let players = [(1, killer), (2, player("victim", Vec2::ZERO))].into();
+            snapshots.insert(tick, players, snapshots.tick_time(tick));
+        }
+
+        let mut cam = KillCam::start(&snapshots, 1, ticks, f64::INFINITY).unwrap();
+        let start = cam.killer_pos().unwrap().x;
+        assert_eq!(start, ticks as f32 - (KILL_CAM_LENGTH * TICK_RATE) as f32);
+        assert_eq!(cam.players().len(), 2);
+
+        // Snapshots after the death change nothing
+        snapshots.insert(ticks + 1, HashMap::new(), 0.0);
+        cam.update(1.0, false);
+        let moved = cam.killer_pos().unwrap().x - start;
+        assert!((moved - (SLOW_MOTION * TICK_RATE) as f32).abs() < 1e-3);
+        assert!(!cam.finished());
+
+        cam.update(KILL_CAM_LENGTH / SLOW_MOTION, false);
+        assert!(cam.finished());
+        assert_eq!(cam.killer_pos().unwrap().x, ticks as f32);
+
+        // A short wait to respawn gets a short replay, which respawning cuts off
+        let mut cam = KillCam::start(&snapshots, 1, ticks, 1.0).unwrap();
+        cam.update(0.9, false);
+        assert!(!cam.finished());
+        cam.update(0.1, false);
+        assert!(cam.finished());
+        let mut cam = KillCam::start(&snapshots, 1, ticks, 1.0).unwrap();
+        cam.update(0.1, true);
+        cam.update(0.1, false);
+        assert!(!cam.finished());
+        cam.update(0.1, true);
+        assert!(cam.finished());
+    }
+}
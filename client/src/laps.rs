@@ -0,0 +1,97 @@
+//! Progress of the local player around the track in time trials and races, as the server reports
+//! it with [`ServerMessage::CheckpointReached`] and the lap and race messages.
+//!
+//! Until the server reports a checkpoint passed, the player is heading for the start line. The
+//! heads-up display points at the checkpoint to reach next and shows the time of the running lap,
+//! and in races which lap it is out of how many.
+//!
+//! [`ServerMessage::CheckpointReached`]: common::message::ServerMessage::CheckpointReached
+
+#[derive(Default)]
+pub struct Laps {
+    /// Index of the checkpoint to reach next
+    next: usize,
+    /// Tick the running lap started at
+    lap_start: Option<u64>,
+    /// Laps completed since the race started, or since joining in time trials
+    completed: u32,
+    /// Laps of the race being driven, none in time trials
+    race_laps: Option<u32>,
+    /// Whether the local player finished the race, which leaves nothing to show
+    finished: bool,
+}
+impl Laps {
+    /// Starts a race of `laps` laps, from the start line
+    pub fn start_race(&mut self, laps: u32) {
+        *self = Self {
+            race_laps: Some(laps),
+            ..Self::default()
+        };
+    }
+
+    /// Notes the local player passed `checkpoint` of a track of `count` at `tick`
+    pub fn reached(&mut self, checkpoint: u32, count: usize, tick: u64) {
+        let checkpoint = checkpoint as usize;
+        if checkpoint == 0 {
+            self.lap_start = Some(tick);
+        }
+        self.next = (checkpoint + 1) % count.max(1);
+    }
+
+    pub fn complete_lap(&mut self) {
+        self.completed += 1;
+    }
+
+    pub fn finish_race(&mut self) {
+        self.finished = true;
+    }
+
+    /// Back to heading for the start line, as after dying, when a race is over or when leaving
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Index of the checkpoint to reach next, none once the race is finished
+    pub fn next(&self) -> Option<usize> {
+        (!self.finished).then_some(self.next)
+    }
+
+    /// Line for the heads-up display at `tick`: the lap, out of how many in races, and its time
+    pub fn status(&self, tick: u64, tick_rate: f64) -> Option<String> {
+        let start = self.lap_start.filter(|_| !self.finished)?;
+        let time = tick.saturating_sub(start) as f64 / tick_rate;
+        let lap = self.completed + 1;
+        Some(match self.race_laps {
+            Some(laps) => format!("Lap {lap}/{laps}  {time:.2}s"),
+            None => format!("Lap {lap}  {time:.2}s"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn laps_follow_the_checkpoints_passed() {
+        let mut laps = Laps::default();
+        assert_eq!(laps.next(), Some(0));
+        assert_eq!(laps.status(10, 10.0), None);
+
+        laps.start_race(2);
+        laps.reached(0, 3, 100);
+        assert_eq!(laps.next(), Some(1));
+        assert_eq!(laps.status(125, 10.0).as_deref(), Some("Lap 1/2  2.50s"));
+
+        laps.reached(1, 3, 130);
+        laps.reached(2, 3, 160);
+        assert_eq!(laps.next(), Some(0));
+        laps.complete_lap();
+        laps.reached(0, 3, 190);
+        assert_eq!(laps.status(190, 10.0).as_deref(), Some("Lap 2/2  0.00s"));
+
+        laps.finish_race();
+        assert_eq!(laps.next(), None);
+        assert_eq!(laps.status(200, 10.0), None);
+    }
+}
@@ -0,0 +1,202 @@
+//! Wording of what the server tells players, in the player's language.
+//!
+//! The server sends what happened, such as who killed whom with which weapon, and the client puts
+//! it into words with a template per kind of notice. Templates name their parameters in braces,
+//! and a locale file given with `--locale` only needs the ones it changes:
+//!
+//! ```toml
+//! player_killed = "{killer} a éliminé {victim}"
+//! weapon_explosive = "explosif"
+//! ```
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use common::{message::announcement::Announcement, world::combat::ProjectileKind};
+
+/// Templates the client speaks when no locale file says otherwise
+const ENGLISH: &[(&str, &str)] = &[
+    ("player_killed", "{victim} was killed by {killer}"),
+    (
+        "player_killed_with",
+        "{victim} was killed by {killer} ({weapon})",
+    ),
+    ("player_died", "{victim} died"),
+    ("assisted_by", "{kill}, assisted by {assists}"),
+    ("round_won", "{winner} wins the round"),
+    ("round_drawn", "Nobody wins the round"),
+    ("lap_completed", "{player} completed a lap in {time}s"),
+    ("race_finished", "{player} finished #{place} in {time}s"),
+    ("player_joined", "{player} joined"),
+    ("player_left", "{player} left"),
+    ("welcome", "Welcome to {server}"),
+    ("shutting_down", "The server is shutting down"),
+    ("weapon_bullet", "bullet"),
+    ("weapon_ricochet", "ricochet"),
+    ("weapon_piercing", "piercing"),
+    ("weapon_explosive", "explosive"),
+];
+
+pub struct Locale {
+    templates: HashMap<String, String>,
+}
+impl Default for Locale {
+    fn default() -> Self {
+        let templates = ENGLISH
+            .iter()
+            .map(|(key, template)| (key.to_string(), template.to_string()))
+            .collect();
+        Self { templates }
+    }
+}
+impl Locale {
+    /// English with the templates of the file at `path` in place of the ones it has
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read locale {}", path.display()))?;
+        let overrides: HashMap<String, String> =
+            toml::from_str(&text).with_context(|| format!("Invalid locale {}", path.display()))?;
+        let mut locale = Self::default();
+        for (key, template) in overrides {
+            if !locale.templates.contains_key(&key) {
+                eprintln!("Locale {} has an unknown template `{key}`", path.display());
+            }
+            locale.templates.insert(key, template);
+        }
+        Ok(locale)
+    }
+
+    /// The template named `key` with every `{name}` replaced by its value. Values are put in as
+    /// they are, so a player named `{killer}` stays that way.
+    pub fn format(&self, key: &str, params: &[(&str, &str)]) -> String {
+        let Some(template) = self.templates.get(key) else {
+            return key.to_string();
+        };
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(open) = rest.find('{') {
+            text.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+            let value = after.find('}').and_then(|close| {
+                let name = &after[..close];
+                let value = params.iter().find(|(param, _)| *param == name)?.1;
+                Some((value, close))
+            });
+            match value {
+                Some((value, close)) => {
+                    text.push_str(value);
+                    rest = &after[close + 1..];
+                }
+                None => {
+                    text.push('{');
+                    rest = after;
+                }
+            }
+        }
+        text.push_str(rest);
+        text
+    }
+
+    pub fn weapon(&self, kind: ProjectileKind) -> String {
+        self.format(&format!("weapon_{}", kind.name()), &[])
+    }
+
+    /// Line telling of a death, given the names of those involved
+    pub fn player_died(
+        &self,
+        victim: &str,
+        killer: Option<&str>,
+        weapon: Option<ProjectileKind>,
+        assists: &[String],
+    ) -> String {
+        let kill = match (killer, weapon) {
+            (Some(killer), Some(weapon)) => self.format(
+                "player_killed_with",
+                &[
+                    ("victim", victim),
+                    ("killer", killer),
+                    ("weapon", &self.weapon(weapon)),
+                ],
+            ),
+            (Some(killer), None) => {
+                self.format("player_killed", &[("victim", victim), ("killer", killer)])
+            }
+            (None, _) => self.format("player_died", &[("victim", victim)]),
+        };
+        if assists.is_empty() {
+            return kill;
+        }
+        self.format(
+            "assisted_by",
+            &[("kill", &kill), ("assists", &assists.join(", "))],
+        )
+    }
+
+    pub fn round_over(&self, winner: Option<&str>) -> String {
+        match winner {
+            Some(winner) => self.format("round_won", &[("winner", winner)]),
+            None => self.format("round_drawn", &[]),
+        }
+    }
+
+    /// A lap completed in `time` seconds
+    pub fn lap_completed(&self, player: &str, time: f32) -> String {
+        let time = format!("{time:.2}");
+        self.format("lap_completed", &[("player", player), ("time", &time)])
+    }
+
+    /// A race finished in `place`, 1 for the winner, after `time` seconds
+    pub fn race_finished(&self, player: &str, place: u32, time: f32) -> String {
+        let (place, time) = (place.to_string(), format!("{time:.2}"));
+        self.format(
+            "race_finished",
+            &[("player", player), ("place", &place), ("time", &time)],
+        )
+    }
+
+    pub fn player_joined(&self, player: &str) -> String {
+        self.format("player_joined", &[("player", player)])
+    }
+
+    pub fn player_left(&self, player: &str) -> String {
+        self.format("player_left", &[("player", player)])
+    }
+
+    pub fn announcement(&self, announcement: &Announcement) -> String {
+        match announcement {
+            Announcement::Welcome { server_name } => {
+                self.format("welcome", &[("server", server_name)])
+            }
+            Announcement::ShuttingDown => self.format("shutting_down", &[]),
+            Announcement::Text(text) => text.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deaths_are_worded_from_templates() {
+        let mut locale = Locale::default();
+        let assists = [String::from("c"), String::from("d")];
+        assert_eq!(
+            locale.player_died("a", Some("b"), Some(ProjectileKind::Explosive), &assists),
+            "a was killed by b (explosive), assisted by c, d"
+        );
+        assert_eq!(locale.player_died("a", None, None, &[]), "a died");
+
+        locale.templates.insert(
+            String::from("player_killed"),
+            String::from("{killer} a éliminé {victim}"),
+        );
+        assert_eq!(
+            locale.player_died("a", Some("b"), None, &[]),
+            "b a éliminé a"
+        );
+        // Neither names nor text from the host are templates
+        assert_eq!(locale.player_joined("{player}"), "{player} joined");
+        let text = Announcement::Text(String::from("{victim}"));
+        assert_eq!(locale.announcement(&text), "{victim}");
+    }
+}
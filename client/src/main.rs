@@ -6,31 +6,112 @@
 use anyhow::Result;
 use clap::Parser;
 
-use common::vec::Vec2;
+use common::{
+    color::Color,
+    physics,
+    time::{monotonic_secs, unix_millis},
+    tunables::Tunables,
+    vec::Vec2,
+};
 use miniquad::{conf::Conf, *};
+use server::{Server, cli::ServerConfig};
 
-use common::message::{ClientMessage, ServerMessage};
-use common::world::{GameWorld, entities::Player};
+use common::message::{ClientMessage, ServerMessage, announcement::Announcement};
+use common::world::{
+    GameWorld, arena::Arena, combat::ProjectileKind, scoreboard::Scoreboard, zone::Zone,
+};
 use tokio::{
     runtime::Runtime,
-    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        oneshot,
+    },
+    task::JoinHandle,
+    time::Duration,
 };
 
+mod bot;
 mod camera;
+mod chat;
 mod cli;
 mod client;
+mod controls;
+mod desync;
+mod effects;
+#[cfg(test)]
+mod end_to_end;
+mod ghost;
+mod haptics;
+mod hud;
+mod input;
+mod interpolation;
+mod killcam;
+mod laps;
+mod locale;
+mod music;
+mod prediction;
+mod presence;
+mod record;
 mod render;
+mod replay;
+mod settings;
+mod snapshot;
+mod style;
+mod toasts;
+mod validate;
 
 use camera::Camera;
+use chat::Chat;
 use cli::Cli;
-use client::Client;
-use render::Render;
+use client::{Client, ConnectionStatus, Login, Route};
+use controls::{Action, Controls, ControlsMenu, MenuEvent};
+use desync::DesyncDetector;
+use effects::Effects;
+use ghost::Ghost;
+use haptics::{Haptics, Rumble};
+use hud::Hud;
+use input::InputState;
+use interpolation::{DelayEstimator, SnapshotBuffer};
+use killcam::KillCam;
+use laps::Laps;
+use locale::Locale;
+use music::{Music, MusicConfig, NoAudio};
+use prediction::Prediction;
+use presence::{NoPresence, Presence, PresenceOutput};
+use record::{Clock, Recorder};
+use render::{Render, Scene, hud::HudView};
+use replay::{ReplayReader, ReplayWriter};
+use settings::{INTERPOLATION_DELAY_STEP, InterpolationDelay, Settings};
+use style::StyleFile;
+use toasts::Toasts;
+
+/// Distance from the camera, in blast radii, within which explosions shake the screen
+const SHAKE_RANGE: f32 = 4.0;
+/// Zoom change for one notch of the mouse wheel
+const ZOOM_STEP: f32 = 1.1;
+/// Longest the window waits on closing for the server to hear we are leaving
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+/// Seconds between two reports of how well we keep up, see [`ClientMessage::QualityReport`]
+const QUALITY_REPORT_INTERVAL: f64 = 2.0;
+/// Ammo counter while a reload is under way
+const RELOADING_COLOR: Color = Color {
+    r: 0.7,
+    g: 0.7,
+    b: 0.7,
+};
 
 /// GameRuntime manages the game loop, rendering, and client-server communication.
 pub struct GameRuntime {
     /* Inbox of messages from the server */
     /// Async context
-    _runtime: tokio::runtime::Runtime,
+    runtime: tokio::runtime::Runtime,
+    /// Task talking to the server, it ends when the connection is lost. There is none when
+    /// watching a replay
+    network: Option<JoinHandle<Result<()>>>,
+    /// Tells the network task to say goodbye to the server and stop
+    quit: Option<oneshot::Sender<()>>,
+    status: ConnectionStatus,
+    clock: Clock,
     server_rx: UnboundedReceiver<ServerMessage>,
     server_tx: UnboundedSender<ClientMessage>,
 
@@ -40,140 +121,1028 @@ pub struct GameRuntime {
     /* Rendering related */
     render: Render,
     camera: Camera,
+    chat: Chat,
+    hud: Hud,
+    show_debug: bool,
+
+    settings: Settings,
+    delay_estimator: DelayEstimator,
+    snapshots: SnapshotBuffer,
 
-    last_frame: f32,
+    /// Replay of the lead up to the local player's last death, while it plays
+    kill_cam: Option<KillCam>,
+
+    prediction: Prediction,
+    /// Watches for prediction and the server lastingly disagreeing
+    desync: DesyncDetector,
+
+    /* Replays and recording */
+    replay: Option<ReplayReader>,
+    replay_writer: Option<ReplayWriter>,
+    recorder: Option<Recorder>,
+
+    last_frame: f64,
     time_accumulator: f32,
+    /// When the server was last told how well we keep up
+    last_report: f64,
 
+    /// Assigned by the server once it accepts us, 0 until then
     player_id: u64,
+    /// Name we asked to play under, replaced by the one the server assigned once it accepts us
     username: String,
+    /// Direction of the last movement, which is where shots go
+    facing: Vec2,
+    /// Keys held down, turned into movement every frame
+    input: InputState,
+    /// Key bindings in use and the menu to change them, open while it is `Some`
+    controls: Controls,
+    controls_menu: Option<ControlsMenu>,
+    /// Weapon fired with the shoot key, picked with the weapon keys
+    weapon: ProjectileKind,
+    /// Explosions and other visuals that only exist on this client
+    effects: Effects,
+    /// Controller rumble for what happens to the local player
+    haptics: Haptics,
+    /// Music that gets more intense with the fighting around the local player
+    music: Music,
+    /// What the player is up to, shown outside the game
+    presence: Presence,
+    /// Notices about other players, such as them joining or leaving
+    toasts: Toasts,
+    /// Sizes, colors and layout everything is drawn with
+    style: StyleFile,
+    /// Templates server notices are worded with
+    locale: Locale,
+    /// Bounds of the current round, in modes that have them
+    arena: Option<Arena>,
+    /// Safe zone of the storm, in modes that have one
+    storm: Option<Zone>,
+    /// Fastest lap of the local player, raced against in time trials
+    ghost: Ghost,
+    /// Where the local player is along the track, in time trials and races
+    laps: Laps,
+    /// Fastest lap of each player, fastest first, in time trials
+    lap_times: Vec<(String, f32)>,
+    /// Tick of the latest snapshot
+    server_tick: u64,
+    scoreboard: Scoreboard,
+    /// Whether the scoreboard key is held down
+    show_scoreboard: bool,
+    /// Last position of the mouse in window pixels
+    mouse: Vec2,
 }
 impl GameRuntime {
     pub fn init(runtime: tokio::runtime::Runtime, cli: Cli) -> Result<Self> {
         let (runtime_tx, server_rx) = unbounded_channel();
         let (server_tx, runtime_rx) = unbounded_channel();
+        let (quit, quit_rx) = oneshot::channel();
 
-        let handle = runtime.handle().clone();
+        let settings = Settings::from_cli(&cli);
+        let controls = Controls::load(Some(cli.controls.clone()))?;
+        let locale = cli
+            .locale
+            .as_deref()
+            .map(Locale::load)
+            .transpose()?
+            .unwrap_or_default();
+        let music = Music::new(Box::new(NoAudio), MusicConfig::load(&cli.music)?);
+        let style = StyleFile::load(&cli.style)?;
+        let presence_output: Box<dyn PresenceOutput> = match &cli.discord_app_id {
+            #[cfg(feature = "discord")]
+            Some(app_id) => Box::new(presence::discord::DiscordPresence::new(app_id)),
+            _ => Box::new(NoPresence),
+        };
 
-        let (id, mut client) = runtime.block_on(Client::connect(
-            cli.address,
-            cli.username.clone(),
-            cli.password.unwrap_or_default(),
-            runtime_tx,
-            runtime_rx,
-        ))?;
+        let replay = cli.replay.as_deref().map(ReplayReader::open).transpose()?;
+        let replay_writer = cli
+            .save_replay
+            .as_deref()
+            .map(ReplayWriter::create)
+            .transpose()?;
+        let recorder = cli
+            .record
+            .as_deref()
+            .map(|target| Recorder::new(target, cli.record_fps, cli.record_size))
+            .transpose()?;
 
-        // Spawn the network listener inside the given runtime
-        handle.spawn(async move {
-            // Create a client
-            let _ = client.listen().await;
-        });
+        let route = Route::from_cli(&cli)?;
+
+        // Single player runs a server of its own, on a port nothing else uses. It stops when the
+        // client exits and takes the runtime with it
+        let address = if cli.single_player {
+            let mut config = ServerConfig::single_player();
+            config.transport = cli.transport;
+            let (addr, _) = runtime.block_on(Server::run_in_background(config))?;
+            Some(addr.to_string())
+        } else {
+            cli.address
+        };
+
+        // Connecting happens in the background so the window can show how it is going
+        let (network, status) = match (&replay, address) {
+            (None, Some(address)) => {
+                let network = runtime.spawn(Client::run(
+                    address,
+                    route,
+                    Login {
+                        username: cli.username.clone(),
+                        password: cli.password.unwrap_or_default(),
+                        color: cli.color,
+                        lobby: cli.lobby.clone(),
+                    },
+                    runtime_tx,
+                    runtime_rx,
+                    quit_rx,
+                ));
+                (Some(network), ConnectionStatus::Connecting)
+            }
+            _ => (None, ConnectionStatus::Connected),
+        };
 
         let world = GameWorld::new();
-        let render = Render::init();
-        let time = miniquad::date::now() as f32;
+        let render = Render::init(&cli.sprites, &cli.shaders);
+        let time = miniquad::date::now();
+
+        // A replay is recorded frame by frame, however long rendering takes
+        let clock = match (&replay, &recorder) {
+            (Some(_), Some(recorder)) => Clock::Fixed {
+                time,
+                step: 1.0 / recorder.fps(),
+            },
+            _ => Clock::Wall,
+        };
 
         Ok(Self {
-            _runtime: runtime,
+            runtime,
+            network,
+            quit: Some(quit),
+            status,
+            clock,
+            replay,
+            replay_writer,
+            recorder,
             server_rx,
             server_tx,
             world,
             render,
             last_frame: time,
             time_accumulator: 0.0,
-            player_id: id,
+            last_report: time,
+            player_id: 0,
             username: cli.username,
-            camera: Camera { pos: Vec2::ZERO },
+            facing: Vec2 { x: 1.0, y: 0.0 },
+            input: InputState::with_dead_zone(settings.dead_zone),
+            controls,
+            controls_menu: None,
+            weapon: ProjectileKind::default(),
+            effects: Effects::default(),
+            haptics: Haptics::default(),
+            music,
+            presence: Presence::new(presence_output),
+            toasts: Toasts::default(),
+            style,
+            locale,
+            arena: None,
+            storm: None,
+            ghost: Ghost::default(),
+            laps: Laps::default(),
+            lap_times: Vec::new(),
+            server_tick: 0,
+            scoreboard: Scoreboard::default(),
+            show_scoreboard: false,
+            mouse: Vec2::ZERO,
+            camera: Camera::new(Vec2::ZERO),
+            chat: Chat::default(),
+            hud: Hud::default(),
+            show_debug: false,
+            settings,
+            delay_estimator: DelayEstimator::default(),
+            snapshots: SnapshotBuffer::default(),
+            kill_cam: None,
+            prediction: Prediction::new(Tunables::default().timestep()),
+            desync: DesyncDetector::default(),
         })
     }
+
+    /// Forgets everything that came from the server we are leaving, the next one sends it all
+    /// again once it accepts us.
+    fn leave_server(&mut self) {
+        self.world = GameWorld::new();
+        self.player_id = 0;
+        self.prediction = Prediction::new(self.world.tunables.timestep());
+        self.hud.reset_connection();
+        self.snapshots = SnapshotBuffer::default();
+        self.kill_cam = None;
+        self.delay_estimator = DelayEstimator::default();
+        self.effects = Effects::default();
+        self.music.stop();
+        self.presence.left();
+        self.arena = None;
+        self.storm = None;
+        self.ghost.clear();
+        self.laps.reset();
+        self.lap_times.clear();
+        self.server_tick = 0;
+        self.scoreboard = Scoreboard::default();
+        self.input.resend();
+    }
+
+    /// Has the network task tell the server we are leaving, cutting it off if that takes longer
+    /// than [`SHUTDOWN_GRACE`]
+    fn shut_down_network(&mut self) {
+        if let Some(quit) = self.quit.take() {
+            let _ = quit.send(());
+        }
+        let Some(mut network) = self.network.take() else {
+            return;
+        };
+        // A finished task was already joined when the connection was lost
+        if network.is_finished() {
+            return;
+        }
+        let joined = self
+            .runtime
+            .block_on(tokio::time::timeout(SHUTDOWN_GRACE, &mut network));
+        match joined {
+            Ok(Ok(Err(e))) => eprintln!("Could not disconnect cleanly: {e}"),
+            Ok(_) => {}
+            Err(_) => {
+                eprintln!("The server did not hear us leave in time");
+                network.abort();
+            }
+        }
+    }
+
+    /// Lines shown by the debug overlay
+    /// Rounds of the selected weapon and the color to show them in, red when it is empty or was
+    /// just fired empty
+    fn ammo_line(&self) -> Option<(String, Color)> {
+        let player = self.world.entities.players.get(&self.player_id)?;
+        let magazine = player.ammo.magazine(self.weapon);
+        let (text, color) = match player.ammo.reloading {
+            Some(reload) => (
+                format!(
+                    "reloading {} {:.1}s",
+                    reload.kind.name(),
+                    reload.left.max(0.0)
+                ),
+                RELOADING_COLOR,
+            ),
+            None if magazine.loaded == 0 => (String::from("ammo: empty"), Color::RED),
+            None => (
+                format!("ammo: {} / {}", magazine.loaded, magazine.reserve),
+                Color::WHITE,
+            ),
+        };
+        let color = if self.hud.dry_firing(self.last_frame) {
+            Color::RED
+        } else {
+            color
+        };
+        Some((text, color))
+    }
+
+    fn debug_lines(&self) -> Vec<String> {
+        let setting = self.settings.interpolation_delay;
+        let delay = self.delay_estimator.effective_delay(setting);
+        let mode = match setting {
+            InterpolationDelay::Auto => "auto",
+            InterpolationDelay::Manual(_) => "manual",
+        };
+        let interval = self
+            .delay_estimator
+            .interval()
+            .map(|interval| format!("{:.0} ms", interval * 1000.0))
+            .unwrap_or_else(|| String::from("-"));
+        let cursor = self.cursor_world();
+        vec![
+            format!("players: {}", self.world.entities.players.len()),
+            format!("unacked inputs: {}", self.prediction.pending()),
+            format!("interp delay: {:.0} ms ({mode})", delay * 1000.0),
+            format!("snapshot interval: {interval}"),
+            format!(
+                "snapshot jitter: {:.1} ms",
+                self.delay_estimator.jitter() * 1000.0
+            ),
+            format!("cursor: {:.2}, {:.2}", cursor.x, cursor.y),
+            format!("zoom: {:.2}", self.camera.zoom),
+            String::from("[ ] adjust delay, \\ auto"),
+        ]
+    }
+
+    /// Starts walking in `dir` right away and sends it to the server, which moves the player the
+    /// same way.
+    fn send_movement(&mut self, dir: Vec2) {
+        let physics = self.world.tunables.physics;
+        let Some(player) = self
+            .world
+            .entities
+            .players
+            .get_mut(&self.player_id)
+            .filter(|player| player.is_alive())
+        else {
+            return;
+        };
+        if dir != Vec2::ZERO {
+            self.facing = dir;
+        }
+        let vel = physics::walk_velocity(dir, &physics);
+        let seq = self.prediction.push_input(vel);
+        player.vel = vel;
+        player.last_input_seq = seq;
+        player.input_ticks = 0;
+
+        let _ = self.server_tx.send(ClientMessage::MoveInput { seq, dir });
+    }
+
+    /// Fires the selected weapon towards `dir`. The server checks the cooldown and whether the
+    /// player can shoot at all, an empty weapon only clicks.
+    fn shoot(&mut self, dir: Vec2) {
+        if let Some(player) = self.world.entities.players.get(&self.player_id)
+            && player.is_alive()
+            && !player.ammo.can_fire(self.weapon)
+        {
+            self.hud.dry_fire(self.last_frame);
+            return;
+        }
+        let _ = self.server_tx.send(ClientMessage::NotifyShot {
+            dir,
+            kind: self.weapon,
+        });
+        self.haptics
+            .play(&self.settings.rumble, Rumble::firing(self.weapon));
+    }
+
+    /// Username of a player, or its id when it is not around
+    fn player_name(&self, id: u64) -> String {
+        self.world
+            .entities
+            .players
+            .get(&id)
+            .map(|player| player.username.clone())
+            .unwrap_or_else(|| format!("#{id}"))
+    }
+
+    /// Living players close enough to the local player to make the music more intense
+    fn nearby_enemies(&self) -> usize {
+        let players = &self.world.entities.players;
+        let Some(me) = players.get(&self.player_id).filter(|me| me.is_alive()) else {
+            return 0;
+        };
+        let range = music::nearby_range(self.world.tunables.physics.player_radius);
+        players
+            .iter()
+            .filter(|(id, player)| {
+                **id != self.player_id
+                    && player.is_alive()
+                    && (player.pos - me.pos).length() <= range
+            })
+            .count()
+    }
+
+    /// How long until the local player is back, while they are dead
+    fn respawn_text(&self) -> Option<String> {
+        let player = self.world.entities.players.get(&self.player_id)?;
+        match player.respawn_in {
+            _ if player.is_alive() => None,
+            // Knockout modes keep players out until the next round
+            respawn_in if respawn_in.is_infinite() => {
+                Some(String::from("Knocked out, waiting for the next round"))
+            }
+            respawn_in => Some(format!("You died, respawning in {:.0}", respawn_in.ceil())),
+        }
+    }
+
+    /// World position under the mouse cursor
+    fn cursor_world(&self) -> Vec2 {
+        let (width, height) = window::screen_size();
+        self.camera.screen_to_world(
+            self.mouse,
+            Vec2 {
+                x: width,
+                y: height,
+            },
+        )
+    }
+
+    /// Points the camera at the local player, or at the middle of everyone when not playing. The
+    /// kill cam follows the killer instead.
+    fn follow_action(&mut self) {
+        let players = &self.world.entities.players;
+        if let Some(pos) = self.kill_cam.as_ref().and_then(KillCam::killer_pos) {
+            self.camera.target = pos;
+        } else if let Some(self_player) = players.get(&self.player_id) {
+            self.camera.target = self_player.pos;
+        } else if !players.is_empty() {
+            let sum = players
+                .values()
+                .fold(Vec2::ZERO, |sum, player| sum + player.pos);
+            self.camera.target = sum / players.len() as f32;
+        }
+    }
+
+    /// Nudges the interpolation delay, switching to a manual setting.
+    fn adjust_interpolation_delay(&mut self, step: f64) {
+        let current = self
+            .delay_estimator
+            .effective_delay(self.settings.interpolation_delay);
+        self.settings.interpolation_delay.adjust(current, step);
+    }
 }
 impl EventHandler for GameRuntime {
     fn update(&mut self) {
-        const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
-        let time = miniquad::date::now() as f32;
+        self.clock.advance();
+        let time = self.clock.now();
         let dt = (time - self.last_frame) as f32;
         self.last_frame = time;
+        self.hud.frame(time);
+        if self.status == ConnectionStatus::Connected
+            && time - self.last_report >= QUALITY_REPORT_INTERVAL
+        {
+            self.last_report = time;
+            // The network task fills in the loss
+            let _ = self.server_tx.send(ClientMessage::QualityReport {
+                snapshot_loss: 0.0,
+                fps: self.hud.fps() as f32,
+            });
+        }
 
         self.time_accumulator += dt;
 
-        while self.time_accumulator >= FIXED_TIMESTEP {
-            self.world.entities.update(FIXED_TIMESTEP);
-            
-            self.time_accumulator -= FIXED_TIMESTEP;
-        }
+        // Steps the same length as the server's, so prediction matches its simulation
+        let timestep = self.world.tunables.timestep();
+        while self.time_accumulator >= timestep {
+            self.world.update(timestep);
+            self.prediction.advance();
+            if let Some(player) = self.world.entities.players.get(&self.player_id) {
+                self.desync.record(player);
+            }
 
-        if let Some(self_player) = self.world.entities.players.get(&self.player_id) {
-            self.camera.pos = self_player.pos;
+            self.time_accumulator -= timestep;
         }
 
+        let mut messages = Vec::new();
         while let Ok(msg) = self.server_rx.try_recv() {
+            messages.push(msg);
+        }
+        if let Some(replay) = &mut self.replay {
+            messages.extend(replay.due(time));
+        }
+
+        for msg in messages {
+            if let Err(e) = validate::check(&msg) {
+                eprintln!("Dropped a malformed message from the server: {e}");
+                continue;
+            }
+            if let Some(writer) = &mut self.replay_writer
+                && let Err(e) = writer.write(time, &msg)
+            {
+                eprintln!("Failed to save replay: {e}");
+                self.replay_writer = None;
+            }
+
             match msg {
-                ServerMessage::UpdateEntities(players) => {
-                    if let Some(self_player) = self.world.entities.players.get(&self.player_id).cloned() {
-                        // Insert or update all players from the server
-                        for (id, player) in players.players.into_iter() {
-                            self.world.entities.players.insert(id, player);
-                        }
-                        // Make sure local player data is preserved (or overwritten by server if needed)
+                // When watching a replay every player is someone else
+                ServerMessage::ConnectionAccepted { id, username, .. } if self.replay.is_none() => {
+                    // Back as someone else, the session could not be resumed
+                    if self.player_id != 0 && id != self.player_id {
+                        self.leave_server();
+                    }
+                    // A player taken back starts out standing still on the server
+                    self.input.resend();
+                    if username != self.username {
+                        let text = format!("Playing as {username}, the name was taken");
+                        self.chat.push(String::from("server"), text);
+                        self.username = username;
+                    }
+                    self.player_id = id;
+                    self.status = ConnectionStatus::Connected;
+                }
+                ServerMessage::QueuePosition(position) => {
+                    self.status = ConnectionStatus::Queued(position);
+                }
+                // The network task is already on its way to the new server
+                ServerMessage::Redirect { address, .. } if self.replay.is_none() => {
+                    self.chat
+                        .push(String::from("server"), format!("Transferring to {address}"));
+                    self.leave_server();
+                    self.status = ConnectionStatus::Transferring;
+                }
+                ServerMessage::Pong { client_time, .. } if self.replay.is_none() => {
+                    self.hud.add_rtt_sample(monotonic_secs() - client_time);
+                }
+                // Unreliable delivery can reorder snapshots, an older one would undo newer state
+                ServerMessage::UpdateEntities { tick, .. } if tick < self.server_tick => {}
+                ServerMessage::UpdateEntities {
+                    tick,
+                    time: sent,
+                    entities,
+                } => {
+                    let now = time;
+                    self.delay_estimator.record_arrival(now, sent);
+                    self.server_tick = tick;
+                    let bodies = entities.bodies();
+                    let radius = self.world.tunables.physics.player_radius;
+                    if let Some(report) = self.desync.check(tick, self.player_id, &entities, radius)
+                    {
+                        eprintln!("{report}");
+                    }
+                    // The local player is predicted from the server state and our unacknowledged inputs
+                    let id = self.player_id;
+                    let local = entities.players.get(&id).map(|player| {
+                        let predicted = self.prediction.reconcile(
+                            id,
+                            player,
+                            &bodies,
+                            &self.world.tunables.physics,
+                            &self.world.environment,
+                        );
+                        (id, predicted)
+                    });
+                    snapshot::apply(&mut self.world.entities, &entities, local);
+                    self.snapshots.insert(tick, entities.players, now);
+                }
+                ServerMessage::UpdateTunables(tunables) => {
+                    // Prediction must simulate with the same settings as the server
+                    self.prediction.set_timestep(tunables.timestep());
+                    self.input.set_interval(1.0 / tunables.tick_rate);
+                    self.snapshots.set_tick_rate(tunables.tick_rate);
+                    self.world.tunables = tunables;
+                }
+                ServerMessage::PlayerDied {
+                    victim,
+                    killer,
+                    assists,
+                    weapon,
+                } => {
+                    let name = |id: u64| {
                         self.world
                             .entities
                             .players
-                            .insert(self.player_id, self_player.clone());
-                    } else {
-                        // If local player not yet in world, just insert all server players
-                        for (id, player) in players.players.into_iter() {
-                            self.world.entities.players.insert(id, player);
-                        }
+                            .get(&id)
+                            .map(|player| player.username.clone())
+                            .unwrap_or_else(|| format!("#{id}"))
+                    };
+                    if victim == self.player_id {
+                        self.haptics.play(&self.settings.rumble, Rumble::death());
+                        self.ghost.abandon_lap();
+                        self.laps.reset();
+                        // Dying to the storm or to yourself leaves nobody to watch
+                        let respawn_delay = self.world.tunables.combat.respawn_delay as f64;
+                        let killer = killer.filter(|killer| *killer != victim);
+                        self.kill_cam = killer.and_then(|killer| {
+                            KillCam::start(&self.snapshots, killer, self.server_tick, respawn_delay)
+                        });
+                    }
+                    let assists: Vec<_> = assists.into_iter().map(name).collect();
+                    let text = self.locale.player_died(
+                        &name(victim),
+                        killer.map(name).as_deref(),
+                        weapon,
+                        &assists,
+                    );
+                    self.chat.push(String::from("server"), text);
+                }
+                ServerMessage::CheckpointReached {
+                    player,
+                    checkpoint,
+                    tick,
+                } if player == self.player_id => {
+                    let count = self.world.environment.checkpoints.len();
+                    self.laps.reached(checkpoint, count, tick);
+                    if checkpoint == 0 {
+                        self.ghost.start_lap(tick);
+                    }
+                }
+                ServerMessage::LapCompleted { player, time } => {
+                    if player == self.player_id {
+                        self.ghost.complete_lap(time);
+                        self.laps.complete_lap();
                     }
+                    let text = self.locale.lap_completed(&self.player_name(player), time);
+                    self.chat.push(String::from("server"), text);
+                }
+                ServerMessage::LapTimes(times) => self.lap_times = times,
+                ServerMessage::RaceStarted { laps } => self.laps.start_race(laps),
+                ServerMessage::RaceFinished {
+                    player,
+                    place,
+                    time,
+                } => {
+                    if player == self.player_id {
+                        self.laps.finish_race();
+                    }
+                    let name = self.player_name(player);
+                    let text = self.locale.race_finished(&name, place, time);
+                    self.chat.push(String::from("server"), text);
+                }
+                ServerMessage::UpdateArena(arena) => self.arena = arena,
+                ServerMessage::UpdateStorm(zone) => self.storm = zone,
+                ServerMessage::PlayerHit {
+                    victim,
+                    attacker,
+                    armor,
+                    health,
+                } => {
+                    // Id 0 is nobody, as when watching a replay
+                    if self.player_id != 0
+                        && (self.player_id == victim || self.player_id == attacker)
+                    {
+                        self.music.intensity.damage();
+                    }
+                    if let Some(player) = self.world.entities.players.get(&victim) {
+                        self.effects.damage(player.pos, armor, health);
+                    }
+                    if victim == self.player_id {
+                        let max_health = self.world.tunables.combat.max_health;
+                        let rumble = Rumble::damage(armor, health, max_health);
+                        self.haptics.play(&self.settings.rumble, rumble);
+                    }
+                }
+                ServerMessage::ExplosionEvent { pos, radius, .. } => {
+                    self.effects.explosion(pos, radius);
+                    // Closer explosions shake the screen harder
+                    let distance = (pos - self.camera.pos).length();
+                    self.camera
+                        .shake(1.0 - distance / (radius * SHAKE_RANGE).max(f32::EPSILON));
+                }
+                ServerMessage::UpdateScoreboard(scoreboard) => self.scoreboard = scoreboard,
+                ServerMessage::PlayerJoined { username, .. } => self
+                    .toasts
+                    .player_joined(self.locale.player_joined(&username)),
+                ServerMessage::PlayerLeft { username, .. } => {
+                    self.toasts.player_left(self.locale.player_left(&username))
+                }
+                ServerMessage::RoundOver { winner, .. } => {
+                    self.laps.reset();
+                    let winner = winner.and_then(|id| self.world.entities.players.get(&id));
+                    let text = self
+                        .locale
+                        .round_over(winner.map(|player| player.username.as_str()));
+                    self.chat.push(String::from("server"), text);
+                }
+                // Text from the host reads like chat, notices of the server itself pop up
+                ServerMessage::Announcement(announcement) => {
+                    let text = self.locale.announcement(&announcement);
+                    if let Announcement::Welcome { server_name } = &announcement
+                        && self.replay.is_none()
+                    {
+                        self.presence.joined(server_name, unix_millis() / 1000);
+                    }
+                    match announcement {
+                        Announcement::Text(_) => self.chat.push(String::from("server"), text),
+                        _ => self.toasts.announcement(text),
+                    }
+                }
+                ServerMessage::MatchInfo { map, mode } => {
+                    self.music.start(&map, &mode);
+                    self.presence.match_info(&map, &mode);
+                }
+                ServerMessage::UpdateObjects(environment) => {
+                    // Walls are part of prediction too
+                    self.world.environment = environment;
+                }
+                ServerMessage::ChatBroadcast {
+                    sender_id, text, ..
+                } => {
+                    // Id 0 is never given to a player, it is the server speaking
+                    let sender = match self.world.entities.players.get(&sender_id) {
+                        Some(player) => player.username.clone(),
+                        None if sender_id == 0 => String::from("server"),
+                        None => format!("#{sender_id}"),
+                    };
+                    self.chat.push(sender, text);
                 }
                 _ => {}
             }
         }
+
+        if let Some(network) = &mut self.network
+            && network.is_finished()
+            && !matches!(self.status, ConnectionStatus::Lost(_))
+        {
+            let reason = match self.runtime.block_on(network) {
+                Ok(Ok(())) => String::from("server closed the connection"),
+                Ok(Err(e)) => e.to_string(),
+                Err(e) => e.to_string(),
+            };
+            self.status = ConnectionStatus::Lost(reason);
+            self.presence.left();
+        }
+
+        if let Some(replay) = &self.replay
+            && replay.finished()
+            && self.status == ConnectionStatus::Connected
+        {
+            if self.recorder.is_some() {
+                // The recording is complete once the last message has played out
+                miniquad::window::order_quit();
+            }
+            self.status = ConnectionStatus::Lost(String::from("end of the replay"));
+        }
+
+        // Remote players are drawn in the past, between the snapshots surrounding the render time
+        let now = time;
+        let delay = self
+            .delay_estimator
+            .effective_delay(self.settings.interpolation_delay);
+        for (id, player) in self.world.entities.players.iter_mut() {
+            if *id != self.player_id
+                && let Some(pos) = self.snapshots.sample(*id, now, delay)
+            {
+                player.pos = pos;
+            }
+        }
+
+        self.effects.update(dt);
+        self.toasts.update(dt);
+        self.style.reload_if_changed(time);
+        let nearby = self.nearby_enemies();
+        self.music.update(dt, nearby, self.settings.music);
+        self.presence.set_players(self.world.entities.players.len());
+        self.presence.update(time);
+
+        // Movement follows the held keys, and goes out again once the player is back from dying
+        let alive = self
+            .world
+            .entities
+            .players
+            .get(&self.player_id)
+            .is_some_and(|player| player.is_alive());
+        if let Some(kill_cam) = &mut self.kill_cam {
+            kill_cam.update(dt as f64, alive);
+            if kill_cam.finished() {
+                self.kill_cam = None;
+            }
+        }
+        if let Some(player) = self.world.entities.players.get(&self.player_id) {
+            self.ghost.record(self.server_tick, player.pos);
+        }
+        self.input.set_stick(self.haptics.stick());
+        if !alive {
+            self.input.resend();
+        } else if let Some(movement) = self.input.due(time) {
+            self.send_movement(movement);
+            self.input.mark_sent(movement, time);
+        }
+
+        self.follow_action();
+        self.camera.update(dt);
     }
 
     fn draw(&mut self) {
-        self.render.draw(&self.camera, &self.world);
-    }
-    fn key_up_event(&mut self, _keycode: KeyCode, _keymods: KeyMods) {
-        let self_player = self.world.entities.players.get(&self.player_id).unwrap();
-        let player = Player {
-            color: self_player.color,
-            pos: self_player.pos,
-            vel: Vec2::ZERO,
-            username: self.username.clone(),
+        let debug_lines = if self.show_debug {
+            self.debug_lines()
+        } else {
+            Vec::new()
+        };
+        let respawn_text = self.respawn_text();
+        // The kill cam says how long until respawning in its caption, out of the way of the replay
+        let kill_cam = self.kill_cam.as_ref().map(|kill_cam| {
+            let killer = self
+                .world
+                .entities
+                .players
+                .get(&kill_cam.killer)
+                .map_or_else(
+                    || format!("#{}", kill_cam.killer),
+                    |player| player.username.clone(),
+                );
+            let caption = format!("Killed by {killer}");
+            let mut world = self.world.clone();
+            world.entities.players = kill_cam.players();
+            match &respawn_text {
+                Some(respawn) => (format!("{caption} - {respawn}"), world),
+                None => (caption, world),
+            }
+        });
+        let status = self
+            .status
+            .message()
+            .or_else(|| respawn_text.filter(|_| kill_cam.is_none()));
+        let storm_timer = self.storm.and_then(|zone| {
+            let seconds = |tick: u64| {
+                (tick.saturating_sub(self.server_tick) as f64 / self.world.tunables.tick_rate)
+                    .ceil()
+            };
+            if self.server_tick < zone.area.start_tick {
+                Some(format!(
+                    "Storm closes in {}s",
+                    seconds(zone.area.start_tick)
+                ))
+            } else if self.server_tick < zone.area.end_tick {
+                Some(format!(
+                    "Storm closing, {}s left",
+                    seconds(zone.area.end_tick)
+                ))
+            } else {
+                None
+            }
+        });
+        let weapon = format!("weapon: {} [1-4]", self.weapon.name());
+        let ammo = self.ammo_line();
+        let scoreboard_rows: Vec<_> = self
+            .scoreboard
+            .rank(self.world.entities.players.keys().copied())
+            .into_iter()
+            .map(|(id, score)| (self.world.entities.players[&id].username.clone(), score))
+            .collect();
+        let menu = self
+            .controls_menu
+            .as_ref()
+            .map(|menu| menu.view(&self.controls));
+        let hud = HudView {
+            health: self
+                .world
+                .entities
+                .players
+                .get(&self.player_id)
+                .map(|player| (player.health, self.world.tunables.combat.max_health)),
+            ping: self.hud.rtt(),
+            fps: self.hud.fps(),
+            players: self.world.entities.players.len(),
+            lap: self
+                .laps
+                .status(self.server_tick, self.world.tunables.tick_rate),
+        };
+        let checkpoints = &self.world.environment.checkpoints;
+        let pointer = self
+            .world
+            .entities
+            .players
+            .get(&self.player_id)
+            .filter(|player| player.is_alive() && checkpoints.len() >= 2)
+            .zip(self.laps.next().and_then(|next| checkpoints.get(next)))
+            .map(|(player, checkpoint)| (player.pos, checkpoint.pos));
+        let scene = Scene {
+            camera: &self.camera,
+            world: kill_cam.as_ref().map_or(&self.world, |(_, world)| world),
+            chat: &self.chat,
+            debug_lines: &debug_lines,
+            status: status.as_deref(),
+            arena: self
+                .arena
+                .map(|arena| (arena.center, arena.radius(self.server_tick))),
+            storm: self
+                .storm
+                .map(|zone| (zone.area.center, zone.area.radius(self.server_tick))),
+            banner: storm_timer.as_deref(),
+            ghost: self.ghost.position(self.server_tick),
+            pointer,
+            effects: &self.effects,
+            weapon: &weapon,
+            ammo: ammo.as_ref().map(|(text, color)| (text.as_str(), *color)),
+            toasts: &self.toasts,
+            scoreboard: self.show_scoreboard.then_some(&scoreboard_rows[..]),
+            lap_times: (self.show_scoreboard && !self.lap_times.is_empty())
+                .then_some(&self.lap_times[..]),
+            kill_cam: kill_cam.as_ref().map(|(caption, _)| caption.as_str()),
+            menu: menu.as_ref(),
+            hud: &hud,
+            style: &self.style.style,
         };
 
-        self.world.entities.players.insert(self.player_id, player.clone());
-
-        let _ = self
-            .server_tx
-            .send(ClientMessage::NotifyUpdatePlayer(player));
+        if let Some(recorder) = &mut self.recorder {
+            let due = recorder.frames_due(self.clock.now());
+            if due > 0 {
+                let pixels = self.render.capture(recorder.size(), &scene);
+                let written = (0..due).try_for_each(|_| recorder.write_frame(&pixels));
+                if let Err(e) = written {
+                    eprintln!("Recording stopped: {e}");
+                    self.recorder = None;
+                }
+            }
+        }
+        self.render.draw(&scene);
+    }
+    fn quit_requested_event(&mut self) {
+        self.shut_down_network();
+    }
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        self.mouse = Vec2 { x, y };
+    }
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        self.mouse = Vec2 { x, y };
+        if button != MouseButton::Left || self.chat.open {
+            return;
+        }
+        if self.kill_cam.is_some() {
+            let (width, height) = window::screen_size();
+            let (pos, size) = render::killcam::skip_button(Vec2 {
+                x: width,
+                y: height,
+            });
+            let offset = self.mouse - pos;
+            if (0.0..=size.x).contains(&offset.x) && (0.0..=size.y).contains(&offset.y) {
+                self.kill_cam = None;
+                return;
+            }
+        }
+        // Aim from the local player towards the cursor
+        let Some(player) = self.world.entities.players.get(&self.player_id) else {
+            return;
+        };
+        let dir = self.cursor_world() - player.pos;
+        self.shoot(dir);
+    }
+    fn mouse_wheel_event(&mut self, _x: f32, y: f32) {
+        if y != 0.0 {
+            self.camera.zoom_by(ZOOM_STEP.powf(y.signum()));
+        }
+    }
+    fn char_event(&mut self, character: char, _keymods: KeyMods, _repeat: bool) {
+        self.chat.type_char(character);
+    }
+    fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
+        if let Some(action) = self.controls.profile().action(keycode) {
+            if action == Action::Scoreboard {
+                self.show_scoreboard = false;
+            }
+            self.input.release(action);
+        }
     }
     fn key_down_event(&mut self, keycode: KeyCode, _mods: KeyMods, _repeat: bool) {
-        // Simulate movement based on key input
-        let mut vx = 0.0;
-        let mut vy = 0.0;
+        // Enter toggles the chat box, which captures the keyboard while open
+        if keycode == KeyCode::Enter {
+            if let Some(text) = self.chat.toggle() {
+                let _ = self.server_tx.send(ClientMessage::Chat(text));
+            }
+            // Typing does not move the player
+            if self.chat.open {
+                self.input.clear();
+            }
+            return;
+        }
+        if self.chat.open {
+            match keycode {
+                KeyCode::Backspace => self.chat.backspace(),
+                KeyCode::Escape => self.chat.cancel(),
+                _ => {}
+            }
+            return;
+        }
+        // The controls menu also captures the keyboard, to rebind keys
+        if let Some(menu) = &mut self.controls_menu {
+            match menu.key_down(keycode, &mut self.controls) {
+                MenuEvent::Changed => {
+                    if let Err(e) = self.controls.save() {
+                        eprintln!("{e}");
+                    }
+                }
+                MenuEvent::Closed => self.controls_menu = None,
+                MenuEvent::None => {}
+            }
+            return;
+        }
 
+        // Debug overlay and live interpolation delay tuning
         match keycode {
-            KeyCode::W => vy = 1.0,
-            KeyCode::S => vy = -1.0,
-            KeyCode::A => vx = -1.0,
-            KeyCode::D => vx = 1.0,
-            _ => return,
+            KeyCode::Escape => {
+                self.controls_menu = Some(ControlsMenu::default());
+                // Keys held now may do something else once the menu closes
+                self.input.clear();
+                self.show_scoreboard = false;
+                return;
+            }
+            KeyCode::F3 => {
+                self.show_debug = !self.show_debug;
+                return;
+            }
+            KeyCode::LeftBracket => {
+                self.adjust_interpolation_delay(-INTERPOLATION_DELAY_STEP);
+                return;
+            }
+            KeyCode::RightBracket => {
+                self.adjust_interpolation_delay(INTERPOLATION_DELAY_STEP);
+                return;
+            }
+            KeyCode::Backslash => {
+                self.settings.interpolation_delay = InterpolationDelay::Auto;
+                return;
+            }
+            _ => {}
         }
 
-        let self_player = self.world.entities.players.get(&self.player_id).unwrap();
-        let player = Player {
-            color: self_player.color,
-            pos: self_player.pos,
-            vel: Vec2 { x: vx, y: vy },
-            username: self.username.clone(),
+        let Some(action) = self.controls.profile().action(keycode) else {
+            return;
         };
-
-        self.world.entities.players.insert(self.player_id, player.clone());
-
-        let _ = self
-            .server_tx
-            .send(ClientMessage::NotifyUpdatePlayer(player));
+        if let Some(index) = action.weapon() {
+            self.weapon = ProjectileKind::ALL[index];
+            return;
+        }
+        match action {
+            // Nothing to shoot with while dead, the shoot key skips the kill cam instead
+            Action::Shoot if self.kill_cam.is_some() => self.kill_cam = None,
+            Action::Shoot => self.shoot(self.facing),
+            Action::Reload => {
+                let _ = self
+                    .server_tx
+                    .send(ClientMessage::Reload { kind: self.weapon });
+            }
+            Action::Scoreboard => self.show_scoreboard = true,
+            // Movement keys are read every frame in `update`
+            _ => self.input.press(action),
+        }
     }
 }
 
@@ -197,6 +1166,23 @@ fn main() {
 
     let runtime = Runtime::new().unwrap();
 
+    if let Some(count) = cli.bot {
+        let route = Route::from_cli(&cli);
+        let login = Login {
+            username: cli.username,
+            password: cli.password.unwrap_or_default(),
+            color: cli.color,
+            lobby: cli.lobby,
+        };
+        let address = cli.address.unwrap_or_default();
+        let bots = route.and_then(|route| bot::run(runtime, count, address, route, login));
+        if let Err(e) = bots {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     miniquad::start(conf, move || {
         Box::new(GameRuntime::init(runtime, cli).unwrap())
     });
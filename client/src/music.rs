@@ -0,0 +1,249 @@
+//! Music that follows how intense the game is around the local player.
+//!
+//! Every track comes as two stems played together, a calm one and a combat one, and only their
+//! volumes change: [`Intensity`] rises with the enemies close by and with damage taken or dealt
+//! lately, and the mix fades between the stems as it moves. Which stems play is set in a TOML file
+//! per map and per mode, the map winning over the mode and both over the defaults:
+//!
+//! ```toml
+//! [default]
+//! calm = "music/calm.ogg"
+//! combat = "music/combat.ogg"
+//!
+//! [mode.battle_royale]
+//! combat = "music/storm.ogg"
+//!
+//! [map.arena]
+//! calm = "music/arena_calm.ogg"
+//! combat = "music/arena_combat.ogg"
+//! ```
+//!
+//! There is no audio backend yet, so [`NoAudio`] takes the stems and volumes and plays nothing; a
+//! backend only has to implement [`AudioOutput`] for the music to be heard. `--no-music` turns it
+//! off altogether.
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Enemies close by that make for full intensity on their own
+const CROWD: f32 = 3.0;
+/// Distance within which enemies count as close by, in player radii
+const NEARBY_RANGE: f32 = 20.0;
+/// Seconds a hit keeps the intensity up, fading over that time
+const DAMAGE_MEMORY: f32 = 6.0;
+/// Intensity the mix moves by per second at most, so the stems fade into each other
+const CROSSFADE_RATE: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stem {
+    Calm,
+    Combat,
+}
+
+/// Files of the two stems, either of which may be left out
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stems {
+    pub calm: Option<String>,
+    pub combat: Option<String>,
+}
+impl Stems {
+    /// These stems with the ones missing taken from `fallback`
+    fn or(&self, fallback: &Stems) -> Stems {
+        Stems {
+            calm: self.calm.clone().or_else(|| fallback.calm.clone()),
+            combat: self.combat.clone().or_else(|| fallback.combat.clone()),
+        }
+    }
+
+    pub fn get(&self, stem: Stem) -> Option<&str> {
+        match stem {
+            Stem::Calm => self.calm.as_deref(),
+            Stem::Combat => self.combat.as_deref(),
+        }
+    }
+}
+
+/// Stems to play, by map and by mode
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MusicConfig {
+    pub default: Stems,
+    pub mode: HashMap<String, Stems>,
+    pub map: HashMap<String, Stems>,
+}
+impl MusicConfig {
+    /// Reads the music file at `path`, or no music at all when there is none
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .with_context(|| format!("Could not read music from {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stems for a match on `map` in `mode`
+    pub fn stems(&self, map: &str, mode: &str) -> Stems {
+        let none = Stems::default();
+        let map = self.map.get(map).unwrap_or(&none);
+        let mode = self.mode.get(mode).unwrap_or(&none);
+        map.or(mode).or(&self.default)
+    }
+}
+
+/// How intense the game is around the local player, from 0 for calm to 1 for full combat
+#[derive(Default)]
+pub struct Intensity {
+    /// Mix of the stems right now, following the target at [`CROSSFADE_RATE`]
+    mix: f32,
+    /// Seconds since the local player last took or dealt damage
+    since_damage: Option<f32>,
+}
+impl Intensity {
+    /// The local player took or dealt damage
+    pub fn damage(&mut self) {
+        self.since_damage = Some(0.0);
+    }
+
+    /// Moves the mix along by `dt` seconds with `nearby` enemies close to the local player
+    pub fn update(&mut self, dt: f32, nearby: usize) {
+        let crowd = nearby as f32 / CROWD;
+        let fight = match &mut self.since_damage {
+            Some(since) => {
+                *since += dt;
+                1.0 - *since / DAMAGE_MEMORY
+            }
+            None => 0.0,
+        };
+        let target = crowd.max(fight).clamp(0.0, 1.0);
+        let step = CROSSFADE_RATE * dt;
+        self.mix += (target - self.mix).clamp(-step, step);
+    }
+
+    /// Volume of `stem` at the current mix, the two adding up to full volume
+    pub fn volume(&self, stem: Stem) -> f32 {
+        match stem {
+            Stem::Calm => 1.0 - self.mix,
+            Stem::Combat => self.mix,
+        }
+    }
+}
+
+/// Something that can play looping stems
+pub trait AudioOutput {
+    /// Starts looping the file at `path` as `stem`, or stops the stem with `None`
+    fn play(&mut self, stem: Stem, path: Option<&str>);
+    /// Sets the volume of `stem`, from 0 for silent to 1
+    fn set_volume(&mut self, stem: Stem, volume: f32);
+}
+
+/// Stands in while there is no audio backend
+pub struct NoAudio;
+impl AudioOutput for NoAudio {
+    fn play(&mut self, _stem: Stem, _path: Option<&str>) {}
+    fn set_volume(&mut self, _stem: Stem, _volume: f32) {}
+}
+
+pub struct Music {
+    output: Box<dyn AudioOutput>,
+    config: MusicConfig,
+    /// Stems of the match being played, none before the server says what it is
+    stems: Stems,
+    pub intensity: Intensity,
+}
+impl Music {
+    pub fn new(output: Box<dyn AudioOutput>, config: MusicConfig) -> Self {
+        Self {
+            output,
+            config,
+            stems: Stems::default(),
+            intensity: Intensity::default(),
+        }
+    }
+
+    /// Switches to the stems of a match on `map` in `mode`, carrying on if they are the same
+    pub fn start(&mut self, map: &str, mode: &str) {
+        let stems = self.config.stems(map, mode);
+        if stems != self.stems {
+            self.play(stems);
+        }
+    }
+
+    /// Stops the music, until the next match starts it again
+    pub fn stop(&mut self) {
+        self.play(Stems::default());
+    }
+
+    fn play(&mut self, stems: Stems) {
+        for stem in [Stem::Calm, Stem::Combat] {
+            self.output.play(stem, stems.get(stem));
+        }
+        self.stems = stems;
+    }
+
+    /// Moves the mix along by `dt` seconds, silent while `enabled` is off
+    pub fn update(&mut self, dt: f32, nearby: usize, enabled: bool) {
+        self.intensity.update(dt, nearby);
+        for stem in [Stem::Calm, Stem::Combat] {
+            let volume = if enabled {
+                self.intensity.volume(stem)
+            } else {
+                0.0
+            };
+            self.output.set_volume(stem, volume);
+        }
+    }
+}
+
+/// Distance within which enemies make the music more intense, for players of `player_radius`
+pub fn nearby_range(player_radius: f32) -> f32 {
+    NEARBY_RANGE * player_radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stems_follow_map_then_mode_then_default() {
+        let config: MusicConfig = toml::from_str(
+            r#"
+            [default]
+            calm = "calm.ogg"
+            combat = "combat.ogg"
+            [mode.sumo]
+            combat = "sumo.ogg"
+            [map.arena]
+            calm = "arena.ogg"
+            "#,
+        )
+        .unwrap();
+
+        let stems = config.stems("arena", "sumo");
+        assert_eq!(stems.get(Stem::Calm), Some("arena.ogg"));
+        assert_eq!(stems.get(Stem::Combat), Some("sumo.ogg"));
+        assert_eq!(config.stems("", "free_for_all"), config.default);
+    }
+
+    #[test]
+    fn intensity_fades_with_enemies_and_damage() {
+        let mut intensity = Intensity::default();
+        // Fades in rather than jumping to combat
+        intensity.update(1.0, CROWD as usize);
+        assert_eq!(intensity.volume(Stem::Combat), CROSSFADE_RATE);
+        intensity.update(1.0, CROWD as usize);
+        assert_eq!(intensity.volume(Stem::Combat), 1.0);
+        assert_eq!(intensity.volume(Stem::Calm), 0.0);
+
+        // A hit keeps it up after the enemies leave, until it is long past
+        intensity.damage();
+        intensity.update(DAMAGE_MEMORY / 2.0, 0);
+        assert!(intensity.volume(Stem::Combat) > 0.0);
+        for _ in 0..20 {
+            intensity.update(1.0, 0);
+        }
+        assert_eq!(intensity.volume(Stem::Combat), 0.0);
+    }
+}
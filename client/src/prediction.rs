@@ -0,0 +1,286 @@
+//! Client-side prediction of the local player.
+//!
+//! Inputs are applied locally as soon as they happen and tagged with a sequence number before
+//! being sent to the server. Every snapshot tells us the last sequence the server applied to our
+//! player and how many steps it has simulated since, so the server state is taken as the truth
+//! and whatever the server has not simulated yet is replayed on top of it. When players push each
+//! other, the replay pushes against the other players where the snapshot has them.
+use std::collections::VecDeque;
+
+use common::{
+    physics::{self, PhysicsConfig},
+    vec::Vec2,
+    world::{
+        entities::{PUSH_REACH, Player},
+        environment::Environment,
+    },
+};
+
+/// An input that the server has not acknowledged yet
+struct PendingInput {
+    seq: u64,
+    vel: Vec2,
+    /// Simulation steps the input has been held for
+    ticks: u32,
+}
+
+pub struct Prediction {
+    next_seq: u64,
+    pending: VecDeque<PendingInput>,
+    /// Simulation step used when replaying inputs
+    timestep: f32,
+}
+impl Prediction {
+    pub fn new(timestep: f32) -> Self {
+        Self {
+            next_seq: 1,
+            pending: VecDeque::new(),
+            timestep,
+        }
+    }
+
+    /// Replays inputs with steps of `timestep` seconds from now on, the server's own step
+    pub fn set_timestep(&mut self, timestep: f32) {
+        self.timestep = timestep;
+    }
+
+    /// Records a new input and returns the sequence number to send along with it.
+    pub fn push_input(&mut self, vel: Vec2) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.pending.push_back(PendingInput { seq, vel, ticks: 0 });
+        seq
+    }
+
+    /// Accounts for one simulation step of the most recent input.
+    pub fn advance(&mut self) {
+        if let Some(input) = self.pending.back_mut() {
+            input.ticks += 1;
+        }
+    }
+
+    /// Number of inputs still waiting for the server
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Drops the inputs the server has moved past and replays what it has not simulated yet on
+    /// top of `server_player`, returning the predicted local player. `bodies` holds every player
+    /// of the snapshot, as given by `Entities::bodies`.
+    pub fn reconcile(
+        &mut self,
+        id: u64,
+        server_player: &Player,
+        bodies: &[(u64, Vec2)],
+        physics: &PhysicsConfig,
+        environment: &Environment,
+    ) -> Player {
+        // The last acknowledged input is kept, the server may not have simulated all of it yet
+        while self
+            .pending
+            .front()
+            .is_some_and(|input| input.seq < server_player.last_input_seq)
+        {
+            self.pending.pop_front();
+        }
+
+        let mut player = server_player.clone();
+        for input in &self.pending {
+            // The server is already partway through the acknowledged input, so it carries on from
+            // the server velocity, while newer inputs start from the velocity they set
+            let ticks = if input.seq == server_player.last_input_seq {
+                input.ticks.saturating_sub(server_player.input_ticks)
+            } else {
+                player.vel = input.vel;
+                input.ticks
+            };
+            for _ in 0..ticks {
+                player.update(self.timestep, physics, environment);
+                if physics.player_collision {
+                    player.pos = physics::push_apart(
+                        id,
+                        player.pos,
+                        physics.player_radius,
+                        bodies,
+                        physics,
+                        &environment.colliders_near(player.pos, PUSH_REACH * physics.player_radius),
+                        self.timestep,
+                    );
+                }
+            }
+        }
+        player
+    }
+}
+
+/// Feeds the same inputs to the server simulation and to client prediction and checks that they
+/// never drift apart, so a change to either side that breaks prediction gets caught here.
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use common::{
+        details::TICK_RATE,
+        physics::{self, PhysicsConfig},
+        vec::Vec2,
+        world::{
+            entities::{Entities, Player},
+            environment::Environment,
+        },
+    };
+    use test_utils::world::{object, player};
+
+    use super::Prediction;
+
+    const TIMESTEP: f32 = (1.0 / TICK_RATE) as f32;
+    const TICKS: u64 = 10_000;
+    const PLAYER_ID: u64 = 1;
+
+    /// Small deterministic generator so runs are reproducible without extra dependencies
+    struct Lcg(u64);
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+    }
+
+    /// Produces an input every few ticks, the velocity being one of the eight key directions or rest
+    fn inputs(seed: u64) -> Vec<Option<Vec2>> {
+        let mut rng = Lcg(seed);
+        (0..TICKS)
+            .map(|_| {
+                rng.next().is_multiple_of(7).then(|| Vec2 {
+                    x: (rng.next() % 3) as f32 - 1.0,
+                    y: (rng.next() % 3) as f32 - 1.0,
+                })
+            })
+            .collect()
+    }
+
+    /// Friction and a few walls so the shared physics is exercised, not just integration
+    fn physics() -> (PhysicsConfig, Environment) {
+        let config = PhysicsConfig {
+            friction: 0.5,
+            player_radius: 0.05,
+            ..PhysicsConfig::default()
+        };
+        let environment = Environment {
+            objects: vec![
+                object(Vec2 { x: -2.0, y: -2.0 }, Vec2 { x: 4.0, y: 0.2 }),
+                object(Vec2 { x: 0.5, y: -0.5 }, Vec2 { x: 0.3, y: 0.3 }),
+                object(Vec2 { x: -2.0, y: 1.0 }, Vec2 { x: 0.5, y: 0.5 }),
+            ],
+            ..Environment::default()
+        };
+        (config, environment)
+    }
+
+    fn initial_player() -> Player {
+        player("tester", Vec2 { x: 0.25, y: -0.5 })
+    }
+
+    /// Runs the client and server side by side, with inputs reaching the server `upstream` ticks
+    /// after they are made and snapshots reaching the client `downstream` ticks after they are
+    /// taken. Calls `check` with the predicted and reconciled local player whenever a snapshot lands.
+    fn simulate(
+        seed: u64,
+        upstream: u64,
+        downstream: u64,
+        mut check: impl FnMut(&Player, &Player),
+    ) {
+        let (config, environment) = physics();
+        let mut server = Entities {
+            players: [(PLAYER_ID, initial_player())].into(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        };
+        let mut predicted = initial_player();
+        let mut prediction = Prediction::new(TIMESTEP);
+
+        let mut in_flight_inputs = VecDeque::new();
+        let mut in_flight_snapshots = VecDeque::new();
+
+        for (tick, input) in inputs(seed).into_iter().enumerate() {
+            let tick = tick as u64;
+
+            // Client: apply the input locally, send it, then step the prediction
+            if let Some(dir) = input {
+                let vel = physics::walk_velocity(dir, &config);
+                let seq = prediction.push_input(vel);
+                predicted.vel = vel;
+                predicted.last_input_seq = seq;
+                predicted.input_ticks = 0;
+                in_flight_inputs.push_back((tick + upstream, seq, dir));
+            }
+            predicted.update(TIMESTEP, &config, &environment);
+            prediction.advance();
+
+            // Server: apply arrived inputs, run the tick and send a snapshot
+            while let Some((_, seq, dir)) =
+                in_flight_inputs.pop_front_if(|(arrival, _, _)| *arrival <= tick)
+            {
+                server.apply_input(PLAYER_ID, seq, dir, &config);
+            }
+            server.update(TIMESTEP, &config, &environment);
+            in_flight_snapshots.push_back((tick + downstream, server.players[&PLAYER_ID].clone()));
+
+            // Client: reconcile with arrived snapshots
+            while let Some((_, snapshot)) =
+                in_flight_snapshots.pop_front_if(|(arrival, _)| *arrival <= tick)
+            {
+                let reconciled =
+                    prediction.reconcile(PLAYER_ID, &snapshot, &[], &config, &environment);
+                check(&predicted, &reconciled);
+                predicted = reconciled;
+            }
+        }
+    }
+
+    #[test]
+    fn lockstep_prediction_is_bit_identical() {
+        let mut snapshots = 0;
+        simulate(1, 0, 0, |predicted, reconciled| {
+            assert_eq!(predicted.pos, reconciled.pos);
+            assert_eq!(predicted.vel, reconciled.vel);
+            snapshots += 1;
+        });
+        assert_eq!(snapshots, TICKS);
+    }
+
+    #[test]
+    fn reconciliation_does_not_drift_with_latency() {
+        for (seed, upstream, downstream) in [(2, 3, 0), (3, 0, 4), (4, 5, 7), (5, 12, 12)] {
+            simulate(seed, upstream, downstream, |predicted, reconciled| {
+                let error = predicted.pos - reconciled.pos;
+                assert!(
+                    error.x.abs() < 1e-5 && error.y.abs() < 1e-5,
+                    "prediction drifted by {error:?} (upstream {upstream}, downstream {downstream})"
+                );
+                assert_eq!(predicted.vel, reconciled.vel);
+            });
+        }
+    }
+
+    #[test]
+    fn acknowledged_inputs_are_dropped() {
+        let mut prediction = Prediction::new(TIMESTEP);
+        for _ in 0..3 {
+            prediction.push_input(Vec2::ONE);
+            prediction.advance();
+        }
+        let (config, environment) = physics();
+        let mut acked = initial_player();
+        acked.last_input_seq = 2;
+        prediction.reconcile(PLAYER_ID, &acked, &[], &config, &environment);
+        assert_eq!(prediction.pending(), 2);
+
+        acked.last_input_seq = 3;
+        acked.input_ticks = 1;
+        prediction.reconcile(PLAYER_ID, &acked, &[], &config, &environment);
+        assert_eq!(prediction.pending(), 1);
+    }
+}
@@ -0,0 +1,68 @@
+//! Presence on the player's Discord profile, through the Discord app running next to the game.
+use anyhow::{Result, anyhow};
+use discord_rich_presence::{
+    DiscordIpc, DiscordIpcClient,
+    activity::{self, Timestamps},
+};
+
+use super::{Activity, PresenceOutput};
+
+/// Talks to the Discord app as the Discord application `app_id`, connecting when there is
+/// something to show and again after the app was closed
+pub struct DiscordPresence {
+    client: DiscordIpcClient,
+    connected: bool,
+}
+impl DiscordPresence {
+    pub fn new(app_id: &str) -> Self {
+        Self {
+            client: DiscordIpcClient::new(app_id),
+            connected: false,
+        }
+    }
+
+    fn try_show(&mut self, activity: Option<&Activity>) -> Result<()> {
+        if !self.connected {
+            self.client
+                .connect()
+                .map_err(|e| anyhow!("Discord is not running: {e}"))?;
+            self.connected = true;
+        }
+        let result = match activity {
+            Some(activity) => {
+                let details = match (activity.mode.as_str(), activity.map.as_str()) {
+                    ("", _) => String::from("In a match"),
+                    (mode, "") => mode.to_string(),
+                    (mode, map) => format!("{mode} on {map}"),
+                };
+                let state = format!("{} ({} playing)", activity.server_name, activity.players);
+                self.client.set_activity(
+                    activity::Activity::new()
+                        .details(details)
+                        .state(state)
+                        .timestamps(Timestamps::new().start(activity.since as i64)),
+                )
+            }
+            None => self.client.clear_activity(),
+        };
+        result.map_err(|e| anyhow!("Could not update Discord: {e}"))
+    }
+}
+impl PresenceOutput for DiscordPresence {
+    fn show(&mut self, activity: Option<&Activity>) -> Result<()> {
+        let result = self.try_show(activity);
+        if result.is_err() && self.connected {
+            // Discord was probably closed, connect anew next time
+            let _ = self.client.close();
+            self.connected = false;
+        }
+        result
+    }
+}
+impl Drop for DiscordPresence {
+    fn drop(&mut self) {
+        if self.connected {
+            let _ = self.client.close();
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Shows what the player is up to outside the game, such as on their Discord profile.
+//!
+//! [`Presence`] follows the server the client plays on: its name from the welcome, the map and
+//! mode from [`MatchInfo`] and the number of players from the snapshots. Whenever that changes it
+//! is handed to a [`PresenceOutput`], at most once every [`UPDATE_INTERVAL`] since services limit
+//! how often presence may change, the latest change winning. Clients built with the `discord`
+//! feature show it on Discord when given `--discord-app-id`, see [`discord`]; the others show it
+//! nowhere, through [`NoPresence`].
+//!
+//! [`MatchInfo`]: common::message::ServerMessage::MatchInfo
+#[cfg(feature = "discord")]
+pub mod discord;
+
+use anyhow::Result;
+
+/// Seconds between changes of what the output shows
+const UPDATE_INTERVAL: f64 = 15.0;
+
+/// What the player is doing, as shown to others
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Activity {
+    pub server_name: String,
+    /// Map and mode, empty until the server says what it plays
+    pub map: String,
+    pub mode: String,
+    pub players: usize,
+    /// When the player joined the server, in unix seconds
+    pub since: u64,
+}
+
+/// Somewhere presence can be shown
+pub trait PresenceOutput {
+    /// Shows `activity`, or that the player is not on a server with `None`
+    fn show(&mut self, activity: Option<&Activity>) -> Result<()>;
+}
+
+/// Stands in when presence is shown nowhere
+pub struct NoPresence;
+impl PresenceOutput for NoPresence {
+    fn show(&mut self, _activity: Option<&Activity>) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Presence {
+    output: Box<dyn PresenceOutput>,
+    /// What the player is doing now, none while not on a server
+    activity: Option<Activity>,
+    /// What the output shows
+    shown: Option<Activity>,
+    /// When the output was last changed or failed to, in seconds
+    last_change: Option<f64>,
+}
+impl Presence {
+    pub fn new(output: Box<dyn PresenceOutput>) -> Self {
+        Self {
+            output,
+            activity: None,
+            shown: None,
+            last_change: None,
+        }
+    }
+
+    /// The server called `server_name` welcomed the player at `since`, in unix seconds
+    pub fn joined(&mut self, server_name: &str, since: u64) {
+        self.activity = Some(Activity {
+            server_name: server_name.to_string(),
+            since,
+            ..Activity::default()
+        });
+    }
+
+    /// The server says it plays `mode` on `map`
+    pub fn match_info(&mut self, map: &str, mode: &str) {
+        if let Some(activity) = &mut self.activity {
+            activity.map = map.to_string();
+            activity.mode = mode.to_string();
+        }
+    }
+
+    pub fn set_players(&mut self, players: usize) {
+        if let Some(activity) = &mut self.activity {
+            activity.players = players;
+        }
+    }
+
+    /// The player is not on a server anymore
+    pub fn left(&mut self) {
+        self.activity = None;
+    }
+
+    /// Passes the latest activity on to the output if it changed and the output may be changed
+    /// again at `now`, in seconds
+    pub fn update(&mut self, now: f64) {
+        if self.activity == self.shown
+            || self
+                .last_change
+                .is_some_and(|at| now - at < UPDATE_INTERVAL)
+        {
+            return;
+        }
+        self.last_change = Some(now);
+        // A failed change is tried again after the interval
+        match self.output.show(self.activity.as_ref()) {
+            Ok(()) => self.shown = self.activity.clone(),
+            Err(e) => eprintln!("Could not show presence: {e:#}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /// Keeps everything it is asked to show
+    struct Shown(Rc<RefCell<Vec<Option<Activity>>>>);
+    impl PresenceOutput for Shown {
+        fn show(&mut self, activity: Option<&Activity>) -> Result<()> {
+            self.0.borrow_mut().push(activity.cloned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn changes_are_shown_at_most_once_an_interval() {
+        let shown = Rc::new(RefCell::new(Vec::new()));
+        let mut presence = Presence::new(Box::new(Shown(shown.clone())));
+        presence.update(0.0);
+        assert!(shown.borrow().is_empty());
+
+        presence.joined("Test Server", 100);
+        presence.match_info("arena", "sumo");
+        presence.update(1.0);
+        assert_eq!(shown.borrow().len(), 1);
+
+        // Waits out the interval, then shows only the latest
+        presence.set_players(2);
+        presence.update(2.0);
+        presence.set_players(3);
+        presence.update(1.0 + UPDATE_INTERVAL);
+        let shown = shown.borrow();
+        assert_eq!(shown.len(), 2);
+        let activity = shown[1].as_ref().unwrap();
+        assert_eq!(activity.players, 3);
+        assert_eq!(activity.map, "arena");
+        assert_eq!(activity.since, 100);
+    }
+}
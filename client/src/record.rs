@@ -0,0 +1,116 @@
+//! Recording what the client renders as video frames.
+//!
+//! Frames are written at a fixed rate either as numbered PNG files in a directory, or as raw RGBA
+//! to stdout so they can be piped straight into ffmpeg:
+//!
+//! `client --replay game.replay --record - | ffmpeg -f rawvideo -pix_fmt rgba -s 1280x720 -r 30 -i - game.mp4`
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Stdout, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Result, anyhow};
+
+/// Time source of the game loop
+pub enum Clock {
+    /// Follows the system clock
+    Wall,
+    /// Advances by a fixed step every frame, so a recording does not depend on how fast frames
+    /// render
+    Fixed { time: f64, step: f64 },
+}
+impl Clock {
+    pub fn now(&self) -> f64 {
+        match self {
+            Clock::Wall => miniquad::date::now(),
+            Clock::Fixed { time, .. } => *time,
+        }
+    }
+
+    /// Moves on to the next frame.
+    pub fn advance(&mut self) {
+        if let Clock::Fixed { time, step } = self {
+            *time += *step;
+        }
+    }
+}
+
+enum FrameOutput {
+    Directory(PathBuf),
+    Stdout(Stdout),
+}
+
+/// Writes rendered frames at a fixed rate
+pub struct Recorder {
+    output: FrameOutput,
+    fps: f64,
+    size: (u32, u32),
+    written: u64,
+    start: Option<f64>,
+}
+impl Recorder {
+    /// Records into `target`, a directory for PNG frames or `-` for raw frames on stdout.
+    pub fn new(target: &str, fps: f64, size: (u32, u32)) -> Result<Self> {
+        let output = if target == "-" {
+            FrameOutput::Stdout(std::io::stdout())
+        } else {
+            fs::create_dir_all(target)?;
+            FrameOutput::Directory(PathBuf::from(target))
+        };
+        Ok(Self {
+            output,
+            fps,
+            size,
+            written: 0,
+            start: None,
+        })
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Number of frames that should be written by `now` but were not yet. When frames render
+    /// slower than the recording rate the same frame is written several times to keep the pace.
+    pub fn frames_due(&mut self, now: f64) -> u64 {
+        let start = *self.start.get_or_insert(now);
+        let frames = ((now - start) * self.fps).floor() as u64 + 1;
+        frames.saturating_sub(self.written)
+    }
+
+    /// Writes one frame of RGBA pixels, rows going from top to bottom.
+    pub fn write_frame(&mut self, pixels: &[u8]) -> Result<()> {
+        match &mut self.output {
+            FrameOutput::Directory(dir) => {
+                let path = dir.join(format!("frame_{:06}.png", self.written));
+                let mut encoder = png::Encoder::new(
+                    BufWriter::new(File::create(path)?),
+                    self.size.0,
+                    self.size.1,
+                );
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.write_header()?.write_image_data(pixels)?;
+            }
+            FrameOutput::Stdout(stdout) => {
+                stdout.write_all(pixels)?;
+                stdout.flush()?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+}
+
+/// Parses a frame size written as `WIDTHxHEIGHT`.
+pub fn parse_size(text: &str) -> Result<(u32, u32)> {
+    let (width, height) = text
+        .split_once('x')
+        .ok_or_else(|| anyhow!("Expected WIDTHxHEIGHT, got `{text}`"))?;
+    Ok((width.trim().parse()?, height.trim().parse()?))
+}
@@ -0,0 +1,16 @@
+//! Draws a short notice at the top of the window, such as the storm timer.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+
+const SCALE: f32 = 2.0;
+const MARGIN: f32 = 16.0;
+
+pub fn draw(ui: &mut UiMesh, banner: &str) {
+    let screen = ui.screen_size();
+    let pos = Vec2 {
+        x: (screen.x - UiMesh::text_width(banner, SCALE)) / 2.0,
+        y: MARGIN,
+    };
+    ui.text(banner, pos, SCALE, Color::WHITE);
+}
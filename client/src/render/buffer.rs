@@ -0,0 +1,87 @@
+//! Vertex buffers that grow with the scene, so busy frames are drawn whole instead of cut off.
+use std::marker::PhantomData;
+
+use miniquad::*;
+
+use super::shapes::Vertex;
+
+/// Triangle vertices uploaded every frame, bound together with an index buffer counting up to match
+pub struct DynamicMesh<V = Vertex> {
+    bindings: Bindings,
+    usage: BufferUsage,
+    /// Vertices the buffers have room for
+    capacity: usize,
+    vertex: PhantomData<V>,
+}
+impl<V> DynamicMesh<V> {
+    pub fn new(ctx: &mut dyn RenderingBackend, usage: BufferUsage, capacity: usize) -> Self {
+        Self {
+            bindings: Self::create_bindings(ctx, usage, capacity),
+            usage,
+            capacity,
+            vertex: PhantomData,
+        }
+    }
+
+    fn create_bindings(
+        ctx: &mut dyn RenderingBackend,
+        usage: BufferUsage,
+        capacity: usize,
+    ) -> Bindings {
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            usage,
+            BufferSource::empty::<V>(capacity),
+        );
+        let indices: Vec<u32> = (0..capacity as u32).collect();
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+        Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![],
+        }
+    }
+
+    /// Uploads the vertices of a frame, replacing the buffers with larger ones if they do not fit.
+    pub fn upload(&mut self, ctx: &mut dyn RenderingBackend, vertices: &[V]) {
+        if vertices.len() > self.capacity {
+            ctx.delete_buffer(self.bindings.vertex_buffers[0]);
+            ctx.delete_buffer(self.bindings.index_buffer);
+            self.capacity = grown_capacity(self.capacity, vertices.len());
+            self.bindings = Self::create_bindings(ctx, self.usage, self.capacity);
+        }
+        ctx.buffer_update(
+            self.bindings.vertex_buffers[0],
+            BufferSource::slice(vertices),
+        );
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+}
+
+/// Capacity to grow to so that `needed` vertices fit, doubling to keep reallocations rare
+fn grown_capacity(capacity: usize, needed: usize) -> usize {
+    let mut capacity = capacity.max(3);
+    while capacity < needed {
+        capacity *= 2;
+    }
+    capacity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_doubles_until_it_fits() {
+        assert_eq!(grown_capacity(6000, 6001), 12000);
+        assert_eq!(grown_capacity(6000, 50000), 96000);
+        assert_eq!(grown_capacity(0, 3), 3);
+    }
+}
@@ -0,0 +1,61 @@
+//! Draws the chat log and input box in the bottom left corner of the window.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+use crate::chat::Chat;
+
+const SCALE: f32 = 2.0;
+const MARGIN: f32 = 10.0;
+const INPUT_BACKGROUND: Color = Color {
+    r: 0.15,
+    g: 0.15,
+    b: 0.15,
+};
+const SENDER_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.85,
+    b: 0.3,
+};
+
+pub fn draw(ui: &mut UiMesh, chat: &Chat) {
+    let line_height = UiMesh::line_height(SCALE);
+    let screen = ui.screen_size();
+    let mut y = screen.y - MARGIN - line_height;
+
+    if chat.open {
+        ui.rect(
+            Vec2 {
+                x: MARGIN - 4.0,
+                y: y - 4.0,
+            },
+            Vec2 {
+                x: screen.x - 2.0 * MARGIN + 8.0,
+                y: line_height + 4.0,
+            },
+            INPUT_BACKGROUND,
+        );
+        ui.text(
+            &format!("> {}_", chat.input),
+            Vec2 { x: MARGIN, y },
+            SCALE,
+            Color::WHITE,
+        );
+    }
+    y -= line_height + 4.0;
+
+    let lines: Vec<_> = chat.visible_lines().collect();
+    for line in lines.into_iter().rev() {
+        let sender = format!("{}: ", line.sender);
+        ui.text(&sender, Vec2 { x: MARGIN, y }, SCALE, SENDER_COLOR);
+        ui.text(
+            &line.text,
+            Vec2 {
+                x: MARGIN + UiMesh::text_width(&sender, SCALE),
+                y,
+            },
+            SCALE,
+            Color::WHITE,
+        );
+        y -= line_height;
+    }
+}
@@ -0,0 +1,27 @@
+//! Draws the debug overlay in the top left corner of the window.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+
+const SCALE: f32 = 2.0;
+const MARGIN: f32 = 10.0;
+const TEXT_COLOR: Color = Color {
+    r: 0.6,
+    g: 1.0,
+    b: 0.6,
+};
+
+pub fn draw(ui: &mut UiMesh, lines: &[String]) {
+    let line_height = UiMesh::line_height(SCALE);
+    for (index, line) in lines.iter().enumerate() {
+        ui.text(
+            line,
+            Vec2 {
+                x: MARGIN,
+                y: MARGIN + index as f32 * line_height,
+            },
+            SCALE,
+            TEXT_COLOR,
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Draws the heads-up display in the bottom right corner of the window: the local player's health
+//! with the ping, frame rate and player count under it, and the running lap above it on tracks.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+use crate::style::HudLayout;
+
+/// What the heads-up display shows in a frame
+pub struct HudView {
+    /// Health and maximum health of the local player, while there is one
+    pub health: Option<(f32, f32)>,
+    /// Round trip to the server in seconds, once it is known
+    pub ping: Option<f64>,
+    pub fps: f64,
+    pub players: usize,
+    /// Lap and its time, while the local player drives one
+    pub lap: Option<String>,
+}
+
+pub fn draw(ui: &mut UiMesh, hud: &HudView, layout: &HudLayout) {
+    let (scale, margin, bar_width) = (layout.scale, layout.margin, layout.health_bar_width);
+    let screen = ui.screen_size();
+    let line_height = UiMesh::line_height(scale);
+    let right = screen.x - margin;
+
+    let ping = hud
+        .ping
+        .map(|ping| format!("{:.0} ms", ping * 1000.0))
+        .unwrap_or_else(|| String::from("-"));
+    let stats = format!("ping {ping}  fps {:.0}  players {}", hud.fps, hud.players);
+    let stats_pos = Vec2 {
+        x: right - UiMesh::text_width(&stats, scale),
+        y: screen.y - margin - line_height,
+    };
+    ui.text(&stats, stats_pos, scale, layout.stats_color);
+
+    let bar_pos = Vec2 {
+        x: right - bar_width,
+        y: stats_pos.y - line_height - margin / 2.0,
+    };
+    if let Some(lap) = &hud.lap {
+        let lap_pos = Vec2 {
+            x: right - UiMesh::text_width(lap, scale),
+            y: bar_pos.y - line_height - margin / 2.0,
+        };
+        ui.text(lap, lap_pos, scale, Color::WHITE);
+    }
+
+    let Some((health, max_health)) = hud.health else {
+        return;
+    };
+    let fill = if max_health > 0.0 {
+        (health / max_health).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    ui.rect(
+        bar_pos,
+        Vec2 {
+            x: bar_width,
+            y: line_height,
+        },
+        layout.health_bar_background,
+    );
+    ui.rect(
+        bar_pos,
+        Vec2 {
+            x: bar_width * fill,
+            y: line_height,
+        },
+        layout.health_bar_fill,
+    );
+    let text = format!("HP {:.0}/{:.0}", health.max(0.0).ceil(), max_health);
+    let text_pos = Vec2 {
+        x: bar_pos.x + (bar_width - UiMesh::text_width(&text, scale)) / 2.0,
+        y: bar_pos.y + scale,
+    };
+    ui.text(&text, text_pos, scale, Color::WHITE);
+}
@@ -0,0 +1,53 @@
+//! Draws the caption of the kill cam and the button that skips it at the bottom of the window.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+
+const SCALE: f32 = 2.0;
+const MARGIN: f32 = 24.0;
+const PADDING: f32 = 8.0;
+const SKIP_LABEL: &str = "Skip";
+const CAPTION_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.4,
+    b: 0.3,
+};
+const BUTTON_COLOR: Color = Color {
+    r: 0.2,
+    g: 0.2,
+    b: 0.25,
+};
+
+/// Corner and size of the skip button on a window of `screen` pixels
+pub fn skip_button(screen: Vec2) -> (Vec2, Vec2) {
+    let size = Vec2 {
+        x: UiMesh::text_width(SKIP_LABEL, SCALE) + 2.0 * PADDING,
+        y: UiMesh::line_height(SCALE) + 2.0 * PADDING,
+    };
+    let pos = Vec2 {
+        x: (screen.x - size.x) / 2.0,
+        y: screen.y - MARGIN - size.y,
+    };
+    (pos, size)
+}
+
+pub fn draw(ui: &mut UiMesh, caption: &str) {
+    let screen = ui.screen_size();
+    let (pos, size) = skip_button(screen);
+    ui.rect(pos, size, BUTTON_COLOR);
+    ui.text(
+        SKIP_LABEL,
+        Vec2 {
+            x: pos.x + PADDING,
+            y: pos.y + PADDING,
+        },
+        SCALE,
+        Color::WHITE,
+    );
+
+    let caption_pos = Vec2 {
+        x: (screen.x - UiMesh::text_width(caption, SCALE)) / 2.0,
+        y: pos.y - PADDING - UiMesh::line_height(SCALE),
+    };
+    ui.text(caption, caption_pos, SCALE, CAPTION_COLOR);
+}
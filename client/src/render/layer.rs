@@ -0,0 +1,112 @@
+//! Layers a frame is drawn in, back to front, each with a draw list of its own.
+//!
+//! Everything is collected into the list of its layer first and only then drawn, so overlays such
+//! as the chat or the scoreboard end up on top of the world whatever order they were added in.
+use miniquad::*;
+
+use super::{
+    buffer::DynamicMesh,
+    shapes::Vertex,
+    sprite::{SpriteBatch, SpriteVertex},
+};
+
+/// Sprite vertices a layer starts out with room for, they grow when a frame needs more
+const SPRITE_VERTEX_CAPACITY: usize = 6 * 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    /// The environment: objects placed in the world
+    Background,
+    /// Players, projectiles, pickups and effects
+    World,
+    /// Text and menus in screen space
+    Ui,
+}
+impl Layer {
+    /// Every layer, in the order they are drawn
+    pub const ALL: [Layer; 3] = [Layer::Background, Layer::World, Layer::Ui];
+
+    /// Whether the layer is in world units seen through the camera, rather than already in
+    /// normalized device coordinates
+    pub fn follows_camera(self) -> bool {
+        self != Layer::Ui
+    }
+
+    /// Vertices the buffers of the layer start out with room for, they grow when a frame needs
+    /// more
+    fn initial_capacity(self) -> usize {
+        match self {
+            Layer::Background => 6 * 512,
+            Layer::World => 3 * 2000,
+            Layer::Ui => 3 * 8000,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// What a layer draws in a frame, flat shapes first and sprites over them
+#[derive(Default)]
+pub struct DrawList {
+    pub vertices: Vec<Vertex>,
+    pub sprites: SpriteBatch,
+}
+
+/// Draw lists of every layer for one frame
+#[derive(Default)]
+pub struct Frame {
+    lists: [DrawList; 3],
+}
+impl Frame {
+    pub fn layer(&mut self, layer: Layer) -> &mut DrawList {
+        &mut self.lists[layer.index()]
+    }
+
+    /// Draw lists in the order they are drawn
+    pub fn layers(&self) -> impl Iterator<Item = (Layer, &DrawList)> {
+        Layer::ALL.into_iter().zip(&self.lists)
+    }
+}
+
+/// Buffers a layer's draw list is uploaded to
+pub struct LayerMeshes {
+    pub flat: DynamicMesh,
+    pub sprites: DynamicMesh<SpriteVertex>,
+}
+impl LayerMeshes {
+    /// Buffers for every layer, indexed like [`Layer::ALL`]
+    pub fn for_all_layers(ctx: &mut dyn RenderingBackend) -> [Self; 3] {
+        Layer::ALL.map(|layer| {
+            // The overlay is rebuilt from scratch every frame and never read back
+            let usage = match layer {
+                Layer::Ui => BufferUsage::Stream,
+                _ => BufferUsage::Dynamic,
+            };
+            Self {
+                flat: DynamicMesh::new(ctx, usage, layer.initial_capacity()),
+                sprites: DynamicMesh::new(ctx, BufferUsage::Stream, SPRITE_VERTEX_CAPACITY),
+            }
+        })
+    }
+
+    pub fn upload(&mut self, ctx: &mut dyn RenderingBackend, list: &DrawList) {
+        self.flat.upload(ctx, &list.vertices);
+        self.sprites.upload(ctx, &list.sprites.vertices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ui_is_drawn_last_in_screen_space() {
+        assert_eq!(Layer::ALL.last(), Some(&Layer::Ui));
+        assert!(!Layer::Ui.follows_camera());
+        let frame = Frame::default();
+        let order: Vec<Layer> = frame.layers().map(|(layer, _)| layer).collect();
+        assert_eq!(order, Layer::ALL);
+    }
+}
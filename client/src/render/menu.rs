@@ -0,0 +1,105 @@
+//! Draws a menu of labelled rows in the middle of the window, such as the controls menu.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+
+const SCALE: f32 = 2.0;
+const PADDING: f32 = 12.0;
+/// Characters kept of a row label, so the values line up
+const LABEL_WIDTH: usize = 14;
+const BACKGROUND: Color = Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.1,
+};
+const HIGHLIGHT: Color = Color {
+    r: 0.25,
+    g: 0.25,
+    b: 0.3,
+};
+const TITLE_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.85,
+    b: 0.3,
+};
+const FOOTER_COLOR: Color = Color {
+    r: 0.7,
+    g: 0.7,
+    b: 0.7,
+};
+
+pub struct Menu {
+    pub title: String,
+    /// Label and value of every row
+    pub rows: Vec<(String, String)>,
+    /// Row drawn highlighted
+    pub selected: usize,
+    /// Hint or message shown under the rows
+    pub footer: String,
+}
+
+pub fn draw(ui: &mut UiMesh, menu: &Menu) {
+    let lines: Vec<String> = menu
+        .rows
+        .iter()
+        .map(|(label, value)| {
+            let label: String = label.chars().take(LABEL_WIDTH).collect();
+            format!("{label:<LABEL_WIDTH$} {value}")
+        })
+        .collect();
+
+    let screen = ui.screen_size();
+    let line_height = UiMesh::line_height(SCALE);
+    let width = lines
+        .iter()
+        .chain([&menu.title, &menu.footer])
+        .map(|line| UiMesh::text_width(line, SCALE))
+        .fold(0.0, f32::max);
+    let size = Vec2 {
+        x: width,
+        // Title, a gap, the rows, a gap and the footer
+        y: line_height * (lines.len() + 4) as f32,
+    };
+    let pos = Vec2 {
+        x: (screen.x - size.x) / 2.0,
+        y: (screen.y - size.y) / 2.0,
+    };
+
+    ui.rect(
+        Vec2 {
+            x: pos.x - PADDING,
+            y: pos.y - PADDING,
+        },
+        Vec2 {
+            x: size.x + 2.0 * PADDING,
+            y: size.y + 2.0 * PADDING,
+        },
+        BACKGROUND,
+    );
+    ui.text(&menu.title, pos, SCALE, TITLE_COLOR);
+    for (index, line) in lines.iter().enumerate() {
+        let row = Vec2 {
+            x: pos.x,
+            y: pos.y + (index + 2) as f32 * line_height,
+        };
+        if index == menu.selected {
+            ui.rect(
+                Vec2 {
+                    x: row.x - PADDING / 2.0,
+                    y: row.y - SCALE,
+                },
+                Vec2 {
+                    x: size.x + PADDING,
+                    y: line_height,
+                },
+                HIGHLIGHT,
+            );
+        }
+        ui.text(line, row, SCALE, Color::WHITE);
+    }
+    let footer = Vec2 {
+        x: pos.x,
+        y: pos.y + (lines.len() + 3) as f32 * line_height,
+    };
+    ui.text(&menu.footer, footer, SCALE, FOOTER_COLOR);
+}
@@ -1,67 +1,149 @@
-use common::world::GameWorld;
+use std::{f32::consts::PI, path::Path};
+
+use common::{
+    color::Color,
+    vec::Vec2,
+    world::{
+        GameWorld,
+        combat::Projectile,
+        environment::Attractor,
+        pickups::{PICKUP_RADIUS, Pickup, PickupKind},
+        scoreboard::Score,
+    },
+};
 use miniquad::*;
 
 use crate::{
     camera::Camera,
+    chat::Chat,
+    effects::Effects,
     render::{
-        shader::Uniforms,
-        shapes::{Mesh, Tri},
+        layer::{DrawList, Frame, Layer, LayerMeshes},
+        shader::{Glsl, ShaderFiles, Uniforms},
+        shapes::{Mesh, Quad, Tri, Vertex},
+        sprite::Sprites,
+        ui::UiMesh,
     },
+    style::{Palette, Style, Weapons},
+    toasts::Toasts,
 };
+mod banner;
+mod buffer;
+mod chat;
+mod debug;
+pub mod hud;
+pub mod killcam;
+mod layer;
+pub mod menu;
+mod scoreboard;
 mod shader;
 mod shapes;
+mod sprite;
+mod status;
+mod text;
+mod toasts;
+mod ui;
+mod weapon;
 
-pub struct Render {
-    ctx: Box<dyn RenderingBackend>,
-    pipeline: Pipeline,
-    bindings: Bindings,
-    uniforms: Uniforms,
-    start_time: f64,
+/// Rings drifting through an attractor at any time, each broken into as many arcs
+const SWIRL_RINGS: usize = 3;
+const SWIRL_ARCS: usize = 3;
+/// Share of the way across an attractor a ring drifts per second
+const SWIRL_DRIFT: f32 = 0.4;
+/// Radians per second the rings turn by
+const SWIRL_SPIN: f32 = 1.5;
+/// Distance from the local player to the arrow pointing at the next checkpoint, and its length
+const POINTER_DISTANCE: f32 = 0.1;
+const POINTER_SIZE: f32 = 0.04;
 
-    player_buffer: BufferId,
+/// Everything that ends up in a frame
+pub struct Scene<'a> {
+    pub camera: &'a Camera,
+    pub world: &'a GameWorld,
+    pub chat: &'a Chat,
+    pub debug_lines: &'a [String],
+    /// Connection status shown in the middle of the screen
+    pub status: Option<&'a str>,
+    /// Center and current radius of the arena, when the mode has one
+    pub arena: Option<(Vec2, f32)>,
+    /// Center and current radius of the storm's safe zone, when the mode has one
+    pub storm: Option<(Vec2, f32)>,
+    /// Short notice shown at the top of the screen, such as the storm timer
+    pub banner: Option<&'a str>,
+    /// Where the ghost of the local player's fastest lap is, during time trial laps
+    pub ghost: Option<Vec2>,
+    /// Where the local player is and the checkpoint it heads for, pointed at by an arrow next to
+    /// the player
+    pub pointer: Option<(Vec2, Vec2)>,
+    pub effects: &'a Effects,
+    /// Name of the selected weapon, shown in the top right corner
+    pub weapon: &'a str,
+    /// Rounds of the selected weapon and the color to show them in, shown under the weapon
+    pub ammo: Option<(&'a str, Color)>,
+    /// Notices shown under the weapon
+    pub toasts: &'a Toasts,
+    /// Leaderboard rows, shown while the scoreboard key is held
+    pub scoreboard: Option<&'a [(String, Score)]>,
+    /// Fastest laps by player, shown in place of the leaderboard in time trials
+    pub lap_times: Option<&'a [(String, f32)]>,
+    /// Caption of the kill cam while it plays, drawn with its skip button
+    pub kill_cam: Option<&'a str>,
+    /// Menu drawn over everything else, such as the controls menu
+    pub menu: Option<&'a menu::Menu>,
+    pub hud: &'a hud::HudView,
+    /// How it all looks
+    pub style: &'a Style,
 }
-impl Render {
-    pub fn init() -> Self {
-        let mut ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
-
-        let player_buffer = ctx.new_buffer(
-            BufferType::VertexBuffer,
-            BufferUsage::Dynamic,
-            BufferSource::empty::<shapes::Vertex>(5 * 3),
-        );
-
-        let indices: Vec<u16> = (0..25 as u16).collect();
 
-        let index_buffer = ctx.new_buffer(
-            BufferType::IndexBuffer,
-            BufferUsage::Immutable,
-            BufferSource::slice(&indices),
-        );
+/// Pipelines of the world and of sprites, along with the shaders they were made from
+struct Programs {
+    pipeline: Pipeline,
+    sprite_pipeline: Pipeline,
+    shaders: [ShaderId; 2],
+}
+impl Programs {
+    /// Compiles the shaders in `files` when given, the built-in ones when not or when those do
+    /// not compile. Returns whether the files were compiled.
+    fn load(ctx: &mut dyn RenderingBackend, files: Option<&ShaderFiles>) -> (Self, bool) {
+        if let Some(files) = files {
+            match Self::compile(ctx, &Glsl::read(&files.dir)) {
+                Ok(programs) => return (programs, true),
+                Err(e) => eprintln!(
+                    "Could not compile the shaders in {}, drawing with the built-in ones: {e}",
+                    files.dir.display()
+                ),
+            }
+        }
+        let programs = Self::compile(ctx, &Glsl::embedded()).expect("built-in shaders compile");
+        (programs, false)
+    }
 
-        let bindings = Bindings {
-            vertex_buffers: vec![player_buffer],
-            index_buffer, // or remove entirely
-            images: vec![],
+    fn compile(ctx: &mut dyn RenderingBackend, glsl: &Glsl) -> Result<Self, ShaderError> {
+        let metal = ctx.info().backend == Backend::Metal;
+        let source = |vertex, fragment, program| {
+            if metal {
+                ShaderSource::Msl { program }
+            } else {
+                ShaderSource::Glsl { vertex, fragment }
+            }
         };
-
-        let shader = ctx
-            .new_shader(
-                match ctx.info().backend {
-                    Backend::OpenGl => ShaderSource::Glsl {
-                        vertex: shader::VERTEX,
-                        fragment: shader::FRAGMENT,
-                    },
-                    Backend::Metal => ShaderSource::Msl {
-                        program: shader::METAL,
-                    },
-                },
-                shader::meta(),
-            )
-            .unwrap();
-
-        let uniforms = shader::Uniforms {
-            time: 0.,
-            offset: (0.0, 0.0),
+        let shader = ctx.new_shader(
+            source(&glsl.vertex, &glsl.fragment, shader::METAL),
+            shader::meta(),
+        )?;
+        let sprite_shader = match ctx.new_shader(
+            source(
+                &glsl.sprite_vertex,
+                &glsl.sprite_fragment,
+                shader::SPRITE_METAL,
+            ),
+            shader::sprite_meta(),
+        ) {
+            Ok(sprite_shader) => sprite_shader,
+            Err(e) => {
+                ctx.delete_shader(shader);
+                return Err(e);
+            }
         };
 
         let pipeline = ctx.new_pipeline(
@@ -76,43 +158,549 @@ impl Render {
                 ..PipelineParams::default()
             },
         );
+        let sprite_pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("in_pos", VertexFormat::Float2),
+                VertexAttribute::new("in_uv", VertexFormat::Float2),
+                VertexAttribute::new("in_color", VertexFormat::Float3),
+            ],
+            sprite_shader,
+            PipelineParams {
+                primitive_type: PrimitiveType::Triangles,
+                // Transparent pixels let what is underneath show through
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                ..PipelineParams::default()
+            },
+        );
+        Ok(Self {
+            pipeline,
+            sprite_pipeline,
+            shaders: [shader, sprite_shader],
+        })
+    }
+
+    fn delete(self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_pipeline(self.pipeline);
+        ctx.delete_pipeline(self.sprite_pipeline);
+        for shader in self.shaders {
+            ctx.delete_shader(shader);
+        }
+    }
+}
+
+pub struct Render {
+    ctx: Box<dyn RenderingBackend>,
+    programs: Programs,
+    /// Shader files compiled again when they change, in debug builds
+    shader_files: Option<ShaderFiles>,
+    uniforms: Uniforms,
+    start_time: f64,
+
+    /// Buffers of every layer, indexed like [`Layer::ALL`]
+    layers: [LayerMeshes; 3],
+    sprites: Sprites,
+
+    /// Offscreen target frames are captured into, created on first use
+    capture_pass: Option<(RenderPass, (u32, u32))>,
+}
+impl Render {
+    /// Sets up drawing, with the sprites found in `sprites_dir` and, in debug builds, the shaders
+    /// found in `shaders_dir`
+    pub fn init(sprites_dir: &Path, shaders_dir: &Path) -> Self {
+        let mut ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
+
+        let layers = LayerMeshes::for_all_layers(&mut *ctx);
+        let sprites = Sprites::load(&mut *ctx, sprites_dir);
+
+        // Only GLSL is read from files, other backends keep the built-in shaders
+        let shader_files = (cfg!(debug_assertions) && ctx.info().backend == Backend::OpenGl)
+            .then(|| ShaderFiles::new(shaders_dir));
+        let (programs, _) = Programs::load(&mut *ctx, shader_files.as_ref());
+        let uniforms = shader::Uniforms {
+            time: 0.,
+            view: shader::IDENTITY,
+        };
 
         let start_time = miniquad::date::now();
 
         Self {
             ctx,
-            pipeline,
-            bindings,
+            programs,
+            shader_files,
             uniforms,
             start_time,
-            player_buffer,
+            layers,
+            sprites,
+            capture_pass: None,
+        }
+    }
+    pub fn draw(&mut self, scene: &Scene) {
+        self.reload_shaders();
+        self.draw_pass(None, window::screen_size(), scene);
+        self.ctx.commit_frame();
+    }
+
+    /// Compiles the shader files again if they changed, going back to the built-in shaders when
+    /// they do not compile
+    fn reload_shaders(&mut self) {
+        let Some(files) = &mut self.shader_files else {
+            return;
+        };
+        if !files.changed(miniquad::date::now()) {
+            return;
+        }
+        let (programs, compiled) = Programs::load(&mut *self.ctx, Some(files));
+        if compiled {
+            println!("Reloaded the shaders from {}", files.dir.display());
         }
+        std::mem::replace(&mut self.programs, programs).delete(&mut *self.ctx);
+    }
+
+    /// Draws the scene into an offscreen framebuffer and returns its RGBA pixels, rows going from
+    /// top to bottom.
+    pub fn capture(&mut self, size: (u32, u32), scene: &Scene) -> Vec<u8> {
+        let pass = match self.capture_pass {
+            Some((pass, pass_size)) if pass_size == size => pass,
+            _ => {
+                let texture = self.ctx.new_render_texture(TextureParams {
+                    width: size.0,
+                    height: size.1,
+                    format: TextureFormat::RGBA8,
+                    ..Default::default()
+                });
+                let pass = self.ctx.new_render_pass(texture, None);
+                self.capture_pass = Some((pass, size));
+                pass
+            }
+        };
+        self.draw_pass(Some(pass), (size.0 as f32, size.1 as f32), scene);
+
+        let mut pixels = vec![0; size.0 as usize * size.1 as usize * 4];
+        let texture = self.ctx.render_pass_texture(pass);
+        self.ctx.texture_read_pixels(texture, &mut pixels);
+        // OpenGL stores the bottom row first
+        let row = size.0 as usize * 4;
+        pixels.chunks_exact(row).rev().flatten().copied().collect()
     }
-    pub fn draw(&mut self, camera: &Camera, world: &GameWorld) {
+
+    fn draw_pass(&mut self, pass: Option<RenderPass>, screen: (f32, f32), scene: &Scene) {
+        let Scene {
+            camera,
+            world,
+            chat,
+            debug_lines,
+            status,
+            arena,
+            storm,
+            banner,
+            ghost,
+            pointer,
+            effects,
+            weapon,
+            ammo,
+            toasts,
+            scoreboard,
+            lap_times,
+            kill_cam,
+            menu,
+            hud,
+            style,
+        } = *scene;
+        let (shapes, palette) = (&style.shapes, &style.palette);
         self.uniforms.time = (miniquad::date::now() - self.start_time) as f32;
-        self.uniforms.offset = (camera.pos.x, camera.pos.y);
+        self.uniforms.view = camera.view_matrix(Vec2 {
+            x: screen.0,
+            y: screen.1,
+        });
+
+        let mut frame = Frame::default();
+
+        let background = frame.layer(Layer::Background);
+        for object in &world.environment.objects {
+            match object.sprite.and_then(|id| self.sprites.get(id)) {
+                Some(texture) => {
+                    background
+                        .sprites
+                        .push(texture, object.pos, object.size, Color::WHITE)
+                }
+                None => background
+                    .vertices
+                    .append(&mut Quad::new(object.pos, object.size, object.color).mesh_vertices()),
+            }
+        }
+        for attractor in &world.environment.attractors {
+            background
+                .vertices
+                .append(&mut swirl(attractor, self.uniforms.time, palette));
+        }
+
+        let DrawList {
+            vertices: triangle_vertices,
+            sprites: sprite_batch,
+        } = frame.layer(Layer::World);
+        if let Some((center, radius)) = arena {
+            triangle_vertices.append(&mut shapes::ring(
+                center,
+                radius,
+                shapes.boundary_thickness,
+                palette.arena,
+            ));
+        }
+        if let Some((center, radius)) = storm {
+            triangle_vertices.append(&mut shapes::ring(
+                center,
+                radius,
+                shapes.boundary_thickness,
+                palette.storm,
+            ));
+        }
+        for (index, checkpoint) in world.environment.checkpoints.iter().enumerate() {
+            let color = if index == 0 {
+                palette.start_line
+            } else {
+                palette.checkpoint
+            };
+            triangle_vertices.append(&mut shapes::ring(
+                checkpoint.pos,
+                checkpoint.radius,
+                shapes.boundary_thickness,
+                color,
+            ));
+        }
+        triangle_vertices.append(&mut pickups(&world.entities.pickups, palette));
+
+        // Under the players, so the ghost never hides anyone
+        if let Some(pos) = ghost {
+            triangle_vertices
+                .append(&mut Tri::point(pos, shapes.player_size, palette.ghost).mesh_vertices());
+        }
 
-        let mut triangle_vertices = Vec::new();
+        let combat = &world.tunables.combat;
+        for player in world.entities.players.values() {
+            if !player.is_alive() {
+                continue;
+            }
+            // Sprites are tinted with the player's color so players stay apart
+            match player.sprite.and_then(|id| self.sprites.get(id)) {
+                Some(texture) => sprite_batch.push(
+                    texture,
+                    player.pos - Vec2::ONE * (shapes.player_sprite_size / 2.0),
+                    Vec2::ONE * shapes.player_sprite_size,
+                    player.color,
+                ),
+                None => triangle_vertices.append(
+                    &mut Tri::point(player.pos, shapes.player_size, player.color).mesh_vertices(),
+                ),
+            }
 
-        for (_, player) in world.entities.players.iter() {
-            triangle_vertices.append(
-                &mut Tri::point(player.pos, 0.05, player.color.clone())
-                    .mesh_vertices(),
+            // Health bar, with the armor bar on top of it once the player has some
+            let bar_size = shapes.bar_size;
+            let mut bar = |offset: f32, fill: f32, back: Color, front: Color| {
+                let corner = player.pos
+                    + Vec2 {
+                        x: -bar_size.x / 2.0,
+                        y: offset,
+                    };
+                let fill = Vec2 {
+                    x: bar_size.x * fill.clamp(0.0, 1.0),
+                    y: bar_size.y,
+                };
+                triangle_vertices.append(&mut shapes::rect(corner, bar_size, back));
+                triangle_vertices.append(&mut shapes::rect(corner, fill, front));
+            };
+            bar(
+                shapes.bar_offset,
+                player.health / combat.max_health,
+                palette.health_lost,
+                palette.health,
             );
+            if player.armor > 0.0 {
+                bar(
+                    shapes.bar_offset + bar_size.y + shapes.bar_gap,
+                    player.armor / combat.max_armor,
+                    palette.armor_lost,
+                    palette.armor,
+                );
+            }
+        }
+        if let Some((from, to)) = pointer {
+            triangle_vertices.append(&mut arrow(from, to, palette.checkpoint));
+        }
+        triangle_vertices.append(&mut projectiles(
+            &world.entities.projectiles,
+            &style.weapons,
+        ));
+        for (center, radius) in effects.blasts() {
+            triangle_vertices.append(&mut shapes::ring(
+                center,
+                radius,
+                shapes.blast_thickness,
+                palette.explosion,
+            ));
+        }
+        for (pos, size) in effects.particles() {
+            triangle_vertices.append(&mut Tri::point(pos, size, palette.explosion).mesh_vertices());
         }
 
-        // Update the player buffer with all triangle vertices
-        self.ctx
-            .buffer_update(self.player_buffer, BufferSource::slice(&triangle_vertices));
+        // Overlays, in screen space
+        let mut ui = UiMesh::new(screen);
+        for player in world.entities.players.values() {
+            if !player.is_alive() {
+                continue;
+            }
+            let above = player.pos
+                + Vec2 {
+                    x: 0.0,
+                    y: shapes.name_offset,
+                };
+            let pixel = camera.world_to_screen(above, ui.screen_size());
+            let width = UiMesh::text_width(&player.username, shapes.name_scale);
+            let pos = Vec2 {
+                x: pixel.x - width / 2.0,
+                y: pixel.y - UiMesh::line_height(shapes.name_scale),
+            };
+            ui.text(&player.username, pos, shapes.name_scale, palette.names);
+        }
+        for (pos, text, color) in effects.numbers() {
+            let pixel = camera.world_to_screen(pos, ui.screen_size());
+            ui.text(text, pixel, shapes.name_scale, color);
+        }
+        debug::draw(&mut ui, debug_lines);
+        chat::draw(&mut ui, chat);
+        weapon::draw(&mut ui, weapon, ammo, &style.hud);
+        toasts::draw(&mut ui, toasts);
+        hud::draw(&mut ui, hud, &style.hud);
+        if let Some(banner) = banner {
+            banner::draw(&mut ui, banner);
+        }
+        if let Some(caption) = kill_cam {
+            killcam::draw(&mut ui, caption);
+        }
+        if let Some(rows) = lap_times {
+            scoreboard::draw_lap_times(&mut ui, rows);
+        } else if let Some(rows) = scoreboard {
+            scoreboard::draw(&mut ui, rows);
+        }
+        if let Some(status) = status {
+            status::draw(&mut ui, status);
+        }
+        if let Some(menu) = menu {
+            menu::draw(&mut ui, menu);
+        }
+        frame.layer(Layer::Ui).vertices = ui.vertices;
 
+        let camera_view = self.uniforms.view;
         self.ctx
-            .begin_default_pass(PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
-        self.ctx.apply_pipeline(&self.pipeline);
-        self.ctx.apply_bindings(&self.bindings);
-        self.ctx
-            .apply_uniforms(UniformsSource::table(&self.uniforms));
-        self.ctx.draw(0, triangle_vertices.len() as i32, 1);
+            .begin_pass(pass, PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+        for (layer, list) in frame.layers() {
+            self.uniforms.view = if layer.follows_camera() {
+                camera_view
+            } else {
+                shader::IDENTITY
+            };
+            self.draw_layer(layer, list);
+        }
         self.ctx.end_render_pass();
-        self.ctx.commit_frame();
+    }
+
+    /// Uploads and draws one layer, its flat shapes first and then its sprites
+    fn draw_layer(&mut self, layer: Layer, list: &DrawList) {
+        let meshes = &mut self.layers[layer.index()];
+        meshes.upload(&mut *self.ctx, list);
+
+        if !list.vertices.is_empty() {
+            self.ctx.apply_pipeline(&self.programs.pipeline);
+            self.ctx.apply_bindings(meshes.flat.bindings());
+            self.ctx
+                .apply_uniforms(UniformsSource::table(&self.uniforms));
+            self.ctx.draw(0, list.vertices.len() as i32, 1);
+        }
+
+        if !list.sprites.draws.is_empty() {
+            self.ctx.apply_pipeline(&self.programs.sprite_pipeline);
+        }
+        for (texture, vertices) in &list.sprites.draws {
+            let mut bindings = meshes.sprites.bindings().clone();
+            bindings.images = vec![*texture];
+            self.ctx.apply_bindings(&bindings);
+            self.ctx
+                .apply_uniforms(UniformsSource::table(&self.uniforms));
+            self.ctx
+                .draw(vertices.start as i32, vertices.len() as i32, 1);
+        }
+    }
+}
+
+/// Pickups that can be taken, in the color of what they give
+fn pickups(pickups: &[Pickup], palette: &Palette) -> Vec<Vertex> {
+    pickups
+        .iter()
+        .filter(|pickup| pickup.is_available())
+        .flat_map(|pickup| {
+            let corner = pickup.pos - Vec2::ONE * PICKUP_RADIUS;
+            let color = match pickup.kind {
+                PickupKind::Armor => palette.armor,
+                PickupKind::Ammo => palette.ammo_pickup,
+            };
+            shapes::rect(corner, Vec2::ONE * (2.0 * PICKUP_RADIUS), color)
+        })
+        .collect()
+}
+
+/// Shots in flight, each looking like the weapon that fired it
+fn projectiles(projectiles: &[Projectile], weapons: &Weapons) -> Vec<Vertex> {
+    projectiles
+        .iter()
+        .flat_map(|projectile| {
+            let shot = weapons.shot(projectile.kind);
+            Tri::point(projectile.pos, shot.size, shot.color).mesh_vertices()
+        })
+        .collect()
+}
+
+/// Arrow next to `from` pointing towards `to`, nothing once there
+fn arrow(from: Vec2, to: Vec2, color: Color) -> Vec<Vertex> {
+    let offset = to - from;
+    let distance = offset.length();
+    if distance <= POINTER_DISTANCE {
+        return Vec::new();
+    }
+    let dir = offset / distance;
+    let side = Vec2 {
+        x: -dir.y,
+        y: dir.x,
+    } * (POINTER_SIZE / 2.0);
+    let base = from + dir * POINTER_DISTANCE;
+    let tip = base + dir * POINTER_SIZE;
+    Tri::new(tip, base + side, base - side, color).mesh_vertices()
+}
+
+/// Rings swirling around an attractor, drifting in towards its center or out towards its edge
+/// when it pushes things away, inside a thin ring marking how far it reaches
+fn swirl(attractor: &Attractor, time: f32, palette: &Palette) -> Vec<Vertex> {
+    let (color, inwards) = if attractor.strength >= 0.0 {
+        (palette.attractor, true)
+    } else {
+        (palette.repulsor, false)
+    };
+    let mut vertices = shapes::ring(attractor.pos, attractor.radius, 0.004, color);
+    let arc_sweep = PI / SWIRL_ARCS as f32;
+    for ring in 0..SWIRL_RINGS {
+        let phase = (ring as f32 / SWIRL_RINGS as f32 + time * SWIRL_DRIFT).fract();
+        let reach = if inwards { 1.0 - phase } else { phase };
+        // Rings fade in at one end of their drift and out at the other
+        let fade = (phase * PI).sin();
+        let faded = Color {
+            r: color.r * fade,
+            g: color.g * fade,
+            b: color.b * fade,
+        };
+        let spin = time * SWIRL_SPIN + ring as f32;
+        for arc in 0..SWIRL_ARCS {
+            let start = spin + arc as f32 * 2.0 * PI / SWIRL_ARCS as f32;
+            vertices.append(&mut shapes::arc(
+                attractor.pos,
+                attractor.radius * reach,
+                0.008,
+                start,
+                arc_sweep,
+                faded,
+            ));
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use common::world::{combat::ProjectileKind, entities::Entities};
+
+    use super::*;
+    use crate::snapshot;
+
+    /// The client's world once `received` came in a snapshot
+    fn world_with(received: Entities) -> GameWorld {
+        let mut world = GameWorld::default();
+        snapshot::apply(&mut world.entities, &received, None);
+        world
+    }
+
+    fn color_of(vertex: &Vertex) -> Color {
+        Color {
+            r: vertex.r,
+            g: vertex.g,
+            b: vertex.b,
+        }
+    }
+
+    #[test]
+    fn snapshot_shots_are_drawn_as_their_weapon() {
+        let shots = ProjectileKind::ALL.map(|kind| Projectile {
+            owner: 1,
+            kind,
+            pos: Vec2::ZERO,
+            vel: Vec2::ZERO,
+            ttl: 1.0,
+            bounces: 0,
+            pierced: Vec::new(),
+        });
+        let world = world_with(Entities {
+            players: HashMap::new(),
+            projectiles: shots.to_vec(),
+            pickups: Vec::new(),
+        });
+
+        let weapons = Weapons::default();
+        let vertices = projectiles(&world.entities.projectiles, &weapons);
+        assert_eq!(vertices.len(), 3 * ProjectileKind::ALL.len());
+        for (triangle, kind) in vertices.chunks(3).zip(ProjectileKind::ALL) {
+            let look = weapons.shot(kind);
+            assert!(triangle.iter().all(|v| color_of(v) == look.color));
+            // The top corner sits one size above the center
+            assert!((triangle[0].y + look.size).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn snapshot_armor_is_drawn_until_taken() {
+        let mut received = Entities {
+            players: HashMap::new(),
+            projectiles: Vec::new(),
+            pickups: Pickup::at(&[Vec2::ZERO], PickupKind::Armor),
+        };
+        let palette = Palette::default();
+        let vertices = pickups(&world_with(received.clone()).entities.pickups, &palette);
+        assert!(!vertices.is_empty());
+        assert!(vertices.iter().all(|v| color_of(v) == palette.armor));
+
+        // Taken pickups wait to come back out of sight
+        received.pickups[0].respawn_in = 5.0;
+        assert!(pickups(&world_with(received).entities.pickups, &palette).is_empty());
+    }
+
+    #[test]
+    fn snapshot_ammo_is_drawn_apart_from_armor() {
+        let points = [Vec2::ZERO, Vec2::ONE];
+        let mut received = Entities {
+            players: HashMap::new(),
+            projectiles: Vec::new(),
+            pickups: Pickup::at(&points[..1], PickupKind::Ammo),
+        };
+        received
+            .pickups
+            .extend(Pickup::at(&points[1..], PickupKind::Armor));
+        let palette = Palette::default();
+        let vertices = pickups(&world_with(received).entities.pickups, &palette);
+        let (ammo, armor) = vertices.split_at(vertices.len() / 2);
+        assert!(ammo.iter().all(|v| color_of(v) == palette.ammo_pickup));
+        assert!(armor.iter().all(|v| color_of(v) == palette.armor));
+        assert_ne!(palette.ammo_pickup, palette.armor);
     }
 }
@@ -0,0 +1,86 @@
+//! Draws the leaderboard in the middle of the window while the scoreboard key is held, or the
+//! fastest laps in time trials.
+use common::{color::Color, vec::Vec2, world::scoreboard::Score};
+
+use super::ui::UiMesh;
+
+const SCALE: f32 = 2.0;
+const PADDING: f32 = 12.0;
+/// Characters kept of a username, so long names do not push the numbers out of line
+const NAME_WIDTH: usize = 16;
+const BACKGROUND: Color = Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.1,
+};
+const HEADER_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.85,
+    b: 0.3,
+};
+
+/// Draws one row per player, in the order given.
+pub fn draw(ui: &mut UiMesh, rows: &[(String, Score)]) {
+    let header = format!(
+        "{:<NAME_WIDTH$} {:>5} {:>7} {:>6} {:>6}",
+        "PLAYER", "KILLS", "ASSISTS", "DEATHS", "RATING"
+    );
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|(name, score)| {
+            let name: String = name.chars().take(NAME_WIDTH).collect();
+            let rating = score
+                .rating
+                .map_or(String::from("-"), |rating| rating.to_string());
+            format!(
+                "{name:<NAME_WIDTH$} {:>5} {:>7} {:>6} {rating:>6}",
+                score.kills, score.assists, score.deaths
+            )
+        })
+        .collect();
+    panel(ui, &header, &lines);
+}
+
+/// Draws one row per player with their fastest lap in seconds, in the order given.
+pub fn draw_lap_times(ui: &mut UiMesh, rows: &[(String, f32)]) {
+    let header = format!("{:<NAME_WIDTH$} {:>9}", "PLAYER", "BEST LAP");
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|(name, time)| {
+            let name: String = name.chars().take(NAME_WIDTH).collect();
+            format!("{name:<NAME_WIDTH$} {time:>9.2}")
+        })
+        .collect();
+    panel(ui, &header, &lines);
+}
+
+/// Draws the header and the lines under it on a background, a quarter of the way down
+fn panel(ui: &mut UiMesh, header: &str, lines: &[String]) {
+    let screen = ui.screen_size();
+    let line_height = UiMesh::line_height(SCALE);
+    let size = Vec2 {
+        x: UiMesh::text_width(header, SCALE),
+        y: line_height * (lines.len() + 1) as f32,
+    };
+    let pos = Vec2 {
+        x: (screen.x - size.x) / 2.0,
+        y: screen.y / 4.0,
+    };
+
+    ui.rect(
+        Vec2 {
+            x: pos.x - PADDING,
+            y: pos.y - PADDING,
+        },
+        Vec2 {
+            x: size.x + 2.0 * PADDING,
+            y: size.y + 2.0 * PADDING,
+        },
+        BACKGROUND,
+    );
+    ui.text(header, pos, SCALE, HEADER_COLOR);
+    for (index, line) in lines.iter().enumerate() {
+        let y = pos.y + (index + 1) as f32 * line_height;
+        ui.text(line, Vec2 { x: pos.x, y }, SCALE, Color::WHITE);
+    }
+}
@@ -1,5 +1,22 @@
+//! Shaders the world and sprites are drawn with.
+//!
+//! The GLSL is built into the client. Debug builds on OpenGL read it from the shaders directory
+//! instead when it has the files, `main.vert`, `main.frag`, `sprite.vert` and `sprite.frag`, and
+//! compile it again whenever one of them changes, so shaders can be worked on while the game
+//! runs. A missing file keeps the built-in shader, and shaders that do not compile are reported
+//! and replaced by the built-in ones until they are fixed.
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
 use miniquad::*;
 
+/// Seconds between looks at whether the shader files changed
+const RELOAD_INTERVAL: f64 = 0.5;
+/// Files in the shaders directory, in the order of [`Glsl::stages`]
+const FILES: [&str; 4] = ["main.vert", "main.frag", "sprite.vert", "sprite.frag"];
+
 pub const VERTEX: &str = r#"
 #version 100
 precision mediump float;
@@ -8,12 +25,12 @@ attribute vec2 in_pos;
 attribute vec3 in_color;
 
 uniform float time;
-uniform vec2 offset;
+uniform mat4 view;
 
 varying vec3 color;
 
 void main() {
-    gl_Position = vec4(in_pos - offset, 0.0, 1.0);
+    gl_Position = view * vec4(in_pos, 0.0, 1.0);
     gl_PointSize = 400.0; // Size in screen pixels
     color = in_color;
 }
@@ -31,20 +48,158 @@ void main() {
 
 pub const METAL: &str = r#""#;
 
+/// Draws a texture over the triangles, tinted by the vertex color
+pub const SPRITE_VERTEX: &str = r#"
+#version 100
+precision mediump float;
+
+attribute vec2 in_pos;
+attribute vec2 in_uv;
+attribute vec3 in_color;
+
+uniform float time;
+uniform mat4 view;
+
+varying vec2 uv;
+varying vec3 color;
+
+void main() {
+    gl_Position = view * vec4(in_pos, 0.0, 1.0);
+    uv = in_uv;
+    color = in_color;
+}
+"#;
+
+pub const SPRITE_FRAGMENT: &str = r#"#version 100
+precision mediump float;
+
+varying vec2 uv;
+varying vec3 color;
+
+uniform sampler2D tex;
+
+void main() {
+    gl_FragColor = texture2D(tex, uv) * vec4(color, 1.0);
+}
+"#;
+
+pub const SPRITE_METAL: &str = r#""#;
+
 pub fn meta() -> ShaderMeta {
     ShaderMeta {
         images: vec![],
         uniforms: UniformBlockLayout {
             uniforms: vec![
                 UniformDesc::new("time", UniformType::Float1),
-                UniformDesc::new("offset", UniformType::Float2),
+                UniformDesc::new("view", UniformType::Mat4),
             ],
         },
     }
 }
 
+/// Same uniforms as [`meta`], with the sprite texture
+pub fn sprite_meta() -> ShaderMeta {
+    ShaderMeta {
+        images: vec![String::from("tex")],
+        ..meta()
+    }
+}
+
 #[repr(C)]
 pub struct Uniforms {
     pub time: f32,
-    pub offset: (f32, f32),
+    /// Takes vertex positions to normalized device coordinates
+    pub view: [f32; 16],
+}
+
+/// View for geometry that is already in normalized device coordinates
+#[rustfmt::skip]
+pub const IDENTITY: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// GLSL of both pipelines
+pub struct Glsl {
+    pub vertex: String,
+    pub fragment: String,
+    pub sprite_vertex: String,
+    pub sprite_fragment: String,
+}
+impl Glsl {
+    /// The shaders built into the client
+    pub fn embedded() -> Self {
+        Self {
+            vertex: VERTEX.to_string(),
+            fragment: FRAGMENT.to_string(),
+            sprite_vertex: SPRITE_VERTEX.to_string(),
+            sprite_fragment: SPRITE_FRAGMENT.to_string(),
+        }
+    }
+
+    /// Reads the shaders `dir` has, keeping the built-in ones for files it does not have
+    pub fn read(dir: &Path) -> Self {
+        let mut glsl = Self::embedded();
+        for (file, source) in FILES.iter().zip(glsl.stages()) {
+            if let Ok(text) = std::fs::read_to_string(dir.join(file)) {
+                *source = text;
+            }
+        }
+        glsl
+    }
+
+    fn stages(&mut self) -> [&mut String; 4] {
+        [
+            &mut self.vertex,
+            &mut self.fragment,
+            &mut self.sprite_vertex,
+            &mut self.sprite_fragment,
+        ]
+    }
+}
+
+/// The shader files of a directory, watched for changes
+pub struct ShaderFiles {
+    pub dir: PathBuf,
+    /// When each file was last modified, none for missing ones
+    modified: Vec<Option<SystemTime>>,
+    /// When the files were last looked at, in seconds
+    checked: f64,
+}
+impl ShaderFiles {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            modified: modified(dir),
+            dir: dir.to_path_buf(),
+            checked: 0.0,
+        }
+    }
+
+    /// Whether any file was changed, added or removed since the last look, looking at most every
+    /// [`RELOAD_INTERVAL`] at `now`, in seconds
+    pub fn changed(&mut self, now: f64) -> bool {
+        if now - self.checked < RELOAD_INTERVAL {
+            return false;
+        }
+        self.checked = now;
+        let modified = modified(&self.dir);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(dir: &Path) -> Vec<Option<SystemTime>> {
+    FILES
+        .iter()
+        .map(|file| {
+            std::fs::metadata(dir.join(file))
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .collect()
 }
@@ -73,15 +73,57 @@ impl Tri {
 impl Mesh for Tri {
     fn mesh_vertices(self) -> Vec<Vertex> {
         vec![
-            Vertex::new(self.v1, self.color.clone()),
-            Vertex::new(self.v2, self.color.clone()),
+            Vertex::new(self.v1, self.color),
+            Vertex::new(self.v2, self.color),
             Vertex::new(self.v3, self.color),
         ]
     }
 }
 
+/// Two triangles covering the rectangle whose bottom left corner is at `pos`.
+pub fn rect(pos: Vec2, size: Vec2, color: Color) -> Vec<Vertex> {
+    Quad::new(pos, size, color).mesh_vertices()
+}
+
+/// Triangles along a circle of `radius` around `center`, `thickness` wide.
+pub fn ring(center: Vec2, radius: f32, thickness: f32, color: Color) -> Vec<Vertex> {
+    arc(center, radius, thickness, 0.0, 2.0 * PI, color)
+}
+
+/// Triangles along the part of a circle of `radius` around `center` from angle `start`, sweeping
+/// `sweep` radians counterclockwise, `thickness` wide.
+pub fn arc(
+    center: Vec2,
+    radius: f32,
+    thickness: f32,
+    start: f32,
+    sweep: f32,
+    color: Color,
+) -> Vec<Vertex> {
+    // As many segments for a full circle as there always were
+    const SEGMENTS_PER_TURN: f32 = 64.0;
+    let segments = ((sweep.abs() / (2.0 * PI) * SEGMENTS_PER_TURN).ceil() as usize).max(1);
+    let point = |index: usize, radius: f32| {
+        let angle = start + index as f32 / segments as f32 * sweep;
+        center
+            + Vec2 {
+                x: angle.cos() * radius,
+                y: angle.sin() * radius,
+            }
+    };
+    let (inner, outer) = (radius - thickness / 2.0, radius + thickness / 2.0);
+    let mut vertices = Vec::with_capacity(segments * 6);
+    for index in 0..segments {
+        let (a, b) = (point(index, inner), point(index, outer));
+        let (c, d) = (point(index + 1, inner), point(index + 1, outer));
+        vertices.append(&mut Tri::new(a, b, d, color).mesh_vertices());
+        vertices.append(&mut Tri::new(a, d, c, color).mesh_vertices());
+    }
+    vertices
+}
+
 /// Represents a quad in the game world.
-/// It consists of four vertices and a color.
+/// It covers the rectangle whose bottom left corner is at `pos`, in a single color.
 /// The quad can be used for rendering larger areas or backgrounds.
 #[derive(Clone)]
 pub struct Quad {
@@ -89,51 +131,54 @@ pub struct Quad {
     size: Vec2,
     color: Color,
 }
+impl Quad {
+    pub fn new(pos: Vec2, size: Vec2, color: Color) -> Self {
+        Self { pos, size, color }
+    }
+}
 impl Mesh for Quad {
+    /// Two counter-clockwise triangles sharing the diagonal from the bottom left corner
     fn mesh_vertices(self) -> Vec<Vertex> {
-        vec![
-            Vertex::new(
-                Vec2 {
-                    x: self.pos.x,
-                    y: self.pos.y,
-                },
-                self.color.clone(),
-            ),
-            Vertex::new(
-                Vec2 {
-                    x: self.pos.x + self.size.x,
-                    y: self.pos.y,
-                },
-                self.color.clone(),
-            ),
-            Vertex::new(
-                Vec2 {
-                    x: self.pos.x,
-                    y: self.pos.y + self.size.y,
-                },
-                self.color.clone(),
-            ),
-            Vertex::new(
-                Vec2 {
-                    x: self.pos.x + self.size.y,
-                    y: self.pos.y,
-                },
-                self.color.clone(),
-            ),
-            Vertex::new(
-                Vec2 {
-                    x: self.pos.x + self.size.x,
-                    y: self.pos.y,
-                },
-                self.color.clone(),
-            ),
-            Vertex::new(
-                Vec2 {
-                    x: self.pos.x + self.size.x,
-                    y: self.pos.y + self.size.y,
-                },
-                self.color.clone(),
-            ),
-        ]
+        let bottom_left = self.pos;
+        let bottom_right = self.pos
+            + Vec2 {
+                x: self.size.x,
+                y: 0.0,
+            };
+        let top_right = self.pos + self.size;
+        let top_left = self.pos
+            + Vec2 {
+                x: 0.0,
+                y: self.size.y,
+            };
+        let mut vertices =
+            Tri::new(bottom_left, bottom_right, top_right, self.color).mesh_vertices();
+        vertices
+            .append(&mut Tri::new(bottom_left, top_right, top_left, self.color).mesh_vertices());
+        vertices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_are_two_counter_clockwise_triangles() {
+        let vertices = Quad::new(
+            Vec2 { x: 1.0, y: 2.0 },
+            Vec2 { x: 3.0, y: 1.0 },
+            Color::WHITE,
+        )
+        .mesh_vertices();
+        assert_eq!(vertices.len(), 6);
+        let mut area = 0.0;
+        for tri in vertices.chunks_exact(3) {
+            let [a, b, c] = tri else { unreachable!() };
+            let signed = ((b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)) / 2.0;
+            assert!(signed > 0.0);
+            area += signed;
+        }
+        assert_eq!(area, 3.0);
     }
 }
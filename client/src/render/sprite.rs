@@ -0,0 +1,141 @@
+//! Textured quads for players and objects that have a sprite.
+//!
+//! Sprites are PNG files named after their id, `3.png` for sprite 3, in the sprites directory.
+//! They are read once at startup, and whatever has no image is drawn as a flat shape instead.
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use anyhow::{Result, bail};
+use common::{color::Color, vec::Vec2, world::sprite::SpriteId};
+use miniquad::*;
+
+/// Vertex of a sprite, the texture coordinates running from the top left corner of the image
+#[repr(C)]
+#[derive(Clone)]
+pub struct SpriteVertex {
+    pub x: f32,
+    pub y: f32,
+    pub u: f32,
+    pub v: f32,
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+impl SpriteVertex {
+    fn new(pos: Vec2, u: f32, v: f32, tint: Color) -> Self {
+        Self {
+            x: pos.x,
+            y: pos.y,
+            u,
+            v,
+            r: tint.r,
+            g: tint.g,
+            b: tint.b,
+        }
+    }
+}
+
+/// Textures of the sprites found at startup
+pub struct Sprites {
+    textures: HashMap<SpriteId, TextureId>,
+}
+impl Sprites {
+    /// Loads every `<id>.png` in `dir`. A missing directory leaves no sprites, and images that
+    /// cannot be read are reported and skipped.
+    pub fn load(ctx: &mut dyn RenderingBackend, dir: &Path) -> Self {
+        let mut textures = HashMap::new();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Self { textures };
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().is_none_or(|extension| extension != "png") {
+                continue;
+            }
+            let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+                .map(SpriteId)
+            else {
+                eprintln!(
+                    "Skipping sprite {}, it is not named by its id",
+                    path.display()
+                );
+                continue;
+            };
+            match load_texture(ctx, &path) {
+                Ok(texture) => {
+                    textures.insert(id, texture);
+                }
+                Err(e) => eprintln!("Skipping sprite {}: {e}", path.display()),
+            }
+        }
+        Self { textures }
+    }
+
+    pub fn get(&self, id: SpriteId) -> Option<TextureId> {
+        self.textures.get(&id).copied()
+    }
+}
+
+fn load_texture(ctx: &mut dyn RenderingBackend, path: &Path) -> Result<TextureId> {
+    let image = image::open(path)?.to_rgba8();
+    let (Ok(width), Ok(height)) = (u16::try_from(image.width()), u16::try_from(image.height()))
+    else {
+        bail!("{}x{} is too large", image.width(), image.height());
+    };
+    let texture = ctx.new_texture_from_rgba8(width, height, image.as_raw());
+    // Sprites are small, blurring them when scaled up would wash them out
+    ctx.texture_set_filter(texture, FilterMode::Nearest, MipmapFilterMode::None);
+    Ok(texture)
+}
+
+/// Sprite quads of a frame, with the draw calls needed to put them on screen
+#[derive(Default)]
+pub struct SpriteBatch {
+    pub vertices: Vec<SpriteVertex>,
+    /// Texture and vertices of every draw call, in order
+    pub draws: Vec<(TextureId, Range<usize>)>,
+}
+impl SpriteBatch {
+    /// Adds `texture` stretched over the rectangle whose bottom left corner is at `pos`. Quads
+    /// with the same texture as the one before share its draw call.
+    pub fn push(&mut self, texture: TextureId, pos: Vec2, size: Vec2, tint: Color) {
+        let start = self.vertices.len();
+        let bottom_left = SpriteVertex::new(pos, 0.0, 1.0, tint);
+        let bottom_right = SpriteVertex::new(pos + Vec2 { x: size.x, y: 0.0 }, 1.0, 1.0, tint);
+        let top_right = SpriteVertex::new(pos + size, 1.0, 0.0, tint);
+        let top_left = SpriteVertex::new(pos + Vec2 { x: 0.0, y: size.y }, 0.0, 0.0, tint);
+        self.vertices.extend([
+            bottom_left.clone(),
+            bottom_right,
+            top_right.clone(),
+            bottom_left,
+            top_right,
+            top_left,
+        ]);
+        let end = self.vertices.len();
+        match self.draws.last_mut() {
+            Some((last, range)) if *last == texture => range.end = end,
+            _ => self.draws.push((texture, start..end)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbours_with_one_texture_share_a_draw() {
+        let (a, b) = (
+            TextureId::from_raw_id(RawId::OpenGl(1)),
+            TextureId::from_raw_id(RawId::OpenGl(2)),
+        );
+        let mut batch = SpriteBatch::default();
+        for texture in [a, a, b, a] {
+            batch.push(texture, Vec2::ZERO, Vec2::ONE, Color::WHITE);
+        }
+        assert_eq!(batch.vertices.len(), 24);
+        assert_eq!(batch.draws, vec![(a, 0..12), (b, 12..18), (a, 18..24)]);
+    }
+}
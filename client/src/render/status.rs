@@ -0,0 +1,37 @@
+//! Draws the connection status in the middle of the window while the client is not playing.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+
+const SCALE: f32 = 3.0;
+const PADDING: f32 = 12.0;
+const BACKGROUND: Color = Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.1,
+};
+
+pub fn draw(ui: &mut UiMesh, status: &str) {
+    let screen = ui.screen_size();
+    let size = Vec2 {
+        x: UiMesh::text_width(status, SCALE),
+        y: UiMesh::line_height(SCALE),
+    };
+    let pos = Vec2 {
+        x: (screen.x - size.x) / 2.0,
+        y: (screen.y - size.y) / 2.0,
+    };
+
+    ui.rect(
+        Vec2 {
+            x: pos.x - PADDING,
+            y: pos.y - PADDING,
+        },
+        Vec2 {
+            x: size.x + 2.0 * PADDING,
+            y: size.y + 2.0 * PADDING,
+        },
+        BACKGROUND,
+    );
+    ui.text(status, pos, SCALE, Color::WHITE);
+}
@@ -0,0 +1,23 @@
+//! Draws notices such as players joining or leaving in the top right corner, under the weapon.
+use common::vec::Vec2;
+
+use super::ui::UiMesh;
+use crate::toasts::Toasts;
+
+const SCALE: f32 = 2.0;
+const MARGIN: f32 = 10.0;
+
+pub fn draw(ui: &mut UiMesh, toasts: &Toasts) {
+    let screen = ui.screen_size();
+    let line_height = UiMesh::line_height(SCALE);
+    // The weapon takes the first line
+    let mut y = MARGIN + line_height * 2.0;
+    for toast in toasts.iter() {
+        let pos = Vec2 {
+            x: screen.x - MARGIN - UiMesh::text_width(&toast.text, SCALE),
+            y,
+        };
+        ui.text(&toast.text, pos, SCALE, toast.color);
+        y += line_height;
+    }
+}
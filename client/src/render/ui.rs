@@ -0,0 +1,87 @@
+//! Screen-space geometry for overlays drawn on top of the world.
+//! Positions are given in window pixels with the origin at the top left corner.
+use common::{color::Color, vec::Vec2};
+
+use super::{
+    shapes::{Mesh, Tri, Vertex},
+    text::{self, GLYPH_ADVANCE, GLYPH_HEIGHT},
+};
+
+/// Collects overlay vertices for a single frame
+pub struct UiMesh {
+    width: f32,
+    height: f32,
+    pub vertices: Vec<Vertex>,
+}
+impl UiMesh {
+    pub fn new((width, height): (f32, f32)) -> Self {
+        Self {
+            width: width.max(1.0),
+            height: height.max(1.0),
+            vertices: Vec::new(),
+        }
+    }
+
+    pub fn screen_size(&self) -> Vec2 {
+        Vec2 {
+            x: self.width,
+            y: self.height,
+        }
+    }
+
+    /// Converts window pixels into normalized device coordinates.
+    fn to_ndc(&self, pos: Vec2) -> Vec2 {
+        Vec2 {
+            x: pos.x / self.width * 2.0 - 1.0,
+            y: 1.0 - pos.y / self.height * 2.0,
+        }
+    }
+
+    /// Adds a filled rectangle whose top left corner is at `pos`.
+    pub fn rect(&mut self, pos: Vec2, size: Vec2, color: Color) {
+        let top_left = self.to_ndc(pos);
+        let bottom_right = self.to_ndc(pos + size);
+        let top_right = Vec2 {
+            x: bottom_right.x,
+            y: top_left.y,
+        };
+        let bottom_left = Vec2 {
+            x: top_left.x,
+            y: bottom_right.y,
+        };
+        self.vertices
+            .append(&mut Tri::new(top_left, top_right, bottom_left, color).mesh_vertices());
+        self.vertices
+            .append(&mut Tri::new(top_right, bottom_right, bottom_left, color).mesh_vertices());
+    }
+
+    /// Adds a line of text whose top left corner is at `pos`, each font pixel being `scale` window pixels.
+    pub fn text(&mut self, text: &str, pos: Vec2, scale: f32, color: Color) {
+        for (index, character) in text.chars().enumerate() {
+            let x = pos.x + (index * GLYPH_ADVANCE) as f32 * scale;
+            for (row, column, length) in text::glyph_runs(character) {
+                self.rect(
+                    Vec2 {
+                        x: x + column as f32 * scale,
+                        y: pos.y + row as f32 * scale,
+                    },
+                    Vec2 {
+                        x: length as f32 * scale,
+                        y: scale,
+                    },
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Width in window pixels taken by `text` at the given scale.
+    pub fn text_width(text: &str, scale: f32) -> f32 {
+        (text.chars().count() * GLYPH_ADVANCE) as f32 * scale
+    }
+
+    /// Height in window pixels of a line of text at the given scale.
+    pub fn line_height(scale: f32) -> f32 {
+        (GLYPH_HEIGHT + 2) as f32 * scale
+    }
+}
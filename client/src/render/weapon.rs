@@ -0,0 +1,24 @@
+//! Draws the selected weapon in the top right corner of the window, with its ammo under it.
+use common::{color::Color, vec::Vec2};
+
+use super::ui::UiMesh;
+use crate::style::HudLayout;
+
+pub fn draw(ui: &mut UiMesh, weapon: &str, ammo: Option<(&str, Color)>, layout: &HudLayout) {
+    let (scale, margin) = (layout.scale, layout.margin);
+    let screen = ui.screen_size();
+    let right_aligned = |text: &str, y| Vec2 {
+        x: screen.x - margin - UiMesh::text_width(text, scale),
+        y,
+    };
+    ui.text(
+        weapon,
+        right_aligned(weapon, margin),
+        scale,
+        layout.weapon_color,
+    );
+    if let Some((text, color)) = ammo {
+        let y = margin + UiMesh::line_height(scale);
+        ui.text(text, right_aligned(text, y), scale, color);
+    }
+}
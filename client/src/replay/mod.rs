@@ -0,0 +1,276 @@
+//! Saving the messages received from the server, so a session can be watched again later.
+//!
+//! A replay file starts with [`MAGIC`] and a [`ReplayHeader`] giving the layout of the file and the
+//! protocol version its messages were recorded with. Each message follows as the seconds since the
+//! first one and the message in its [`frame`], as it came over the network. Framed messages are
+//! read like those of a peer one version apart: types this client does not know are skipped and
+//! fields added at the end are left out, so replays stay watchable across protocol changes.
+//!
+//! Files from before the header are read by [`v1`]. When some messages of a replay cannot be read
+//! at all, opening it fails listing every message type that did not fit and why, rather than
+//! playing part of the session.
+//!
+//! [`frame`]: common::message::frame
+mod v1;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
+use bincode::{Decode, Encode, config};
+use common::{
+    message::{
+        ServerMessage,
+        frame::{self, Frame, HEADER_SIZE, MAX_FRAME_SIZE},
+    },
+    version::{PROTOCOL_VERSION, Version},
+};
+
+/// Start of every replay file with a header
+const MAGIC: &[u8; 8] = b"MPREPLAY";
+/// Layout of the replay files written, counting headerless files as the first
+const REPLAY_FORMAT: u16 = 2;
+
+#[derive(Decode, Encode)]
+struct ReplayHeader {
+    format: u16,
+    protocol: Version,
+}
+
+#[derive(Decode, Encode)]
+struct ReplayFrame {
+    /// Seconds since the start of the recording
+    time: f64,
+    message: ServerMessage,
+}
+
+/// Appends received messages to a replay file
+pub struct ReplayWriter {
+    file: BufWriter<File>,
+    start: Option<f64>,
+}
+impl ReplayWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        let header = ReplayHeader {
+            format: REPLAY_FORMAT,
+            protocol: PROTOCOL_VERSION,
+        };
+        bincode::encode_into_std_write(header, &mut file, config::standard())?;
+        file.flush()?;
+        Ok(Self { file, start: None })
+    }
+
+    pub fn write(&mut self, now: f64, message: &ServerMessage) -> Result<()> {
+        let start = *self.start.get_or_insert(now);
+        let framed = message.encode()?;
+        bincode::encode_into_std_write(now - start, &mut self.file, config::standard())?;
+        self.file.write_all(&framed)?;
+        // The game can be closed at any moment, so nothing is left in the buffer
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Hands out the messages of a replay file as playback reaches them
+pub struct ReplayReader {
+    frames: VecDeque<ReplayFrame>,
+    start: Option<f64>,
+}
+impl ReplayReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let frames =
+            decode(&bytes).with_context(|| format!("Cannot play replay {}", path.display()))?;
+        Ok(Self {
+            frames,
+            start: None,
+        })
+    }
+
+    /// Messages whose time has come, playback starting with the first call.
+    pub fn due(&mut self, now: f64) -> Vec<ServerMessage> {
+        let start = *self.start.get_or_insert(now);
+        let mut due = Vec::new();
+        while let Some(frame) = self.frames.pop_front_if(|frame| frame.time <= now - start) {
+            due.push(frame.message);
+        }
+        due
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Messages of a replay file of any format
+fn decode(bytes: &[u8]) -> Result<VecDeque<ReplayFrame>> {
+    let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+        return v1::decode(bytes);
+    };
+    let config = config::standard();
+    let (header, read): (ReplayHeader, usize) =
+        bincode::decode_from_slice(bytes, config).context("The replay header is cut short")?;
+    if header.format != REPLAY_FORMAT {
+        bail!(
+            "Replay format {} is not one this client reads, it reads up to {REPLAY_FORMAT}",
+            header.format
+        );
+    }
+
+    let mut bytes = &bytes[read..];
+    let mut frames = VecDeque::new();
+    let mut skipped = 0;
+    // Messages that did not decode by type id, with how many and the first reason
+    let mut failed = BTreeMap::<u16, (usize, String)>::new();
+    // A recording cut short can end anywhere, playback stops where it does
+    while let Ok((time, read)) = bincode::decode_from_slice::<f64, _>(bytes, config) {
+        bytes = &bytes[read..];
+        let len = match frame::decode_auto::<ServerMessage>(bytes) {
+            Ok(Frame::Message(message, len)) => {
+                frames.push_back(ReplayFrame { time, message });
+                len
+            }
+            Ok(Frame::Unknown { len, .. }) => {
+                skipped += 1;
+                len
+            }
+            Ok(Frame::Incomplete) => break,
+            Err(e) => {
+                let Some((type_id, len)) = frame_header(bytes) else {
+                    return Err(e);
+                };
+                failed.entry(type_id).or_insert((0, e.to_string())).0 += 1;
+                len
+            }
+        };
+        bytes = &bytes[len..];
+    }
+
+    if !failed.is_empty() {
+        let failed: Vec<_> = failed
+            .into_iter()
+            .map(|(type_id, (count, reason))| {
+                format!("message type {type_id}, {count} time(s): {reason}")
+            })
+            .collect();
+        bail!(
+            "Recorded with protocol {}, some messages do not fit those of {PROTOCOL_VERSION}:\n  {}",
+            header.protocol,
+            failed.join("\n  ")
+        );
+    }
+    if skipped > 0 {
+        eprintln!(
+            "Skipped {skipped} message(s) of types protocol {PROTOCOL_VERSION} does not have, \
+             the replay was recorded with {}",
+            header.protocol
+        );
+    }
+    Ok(frames)
+}
+
+/// Type id and size of the whole frame starting `bytes`, if the frame is all there
+fn frame_header(bytes: &[u8]) -> Option<(u16, usize)> {
+    let header = bytes.get(..HEADER_SIZE)?;
+    let type_id = u16::from_le_bytes([header[0], header[1]]);
+    let len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    (len <= MAX_FRAME_SIZE && bytes.len() >= HEADER_SIZE + len)
+        .then_some((type_id, HEADER_SIZE + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(protocol: Version) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let header = ReplayHeader {
+            format: REPLAY_FORMAT,
+            protocol,
+        };
+        bytes.extend(bincode::encode_to_vec(header, config::standard()).unwrap());
+        bytes
+    }
+
+    fn push_frame(bytes: &mut Vec<u8>, time: f64, framed: &[u8]) {
+        bytes.extend(bincode::encode_to_vec(time, config::standard()).unwrap());
+        bytes.extend_from_slice(framed);
+    }
+
+    fn messages(frames: VecDeque<ReplayFrame>) -> Vec<(f64, ServerMessage)> {
+        frames
+            .into_iter()
+            .map(|frame| (frame.time, frame.message))
+            .collect()
+    }
+
+    #[test]
+    fn unknown_types_are_skipped_and_cut_off_ends_dropped() {
+        let mut bytes = header(PROTOCOL_VERSION);
+        push_frame(&mut bytes, 0.0, &ServerMessage::Ping.encode().unwrap());
+        // A type from a newer protocol
+        push_frame(&mut bytes, 0.5, &[200, 0, 2, 0, 0, 0, 1, 2]);
+        push_frame(
+            &mut bytes,
+            1.0,
+            &ServerMessage::QueuePosition(3).encode().unwrap(),
+        );
+        let framed = ServerMessage::Disconnect.encode().unwrap();
+        push_frame(&mut bytes, 1.5, &framed[..framed.len() - 1]);
+        assert_eq!(
+            messages(decode(&bytes).unwrap()),
+            vec![
+                (0.0, ServerMessage::Ping),
+                (1.0, ServerMessage::QueuePosition(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn messages_that_do_not_fit_are_listed() {
+        let protocol = Version {
+            major: PROTOCOL_VERSION.major + 1,
+            minor: 0,
+            patch: 0,
+        };
+        let mut bytes = header(protocol);
+        push_frame(&mut bytes, 0.0, &ServerMessage::Ping.encode().unwrap());
+        // Queue positions that became too large for the field they are read into, twice
+        for time in [0.1, 0.2] {
+            let mut framed = vec![5, 0, 9, 0, 0, 0, 0xfd];
+            framed.extend((1u64 << 40).to_le_bytes());
+            push_frame(&mut bytes, time, &framed);
+        }
+        let Err(error) = decode(&bytes) else {
+            panic!("A replay with messages that do not fit was read");
+        };
+        let error = error.to_string();
+        assert!(error.contains(&format!("protocol {protocol}")), "{error}");
+        assert!(error.contains("message type 5, 2 time(s)"), "{error}");
+    }
+
+    #[test]
+    fn files_without_a_header_are_read() {
+        let mut bytes = Vec::new();
+        for (time, message) in [
+            (0.0, ServerMessage::Ping),
+            (0.25, ServerMessage::QueuePosition(1)),
+        ] {
+            let frame = ReplayFrame { time, message };
+            bytes.extend(bincode::encode_to_vec(frame, config::standard()).unwrap());
+        }
+        assert_eq!(
+            messages(decode(&bytes).unwrap()),
+            vec![
+                (0.0, ServerMessage::Ping),
+                (0.25, ServerMessage::QueuePosition(1))
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Replays from before they had a header, the first format: [`ReplayFrame`]s one after the other,
+//! each message encoded whole rather than in a frame. Such a file cannot say which protocol its
+//! messages follow, the last to write them was [`PROTOCOL`].
+use std::collections::VecDeque;
+
+use anyhow::{Result, anyhow};
+use bincode::{config, error::DecodeError};
+use common::version::{PROTOCOL_VERSION, Version};
+
+use super::ReplayFrame;
+
+/// Protocol of the messages in headerless replays. Messages of this version decode as they are,
+/// a change to them has to keep their old layout here to convert from.
+pub const PROTOCOL: Version = Version {
+    major: 1,
+    minor: 0,
+    patch: 0,
+};
+
+pub fn decode(mut bytes: &[u8]) -> Result<VecDeque<ReplayFrame>> {
+    let mut frames = VecDeque::new();
+    loop {
+        match bincode::decode_from_slice::<ReplayFrame, _>(bytes, config::standard()) {
+            Ok((frame, read)) => {
+                frames.push_back(frame);
+                bytes = &bytes[read..];
+            }
+            // The end of the file, a recording cut short can also end halfway through a frame
+            Err(DecodeError::UnexpectedEnd { .. }) => break,
+            Err(e) => {
+                return Err(anyhow!(
+                    "Replay without a header, taken as protocol {PROTOCOL}: message {} does not \
+                     fit those of {PROTOCOL_VERSION}: {e}",
+                    frames.len() + 1
+                ));
+            }
+        }
+    }
+    Ok(frames)
+}
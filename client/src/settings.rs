@@ -0,0 +1,49 @@
+//! Client settings that can be changed while the game is running.
+use crate::{cli::Cli, haptics::RumbleSettings};
+
+/// Smallest manual interpolation delay, in seconds
+pub const MIN_INTERPOLATION_DELAY: f64 = 0.0;
+/// Largest interpolation delay, in seconds
+pub const MAX_INTERPOLATION_DELAY: f64 = 0.5;
+/// Step used when adjusting the interpolation delay from the keyboard, in seconds
+pub const INTERPOLATION_DELAY_STEP: f64 = 0.01;
+
+/// How far in the past remote entities are rendered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InterpolationDelay {
+    /// Picked from the measured snapshot interval and jitter
+    Auto,
+    /// Fixed delay in seconds
+    Manual(f64),
+}
+impl InterpolationDelay {
+    /// Moves to a manual delay `step` seconds away from `current`, the delay in effect right now.
+    pub fn adjust(&mut self, current: f64, step: f64) {
+        *self =
+            Self::Manual((current + step).clamp(MIN_INTERPOLATION_DELAY, MAX_INTERPOLATION_DELAY));
+    }
+}
+
+pub struct Settings {
+    pub interpolation_delay: InterpolationDelay,
+    pub rumble: RumbleSettings,
+    /// Share of the stick's range that does not move the player, see [`crate::input`]
+    pub dead_zone: f32,
+    /// Whether music plays at all
+    pub music: bool,
+}
+impl Settings {
+    pub fn from_cli(cli: &Cli) -> Self {
+        Self {
+            interpolation_delay: match cli.interp_delay {
+                Some(ms) => InterpolationDelay::Manual(
+                    (ms as f64 / 1000.0).clamp(MIN_INTERPOLATION_DELAY, MAX_INTERPOLATION_DELAY),
+                ),
+                None => InterpolationDelay::Auto,
+            },
+            rumble: RumbleSettings::without(&cli.no_rumble),
+            dead_zone: cli.dead_zone,
+            music: !cli.no_music,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Applying the entity snapshots the server sends to the client's copy of the world.
+//!
+//! Every snapshot replaces the players, projectiles and pickups the client knows of. Players left
+//! out of a snapshot are gone, or far from ours on servers with an interest radius. The local
+//! player is the exception: it takes the state prediction worked out from the snapshot.
+use common::world::entities::{Entities, Player};
+
+/// Copies `snapshot` into `world`, with `local` in place of the local player when it is in it
+pub fn apply(world: &mut Entities, snapshot: &Entities, local: Option<(u64, Player)>) {
+    world.players = snapshot.players.clone();
+    if let Some((id, player)) = local {
+        world.players.insert(id, player);
+    }
+    world.projectiles.clone_from(&snapshot.projectiles);
+    world.pickups.clone_from(&snapshot.pickups);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use common::{
+        vec::Vec2,
+        world::{
+            combat::{Projectile, ProjectileKind},
+            pickups::{Pickup, PickupKind},
+        },
+    };
+    use test_utils::world::player;
+
+    use super::*;
+
+    fn empty() -> Entities {
+        Entities {
+            players: HashMap::new(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        }
+    }
+
+    /// A snapshot with the local player, a remote one, an explosive shot and an armor pickup
+    fn snapshot() -> Entities {
+        let mut entities = empty();
+        entities.players.insert(1, player("local", Vec2::ZERO));
+        entities.players.insert(2, player("remote", Vec2::ONE));
+        entities.projectiles.push(Projectile {
+            owner: 2,
+            kind: ProjectileKind::Explosive,
+            pos: Vec2::ONE,
+            vel: Vec2 { x: 1.0, y: 0.0 },
+            ttl: 1.0,
+            bounces: 0,
+            pierced: Vec::new(),
+        });
+        entities.pickups = Pickup::at(&[Vec2::ZERO], PickupKind::Armor);
+        entities
+    }
+
+    #[test]
+    fn snapshots_bring_projectiles_and_pickups() {
+        let mut world = empty();
+        let snapshot = snapshot();
+        apply(&mut world, &snapshot, None);
+        assert_eq!(world.projectiles, snapshot.projectiles);
+        assert_eq!(world.pickups, snapshot.pickups);
+
+        // Shots that ended and pickups taken since go away with the next snapshot
+        apply(&mut world, &empty(), None);
+        assert!(world.projectiles.is_empty());
+        assert!(world.pickups.is_empty());
+    }
+
+    #[test]
+    fn the_local_player_takes_the_predicted_state() {
+        let mut world = empty();
+        world.players.insert(3, player("gone", Vec2::ZERO));
+        let predicted = player("local", Vec2 { x: 5.0, y: 0.0 });
+        apply(&mut world, &snapshot(), Some((1, predicted.clone())));
+        assert_eq!(world.players[&1], predicted);
+        assert_eq!(world.players[&2].username, "remote");
+        assert!(!world.players.contains_key(&3));
+    }
+}
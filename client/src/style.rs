@@ -0,0 +1,303 @@
+//! How the game looks: sizes of shapes, the palette, the layout of the heads-up display and how
+//! each weapon's shots are drawn.
+//!
+//! It all comes from a TOML file read at startup, `style.toml` unless `--style` says otherwise.
+//! Every value can be left out and keeps its built-in default, so the file only needs what should
+//! look different:
+//!
+//! ```toml
+//! [palette]
+//! armor = { r = 0.3, g = 0.6, b = 1.0 }
+//!
+//! [hud]
+//! health_bar_width = 200.0
+//!
+//! [weapons.explosive]
+//! size = 0.04
+//! ```
+//!
+//! Debug builds watch the file and pick up changes while the game runs, so the look can be tuned
+//! without restarting. A file that does not parse is reported and the previous look kept.
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use common::{color::Color, vec::Vec2, world::combat::ProjectileKind};
+use serde::Deserialize;
+
+/// Seconds between looks at whether the style file changed, in debug builds
+const RELOAD_INTERVAL: f64 = 0.5;
+
+const fn rgb(r: f32, g: f32, b: f32) -> Color {
+    Color { r, g, b }
+}
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub shapes: Shapes,
+    pub palette: Palette,
+    pub hud: HudLayout,
+    pub weapons: Weapons,
+}
+
+/// Sizes of what is drawn in the world, in world units
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Shapes {
+    /// Distance from the center of a player's triangle to its corners
+    pub player_size: f32,
+    /// Width and height of player sprites
+    pub player_sprite_size: f32,
+    /// Size of the health and armor bars drawn above players
+    pub bar_size: Vec2,
+    /// Height above players of the health bar, and the space between it and the armor bar
+    pub bar_offset: f32,
+    pub bar_gap: f32,
+    /// Height above players where their name is drawn
+    pub name_offset: f32,
+    /// Text size of names and damage numbers, in pixels per font pixel
+    pub name_scale: f32,
+    /// Thickness of the arena and storm edges, and of checkpoints
+    pub boundary_thickness: f32,
+    /// Thickness of the rings explosions leave
+    pub blast_thickness: f32,
+}
+impl Default for Shapes {
+    fn default() -> Self {
+        Self {
+            player_size: 0.05,
+            player_sprite_size: 0.1,
+            bar_size: Vec2 { x: 0.1, y: 0.012 },
+            bar_offset: 0.08,
+            bar_gap: 0.004,
+            name_offset: 0.115,
+            name_scale: 1.5,
+            boundary_thickness: 0.01,
+            blast_thickness: 0.015,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+    /// Armor bars and pickups
+    pub armor: Color,
+    pub ammo_pickup: Color,
+    pub health: Color,
+    /// Behind the health bar, showing the health lost
+    pub health_lost: Color,
+    /// Behind the armor bar
+    pub armor_lost: Color,
+    /// Edge of the arena
+    pub arena: Color,
+    /// Edge of the storm's safe zone
+    pub storm: Color,
+    /// Attractors pulling things in, and those pushing them away
+    pub attractor: Color,
+    pub repulsor: Color,
+    /// Blasts and the particles they throw
+    pub explosion: Color,
+    pub names: Color,
+    /// Checkpoints of the time trial, and the first one where laps start and end
+    pub checkpoint: Color,
+    pub start_line: Color,
+    /// Ghost of the fastest lap, dim enough to read as see-through against the dark background
+    pub ghost: Color,
+}
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            armor: rgb(0.3, 0.6, 1.0),
+            ammo_pickup: rgb(0.9, 0.75, 0.2),
+            health: Color::GREEN,
+            health_lost: Color::RED,
+            armor_lost: Color::BLACK,
+            arena: Color::RED,
+            storm: rgb(0.6, 0.2, 0.9),
+            attractor: rgb(0.2, 0.7, 0.8),
+            repulsor: rgb(0.9, 0.5, 0.2),
+            explosion: rgb(1.0, 0.5, 0.1),
+            names: Color::WHITE,
+            checkpoint: rgb(0.3, 0.8, 0.4),
+            start_line: Color::WHITE,
+            ghost: rgb(0.3, 0.3, 0.35),
+        }
+    }
+}
+
+/// Layout of the heads-up display, in pixels
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HudLayout {
+    /// Text size, in pixels per font pixel
+    pub scale: f32,
+    /// Space between the display and the edges of the window
+    pub margin: f32,
+    pub health_bar_width: f32,
+    pub health_bar_background: Color,
+    pub health_bar_fill: Color,
+    /// Ping, frame rate and player count
+    pub stats_color: Color,
+    /// Name of the selected weapon
+    pub weapon_color: Color,
+}
+impl Default for HudLayout {
+    fn default() -> Self {
+        Self {
+            scale: 2.0,
+            margin: 10.0,
+            health_bar_width: 160.0,
+            health_bar_background: rgb(0.35, 0.05, 0.05),
+            health_bar_fill: rgb(0.15, 0.6, 0.2),
+            stats_color: rgb(0.8, 0.8, 0.8),
+            weapon_color: Color::WHITE,
+        }
+    }
+}
+
+/// How a weapon's shots are drawn
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ShotLook {
+    /// Distance from the center of the shot's triangle to its corners, in world units
+    pub size: f32,
+    pub color: Color,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weapons {
+    pub bullet: ShotLook,
+    pub ricochet: ShotLook,
+    pub piercing: ShotLook,
+    pub explosive: ShotLook,
+}
+impl Default for Weapons {
+    fn default() -> Self {
+        Self {
+            bullet: ShotLook {
+                size: 0.015,
+                color: Color::WHITE,
+            },
+            ricochet: ShotLook {
+                size: 0.015,
+                color: Color::GREEN,
+            },
+            piercing: ShotLook {
+                size: 0.02,
+                color: Color::BLUE,
+            },
+            explosive: ShotLook {
+                size: 0.025,
+                color: Palette::default().explosion,
+            },
+        }
+    }
+}
+impl Weapons {
+    pub fn shot(&self, kind: ProjectileKind) -> ShotLook {
+        match kind {
+            ProjectileKind::Bullet => self.bullet,
+            ProjectileKind::Ricochet => self.ricochet,
+            ProjectileKind::Piercing => self.piercing,
+            ProjectileKind::Explosive => self.explosive,
+        }
+    }
+}
+
+/// The style file and what was read from it, read again when it changes in debug builds
+pub struct StyleFile {
+    path: PathBuf,
+    pub style: Style,
+    /// When the file was last modified as of the last read, none when there was no file
+    modified: Option<SystemTime>,
+    /// When the file was last looked at, in seconds
+    checked: f64,
+}
+impl StyleFile {
+    /// Reads the style at `path`, or the built-in look when there is no file
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut file = Self {
+            path: path.to_path_buf(),
+            style: Style::default(),
+            modified: None,
+            checked: 0.0,
+        };
+        file.read()?;
+        Ok(file)
+    }
+
+    fn read(&mut self) -> Result<()> {
+        self.modified = modified(&self.path);
+        self.style = match std::fs::read_to_string(&self.path) {
+            Ok(text) => toml::from_str(&text)
+                .with_context(|| format!("Could not read style from {}", self.path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Style::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(())
+    }
+
+    /// Reads the file again if it changed since, looking at most every [`RELOAD_INTERVAL`] at
+    /// `now`, in seconds. Only debug builds do, release builds keep the look they started with.
+    pub fn reload_if_changed(&mut self, now: f64) {
+        if !cfg!(debug_assertions) || now - self.checked < RELOAD_INTERVAL {
+            return;
+        }
+        self.checked = now;
+        if modified(&self.path) == self.modified {
+            return;
+        }
+        let previous = self.style.clone();
+        match self.read() {
+            Ok(()) => println!("Reloaded the style from {}", self.path.display()),
+            Err(e) => {
+                // Keeps the look until the file is fixed, without complaining again until then
+                eprintln!("{e:#}");
+                self.style = previous;
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_left_out_keep_their_defaults() {
+        let style: Style = toml::from_str(
+            r#"
+            [palette]
+            armor = { r = 1.0, g = 0.0, b = 0.0 }
+            [hud]
+            health_bar_width = 200.0
+            [weapons.explosive]
+            size = 0.04
+            color = { r = 0.0, g = 0.0, b = 0.0 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(style.palette.armor, Color::RED);
+        assert_eq!(style.palette.storm, Palette::default().storm);
+        assert_eq!(style.hud.health_bar_width, 200.0);
+        assert_eq!(style.hud.margin, HudLayout::default().margin);
+        assert_eq!(style.weapons.shot(ProjectileKind::Explosive).size, 0.04);
+        assert_eq!(style.weapons.bullet, Weapons::default().bullet);
+        assert_eq!(style.shapes, Shapes::default());
+
+        assert!(
+            toml::from_str::<Style>("[palette]\narmour = { r = 1.0, g = 0.0, b = 0.0 }").is_err()
+        );
+    }
+}
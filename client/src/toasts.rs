@@ -0,0 +1,97 @@
+//! Notices that show up for a few seconds, such as another player joining or leaving.
+use std::collections::VecDeque;
+
+use common::color::Color;
+
+/// Seconds a notice stays up
+const TOAST_TIME: f32 = 4.0;
+/// Notices shown at once, the oldest goes when another comes in
+const MAX_TOASTS: usize = 4;
+const JOINED_COLOR: Color = Color {
+    r: 0.4,
+    g: 0.9,
+    b: 0.4,
+};
+const LEFT_COLOR: Color = Color {
+    r: 0.7,
+    g: 0.7,
+    b: 0.7,
+};
+/// Notices from the server itself, such as the welcome
+const ANNOUNCEMENT_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.85,
+    b: 0.4,
+};
+
+pub struct Toast {
+    pub text: String,
+    pub color: Color,
+    /// Seconds left before it goes away
+    ttl: f32,
+}
+
+#[derive(Default)]
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+}
+impl Toasts {
+    pub fn push(&mut self, text: String, color: Color) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast {
+            text,
+            color,
+            ttl: TOAST_TIME,
+        });
+    }
+
+    /// Notices come worded by the [`Locale`](crate::locale::Locale), only their color is picked
+    /// here
+    pub fn player_joined(&mut self, text: String) {
+        self.push(text, JOINED_COLOR);
+    }
+
+    pub fn player_left(&mut self, text: String) {
+        self.push(text, LEFT_COLOR);
+    }
+
+    pub fn announcement(&mut self, text: String) {
+        self.push(text, ANNOUNCEMENT_COLOR);
+    }
+
+    /// Ages the notices by `dt` seconds, dropping those whose time is up
+    pub fn update(&mut self, dt: f32) {
+        for toast in &mut self.toasts {
+            toast.ttl -= dt;
+        }
+        self.toasts.retain(|toast| toast.ttl > 0.0);
+    }
+
+    /// Notices on screen, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Toast> {
+        self.toasts.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toasts_go_away_after_a_while() {
+        let mut toasts = Toasts::default();
+        for name in ["a", "b", "c", "d", "e"] {
+            toasts.player_joined(format!("{name} joined"));
+        }
+        let texts: Vec<_> = toasts.iter().map(|toast| toast.text.as_str()).collect();
+        assert_eq!(texts, ["b joined", "c joined", "d joined", "e joined"]);
+
+        toasts.update(TOAST_TIME / 2.0);
+        toasts.player_left(String::from("b left"));
+        toasts.update(TOAST_TIME / 2.0);
+        let texts: Vec<_> = toasts.iter().map(|toast| toast.text.as_str()).collect();
+        assert_eq!(texts, ["b left"]);
+    }
+}
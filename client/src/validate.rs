@@ -0,0 +1,322 @@
+//! Checks on messages from the server before they are applied.
+//!
+//! A buggy or malicious server should not be able to crash the client or fill the screen with
+//! garbage. Every message goes through [`check`] first, and whatever fails it is logged and
+//! dropped: NaN or infinite numbers, more entities than any real match has and overlong strings.
+use anyhow::{Result, bail, ensure};
+
+use common::{
+    color::Color,
+    message::{MAX_CHAT_LENGTH, MAX_USERNAME_LENGTH, ServerMessage, announcement::Announcement},
+    tunables::Tunables,
+    vec::Vec2,
+    world::{
+        arena::Arena,
+        entities::{Entities, Player},
+        environment::{Environment, TileMap},
+    },
+};
+
+/// Most players a snapshot or scoreboard may hold
+const MAX_PLAYERS: usize = 1024;
+/// Most projectiles a snapshot may hold
+const MAX_PROJECTILES: usize = 16 * 1024;
+/// Most pickups, objects, spawn points or pickup points a world may hold
+const MAX_ITEMS: usize = 16 * 1024;
+/// Most tiles a map may have
+const MAX_TILES: usize = 4096 * 4096;
+/// Longest redirect address, token or session, in characters
+const MAX_ADDRESS_LENGTH: usize = 256;
+/// Longest map or mode name, in characters
+const MAX_NAME_LENGTH: usize = 256;
+
+/// Returns why a message from the server cannot be applied, if it cannot.
+pub fn check(msg: &ServerMessage) -> Result<()> {
+    match msg {
+        ServerMessage::ConnectionAccepted {
+            username, session, ..
+        } => {
+            check_text(username, MAX_USERNAME_LENGTH)?;
+            check_text(session, MAX_ADDRESS_LENGTH)
+        }
+        ServerMessage::Redirect { address, token } => {
+            check_text(address, MAX_ADDRESS_LENGTH)?;
+            check_text(token, MAX_ADDRESS_LENGTH)
+        }
+        ServerMessage::UpdateObjects(environment) => check_environment(environment),
+        ServerMessage::UpdateEntities { time, entities, .. } => {
+            ensure!(time.is_finite(), "Server time {time} is not finite");
+            check_entities(entities)
+        }
+        ServerMessage::UpdateTunables(tunables) => check_tunables(tunables),
+        ServerMessage::PlayerDied { assists, .. } => check_count(assists.len(), MAX_PLAYERS),
+        ServerMessage::UpdateScoreboard(scoreboard) => {
+            check_count(scoreboard.scores.len(), MAX_PLAYERS)
+        }
+        ServerMessage::UpdateArena(Some(arena)) => check_arena(arena),
+        ServerMessage::UpdateStorm(Some(zone)) => {
+            check_arena(&zone.area)?;
+            check_floats(&[zone.damage])
+        }
+        ServerMessage::PlayerHit { armor, health, .. } => check_floats(&[*armor, *health]),
+        ServerMessage::ExplosionEvent { pos, radius, .. } => {
+            check_points(&[*pos])?;
+            check_floats(&[*radius])
+        }
+        ServerMessage::RoundOver { placements, .. } => check_count(placements.len(), MAX_PLAYERS),
+        ServerMessage::LapCompleted { time, .. } | ServerMessage::RaceFinished { time, .. } => {
+            check_floats(&[*time])
+        }
+        ServerMessage::LapTimes(times) => {
+            check_count(times.len(), MAX_PLAYERS)?;
+            for (username, time) in times {
+                check_text(username, MAX_USERNAME_LENGTH)?;
+                check_floats(&[*time])?;
+            }
+            Ok(())
+        }
+        ServerMessage::ChatBroadcast { text, .. }
+        | ServerMessage::Announcement(Announcement::Text(text))
+        | ServerMessage::Announcement(Announcement::Welcome { server_name: text }) => {
+            check_text(text, MAX_CHAT_LENGTH)
+        }
+        ServerMessage::PlayerJoined { username, .. }
+        | ServerMessage::PlayerLeft { username, .. } => check_text(username, MAX_USERNAME_LENGTH),
+        ServerMessage::MatchInfo { map, mode } => {
+            check_text(map, MAX_NAME_LENGTH)?;
+            check_text(mode, MAX_NAME_LENGTH)
+        }
+        ServerMessage::Pong {
+            client_time,
+            server_time,
+        } => {
+            ensure!(
+                client_time.is_finite() && server_time.is_finite(),
+                "Pong times {client_time} and {server_time} are not finite"
+            );
+            Ok(())
+        }
+        ServerMessage::Ping
+        | ServerMessage::Status(_)
+        | ServerMessage::Disconnect
+        | ServerMessage::PasswordFailed
+        | ServerMessage::ServerFull
+        | ServerMessage::SessionExpired
+        | ServerMessage::Banned
+        | ServerMessage::SigningAgreed(_)
+        | ServerMessage::CheckpointReached { .. }
+        | ServerMessage::RaceStarted { .. }
+        | ServerMessage::QueuePosition(_)
+        | ServerMessage::UpdateArena(None)
+        | ServerMessage::UpdateStorm(None)
+        | ServerMessage::IncompatibleVersion(_)
+        | ServerMessage::Announcement(Announcement::ShuttingDown) => Ok(()),
+    }
+}
+
+fn check_count(count: usize, max: usize) -> Result<()> {
+    ensure!(count <= max, "{count} items, at most {max} are allowed");
+    Ok(())
+}
+
+fn check_text(text: &str, max: usize) -> Result<()> {
+    let length = text.chars().count();
+    ensure!(
+        length <= max,
+        "Text of {length} characters, at most {max} are allowed"
+    );
+    Ok(())
+}
+
+fn check_floats(values: &[f32]) -> Result<()> {
+    if let Some(value) = values.iter().find(|value| !value.is_finite()) {
+        bail!("Number {value} is not finite");
+    }
+    Ok(())
+}
+
+fn check_points(points: &[Vec2]) -> Result<()> {
+    if let Some(point) = points.iter().find(|point| !point.is_finite()) {
+        bail!("Position {point:?} is not finite");
+    }
+    Ok(())
+}
+
+fn check_color(color: Color) -> Result<()> {
+    check_floats(&[color.r, color.g, color.b])
+}
+
+fn check_player(player: &Player) -> Result<()> {
+    check_text(&player.username, MAX_USERNAME_LENGTH)?;
+    check_color(player.color)?;
+    check_points(&[player.pos, player.vel])?;
+    check_floats(&[player.health, player.armor, player.respawn_in])?;
+    let reload = player.ammo.reloading.map_or(0.0, |reload| reload.left);
+    check_floats(&[reload])
+}
+
+fn check_entities(entities: &Entities) -> Result<()> {
+    check_count(entities.players.len(), MAX_PLAYERS)?;
+    check_count(entities.projectiles.len(), MAX_PROJECTILES)?;
+    check_count(entities.pickups.len(), MAX_ITEMS)?;
+    for player in entities.players.values() {
+        check_player(player)?;
+    }
+    for projectile in &entities.projectiles {
+        check_points(&[projectile.pos, projectile.vel])?;
+        check_floats(&[projectile.ttl])?;
+        check_count(projectile.pierced.len(), MAX_PLAYERS)?;
+    }
+    for pickup in &entities.pickups {
+        check_points(&[pickup.pos])?;
+        check_floats(&[pickup.respawn_in])?;
+    }
+    Ok(())
+}
+
+fn check_tiles(tiles: &TileMap) -> Result<()> {
+    check_points(&[tiles.origin])?;
+    ensure!(
+        tiles.tile_size.is_finite() && tiles.tile_size > 0.0,
+        "Tile size {} is not positive",
+        tiles.tile_size
+    );
+    let cells = tiles.width as usize * tiles.height as usize;
+    check_count(cells, MAX_TILES)?;
+    ensure!(
+        tiles.tiles.len() == cells,
+        "Map of {}x{} tiles holds {} tiles",
+        tiles.width,
+        tiles.height,
+        tiles.tiles.len()
+    );
+    check_count(tiles.kinds.len(), u16::MAX as usize + 1)?;
+    if let Some(tile) = tiles
+        .tiles
+        .iter()
+        .find(|tile| **tile as usize >= tiles.kinds.len())
+    {
+        bail!("Tile id {tile} has no kind");
+    }
+    for kind in &tiles.kinds {
+        check_color(kind.color)?;
+    }
+    check_count(tiles.spawn_points.len(), MAX_ITEMS)?;
+    check_count(tiles.pickup_points.len(), MAX_ITEMS)?;
+    check_count(tiles.ammo_points.len(), MAX_ITEMS)?;
+    check_points(&tiles.spawn_points)?;
+    check_points(&tiles.pickup_points)?;
+    check_points(&tiles.ammo_points)
+}
+
+fn check_environment(environment: &Environment) -> Result<()> {
+    check_tiles(&environment.tiles)?;
+    check_count(environment.objects.len(), MAX_ITEMS)?;
+    for object in &environment.objects {
+        check_points(&[object.pos, object.size])?;
+        check_color(object.color)?;
+    }
+    check_count(environment.attractors.len(), MAX_ITEMS)?;
+    for attractor in &environment.attractors {
+        check_points(&[attractor.pos])?;
+        check_floats(&[attractor.strength, attractor.radius])?;
+    }
+    check_count(environment.checkpoints.len(), MAX_ITEMS)?;
+    for checkpoint in &environment.checkpoints {
+        check_points(&[checkpoint.pos])?;
+        check_floats(&[checkpoint.radius])?;
+    }
+    Ok(())
+}
+
+fn check_arena(arena: &Arena) -> Result<()> {
+    check_points(&[arena.center])?;
+    check_floats(&[arena.start_radius, arena.end_radius])
+}
+
+/// Prediction simulates with these, so anything non-finite would corrupt the local player
+fn check_tunables(tunables: &Tunables) -> Result<()> {
+    let physics = &tunables.physics;
+    check_floats(&[
+        physics.friction,
+        physics.player_radius,
+        physics.max_speed,
+        physics.push_stiffness,
+    ])?;
+    let combat = &tunables.combat;
+    check_floats(&[
+        combat.max_health,
+        combat.projectile_damage,
+        combat.projectile_speed,
+        combat.projectile_lifetime,
+        combat.projectile_radius,
+        combat.knockback,
+        combat.fire_cooldown,
+        combat.respawn_delay,
+        combat.restitution,
+        combat.blast_radius,
+        combat.blast_damage,
+        combat.blast_knockback,
+        combat.max_armor,
+        combat.armor_absorption,
+        combat.armor_pickup,
+        combat.pickup_respawn,
+    ])?;
+    let race = &tunables.race;
+    check_floats(&[race.finish_time, race.round_delay])
+}
+
+#[cfg(test)]
+mod tests {
+    use common::world::{GameWorld, ammo::Ammo};
+
+    use super::*;
+
+    fn valid_player() -> Player {
+        Player {
+            username: String::from("tester"),
+            color: Color::WHITE,
+            pos: Vec2::ZERO,
+            vel: Vec2::ZERO,
+            last_input_seq: 0,
+            input_ticks: 0,
+            health: 100.0,
+            armor: 0.0,
+            respawn_in: 0.0,
+            sprite: None,
+            ammo: Ammo::default(),
+        }
+    }
+
+    fn snapshot(player: Player) -> ServerMessage {
+        let mut entities = GameWorld::new().entities;
+        entities.players.insert(1, player);
+        ServerMessage::UpdateEntities {
+            tick: 1,
+            time: 0.0,
+            entities,
+        }
+    }
+
+    #[test]
+    fn bad_snapshots_are_caught() {
+        assert!(check(&snapshot(valid_player())).is_ok());
+
+        let mut player = valid_player();
+        player.pos.x = f32::NAN;
+        assert!(check(&snapshot(player)).is_err());
+
+        let mut player = valid_player();
+        player.username = "x".repeat(MAX_USERNAME_LENGTH + 1);
+        assert!(check(&snapshot(player)).is_err());
+    }
+
+    #[test]
+    fn maps_must_match_their_size() {
+        let mut environment = Environment::default();
+        environment.tiles.width = 2;
+        environment.tiles.height = 2;
+        environment.tiles.tiles = vec![0; 3];
+        assert!(check(&ServerMessage::UpdateObjects(environment)).is_err());
+    }
+}
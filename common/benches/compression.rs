@@ -0,0 +1,78 @@
+//! What compressing snapshots saves and what it costs, for worlds of growing size.
+//!
+//! `cargo bench -p common --bench compression` prints, per number of players, the size of an
+//! `UpdateEntities` frame as it is and compressed, and the time taken to encode and decode each.
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use common::{
+    message::{ServerMessage, frame::Compression},
+    vec::Vec2,
+};
+use test_utils::world::WorldBuilder;
+
+const ROUNDS: u32 = 2000;
+
+fn snapshot(players: u64) -> ServerMessage {
+    let mut world = WorldBuilder::new();
+    for id in 1..=players {
+        // Spread over the arena like a match in progress
+        let angle = id as f32 * 2.4;
+        let pos = Vec2 {
+            x: angle.cos() * (id as f32 / players as f32),
+            y: angle.sin() * (id as f32 / players as f32),
+        };
+        world = world.player(id, &format!("player{id}"), pos);
+    }
+    let world = world.build();
+    ServerMessage::UpdateEntities {
+        tick: 123_456,
+        time: 2057.6,
+        entities: world.entities,
+    }
+}
+
+/// Average time `f` takes over the rounds
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    println!(
+        "{:>7} {:>9} {:>9} {:>6} {:>11} {:>11} {:>11} {:>11}",
+        "players", "plain B", "lz4 B", "ratio", "encode", "encode lz4", "decode", "decode lz4"
+    );
+    for players in [4, 16, 64, 256] {
+        let msg = snapshot(players);
+        let plain = msg.encode().unwrap();
+        let compressed = msg.encode_compressed(Compression::Lz4).unwrap();
+        let encode = time(|| {
+            black_box(black_box(&msg).encode().unwrap());
+        });
+        let encode_lz4 = time(|| {
+            black_box(black_box(&msg).encode_compressed(Compression::Lz4).unwrap());
+        });
+        let decode = time(|| {
+            black_box(ServerMessage::decode(black_box(&plain)).unwrap());
+        });
+        let decode_lz4 = time(|| {
+            black_box(ServerMessage::decode(black_box(&compressed)).unwrap());
+        });
+        println!(
+            "{players:>7} {:>9} {:>9} {:>6.2} {:>11.2?} {:>11.2?} {:>11.2?} {:>11.2?}",
+            plain.len(),
+            compressed.len(),
+            compressed.len() as f64 / plain.len() as f64,
+            encode,
+            encode_lz4,
+            decode,
+            decode_lz4
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use anyhow::{Result, anyhow, ensure};
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
@@ -36,6 +37,7 @@ impl Color {
     };
 }
 impl Color {
+    /// Color from the operating system's generator, for effects only, see [`Vec2::random`](crate::vec::Vec2::random)
     pub fn random() -> Self {
         use rand::Rng;
         let mut rng = rand::rng();
@@ -45,4 +47,111 @@ impl Color {
             b: rng.random_range(0.0..1.0),
         }
     }
+
+    /// Bright color for player `id`, stepping around the hue circle by the golden ratio so the
+    /// players on a server stay easy to tell apart
+    pub fn for_player(id: u64) -> Self {
+        const GOLDEN_RATIO_CONJUGATE: f64 = 0.618_033_988_749_895;
+        let hue = (id as f64 * GOLDEN_RATIO_CONJUGATE).fract() as f32;
+        Self::from_hsv(hue, 0.65, 0.95)
+    }
+
+    /// Converts a hue, saturation and value, each from 0 to 1, to RGB
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let sector = hue.rem_euclid(1.0) * 6.0;
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let min = value - chroma;
+        Self {
+            r: r + min,
+            g: g + min,
+            b: b + min,
+        }
+    }
+
+    /// Parses a `#rrggbb` hex color, the leading `#` being optional
+    pub fn from_hex(text: &str) -> Result<Self> {
+        let hex = text.trim().trim_start_matches('#');
+        ensure!(
+            hex.len() == 6 && hex.is_ascii(),
+            "Expected a color like #ff8800, got `{text}`"
+        );
+        let channel = |index: usize| -> Result<f32> {
+            let value = u8::from_str_radix(&hex[index..index + 2], 16)
+                .map_err(|_| anyhow!("Expected a color like #ff8800, got `{text}`"))?;
+            Ok(value as f32 / 255.0)
+        };
+        Ok(Self {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+
+    /// Formats the color as `#rrggbb`
+    pub fn to_hex(&self) -> String {
+        let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            channel(self.r),
+            channel(self.g),
+            channel(self.b)
+        )
+    }
+
+    /// The color with every channel brought into 0 to 1, or `None` if one is not a number
+    pub fn clamped(&self) -> Option<Self> {
+        [self.r, self.g, self.b]
+            .iter()
+            .all(|value| value.is_finite())
+            .then(|| Self {
+                r: self.r.clamp(0.0, 1.0),
+                g: self.g.clamp(0.0, 1.0),
+                b: self.b.clamp(0.0, 1.0),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hsv_primaries() {
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), Color::RED);
+        assert_eq!(Color::from_hsv(1.0 / 3.0, 1.0, 1.0), Color::GREEN);
+        assert_eq!(Color::from_hsv(2.0 / 3.0, 1.0, 1.0), Color::BLUE);
+        assert_eq!(Color::from_hsv(0.5, 0.0, 1.0), Color::WHITE);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(Color::from_hex("#ff0000").unwrap(), Color::RED);
+        assert_eq!(Color::from_hex("00ff00").unwrap(), Color::GREEN);
+        assert_eq!(Color::from_hex("#3366cc").unwrap().to_hex(), "#3366cc");
+        assert!(Color::from_hex("#12345").is_err());
+        assert!(Color::from_hex("#gg0000").is_err());
+        assert!(Color::from_hex("#ffé00").is_err());
+    }
+
+    #[test]
+    fn player_colors_differ() {
+        let colors: Vec<Color> = (1..=8).map(Color::for_player).collect();
+        for (i, a) in colors.iter().enumerate() {
+            // Bright enough to stand out from the black background
+            assert!(a.r.max(a.g).max(a.b) > 0.9);
+            for b in &colors[i + 1..] {
+                let distance = (a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs();
+                assert!(distance > 0.1, "{a:?} and {b:?} look alike");
+            }
+        }
+    }
 }
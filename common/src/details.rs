@@ -3,4 +3,7 @@ pub const AUTHOR: &str = "Liam Israel";
 
 pub const DEFAULT_PORT: u16 = 8000;
 
+/// Simulation steps per second unless the server is started with another `--tick-rate`
+pub const TICK_RATE: f64 = 60.0;
+
 pub const DEFAULT_USERNAME: &str = "Newbie";
@@ -0,0 +1,176 @@
+//! Finding servers and what they are playing without joining them.
+//!
+//! On the local network a client broadcasts [`PROBE`] to [`DISCOVERY_PORT`], and every server
+//! listening for probes answers from that port with [`RESPONSE_MAGIC`] followed by its
+//! [`ServerStatus`] encoded with bincode. The game itself is played on the port the status names,
+//! at the address the answer came from.
+//!
+//! A server whose address is known is asked directly instead, see [`query_status`].
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow, bail};
+use bincode::{Decode, Encode, config};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    time::{self, Instant},
+};
+
+use crate::{
+    details,
+    message::{ClientMessage, ServerMessage},
+    version::Version,
+};
+
+/// Port servers listen for probes on, next to the default game port
+pub const DISCOVERY_PORT: u16 = details::DEFAULT_PORT + 1;
+/// Datagram asking servers to answer with their status
+pub const PROBE: &[u8] = b"battle_game/discover";
+/// Start of every answer, so stray datagrams are not taken for servers
+pub const RESPONSE_MAGIC: &[u8] = b"battle_game/status";
+
+/// What a server tells about itself in answer to a probe
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub struct ServerStatus {
+    pub name: String,
+    /// Port the game is played on
+    pub port: u16,
+    pub players: u32,
+    pub max_players: u32,
+    /// Rules the server plays by, as given to `--mode`
+    pub mode: String,
+    /// Whether joining takes a password
+    pub password: bool,
+    /// Protocol version of the server
+    pub version: Version,
+    /// Worlds the players are spread over, the main one first. A client can ask for one with
+    /// [`ClientMessage::SelectLobby`]
+    pub lobbies: Vec<LobbyStatus>,
+}
+
+/// One of the worlds a server hosts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub struct LobbyStatus {
+    pub name: String,
+    pub players: u32,
+    /// Players it holds, only the server's limit applies when there is none
+    pub max_players: Option<u32>,
+}
+impl ServerStatus {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut bytes = RESPONSE_MAGIC.to_vec();
+        bytes.extend(bincode::encode_to_vec(self, config::standard())?);
+        Ok(bytes)
+    }
+
+    /// Reads an answer to a probe, `None` for anything else
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let fields = bytes.strip_prefix(RESPONSE_MAGIC)?;
+        bincode::decode_from_slice(fields, config::standard())
+            .ok()
+            .map(|(status, _)| status)
+    }
+}
+
+/// Broadcasts a probe and collects the servers that answer within `wait`, each with the address
+/// its game is played at.
+pub async fn find_servers(wait: Duration) -> Result<Vec<(SocketAddr, ServerStatus)>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    // Without a network there is nobody to broadcast to, but servers on this machine can still
+    // be asked directly
+    if let Err(e) = socket
+        .send_to(PROBE, (Ipv4Addr::BROADCAST, DISCOVERY_PORT))
+        .await
+    {
+        eprintln!("Could not broadcast a discovery probe: {e}");
+    }
+    socket
+        .send_to(PROBE, (Ipv4Addr::LOCALHOST, DISCOVERY_PORT))
+        .await?;
+
+    let mut found = HashMap::new();
+    let mut buffer = [0; 1024];
+    let deadline = time::Instant::now() + wait;
+    while let Ok(received) = time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        let (len, from) = received?;
+        if let Some(status) = ServerStatus::decode(&buffer[..len]) {
+            found.insert(SocketAddr::new(from.ip(), status.port), status);
+        }
+    }
+    // A server on this machine that heard the broadcast answers twice, its network address is
+    // the one others can reach too
+    let heard_twice = |addr: &SocketAddr, status: &ServerStatus| {
+        addr.ip().is_loopback()
+            && found.iter().any(|(other, other_status)| {
+                !other.ip().is_loopback() && other.port() == addr.port() && other_status == status
+            })
+    };
+    let mut servers: Vec<_> = found
+        .iter()
+        .filter(|(addr, status)| !heard_twice(addr, status))
+        .map(|(addr, status)| (*addr, status.clone()))
+        .collect();
+    servers.sort_by_key(|(addr, _)| *addr);
+    Ok(servers)
+}
+
+/// Asks the server at `addr` for its status over TCP, giving up after `wait`. Returns the status
+/// and the round trip of the question, which leaves out setting up the connection.
+pub async fn query_status<T: ToSocketAddrs>(
+    addr: T,
+    wait: Duration,
+) -> Result<(ServerStatus, Duration)> {
+    let query = async {
+        let mut stream = TcpStream::connect(addr).await?;
+        let sent = Instant::now();
+        ClientMessage::QueryStatus
+            .write_to_stream(&mut stream)
+            .await?;
+        let mut buffer = Vec::new();
+        loop {
+            match ServerMessage::read_from_stream(&mut stream, &mut buffer).await? {
+                Some(ServerMessage::Status(status)) => return Ok((status, sent.elapsed())),
+                // Heartbeats come before the server gets to the question
+                Some(_) => {}
+                None => bail!("Server closed the connection without answering"),
+            }
+        }
+    };
+    // Servers that predate the question skip it and never answer
+    time::timeout(wait, query)
+        .await
+        .map_err(|_| anyhow!("No answer within {} ms", wait.as_millis()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::version::PROTOCOL_VERSION;
+
+    #[test]
+    fn only_answers_decode() {
+        let status = ServerStatus {
+            name: String::from("Home"),
+            port: 8000,
+            players: 2,
+            max_players: 10,
+            mode: String::from("free-for-all"),
+            password: false,
+            version: PROTOCOL_VERSION,
+            lobbies: vec![LobbyStatus {
+                name: String::from("main"),
+                players: 2,
+                max_players: Some(4),
+            }],
+        };
+        let bytes = status.encode().unwrap();
+        assert_eq!(ServerStatus::decode(&bytes), Some(status));
+        assert_eq!(ServerStatus::decode(PROBE), None);
+        assert_eq!(ServerStatus::decode(&bytes[RESPONSE_MAGIC.len()..]), None);
+    }
+}
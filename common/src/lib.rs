@@ -2,9 +2,13 @@
 //! It defines the main modules and components of the game, including the world structure,
 //! entities, and communication messages.
 pub mod details;
+pub mod discovery;
 pub mod message;
+pub mod physics;
 pub mod world;
 
 pub mod color;
+pub mod time;
+pub mod tunables;
 pub mod vec;
 pub mod version;
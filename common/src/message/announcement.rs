@@ -0,0 +1,20 @@
+//! Notices the server gives players, sent as what happened rather than as text.
+//!
+//! Clients put an [`Announcement`] into words in their own language and style it as they see fit.
+//! Kills and round results have messages of their own for the same reason, see
+//! [`ServerMessage::PlayerDied`](super::ServerMessage::PlayerDied) and
+//! [`ServerMessage::RoundOver`](super::ServerMessage::RoundOver). Text only a person can write,
+//! such as what a host types into the console, comes as [`Announcement::Text`] and is shown as is.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// As for messages, new variants go at the end
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub enum Announcement {
+    /// Greets a player that just joined
+    Welcome { server_name: String },
+    /// The server is stopping and is about to disconnect everyone
+    ShuttingDown,
+    /// Written by the host or a plugin, in whatever language they used
+    Text(String),
+}
@@ -0,0 +1,246 @@
+//! Authentication of client messages, so packets injected by someone else on the network are
+//! told apart from the client's own.
+//!
+//! A client proposes signing with [`ClientMessage::ProposeSigning`] before it joins, carrying the
+//! public half of a fresh X25519 key. Once the server lets it join, it answers with its own half in
+//! [`ServerMessage::SigningAgreed`], ahead of [`ServerMessage::ConnectionAccepted`]. Both sides
+//! derive the same session key from the exchange, and from then on every frame the client sends
+//! is marked with [`SIGNED`] and ends with a [`TRAILER_SIZE`] bytes trailer:
+//!
+//! | bytes | content                                                      |
+//! |-------|--------------------------------------------------------------|
+//! | 8     | counter, numbering the signed frames from 1, little endian   |
+//! | 32    | HMAC-SHA256 of the frame up to here, with the session key    |
+//!
+//! The length in the header covers the trailer. The counter keeps recorded frames from being
+//! played again, the server accepting each one once and frames up to [`REPLAY_WINDOW`] behind the
+//! newest, as UDP may reorder them.
+//!
+//! The exchange itself is not authenticated, so someone able to rewrite the connection as it is
+//! opened could still sit in the middle, which only TLS prevents. Servers that do not know about
+//! signing skip the proposal, and the client then sends its frames as they are.
+//!
+//! [`ClientMessage::ProposeSigning`]: super::ClientMessage::ProposeSigning
+//! [`ServerMessage::SigningAgreed`]: super::ServerMessage::SigningAgreed
+//! [`ServerMessage::ConnectionAccepted`]: super::ServerMessage::ConnectionAccepted
+use anyhow::{Result, anyhow, bail};
+use ring::{agreement, hkdf, hmac, rand::SystemRandom};
+
+use super::frame::{HEADER_SIZE, MAX_FRAME_SIZE, SIGNED};
+
+/// Public half of a key exchange, as sent to the peer
+pub type PublicKey = [u8; 32];
+
+const COUNTER_SIZE: usize = 8;
+const TAG_SIZE: usize = 32;
+/// Bytes a signed frame carries after its fields
+pub const TRAILER_SIZE: usize = COUNTER_SIZE + TAG_SIZE;
+/// How many frames behind the newest one a signed frame may arrive and still be accepted
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// Sets the derived key apart from anything else the exchange could be used for
+const KEY_INFO: &[u8] = b"multiplayer_game client frames v1";
+
+/// Our half of a key exchange, kept until the peer's half arrives
+pub struct Handshake {
+    private: agreement::EphemeralPrivateKey,
+    public: PublicKey,
+}
+impl Handshake {
+    pub fn new() -> Result<Self> {
+        let rng = SystemRandom::new();
+        let private = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)
+            .map_err(|_| anyhow!("Could not generate a key"))?;
+        let public = private
+            .compute_public_key()
+            .map_err(|_| anyhow!("Could not compute the public key"))?;
+        let public = public
+            .as_ref()
+            .try_into()
+            .map_err(|_| anyhow!("X25519 public keys are 32 bytes"))?;
+        Ok(Self { private, public })
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.public
+    }
+
+    /// Derives the session key from the peer's half of the exchange
+    pub fn finish(self, peer: &PublicKey) -> Result<SessionKey> {
+        let peer = agreement::UnparsedPublicKey::new(&agreement::X25519, peer);
+        agreement::agree_ephemeral(self.private, &peer, |shared| {
+            let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &[]).extract(shared);
+            prk.expand(&[KEY_INFO], hmac::HMAC_SHA256)
+                .map(|okm| SessionKey(okm.into()))
+        })
+        .map_err(|_| anyhow!("The peer's public key is not usable"))?
+        .map_err(|_| anyhow!("Could not derive the session key"))
+    }
+}
+
+/// Answers a proposal with our half of the exchange and the session key both sides end up with
+pub fn respond(peer: &PublicKey) -> Result<(PublicKey, SessionKey)> {
+    let handshake = Handshake::new()?;
+    let public = handshake.public_key();
+    Ok((public, handshake.finish(peer)?))
+}
+
+/// Key the frames of one connection are signed with
+#[derive(Clone)]
+pub struct SessionKey(hmac::Key);
+
+/// Signs the frames a client sends
+pub struct Signer {
+    key: SessionKey,
+    /// Counter of the last frame signed
+    sent: u64,
+}
+impl Signer {
+    pub fn new(key: SessionKey) -> Self {
+        Self { key, sent: 0 }
+    }
+
+    /// Marks an encoded frame as signed and appends its trailer
+    pub fn sign(&mut self, mut frame: Vec<u8>) -> Result<Vec<u8>> {
+        let (marked, len) = header(&frame)?;
+        if marked & SIGNED != 0 {
+            bail!("The frame is already signed");
+        }
+        let len = len + TRAILER_SIZE;
+        if len > MAX_FRAME_SIZE {
+            bail!("Message of {len} bytes is too large to send signed");
+        }
+        self.sent += 1;
+        frame[..2].copy_from_slice(&(marked | SIGNED).to_le_bytes());
+        frame[2..HEADER_SIZE].copy_from_slice(&(len as u32).to_le_bytes());
+        frame.extend_from_slice(&self.sent.to_le_bytes());
+        let tag = hmac::sign(&self.key.0, &frame);
+        frame.extend_from_slice(tag.as_ref());
+        Ok(frame)
+    }
+}
+
+/// Checks the frames a client sends, see the [module](self) for what is accepted
+pub struct Verifier {
+    key: SessionKey,
+    /// Highest counter accepted so far
+    newest: u64,
+    /// Which of the [`REPLAY_WINDOW`] counters up to the newest were seen, bit `n` standing for
+    /// `newest - n`
+    seen: u64,
+}
+impl Verifier {
+    pub fn new(key: SessionKey) -> Self {
+        Self {
+            key,
+            newest: 0,
+            seen: 0,
+        }
+    }
+
+    /// Accepts a complete frame that was signed with the session key and not seen before
+    pub fn verify(&mut self, frame: &[u8]) -> Result<()> {
+        let (marked, len) = header(frame)?;
+        if marked & SIGNED == 0 {
+            bail!("The message is not signed");
+        }
+        if len < TRAILER_SIZE || frame.len() != HEADER_SIZE + len {
+            bail!("The message is too short to be signed");
+        }
+        let (signed, tag) = frame.split_at(frame.len() - TAG_SIZE);
+        hmac::verify(&self.key.0, signed, tag)
+            .map_err(|_| anyhow!("The message has a wrong signature"))?;
+
+        let counter = signed[signed.len() - COUNTER_SIZE..].try_into()?;
+        let counter = u64::from_le_bytes(counter);
+        if counter > self.newest {
+            let shift = counter - self.newest;
+            self.seen = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.newest = counter;
+            return Ok(());
+        }
+        let behind = self.newest - counter;
+        if behind >= REPLAY_WINDOW || self.seen & (1 << behind) != 0 {
+            bail!("The message was already received");
+        }
+        self.seen |= 1 << behind;
+        Ok(())
+    }
+}
+
+/// Marked type id and length of the fields of a frame
+fn header(frame: &[u8]) -> Result<(u16, usize)> {
+    let Some(header) = frame.get(..HEADER_SIZE) else {
+        bail!("Truncated frame");
+    };
+    let marked = u16::from_le_bytes([header[0], header[1]]);
+    let len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    Ok((marked, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ClientMessage, frame::Frame};
+
+    /// Signer and verifier sharing a key agreed the way a client and server do
+    fn pair() -> (Signer, Verifier) {
+        let client = Handshake::new().unwrap();
+        let (server_public, server_key) = respond(&client.public_key()).unwrap();
+        let client_key = client.finish(&server_public).unwrap();
+        (Signer::new(client_key), Verifier::new(server_key))
+    }
+
+    fn chat(text: &str) -> Vec<u8> {
+        ClientMessage::Chat(String::from(text)).encode().unwrap()
+    }
+
+    #[test]
+    fn signed_frames_are_accepted_and_read_back() {
+        let (mut signer, mut verifier) = pair();
+        let frame = signer.sign(chat("hi")).unwrap();
+        assert_eq!(frame.len(), chat("hi").len() + TRAILER_SIZE);
+        verifier.verify(&frame).unwrap();
+        assert_eq!(
+            ClientMessage::decode(&frame).unwrap(),
+            Frame::Message(ClientMessage::Chat(String::from("hi")), frame.len())
+        );
+    }
+
+    #[test]
+    fn forged_frames_are_rejected() {
+        let (mut signer, mut verifier) = pair();
+        assert!(verifier.verify(&chat("hi")).is_err());
+
+        let mut tampered = signer.sign(chat("hi")).unwrap();
+        tampered[HEADER_SIZE + 1] ^= 1;
+        assert!(verifier.verify(&tampered).is_err());
+
+        // Signed by someone who agreed on another key
+        let (mut other, _) = pair();
+        assert!(verifier.verify(&other.sign(chat("hi")).unwrap()).is_err());
+    }
+
+    #[test]
+    fn frames_are_accepted_once_and_may_be_reordered() {
+        let (mut signer, mut verifier) = pair();
+        let frames: Vec<_> = (0..3).map(|_| signer.sign(chat("hi")).unwrap()).collect();
+        verifier.verify(&frames[2]).unwrap();
+        verifier.verify(&frames[0]).unwrap();
+        assert!(verifier.verify(&frames[0]).is_err());
+        assert!(verifier.verify(&frames[2]).is_err());
+        verifier.verify(&frames[1]).unwrap();
+
+        // Too far behind to tell whether it was seen
+        let late = signer.sign(chat("late")).unwrap();
+        for _ in 0..REPLAY_WINDOW {
+            verifier.verify(&signer.sign(chat("hi")).unwrap()).unwrap();
+        }
+        assert!(verifier.verify(&late).is_err());
+    }
+}
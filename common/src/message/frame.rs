@@ -0,0 +1,344 @@
+//! Framing of encoded messages, so receivers can skip message types they do not know.
+//!
+//! Every message goes out as a header followed by the fields of its variant:
+//!
+//! | bytes    | content                                                 |
+//! |----------|---------------------------------------------------------|
+//! | 2        | type id, the index of the variant, little endian        |
+//! | 4        | length of the fields in bytes, little endian            |
+//! | length   | the fields, encoded with bincode                        |
+//!
+//! A peer one minor version ahead may send types that were added after ours. Those come back as
+//! [`Frame::Unknown`] and the stream carries on with the next frame, as it does for a known type
+//! with fields added at the end.
+//!
+//! Large fields can be sent compressed with [`encode_compressed`] to peers that said they accept
+//! it, which marks the frame by setting [`COMPRESSED`] in its type id. [`decode_auto`] reads
+//! frames either way. Only fields of [`COMPRESS_THRESHOLD`] bytes or more are compressed, and only
+//! when that makes them smaller; `cargo bench -p common --bench compression` shows what it saves
+//! on snapshots and what it costs.
+//!
+//! Frames signed as described in [`auth`](super::auth) are marked by setting [`SIGNED`] in their
+//! type id, and [`decode_auto`] leaves out their trailer. Checking the signature is up to the
+//! receiver, on the frame as [`read_frame`] returns it.
+use anyhow::{Result, bail};
+use bincode::{Decode, Encode, config, error::DecodeError};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::auth::TRAILER_SIZE;
+
+pub const HEADER_SIZE: usize = 6;
+/// Largest frame accepted, anything bigger is taken as a corrupted stream. Compressed fields are
+/// held to it once decompressed too
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+/// Set in the type id of frames whose fields are compressed
+pub const COMPRESSED: u16 = 0x8000;
+/// Set in the type id of frames that end with a signature
+pub const SIGNED: u16 = 0x4000;
+/// Fields shorter than this many bytes are sent as they are, compressing them saves next to nothing
+pub const COMPRESS_THRESHOLD: usize = 256;
+
+/// How frames can be compressed. There is only LZ4 for now, a second algorithm would need its own
+/// mark in the frame
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Decode, Encode)]
+pub enum Compression {
+    /// LZ4 blocks, fast to compress and decompress for a moderate saving
+    Lz4,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Frame<M> {
+    /// More bytes are needed before anything can be decoded
+    Incomplete,
+    /// A message and the number of bytes its frame took
+    Message(M, usize),
+    /// A message this side cannot read, such as a type added in a newer version, and the number
+    /// of bytes to skip over it
+    Unknown { type_id: u16, len: usize },
+}
+
+/// Encodes an enum message into a frame.
+pub fn encode<M: Encode>(msg: &M) -> Result<Vec<u8>> {
+    let (type_id, fields) = split(msg)?;
+    frame(type_id, &fields)
+}
+
+/// Encodes an enum message into a frame, its fields compressed with `compression` when they are
+/// large enough for it to pay off.
+pub fn encode_compressed<M: Encode>(msg: &M, compression: Compression) -> Result<Vec<u8>> {
+    let (type_id, fields) = split(msg)?;
+    if fields.len() >= COMPRESS_THRESHOLD {
+        let compressed = match compression {
+            Compression::Lz4 => lz4_flex::compress_prepend_size(&fields),
+        };
+        if compressed.len() < fields.len() {
+            return frame(type_id | COMPRESSED, &compressed);
+        }
+    }
+    frame(type_id, &fields)
+}
+
+/// Type id and fields of an enum message
+fn split<M: Encode>(msg: &M) -> Result<(u16, Vec<u8>)> {
+    let config = config::standard();
+    let mut encoded = bincode::encode_to_vec(msg, config)?;
+    // Bincode starts an enum with its variant index, which moves to the header
+    let (type_id, prefix): (u32, usize) = bincode::decode_from_slice(&encoded, config)?;
+    let type_id = u16::try_from(type_id)?;
+    if type_id & (COMPRESSED | SIGNED) != 0 {
+        bail!("Type id {type_id} collides with the frame marks");
+    }
+    encoded.drain(..prefix);
+    Ok((type_id, encoded))
+}
+
+fn frame(type_id: u16, fields: &[u8]) -> Result<Vec<u8>> {
+    if fields.len() > MAX_FRAME_SIZE {
+        bail!("Message of {} bytes is too large to send", fields.len());
+    }
+    let mut frame = Vec::with_capacity(HEADER_SIZE + fields.len());
+    frame.extend_from_slice(&type_id.to_le_bytes());
+    frame.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+    frame.extend_from_slice(fields);
+    Ok(frame)
+}
+
+/// Size of the frame at the start of `bytes`, once its header is there
+fn frame_size(bytes: &[u8]) -> Result<Option<usize>> {
+    let Some(header) = bytes.get(..HEADER_SIZE) else {
+        return Ok(None);
+    };
+    let len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    if len > MAX_FRAME_SIZE {
+        bail!("Frame of {len} bytes is too large");
+    }
+    Ok(Some(HEADER_SIZE + len))
+}
+
+/// Decodes the frame at the start of `bytes`, compressed, signed or not.
+pub fn decode_auto<M: Decode<()>>(bytes: &[u8]) -> Result<Frame<M>> {
+    let Some(size) = frame_size(bytes)? else {
+        return Ok(Frame::Incomplete);
+    };
+    let Some(fields) = bytes.get(HEADER_SIZE..size) else {
+        return Ok(Frame::Incomplete);
+    };
+    let marked = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let type_id = marked & !(COMPRESSED | SIGNED);
+    let fields = if marked & SIGNED != 0 {
+        let Some(end) = fields.len().checked_sub(TRAILER_SIZE) else {
+            bail!("Frame is too short to be signed");
+        };
+        &fields[..end]
+    } else {
+        fields
+    };
+    let decompressed;
+    let fields = if marked & COMPRESSED != 0 {
+        decompressed = decompress(fields)?;
+        &decompressed[..]
+    } else {
+        fields
+    };
+
+    let mut encoded = bincode::encode_to_vec(type_id as u32, config::standard())?;
+    encoded.extend_from_slice(fields);
+    // Collections are allocated for the length they claim before their items are read, the limit
+    // keeps a bogus length from asking for more memory than the largest frame
+    let config = config::standard().with_limit::<MAX_FRAME_SIZE>();
+    match bincode::decode_from_slice(&encoded, config) {
+        Ok((msg, _)) => Ok(Frame::Message(msg, size)),
+        Err(DecodeError::UnexpectedVariant { .. }) => Ok(Frame::Unknown { type_id, len: size }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn decompress(fields: &[u8]) -> Result<Vec<u8>> {
+    let (size, block) = lz4_flex::block::uncompressed_size(fields)?;
+    if size > MAX_FRAME_SIZE {
+        bail!("Compressed frame of {size} bytes is too large");
+    }
+    Ok(lz4_flex::block::decompress(block, size)?)
+}
+
+/// Reads the next message from a stream, keeping bytes that belong to later frames in `buffer`.
+/// Frames of unknown types are skipped. Returns `None` once the stream is closed.
+pub async fn read<M: Decode<()>, R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<M>> {
+    loop {
+        let Some(frame) = read_frame(reader, buffer).await? else {
+            return Ok(None);
+        };
+        match decode_auto::<M>(&frame)? {
+            Frame::Message(msg, _) => return Ok(Some(msg)),
+            Frame::Unknown { type_id, .. } => {
+                eprintln!("Skipped a message of unknown type {type_id}");
+            }
+            Frame::Incomplete => bail!("Truncated message"),
+        }
+    }
+}
+
+/// Reads the next frame from a stream as it is, keeping bytes that belong to later frames in
+/// `buffer`. Returns `None` once the stream is closed.
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<Vec<u8>>> {
+    loop {
+        if let Some(size) = frame_size(buffer)?
+            && buffer.len() >= size
+        {
+            return Ok(Some(buffer.drain(..size).collect()));
+        }
+        let mut chunk = [0u8; 4096];
+        let n = reader.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What an older peer knows about
+    #[derive(Encode, Decode, Debug, PartialEq)]
+    enum Old {
+        Ping,
+        Chat(String),
+    }
+
+    /// The same messages one version later, with a new type
+    #[derive(Encode, Decode, Debug, PartialEq)]
+    enum New {
+        Ping,
+        Chat(String),
+        Emote(u32),
+    }
+
+    fn stream(messages: &[New]) -> Vec<u8> {
+        messages
+            .iter()
+            .flat_map(|msg| encode(msg).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn unknown_frames_are_skipped() {
+        let bytes = stream(&[New::Chat(String::from("hi")), New::Emote(3), New::Ping]);
+
+        let mut decoded = Vec::new();
+        let mut rest = &bytes[..];
+        loop {
+            match decode_auto::<Old>(rest).unwrap() {
+                Frame::Message(msg, len) => {
+                    decoded.push(Some(msg));
+                    rest = &rest[len..];
+                }
+                Frame::Unknown { type_id, len } => {
+                    assert_eq!(type_id, 2);
+                    decoded.push(None);
+                    rest = &rest[len..];
+                }
+                Frame::Incomplete => break,
+            }
+        }
+        assert!(rest.is_empty());
+        assert_eq!(
+            decoded,
+            vec![Some(Old::Chat(String::from("hi"))), None, Some(Old::Ping)]
+        );
+    }
+
+    #[test]
+    fn partial_frames_wait_for_more() {
+        let bytes = stream(&[New::Chat(String::from("hello"))]);
+        for end in 0..bytes.len() {
+            assert_eq!(
+                decode_auto::<New>(&bytes[..end]).unwrap(),
+                Frame::Incomplete
+            );
+        }
+        assert_eq!(
+            decode_auto::<New>(&bytes).unwrap(),
+            Frame::Message(New::Chat(String::from("hello")), bytes.len())
+        );
+    }
+
+    #[test]
+    fn bogus_lengths_are_rejected() {
+        // A chat message claiming to hold u64::MAX bytes
+        let mut bytes = vec![1, 0, 9, 0, 0, 0, 0xFF];
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(decode_auto::<Old>(&bytes).is_err());
+    }
+
+    #[test]
+    fn large_fields_are_compressed_and_read_back() {
+        let long = New::Chat("all work and no play ".repeat(40));
+        let plain = encode(&long).unwrap();
+        let compressed = encode_compressed(&long, Compression::Lz4).unwrap();
+        assert!(compressed.len() < plain.len() / 4);
+        assert_eq!(compressed[1] & 0x80, 0x80);
+        assert_eq!(
+            decode_auto::<New>(&compressed).unwrap(),
+            Frame::Message(long, compressed.len())
+        );
+
+        // Small enough to go as it is
+        let short = New::Chat(String::from("hi"));
+        assert_eq!(
+            encode_compressed(&short, Compression::Lz4).unwrap(),
+            encode(&short).unwrap()
+        );
+    }
+
+    #[test]
+    fn compressed_frames_of_unknown_types_are_skipped() {
+        // A large message of a type added later
+        let fields = lz4_flex::compress_prepend_size(&[7; 300]);
+        let mut bytes = vec![2, 0x80];
+        bytes.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&fields);
+        assert_eq!(
+            decode_auto::<Old>(&bytes).unwrap(),
+            Frame::Unknown {
+                type_id: 2,
+                len: bytes.len()
+            }
+        );
+    }
+
+    #[test]
+    fn bogus_decompressed_sizes_are_rejected() {
+        // Claims to decompress into 4 GiB
+        let mut bytes = vec![1, 0x80, 5, 0, 0, 0];
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.push(0);
+        assert!(decode_auto::<Old>(&bytes).is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_mix_known_and_unknown_frames() {
+        let bytes = stream(&[
+            New::Emote(1),
+            New::Ping,
+            New::Emote(2),
+            New::Chat(String::new()),
+        ]);
+        let mut reader = &bytes[..];
+        let mut buffer = Vec::new();
+        let first = read::<Old, _>(&mut reader, &mut buffer).await.unwrap();
+        let second = read::<Old, _>(&mut reader, &mut buffer).await.unwrap();
+        let end = read::<Old, _>(&mut reader, &mut buffer).await.unwrap();
+        assert_eq!(first, Some(Old::Ping));
+        assert_eq!(second, Some(Old::Chat(String::new())));
+        assert_eq!(end, None);
+    }
+}
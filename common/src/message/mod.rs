@@ -0,0 +1,507 @@
+//! Defines how the server and client communicate
+//!
+//! This module provides `ServerMessage` and `ClientMessage` enums which define
+//! the types of messages that can be exchanged between a server and a client.
+//! These messages can be serialized and deserialized using `bincode` for efficient
+//! binary communication. Each message type implements `encode` and `decode` methods
+//! to handle this serialization logic, wrapping every message in a [`frame`] so receivers can
+//! skip types they do not know.
+//!
+//! Messages travel over TCP by default, encrypted with [`tls`] when both sides are set up for it,
+//! or over UDP through the reliability layer in [`udp`].
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{Result, bail};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::color::Color;
+use crate::discovery::ServerStatus;
+use crate::message::announcement::Announcement;
+use crate::message::auth::PublicKey;
+use crate::message::frame::{Compression, Frame};
+use crate::tunables::Tunables;
+use crate::vec::Vec2;
+use crate::version::Version;
+use crate::world::{
+    arena::Arena, combat::ProjectileKind, entities::Entities, environment::Environment,
+    scoreboard::Scoreboard, zone::Zone,
+};
+
+pub mod announcement;
+pub mod auth;
+pub mod frame;
+pub mod tls;
+pub mod udp;
+
+/// Network protocol used between the server and its clients
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transport {
+    #[default]
+    Tcp,
+    /// Avoids head-of-line blocking, see [`udp`]
+    Udp,
+}
+impl FromStr for Transport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(Transport::Tcp),
+            "udp" => Ok(Transport::Udp),
+            _ => bail!("Unknown transport `{s}`, expected `tcp` or `udp`"),
+        }
+    }
+}
+impl Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Transport::Tcp => "tcp",
+            Transport::Udp => "udp",
+        })
+    }
+}
+
+/// Byte stream messages are framed over, a TCP stream whether or not [`tls`] wraps it
+pub trait MessageStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> MessageStream for S {}
+
+/// How a message has to be delivered when the transport does not guarantee it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    /// May be lost, and is dropped if a newer unreliable message already arrived
+    Unreliable,
+    /// Resent until acknowledged and handed over in the order it was sent
+    Reliable,
+}
+
+/// Behaviour shared by both message directions so transports only have to be written once
+pub trait Message: Sized {
+    fn encode(&self) -> Result<Vec<u8>>;
+    fn decode(bytes: &[u8]) -> Result<Frame<Self>>;
+    fn delivery(&self) -> Delivery;
+}
+
+/// Longest chat line, in characters, that the server will relay
+pub const MAX_CHAT_LENGTH: usize = 200;
+/// Longest username, in characters, that the server will give out
+pub const MAX_USERNAME_LENGTH: usize = 32;
+
+/// Messages that are sent from the Server to the Client
+///
+/// The index of a variant is its type id on the wire, so new variants go at the end to keep
+/// older peers able to read the ones they know.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub enum ServerMessage {
+    /* Connection handling */
+    /// Heartbeat, answered with [`ClientMessage::Pong`] so the server knows the client is alive
+    /// and how long the round trip took
+    Ping,
+    Disconnect,
+    /// The client joined as player `id`, under `username` which the server may have changed to
+    /// keep names unique. `session` takes the player back with [`ClientMessage::Resume`] should
+    /// the connection drop
+    ConnectionAccepted {
+        id: u64,
+        username: String,
+        session: String,
+    },
+    /// The password given to [`ClientMessage::Connect`] was wrong, the server closes the
+    /// connection right after
+    PasswordFailed,
+    /// The server is full and has no join queue
+    ServerFull,
+    /// Place in the join queue, 1 being next in line
+    QueuePosition(u32),
+    /// Tells the client to leave and join the server at `address` instead, such as a lobby
+    /// sending players to a match server. The client gives `token` as its password there, so
+    /// match servers can be kept to the players sent to them. The connection closes right after
+    Redirect {
+        address: String,
+        token: String,
+    },
+
+    /* Notifies players of world updates */
+    UpdateObjects(Environment),
+    /// Entity snapshot taken at server simulation step `tick`, `time` seconds after the server
+    /// started. Ticks only ever increase, so a snapshot older than one already received is stale
+    UpdateEntities {
+        tick: u64,
+        time: f64,
+        entities: Entities,
+    },
+    /// Resolved gameplay values, sent once a client is accepted
+    UpdateTunables(Tunables),
+    /// A player died, killed by another player or by the world, with whoever else helped
+    PlayerDied {
+        victim: u64,
+        killer: Option<u64>,
+        assists: Vec<u64>,
+        /// Kind of projectile the killer used, if any
+        weapon: Option<ProjectileKind>,
+    },
+    /// Kills, assists and deaths, sent whenever they change
+    UpdateScoreboard(Scoreboard),
+    /// Bounds players must stay within, if the mode has any
+    UpdateArena(Option<Arena>),
+    /// Safe zone of the storm, if the mode has one
+    UpdateStorm(Option<Zone>),
+    /// A projectile or explosion hurt a player, split into what the armor and health took
+    PlayerHit {
+        victim: u64,
+        attacker: u64,
+        armor: f32,
+        health: f32,
+    },
+    /// An explosive projectile went off, for clients to show
+    ExplosionEvent {
+        owner: u64,
+        pos: Vec2,
+        radius: f32,
+    },
+    /// The round ended, won by the last player standing if anyone was left
+    RoundOver {
+        winner: Option<u64>,
+        /// Contestants still around at the end, from first to last place
+        placements: Vec<u64>,
+    },
+
+    /* Chat */
+    /// A chat line relayed to every client, timestamped in unix milliseconds
+    ChatBroadcast {
+        sender_id: u64,
+        text: String,
+        timestamp: u64,
+    },
+
+    /* Versioning */
+    /// The client's protocol version cannot talk to this server, which runs the given one. The
+    /// connection closes right after
+    IncompatibleVersion(Version),
+
+    /* Latency */
+    /// Answer to [`ClientMessage::Ping`], echoing its `client_time` along with the seconds since
+    /// the server started
+    Pong {
+        client_time: f64,
+        server_time: f64,
+    },
+
+    /* Presence */
+    /// Another player joined the game, sent to everyone already playing
+    PlayerJoined {
+        id: u64,
+        username: String,
+    },
+    /// A player left the game or lost its connection
+    PlayerLeft {
+        id: u64,
+        username: String,
+    },
+
+    /* Server browser */
+    /// Answer to [`ClientMessage::QueryStatus`], the connection closes right after
+    Status(ServerStatus),
+
+    /* Announcements */
+    /// Notice for the players, for clients to word and show
+    Announcement(Announcement),
+
+    /* Match */
+    /// What is being played, sent to a player as it joins. `map` is the name of the map file
+    /// without its extension, empty for the built-in world, and `mode` is as given to `--mode`
+    MatchInfo {
+        map: String,
+        mode: String,
+    },
+
+    /* Sessions */
+    /// Answer to [`ClientMessage::Resume`] when the session is unknown or its grace period ran
+    /// out, the client can still join afresh with [`ClientMessage::Connect`]
+    SessionExpired,
+
+    /* Moderation */
+    /// The client's username or address is on the server's ban list, sent instead of letting it
+    /// join or before closing its connection
+    Banned,
+
+    /* Message authentication */
+    /// The server's half of the key exchange the client proposed, sent right before
+    /// [`ServerMessage::ConnectionAccepted`]. The client signs everything it sends from then on,
+    /// see [`auth`]
+    SigningAgreed(PublicKey),
+
+    /* Time trials and races */
+    /// A player reached the checkpoint it was heading for at simulation step `tick`, see
+    /// [`checkpoint`](crate::world::checkpoint). Checkpoint 0 is the start line, which starts a
+    /// lap
+    CheckpointReached {
+        player: u64,
+        checkpoint: u32,
+        tick: u64,
+    },
+    /// A player completed a lap in `time` seconds, sent right before the
+    /// [`ServerMessage::CheckpointReached`] starting its next one
+    LapCompleted {
+        player: u64,
+        time: f32,
+    },
+    /// Fastest lap of each player by username, fastest first. Sent whenever it changes, and now
+    /// and then for players who joined since
+    LapTimes(Vec<(String, f32)>),
+    /// A race of `laps` laps started, with every racer on the start line
+    RaceStarted {
+        laps: u32,
+    },
+    /// A player completed the race in `time` seconds, `place` starting at 1 for the winner. The
+    /// race ends with a [`ServerMessage::RoundOver`] once everyone finished or ran out of time
+    RaceFinished {
+        player: u64,
+        place: u32,
+        time: f32,
+    },
+}
+impl ServerMessage {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        frame::encode(self)
+    }
+    /// Encodes the message with large fields compressed, for clients that sent
+    /// [`ClientMessage::AcceptCompression`]
+    pub fn encode_compressed(&self, compression: Compression) -> Result<Vec<u8>> {
+        frame::encode_compressed(self, compression)
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Frame<Self>> {
+        frame::decode_auto(bytes)
+    }
+
+    /// Name of the message type, as in the code
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerMessage::Ping => "Ping",
+            ServerMessage::Disconnect => "Disconnect",
+            ServerMessage::ConnectionAccepted { .. } => "ConnectionAccepted",
+            ServerMessage::PasswordFailed => "PasswordFailed",
+            ServerMessage::ServerFull => "ServerFull",
+            ServerMessage::QueuePosition(_) => "QueuePosition",
+            ServerMessage::Redirect { .. } => "Redirect",
+            ServerMessage::UpdateObjects(_) => "UpdateObjects",
+            ServerMessage::UpdateEntities { .. } => "UpdateEntities",
+            ServerMessage::UpdateTunables(_) => "UpdateTunables",
+            ServerMessage::PlayerDied { .. } => "PlayerDied",
+            ServerMessage::UpdateScoreboard(_) => "UpdateScoreboard",
+            ServerMessage::UpdateArena(_) => "UpdateArena",
+            ServerMessage::UpdateStorm(_) => "UpdateStorm",
+            ServerMessage::PlayerHit { .. } => "PlayerHit",
+            ServerMessage::ExplosionEvent { .. } => "ExplosionEvent",
+            ServerMessage::RoundOver { .. } => "RoundOver",
+            ServerMessage::ChatBroadcast { .. } => "ChatBroadcast",
+            ServerMessage::IncompatibleVersion(_) => "IncompatibleVersion",
+            ServerMessage::Pong { .. } => "Pong",
+            ServerMessage::PlayerJoined { .. } => "PlayerJoined",
+            ServerMessage::PlayerLeft { .. } => "PlayerLeft",
+            ServerMessage::Status(_) => "Status",
+            ServerMessage::Announcement(_) => "Announcement",
+            ServerMessage::MatchInfo { .. } => "MatchInfo",
+            ServerMessage::SessionExpired => "SessionExpired",
+            ServerMessage::Banned => "Banned",
+            ServerMessage::SigningAgreed(_) => "SigningAgreed",
+            ServerMessage::CheckpointReached { .. } => "CheckpointReached",
+            ServerMessage::LapCompleted { .. } => "LapCompleted",
+            ServerMessage::LapTimes(_) => "LapTimes",
+            ServerMessage::RaceStarted { .. } => "RaceStarted",
+            ServerMessage::RaceFinished { .. } => "RaceFinished",
+        }
+    }
+}
+impl Message for ServerMessage {
+    fn encode(&self) -> Result<Vec<u8>> {
+        ServerMessage::encode(self)
+    }
+    fn decode(bytes: &[u8]) -> Result<Frame<Self>> {
+        ServerMessage::decode(bytes)
+    }
+    fn delivery(&self) -> Delivery {
+        match self {
+            // Snapshots are sent every tick, a lost one is replaced by the next
+            ServerMessage::Ping | ServerMessage::UpdateEntities { .. } => Delivery::Unreliable,
+            // Only a round trip sample, the next ping gives another
+            ServerMessage::Pong { .. } => Delivery::Unreliable,
+            _ => Delivery::Reliable,
+        }
+    }
+}
+impl ServerMessage {
+    pub async fn write_to_stream<S: AsyncWrite + Unpin>(&self, stream: &mut S) -> Result<()> {
+        let encoded = self.encode()?;
+        stream.write_all(&encoded).await?;
+        // TLS holds on to what it encrypted until flushed
+        stream.flush().await?;
+        Ok(())
+    }
+    /// Reads the next message from a stream, keeping bytes of later messages in `buffer`.
+    /// Returns `None` once the stream is closed.
+    pub async fn read_from_stream<S: AsyncRead + Unpin>(
+        stream: &mut S,
+        buffer: &mut Vec<u8>,
+    ) -> Result<Option<Self>> {
+        frame::read(stream, buffer).await
+    }
+}
+
+/// Messages that are sent from the Client to the Server
+///
+/// As for [`ServerMessage`], new variants go at the end.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub enum ClientMessage {
+    /* Connection handling */
+    /// Username, Password. A username already in use gets a suffix, see
+    /// [`ServerMessage::ConnectionAccepted`]
+    Connect(String, String),
+    Disconnect,
+    /// Asks for a [`ServerMessage::Pong`] to time the round trip, `client_time` being when the
+    /// ping was sent in seconds on the client's clock
+    Ping {
+        client_time: f64,
+    },
+    /// Answer to [`ServerMessage::Ping`], lets the server know the client is still alive
+    Pong,
+
+    /* Notifies server of client updates */
+    /// Direction the player wants to walk in, of at most unit length, as the input numbered
+    /// `seq`. Sequence numbers increase with every input. Shorter directions walk slower, as an
+    /// analog stick pushed part of the way does; servers shorten longer ones to unit length and
+    /// ignore those that are not finite
+    MoveInput {
+        seq: u64,
+        dir: Vec2,
+    },
+    /// Fires a projectile of the chosen weapon from the player towards `dir`
+    NotifyShot {
+        dir: Vec2,
+        kind: ProjectileKind,
+    },
+
+    /* Chat */
+    /// Text to relay to every connected client
+    Chat(String),
+
+    /* Versioning */
+    /// Protocol version of the client, sent before [`ClientMessage::Connect`]
+    Hello(Version),
+
+    /* Appearance */
+    /// Color the player would like to have, sent before [`ClientMessage::Connect`]. Servers
+    /// that do not know it pick a color themselves
+    PreferColor(Color),
+
+    /* Server browser */
+    /// Asks for a [`ServerMessage::Status`] instead of joining, needs no
+    /// [`ClientMessage::Hello`]
+    QueryStatus,
+
+    /* Ammo */
+    /// Starts reloading a weapon, see [`Ammo`](crate::world::ammo::Ammo)
+    Reload {
+        kind: ProjectileKind,
+    },
+
+    /* Bandwidth */
+    /// The client reads frames compressed this way, sent before [`ClientMessage::Connect`].
+    /// Servers that do not know it send every frame as it is
+    AcceptCompression(Compression),
+
+    /* Sessions */
+    /// Joins as the player of a dropped connection, with the `session` of its
+    /// [`ServerMessage::ConnectionAccepted`]. Sent instead of [`ClientMessage::Connect`], it keeps
+    /// the player's id, place and score when the server still holds the session
+    Resume(String),
+
+    /* Lobbies */
+    /// Name of the lobby to play in, sent before [`ClientMessage::Connect`]. Servers hosting
+    /// several put the client in their first lobby otherwise
+    SelectLobby(String),
+
+    /* Message authentication */
+    /// The client's half of a key exchange, sent before [`ClientMessage::Connect`] by clients
+    /// that can sign their messages, see [`auth`]. Servers that do not know it let the client
+    /// send its messages unsigned
+    ProposeSigning(PublicKey),
+
+    /* Adaptive quality */
+    /// How well the client keeps up, sent every few seconds while playing. The server sends
+    /// fewer snapshots to clients that lose many or draw fewer frames than it sends
+    QualityReport {
+        /// Share of the datagrams from the server lost on the way since the last report, from 0
+        /// to 1. Over TCP nothing is lost
+        snapshot_loss: f32,
+        /// Frames the client draws per second
+        fps: f32,
+    },
+}
+impl ClientMessage {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        frame::encode(self)
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Frame<Self>> {
+        frame::decode_auto(bytes)
+    }
+
+    /// Name of the message type, as in the code
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClientMessage::Connect(..) => "Connect",
+            ClientMessage::Disconnect => "Disconnect",
+            ClientMessage::Ping { .. } => "Ping",
+            ClientMessage::Pong => "Pong",
+            ClientMessage::MoveInput { .. } => "MoveInput",
+            ClientMessage::NotifyShot { .. } => "NotifyShot",
+            ClientMessage::Chat(_) => "Chat",
+            ClientMessage::Hello(_) => "Hello",
+            ClientMessage::PreferColor(_) => "PreferColor",
+            ClientMessage::QueryStatus => "QueryStatus",
+            ClientMessage::Reload { .. } => "Reload",
+            ClientMessage::AcceptCompression(_) => "AcceptCompression",
+            ClientMessage::Resume(_) => "Resume",
+            ClientMessage::SelectLobby(_) => "SelectLobby",
+            ClientMessage::ProposeSigning(_) => "ProposeSigning",
+            ClientMessage::QualityReport { .. } => "QualityReport",
+        }
+    }
+}
+impl Message for ClientMessage {
+    fn encode(&self) -> Result<Vec<u8>> {
+        ClientMessage::encode(self)
+    }
+    fn decode(bytes: &[u8]) -> Result<Frame<Self>> {
+        ClientMessage::decode(bytes)
+    }
+    fn delivery(&self) -> Delivery {
+        match self {
+            ClientMessage::Ping { .. } | ClientMessage::Pong => Delivery::Unreliable,
+            // The next report replaces a lost one
+            ClientMessage::QualityReport { .. } => Delivery::Unreliable,
+            // Inputs are only sent when they change, so every one of them has to arrive
+            _ => Delivery::Reliable,
+        }
+    }
+}
+impl ClientMessage {
+    pub async fn write_to_stream<S: AsyncWrite + Unpin>(&self, stream: &mut S) -> Result<()> {
+        let encoded = self.encode()?;
+        stream.write_all(&encoded).await?;
+        // TLS holds on to what it encrypted until flushed
+        stream.flush().await?;
+        Ok(())
+    }
+
+    /// Reads the next message from a stream, keeping bytes of later messages in `buffer`.
+    /// Returns `None` once the stream is closed.
+    pub async fn read_from_stream<S: AsyncRead + Unpin>(
+        stream: &mut S,
+        buffer: &mut Vec<u8>,
+    ) -> Result<Option<Self>> {
+        frame::read(stream, buffer).await
+    }
+}
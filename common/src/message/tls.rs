@@ -0,0 +1,92 @@
+//! Encryption of TCP connections with TLS, so passwords and the rest of the game cannot be read on
+//! the way.
+//!
+//! The server presents a certificate, read with its private key from PEM files. Clients accept it
+//! when it is signed by, or is, one of the certificates in the PEM file they trust. A server run
+//! among friends can use a self-signed certificate, as long as it is not marked as an authority
+//! and names the host clients connect to:
+//!
+//! ```text
+//! openssl req -x509 -newkey rsa:2048 -nodes -days 365 -keyout key.pem -out cert.pem \
+//!     -subj /CN=localhost -addext subjectAltName=DNS:localhost,IP:127.0.0.1 \
+//!     -addext basicConstraints=critical,CA:FALSE
+//! ```
+//!
+//! Only TCP can be encrypted, UDP connections stay in the clear.
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio_rustls::{
+    TlsAcceptor, TlsConnector,
+    rustls::{
+        self, RootCertStore,
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+    },
+};
+
+/// Server side, presenting the certificate chain in `cert` signed with the private key in `key`
+pub fn acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor> {
+    let chain = certificates(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| anyhow!("Cannot read a private key from {}: {e}", key.display()))?;
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .context("The TLS certificate does not go with its key")?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Client side, trusting the certificates in `ca` and those they signed
+pub fn connector(ca: &Path) -> Result<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+    for cert in certificates(ca)? {
+        roots
+            .add(cert)
+            .with_context(|| format!("Cannot trust a certificate of {}", ca.display()))?;
+    }
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Name the server's certificate has to be for when connecting to `addr`, its host without the
+/// port
+pub fn server_name(addr: &str) -> Result<ServerName<'static>> {
+    let host = match addr.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => addr,
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_string())
+        .map_err(|_| anyhow!("`{host}` is not a name a certificate can be for"))
+}
+
+fn certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("Cannot read certificates from {}: {e}", path.display()))?;
+    if certs.is_empty() {
+        bail!("No certificate in {}", path.display());
+    }
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_names_leave_out_the_port() {
+        for (addr, name) in [
+            ("localhost:8000", "localhost"),
+            ("game.example.com", "game.example.com"),
+            ("127.0.0.1:8000", "127.0.0.1"),
+            ("[::1]:8000", "::1"),
+        ] {
+            let expected = ServerName::try_from(name).unwrap();
+            assert_eq!(server_name(addr).unwrap(), expected, "{addr}");
+        }
+        assert!(server_name("not a host:8000").is_err());
+    }
+}
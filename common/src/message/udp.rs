@@ -0,0 +1,577 @@
+//! UDP transport with a small reliability layer on top.
+//!
+//! Every datagram carries a packet sequence number along with an acknowledgement of the latest
+//! packet received from the other side and a bitfield of the 32 before it. Reliable messages are
+//! resent until a packet carrying them is acknowledged and are handed over in the order they were
+//! sent, while unreliable messages are delivered as they come and dropped when a newer one
+//! already arrived. Unlike TCP, a lost snapshot never holds back the ones after it.
+//!
+//! Messages are not split across datagrams, so none can be longer than [`MAX_PAYLOAD_SIZE`]
+//! once encoded. Reliable messages that arrive more than [`RELIABLE_WINDOW`] ahead of the next
+//! one due are dropped unacknowledged, which bounds what a peer can make us hold on to.
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use anyhow::{Result, anyhow, bail};
+use bincode::{Decode, Encode, config};
+use tokio::{
+    net::{ToSocketAddrs, UdpSocket, lookup_host},
+    select,
+    sync::mpsc::UnboundedReceiver,
+    time::{self, Duration, Instant, Interval, MissedTickBehavior},
+};
+
+use super::{Delivery, Message, frame::Frame};
+
+/// Time after which an unacknowledged reliable message is sent again
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+/// Longest time without sending anything, so the other side knows we are still here
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(500);
+/// Time without hearing from the other side after which the connection counts as closed
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest payload a single UDP datagram can carry
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+/// Longest encoded header, with every number taking its full varint length
+const MAX_HEADER_SIZE: usize = 33;
+/// Longest encoded message that fits in a datagram along with its header
+pub const MAX_PAYLOAD_SIZE: usize = MAX_DATAGRAM_SIZE - MAX_HEADER_SIZE;
+/// How far ahead of the next reliable message due one may arrive and still be kept
+pub const RELIABLE_WINDOW: u64 = 1024;
+
+/// Number of earlier packets acknowledged by the bitfield of every packet
+const ACK_BITS: u64 = 32;
+
+/// Prefix of every datagram, followed by the encoded message if there is one
+#[derive(Debug, Decode, Encode)]
+struct Header {
+    seq: u64,
+    /// Latest packet received from the other side, 0 if none yet
+    ack: u64,
+    /// Bit `n` acknowledges packet `ack - 1 - n`
+    ack_bits: u32,
+    /// Position of the message in the reliable stream, `None` for unreliable messages
+    reliable_id: Option<u64>,
+}
+
+/// A reliable message waiting to be acknowledged
+struct InFlight {
+    payload: Vec<u8>,
+    sent_at: Instant,
+}
+
+/// Sequencing, acknowledgement and resend bookkeeping for one side of a connection.
+///
+/// It does no IO by itself: it turns payloads into datagrams and datagrams back into payloads,
+/// so both the server and the client can drive it from their own sockets.
+pub struct Endpoint {
+    next_seq: u64,
+    /// Latest packet received and the bitfield of the ones before it
+    received_seq: u64,
+    received_bits: u32,
+    /// Latest packet when the loss was last taken, and the packets received since
+    loss_from: u64,
+    loss_received: u64,
+
+    next_reliable_id: u64,
+    in_flight: BTreeMap<u64, InFlight>,
+    /// Reliable message carried by each packet that may still be acknowledged
+    packet_reliable_ids: BTreeMap<u64, u64>,
+
+    /// Next reliable message to hand over and the ones that arrived ahead of it
+    expected_reliable_id: u64,
+    early_reliable: BTreeMap<u64, Vec<u8>>,
+    /// Packet of the newest unreliable message handed over
+    newest_unreliable_seq: u64,
+
+    /// Whether a reliable message arrived that the other side has not seen an ack for
+    ack_owed: bool,
+    last_sent: Instant,
+}
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Endpoint {
+    pub fn new() -> Self {
+        Self {
+            next_seq: 1,
+            received_seq: 0,
+            received_bits: 0,
+            loss_from: 0,
+            loss_received: 0,
+            next_reliable_id: 0,
+            in_flight: BTreeMap::new(),
+            packet_reliable_ids: BTreeMap::new(),
+            expected_reliable_id: 0,
+            early_reliable: BTreeMap::new(),
+            newest_unreliable_seq: 0,
+            ack_owed: false,
+            last_sent: Instant::now(),
+        }
+    }
+
+    /// Wraps a payload in a datagram, remembering it for resending if it is reliable. Payloads
+    /// longer than [`MAX_PAYLOAD_SIZE`] are refused.
+    pub fn send(&mut self, payload: Vec<u8>, delivery: Delivery, now: Instant) -> Result<Vec<u8>> {
+        if payload.len() > MAX_PAYLOAD_SIZE {
+            bail!(
+                "A message of {} bytes does not fit in a datagram, the most is {MAX_PAYLOAD_SIZE}",
+                payload.len()
+            );
+        }
+        let datagram = match delivery {
+            Delivery::Unreliable => self.packet(None, &payload, now),
+            Delivery::Reliable => {
+                let id = self.next_reliable_id;
+                self.next_reliable_id += 1;
+                let datagram = self.packet(Some(id), &payload, now);
+                self.in_flight.insert(
+                    id,
+                    InFlight {
+                        payload,
+                        sent_at: now,
+                    },
+                );
+                datagram
+            }
+        };
+        Ok(datagram)
+    }
+
+    /// Processes a datagram from the other side and returns the payloads that are ready, in order.
+    pub fn receive(&mut self, datagram: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let (header, len): (Header, usize) =
+            bincode::decode_from_slice(datagram, config::standard())?;
+        let payload = &datagram[len..];
+
+        self.process_acks(header.ack, header.ack_bits);
+        if header
+            .reliable_id
+            .is_some_and(|id| id >= self.expected_reliable_id + RELIABLE_WINDOW)
+        {
+            // Left unacknowledged, so a genuine peer sends it again once we caught up
+            return Ok(Vec::new());
+        }
+        if !self.record_received(header.seq) {
+            // Duplicate packet
+            return Ok(Vec::new());
+        }
+
+        match header.reliable_id {
+            Some(id) => {
+                self.ack_owed = true;
+                if id >= self.expected_reliable_id {
+                    self.early_reliable.insert(id, payload.to_vec());
+                }
+                let mut ready = Vec::new();
+                while let Some(payload) = self.early_reliable.remove(&self.expected_reliable_id) {
+                    ready.push(payload);
+                    self.expected_reliable_id += 1;
+                }
+                Ok(ready)
+            }
+            // Packets without a payload only carry acks
+            None if payload.is_empty() => Ok(Vec::new()),
+            None if header.seq > self.newest_unreliable_seq => {
+                self.newest_unreliable_seq = header.seq;
+                Ok(vec![payload.to_vec()])
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the datagrams that are due: reliable messages that went unacknowledged for too
+    /// long, and a bare ack when one is owed or nothing was sent for a while.
+    pub fn poll(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let due: Vec<(u64, Vec<u8>)> = self
+            .in_flight
+            .iter()
+            .filter(|(_, msg)| now - msg.sent_at >= RESEND_INTERVAL)
+            .map(|(id, msg)| (*id, msg.payload.clone()))
+            .collect();
+
+        let mut datagrams = Vec::new();
+        for (id, payload) in due {
+            datagrams.push(self.packet(Some(id), &payload, now));
+            if let Some(msg) = self.in_flight.get_mut(&id) {
+                msg.sent_at = now;
+            }
+        }
+        if datagrams.is_empty() && (self.ack_owed || now - self.last_sent >= KEEPALIVE_INTERVAL) {
+            datagrams.push(self.packet(None, &[], now));
+        }
+        datagrams
+    }
+
+    /// A bare ack for the reliable messages received, if one is owed
+    pub fn owed_ack(&mut self, now: Instant) -> Option<Vec<u8>> {
+        self.ack_owed.then(|| self.packet(None, &[], now))
+    }
+
+    /// Whether every reliable message sent so far was acknowledged
+    pub fn all_acknowledged(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Share of the packets the other side sent since the last call that never arrived, from 0
+    /// to 1. None when no newer packet arrived since.
+    pub fn take_loss(&mut self) -> Option<f32> {
+        let expected = self.received_seq - self.loss_from;
+        let received = std::mem::take(&mut self.loss_received).min(expected);
+        self.loss_from = self.received_seq;
+        (expected > 0).then(|| 1.0 - received as f32 / expected as f32)
+    }
+
+    fn packet(&mut self, reliable_id: Option<u64>, payload: &[u8], now: Instant) -> Vec<u8> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some(id) = reliable_id {
+            self.packet_reliable_ids.insert(seq, id);
+        }
+        self.ack_owed = false;
+        self.last_sent = now;
+
+        let header = Header {
+            seq,
+            ack: self.received_seq,
+            ack_bits: self.received_bits,
+            reliable_id,
+        };
+        let mut datagram = bincode::encode_to_vec(&header, config::standard())
+            .expect("Encoding a header cannot fail");
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    fn process_acks(&mut self, ack: u64, ack_bits: u32) {
+        if ack == 0 {
+            return;
+        }
+        let acked = (0..ACK_BITS)
+            .filter(|bit| ack_bits & (1 << bit) != 0)
+            .filter_map(|bit| ack.checked_sub(bit + 1))
+            .chain([ack]);
+        for seq in acked {
+            if let Some(id) = self.packet_reliable_ids.remove(&seq) {
+                self.in_flight.remove(&id);
+            }
+        }
+        // Packets older than the bitfield can no longer be acknowledged
+        self.packet_reliable_ids = self
+            .packet_reliable_ids
+            .split_off(&ack.saturating_sub(ACK_BITS));
+    }
+
+    /// Marks a packet as received, returning false if it was already.
+    fn record_received(&mut self, seq: u64) -> bool {
+        let new = self.record_seq(seq);
+        if new {
+            self.loss_received += 1;
+        }
+        new
+    }
+
+    fn record_seq(&mut self, seq: u64) -> bool {
+        if seq > self.received_seq {
+            let shift = seq - self.received_seq;
+            self.received_bits = if shift > ACK_BITS || self.received_seq == 0 {
+                0
+            } else {
+                // The previous latest packet moves into the bitfield
+                ((self.received_bits as u64) << shift | 1 << (shift - 1)) as u32
+            };
+            self.received_seq = seq;
+            true
+        } else if seq == self.received_seq {
+            false
+        } else {
+            let bit = self.received_seq - seq - 1;
+            // Too old to be tracked, it could be a duplicate so it is dropped
+            if bit >= ACK_BITS || self.received_bits & (1 << bit) != 0 {
+                return false;
+            }
+            self.received_bits |= 1 << bit;
+            true
+        }
+    }
+}
+
+/// Whether a datagram carries the first reliable message of a connection, which is what a peer
+/// sends to open one. Other datagrams from unknown peers are leftovers of closed connections.
+pub fn opens_connection(datagram: &[u8]) -> bool {
+    bincode::decode_from_slice::<Header, _>(datagram, config::standard())
+        .is_ok_and(|(header, _)| header.reliable_id == Some(0))
+}
+
+/// Where a connection gets its datagrams from
+enum Incoming {
+    /// Read from the socket, which only talks to the peer
+    Socket,
+    /// Handed over by whoever owns a socket shared between several peers
+    Channel(UnboundedReceiver<Vec<u8>>),
+}
+
+/// A message connection to a single peer over UDP.
+pub struct UdpConnection {
+    socket: Arc<UdpSocket>,
+    peer: SocketAddr,
+    incoming: Incoming,
+    endpoint: Endpoint,
+    /// Payloads received but not handed over yet
+    ready: VecDeque<Vec<u8>>,
+    timer: Interval,
+    last_heard: Instant,
+}
+impl UdpConnection {
+    /// Opens a connection from a fresh local socket to `addr`.
+    pub async fn connect<T: ToSocketAddrs>(addr: T) -> Result<Self> {
+        let peer = lookup_host(addr)
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("Address did not resolve"))?;
+        let local: SocketAddr = if peer.is_ipv4() {
+            "0.0.0.0:0".parse()?
+        } else {
+            "[::]:0".parse()?
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(peer).await?;
+        Ok(Self::new(Arc::new(socket), peer, Incoming::Socket))
+    }
+
+    /// Creates the connection for a peer of a shared socket, whose datagrams arrive on `incoming`.
+    pub fn accept(
+        socket: Arc<UdpSocket>,
+        peer: SocketAddr,
+        incoming: UnboundedReceiver<Vec<u8>>,
+    ) -> Self {
+        Self::new(socket, peer, Incoming::Channel(incoming))
+    }
+
+    fn new(socket: Arc<UdpSocket>, peer: SocketAddr, incoming: Incoming) -> Self {
+        let mut timer = time::interval(RESEND_INTERVAL);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            socket,
+            peer,
+            incoming,
+            endpoint: Endpoint::new(),
+            ready: VecDeque::new(),
+            timer,
+            last_heard: Instant::now(),
+        }
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Share of the datagrams from the peer lost since the last call, see [`Endpoint::take_loss`]
+    pub fn take_loss(&mut self) -> Option<f32> {
+        self.endpoint.take_loss()
+    }
+
+    /// Sends a message with the delivery it asks for.
+    pub async fn send<M: Message>(&mut self, msg: &M) -> Result<()> {
+        self.send_frame(msg.encode()?, msg.delivery()).await
+    }
+
+    /// Sends a message already encoded into a frame, such as a compressed one
+    pub async fn send_frame(&mut self, frame: Vec<u8>, delivery: Delivery) -> Result<()> {
+        let datagram = self.endpoint.send(frame, delivery, Instant::now())?;
+        self.socket.send_to(&datagram, self.peer).await?;
+        Ok(())
+    }
+
+    /// Waits for the next message, returning `None` once the peer timed out or went away.
+    ///
+    /// Resends and acks are sent from here, so the connection has to be read from continuously.
+    /// Cancelling the returned future does not lose any message.
+    pub async fn recv<M: Message>(&mut self) -> Result<Option<M>> {
+        loop {
+            let Some(payload) = self.recv_frame().await? else {
+                return Ok(None);
+            };
+            match M::decode(&payload)? {
+                Frame::Message(msg, _) => return Ok(Some(msg)),
+                Frame::Unknown { type_id, .. } => {
+                    eprintln!("Skipped a message of unknown type {type_id}");
+                }
+                Frame::Incomplete => bail!("Truncated message"),
+            }
+        }
+    }
+
+    /// Waits for the next frame as it was sent, like [`recv`](Self::recv) does for messages
+    pub async fn recv_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            // Every payload holds exactly one frame
+            if let Some(payload) = self.ready.pop_front() {
+                return Ok(Some(payload));
+            }
+
+            select! {
+                datagram = Self::next_datagram(&self.socket, &mut self.incoming, self.peer) => {
+                    let Some(datagram) = datagram? else {
+                        return Ok(None);
+                    };
+                    // Anything that does not parse is not from our peer, so it is ignored
+                    if let Ok(payloads) = self.endpoint.receive(&datagram) {
+                        self.last_heard = Instant::now();
+                        self.ready.extend(payloads);
+                    }
+                }
+                _ = self.timer.tick() => {
+                    if self.last_heard.elapsed() > TIMEOUT {
+                        return Ok(None);
+                    }
+                    for datagram in self.endpoint.poll(Instant::now()) {
+                        self.socket.send_to(&datagram, self.peer).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Acknowledges the reliable messages received right away, as when dropping the connection
+    /// after the last one, which otherwise would be sent again until the peer gives up.
+    pub async fn acknowledge(&mut self) -> Result<()> {
+        if let Some(datagram) = self.endpoint.owed_ack(Instant::now()) {
+            self.socket.send_to(&datagram, self.peer).await?;
+        }
+        Ok(())
+    }
+
+    /// Waits until the peer acknowledged every reliable message sent, resending them as needed.
+    /// Gives up once the peer timed out or went away, messages received meanwhile are kept for
+    /// [`recv`](Self::recv).
+    pub async fn flush(&mut self) -> Result<()> {
+        while !self.endpoint.all_acknowledged() {
+            select! {
+                datagram = Self::next_datagram(&self.socket, &mut self.incoming, self.peer) => {
+                    let Some(datagram) = datagram? else {
+                        return Ok(());
+                    };
+                    if let Ok(payloads) = self.endpoint.receive(&datagram) {
+                        self.last_heard = Instant::now();
+                        self.ready.extend(payloads);
+                    }
+                }
+                _ = self.timer.tick() => {
+                    if self.last_heard.elapsed() > TIMEOUT {
+                        return Ok(());
+                    }
+                    for datagram in self.endpoint.poll(Instant::now()) {
+                        self.socket.send_to(&datagram, self.peer).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn next_datagram(
+        socket: &UdpSocket,
+        incoming: &mut Incoming,
+        peer: SocketAddr,
+    ) -> Result<Option<Vec<u8>>> {
+        match incoming {
+            Incoming::Channel(rx) => Ok(rx.recv().await),
+            Incoming::Socket => {
+                let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+                loop {
+                    let (len, from) = socket.recv_from(&mut buffer).await?;
+                    if from == peer {
+                        buffer.truncate(len);
+                        return Ok(Some(buffer));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ClientMessage;
+
+    /// Datagram carrying reliable message `id` in packet `seq`, as a peer could forge it
+    fn reliable(seq: u64, id: u64) -> Vec<u8> {
+        let header = Header {
+            seq,
+            ack: 0,
+            ack_bits: 0,
+            reliable_id: Some(id),
+        };
+        let mut datagram = bincode::encode_to_vec(&header, config::standard()).unwrap();
+        datagram.extend_from_slice(&[0; 1024]);
+        datagram
+    }
+
+    #[test]
+    fn reliable_messages_far_ahead_are_dropped() {
+        let mut endpoint = Endpoint::new();
+        assert!(
+            endpoint
+                .receive(&reliable(1, RELIABLE_WINDOW))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(endpoint.early_reliable.is_empty());
+        assert_eq!(endpoint.owed_ack(Instant::now()), None);
+
+        // Within the window it waits for the ones before it
+        assert!(
+            endpoint
+                .receive(&reliable(2, RELIABLE_WINDOW - 1))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(endpoint.early_reliable.len(), 1);
+    }
+
+    #[test]
+    fn payloads_must_fit_in_a_datagram() {
+        let mut endpoint = Endpoint::new();
+        // Numbers as long as they get encoded
+        endpoint.next_seq = u64::MAX - 1;
+        endpoint.received_seq = u64::MAX;
+        endpoint.received_bits = u32::MAX;
+        endpoint.next_reliable_id = u64::MAX - 1;
+        let now = Instant::now();
+
+        let datagram = endpoint
+            .send(vec![0; MAX_PAYLOAD_SIZE], Delivery::Reliable, now)
+            .unwrap();
+        assert_eq!(datagram.len(), MAX_DATAGRAM_SIZE);
+        assert!(
+            endpoint
+                .send(vec![0; MAX_PAYLOAD_SIZE + 1], Delivery::Reliable, now)
+                .is_err()
+        );
+        // A refused message is not resent
+        assert_eq!(endpoint.in_flight.len(), 1);
+    }
+
+    #[test]
+    fn a_disconnect_is_acknowledged_without_waiting_for_a_poll() {
+        let now = Instant::now();
+        let (mut client, mut server) = (Endpoint::new(), Endpoint::new());
+        let goodbye = ClientMessage::Disconnect.encode().unwrap();
+        let datagram = client
+            .send(goodbye.clone(), Delivery::Reliable, now)
+            .unwrap();
+        assert_eq!(server.receive(&datagram).unwrap(), [goodbye]);
+
+        // The server owes one ack, which it sends before dropping the connection
+        let ack = server.owed_ack(now).expect("the Disconnect is owed an ack");
+        assert_eq!(server.owed_ack(now), None);
+        assert!(client.receive(&ack).unwrap().is_empty());
+        assert!(client.all_acknowledged());
+    }
+}
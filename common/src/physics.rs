@@ -0,0 +1,497 @@
+//! Movement physics shared by the server simulation and client prediction.
+//!
+//! Every function here is pure: it takes the current state and a [`PhysicsConfig`] and returns the
+//! new state, so both sides of the connection compute exactly the same motion from the same inputs.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    vec::Vec2,
+    world::environment::{Attractor, Object},
+};
+
+/// Tunables that control how entities move
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsConfig {
+    /// Fraction of velocity lost per second
+    pub friction: f32,
+    /// Collision radius of a player
+    pub player_radius: f32,
+    /// Fastest a player can walk, in world units per second
+    pub max_speed: f32,
+    /// Whether players push each other apart, off lets them walk through one another
+    pub player_collision: bool,
+    /// How quickly overlapping players are pushed apart, as the fraction of the overlap resolved
+    /// per second
+    pub push_stiffness: f32,
+}
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            friction: 0.0,
+            player_radius: 0.05,
+            max_speed: 1.0,
+            player_collision: false,
+            push_stiffness: 10.0,
+        }
+    }
+}
+
+/// Velocity a player walks at when heading in `dir`, which is clamped to at most unit length so
+/// that no input can go faster than `max_speed`. Anything that is not a finite direction stops.
+pub fn walk_velocity(dir: Vec2, config: &PhysicsConfig) -> Vec2 {
+    if !dir.is_finite() {
+        return Vec2::ZERO;
+    }
+    clamp_to_unit(dir) * config.max_speed
+}
+
+/// `dir` shortened to unit length if it is longer, the furthest an input can push
+pub fn clamp_to_unit(dir: Vec2) -> Vec2 {
+    let length = dir.length();
+    if length > 1.0 { dir / length } else { dir }
+}
+
+/// Moves a position along a velocity for `dt` seconds.
+pub fn integrate(pos: Vec2, vel: Vec2, dt: f32) -> Vec2 {
+    Vec2 {
+        x: pos.x + vel.x * dt,
+        y: pos.y + vel.y * dt,
+    }
+}
+
+/// Slows a velocity down by `friction` over `dt` seconds, never reversing its direction.
+pub fn apply_friction(vel: Vec2, friction: f32, dt: f32) -> Vec2 {
+    vel * (1.0 - friction * dt).clamp(0.0, 1.0)
+}
+
+/// Speeds up a body at `pos` moving at `vel` by the pull of every attractor it is within reach of
+/// over `dt` seconds. The pull is strongest at the center of an attractor and fades linearly to
+/// nothing at its radius, a body right on the center is not pulled anywhere.
+pub fn attract(pos: Vec2, vel: Vec2, attractors: &[Attractor], dt: f32) -> Vec2 {
+    let mut vel = vel;
+    for attractor in attractors {
+        let offset = attractor.pos - pos;
+        let distance = offset.length();
+        if distance <= 0.0 || distance >= attractor.radius {
+            continue;
+        }
+        let pull = attractor.strength * (1.0 - distance / attractor.radius);
+        vel += offset * (pull * dt / distance);
+    }
+    vel
+}
+
+/// Whether a circle at `pos` touches an object
+pub fn overlaps(pos: Vec2, radius: f32, object: &Object) -> bool {
+    let closest = Vec2 {
+        x: pos.x.clamp(object.pos.x, object.pos.x + object.size.x),
+        y: pos.y.clamp(object.pos.y, object.pos.y + object.size.y),
+    };
+    (pos - closest).length() < radius
+}
+
+/// Whether the line segment from `from` to `to` passes through an object
+pub fn segment_hits(from: Vec2, to: Vec2, object: &Object) -> bool {
+    let delta = to - from;
+    let min = object.pos;
+    let max = object.pos + object.size;
+    // Clip the segment against the box one axis at a time
+    let mut enter: f32 = 0.0;
+    let mut exit: f32 = 1.0;
+    for (start, step, low, high) in [
+        (from.x, delta.x, min.x, max.x),
+        (from.y, delta.y, min.y, max.y),
+    ] {
+        if step == 0.0 {
+            if start < low || start > high {
+                return false;
+            }
+            continue;
+        }
+        let a = (low - start) / step;
+        let b = (high - start) / step;
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+        if enter > exit {
+            return false;
+        }
+    }
+    true
+}
+
+/// Pushes a circle at `pos` out of every object it overlaps, returning the corrected position.
+pub fn resolve_collisions(pos: Vec2, radius: f32, objects: &[Object]) -> Vec2 {
+    let mut pos = pos;
+    for object in objects {
+        let min = object.pos;
+        let max = object.pos + object.size;
+        let closest = Vec2 {
+            x: pos.x.clamp(min.x, max.x),
+            y: pos.y.clamp(min.y, max.y),
+        };
+        let offset = pos - closest;
+        let distance = offset.length();
+
+        if distance > 0.0 {
+            // Center outside the box: push away from the closest point on its edge
+            if distance < radius {
+                pos += offset * ((radius - distance) / distance);
+            }
+        } else {
+            // Center inside the box: leave through the nearest side
+            let exits = [
+                (pos.x - min.x + radius, Vec2 { x: -1.0, y: 0.0 }),
+                (max.x - pos.x + radius, Vec2 { x: 1.0, y: 0.0 }),
+                (pos.y - min.y + radius, Vec2 { x: 0.0, y: -1.0 }),
+                (max.y - pos.y + radius, Vec2 { x: 0.0, y: 1.0 }),
+            ];
+            let (depth, direction) = exits
+                .into_iter()
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .unwrap();
+            pos += direction * depth;
+        }
+    }
+    pos
+}
+
+/// Bounces a circle at `pos` moving at `vel` off the objects it overlaps. Returns its position
+/// pushed out of them and its velocity mirrored off their surface and scaled by `restitution`, or
+/// `None` if it touches nothing.
+pub fn bounce(
+    pos: Vec2,
+    vel: Vec2,
+    radius: f32,
+    objects: &[Object],
+    restitution: f32,
+) -> Option<(Vec2, Vec2)> {
+    if !objects.iter().any(|object| overlaps(pos, radius, object)) {
+        return None;
+    }
+    let resolved = resolve_collisions(pos, radius, objects);
+    let push = resolved - pos;
+    let normal = if push.length() > 0.0 {
+        push / push.length()
+    } else if vel.length() > 0.0 {
+        vel / -vel.length()
+    } else {
+        return None;
+    };
+    let reflected = vel - normal * (2.0 * vel.dot(normal));
+    Some((resolved, reflected * restitution))
+}
+
+/// Moves the body `id` at `pos` out of the other bodies it overlaps, each body taking half of the
+/// push, and returns its new position. Bodies are given with their ids so that two bodies at the
+/// exact same spot still part ways, the lower id going left.
+///
+/// Only positions from before the step are read, so the result does not depend on the order
+/// bodies are processed in.
+pub fn push_apart(
+    id: u64,
+    pos: Vec2,
+    radius: f32,
+    bodies: &[(u64, Vec2)],
+    config: &PhysicsConfig,
+    objects: &[Object],
+    dt: f32,
+) -> Vec2 {
+    let softness = (config.push_stiffness * dt).clamp(0.0, 1.0);
+    let mut push = Vec2::ZERO;
+    for &(other_id, other_pos) in bodies {
+        if other_id == id {
+            continue;
+        }
+        let offset = pos - other_pos;
+        let distance = offset.length();
+        let overlap = 2.0 * radius - distance;
+        if overlap <= 0.0 {
+            continue;
+        }
+        let direction = if distance > 0.0 {
+            offset / distance
+        } else if id < other_id {
+            Vec2 { x: -1.0, y: 0.0 }
+        } else {
+            Vec2 { x: 1.0, y: 0.0 }
+        };
+        push += direction * (overlap * 0.5 * softness);
+    }
+    // Players are never pushed into walls
+    let pushed = resolve_collisions(pos + push.sanitize_or_zero(), radius, objects);
+    if pushed.is_finite() { pushed } else { pos }
+}
+
+/// Runs one simulation step for a body, returning its new position and velocity.
+///
+/// Inputs are sanitized before they get here, so a body that is not finite is a bug. Should a
+/// step still come out that way, the body stops where it was instead of carrying NaN into the
+/// world every client draws.
+pub fn step(
+    pos: Vec2,
+    vel: Vec2,
+    radius: f32,
+    config: &PhysicsConfig,
+    objects: &[Object],
+    dt: f32,
+) -> (Vec2, Vec2) {
+    debug_assert!(
+        pos.is_finite() && vel.is_finite(),
+        "Stepping a body at {pos:?} moving at {vel:?}"
+    );
+    let vel = apply_friction(vel, config.friction, dt).sanitize_or_zero();
+    let moved = resolve_collisions(integrate(pos, vel, dt), radius, objects);
+    if moved.is_finite() {
+        (moved, vel)
+    } else {
+        (pos.sanitize_or_zero(), Vec2::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::environment::OBJECT_COLOR;
+
+    const EPSILON: f32 = 1e-6;
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!((a - b).length() < EPSILON, "expected {b:?}, got {a:?}");
+    }
+
+    fn unit_box() -> Object {
+        Object {
+            pos: Vec2::ZERO,
+            size: Vec2::ONE,
+            color: OBJECT_COLOR,
+            sprite: None,
+        }
+    }
+
+    #[test]
+    fn integrate_moves_along_velocity() {
+        let pos = integrate(Vec2 { x: 1.0, y: 2.0 }, Vec2 { x: 2.0, y: -4.0 }, 0.5);
+        assert_close(pos, Vec2 { x: 2.0, y: 0.0 });
+    }
+
+    #[test]
+    fn integrate_without_velocity_stays_put() {
+        let start = Vec2 { x: 3.0, y: -1.0 };
+        assert_eq!(integrate(start, Vec2::ZERO, 1.0), start);
+    }
+
+    #[test]
+    fn walking_is_capped_at_max_speed() {
+        let config = PhysicsConfig {
+            max_speed: 2.0,
+            ..PhysicsConfig::default()
+        };
+        // A teleport sized input walks at full speed and no faster
+        let vel = walk_velocity(Vec2 { x: 300.0, y: 400.0 }, &config);
+        assert_close(vel, Vec2 { x: 1.2, y: 1.6 });
+        // Slower inputs are kept as they are
+        let slow = Vec2 { x: 0.25, y: 0.0 };
+        assert_close(walk_velocity(slow, &config), slow * 2.0);
+        let nan = Vec2 {
+            x: f32::NAN,
+            y: 0.0,
+        };
+        assert_eq!(walk_velocity(nan, &config), Vec2::ZERO);
+    }
+
+    #[test]
+    fn friction_scales_velocity() {
+        let vel = apply_friction(Vec2 { x: 2.0, y: -2.0 }, 0.5, 0.5);
+        assert_close(vel, Vec2 { x: 1.5, y: -1.5 });
+    }
+
+    #[test]
+    fn zero_friction_keeps_velocity() {
+        let vel = Vec2 { x: 1.0, y: 1.0 };
+        assert_eq!(apply_friction(vel, 0.0, 1.0), vel);
+    }
+
+    #[test]
+    fn friction_never_reverses_velocity() {
+        let vel = apply_friction(Vec2 { x: 1.0, y: 0.0 }, 10.0, 1.0);
+        assert_eq!(vel, Vec2::ZERO);
+    }
+
+    #[test]
+    fn no_collision_far_from_objects() {
+        let pos = Vec2 { x: 3.0, y: 3.0 };
+        assert_eq!(resolve_collisions(pos, 0.1, &[unit_box()]), pos);
+    }
+
+    #[test]
+    fn touching_an_edge_is_not_a_collision() {
+        let pos = Vec2 { x: 1.1, y: 0.5 };
+        assert_eq!(resolve_collisions(pos, 0.1, &[unit_box()]), pos);
+    }
+
+    #[test]
+    fn overlap_with_side_pushes_out() {
+        let pos = resolve_collisions(Vec2 { x: 1.05, y: 0.5 }, 0.1, &[unit_box()]);
+        assert_close(pos, Vec2 { x: 1.1, y: 0.5 });
+    }
+
+    #[test]
+    fn overlap_with_corner_pushes_out_diagonally() {
+        let pos = resolve_collisions(Vec2 { x: 1.05, y: 1.05 }, 0.1, &[unit_box()]);
+        let diagonal = (0.1f32 * 0.1 / 2.0).sqrt();
+        assert_close(
+            pos,
+            Vec2 {
+                x: 1.0 + diagonal,
+                y: 1.0 + diagonal,
+            },
+        );
+    }
+
+    #[test]
+    fn center_inside_exits_through_nearest_side() {
+        let pos = resolve_collisions(Vec2 { x: 0.5, y: 0.9 }, 0.1, &[unit_box()]);
+        assert_close(pos, Vec2 { x: 0.5, y: 1.1 });
+    }
+
+    #[test]
+    fn resolves_against_every_object() {
+        let left = Object {
+            pos: Vec2 { x: -1.0, y: 0.0 },
+            size: Vec2::ONE,
+            color: OBJECT_COLOR,
+            sprite: None,
+        };
+        let right = Object {
+            pos: Vec2 { x: 1.0, y: 0.0 },
+            size: Vec2::ONE,
+            color: OBJECT_COLOR,
+            sprite: None,
+        };
+        let pos = resolve_collisions(Vec2 { x: 0.05, y: 0.5 }, 0.1, &[left, right]);
+        assert_close(pos, Vec2 { x: 0.1, y: 0.5 });
+    }
+
+    #[test]
+    fn step_applies_friction_then_integrates_then_collides() {
+        let config = PhysicsConfig {
+            friction: 1.0,
+            player_radius: 0.1,
+            ..PhysicsConfig::default()
+        };
+        let (pos, vel) = step(
+            Vec2 { x: 1.4, y: 0.5 },
+            Vec2 { x: -2.0, y: 0.0 },
+            config.player_radius,
+            &config,
+            &[unit_box()],
+            0.25,
+        );
+        assert_close(vel, Vec2 { x: -1.5, y: 0.0 });
+        assert_close(pos, Vec2 { x: 1.1, y: 0.5 });
+    }
+
+    #[test]
+    fn step_is_deterministic() {
+        let config = PhysicsConfig::default();
+        let objects = [unit_box()];
+        let run = || {
+            let (mut pos, mut vel) = (Vec2 { x: -0.5, y: 0.3 }, Vec2 { x: 0.7, y: 0.1 });
+            for _ in 0..1000 {
+                (pos, vel) = step(
+                    pos,
+                    vel,
+                    config.player_radius,
+                    &config,
+                    &objects,
+                    1.0 / 60.0,
+                );
+            }
+            (pos, vel)
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn attractors_pull_within_their_radius() {
+        let well = Attractor {
+            pos: Vec2::ZERO,
+            strength: 4.0,
+            radius: 2.0,
+        };
+        // Halfway out the pull is at half strength
+        let vel = attract(Vec2 { x: 1.0, y: 0.0 }, Vec2::ZERO, &[well], 0.5);
+        assert_close(vel, Vec2 { x: -1.0, y: 0.0 });
+
+        let repulsor = Attractor {
+            strength: -4.0,
+            ..well
+        };
+        let vel = attract(Vec2 { x: 0.0, y: 1.0 }, Vec2::ZERO, &[repulsor], 0.5);
+        assert_close(vel, Vec2 { x: 0.0, y: 1.0 });
+
+        // Out of reach and dead center alike leave the velocity alone
+        for pos in [Vec2 { x: 3.0, y: 0.0 }, Vec2::ZERO] {
+            assert_close(attract(pos, Vec2::ONE, &[well], 0.5), Vec2::ONE);
+        }
+    }
+
+    #[test]
+    fn broken_steps_stop_the_body() {
+        let config = PhysicsConfig {
+            friction: f32::NAN,
+            ..PhysicsConfig::default()
+        };
+        let pos = Vec2 { x: 3.0, y: 3.0 };
+        let (next, vel) = step(pos, Vec2::ONE, config.player_radius, &config, &[], 0.1);
+        assert_eq!((next, vel), (pos, Vec2::ZERO));
+
+        let config = PhysicsConfig::default();
+        let (next, vel) = step(pos, Vec2::ONE * f32::MAX, 0.05, &config, &[], 10.0);
+        assert_eq!((next, vel), (pos, Vec2::ZERO));
+    }
+
+    fn pushy() -> PhysicsConfig {
+        PhysicsConfig {
+            player_collision: true,
+            push_stiffness: 10.0,
+            ..PhysicsConfig::default()
+        }
+    }
+
+    #[test]
+    fn overlapping_bodies_push_each_other_equally() {
+        let bodies = [(1, Vec2 { x: 0.0, y: 0.0 }), (2, Vec2 { x: 0.1, y: 0.0 })];
+        let config = pushy();
+        let a = push_apart(1, bodies[0].1, 0.1, &bodies, &config, &[], 0.05);
+        let b = push_apart(2, bodies[1].1, 0.1, &bodies, &config, &[], 0.05);
+        // Half the 0.1 overlap, softened to half of that in a 0.05s step
+        assert_close(a, Vec2 { x: -0.025, y: 0.0 });
+        assert_close(b, Vec2 { x: 0.125, y: 0.0 });
+    }
+
+    #[test]
+    fn separated_bodies_are_not_pushed() {
+        let bodies = [(1, Vec2::ZERO), (2, Vec2 { x: 0.3, y: 0.0 })];
+        let pos = push_apart(1, Vec2::ZERO, 0.1, &bodies, &pushy(), &[], 0.05);
+        assert_eq!(pos, Vec2::ZERO);
+    }
+
+    #[test]
+    fn bodies_on_the_same_spot_part_ways() {
+        let bodies = [(1, Vec2::ZERO), (2, Vec2::ZERO)];
+        let a = push_apart(1, Vec2::ZERO, 0.1, &bodies, &pushy(), &[], 0.05);
+        let b = push_apart(2, Vec2::ZERO, 0.1, &bodies, &pushy(), &[], 0.05);
+        assert!(a.x < 0.0 && b.x > 0.0);
+        assert_close(a, b * -1.0);
+    }
+
+    #[test]
+    fn push_does_not_go_through_walls() {
+        let bodies = [(1, Vec2 { x: 1.1, y: 0.5 }), (2, Vec2 { x: 1.15, y: 0.5 })];
+        let pos = push_apart(1, bodies[0].1, 0.1, &bodies, &pushy(), &[unit_box()], 0.1);
+        assert_close(pos, Vec2 { x: 1.1, y: 0.5 });
+    }
+}
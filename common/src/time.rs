@@ -0,0 +1,19 @@
+//! Time helpers shared by the server and client.
+use std::{
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Milliseconds elapsed since the unix epoch, used to timestamp messages.
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Seconds on a monotonic clock shared by every thread of the process, used to time round trips.
+pub fn monotonic_secs() -> f64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs_f64()
+}
@@ -0,0 +1,65 @@
+//! Gameplay values the server resolves at startup and shares with every client, so that the
+//! simulation and client prediction agree on them.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    details::TICK_RATE,
+    physics::PhysicsConfig,
+    world::{
+        arena::SumoConfig,
+        checkpoint::RaceConfig,
+        combat::CombatConfig,
+        scoreboard::ScoringConfig,
+        zone::{BattleRoyaleConfig, StormConfig},
+    },
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tunables {
+    /// Simulation steps per second, on the server and in client prediction alike
+    pub tick_rate: f64,
+    pub physics: PhysicsConfig,
+    pub combat: CombatConfig,
+    pub sumo: SumoConfig,
+    pub storm: StormConfig,
+    pub battle_royale: BattleRoyaleConfig,
+    pub race: RaceConfig,
+    pub scoring: ScoringConfig,
+}
+impl Default for Tunables {
+    fn default() -> Self {
+        Self {
+            tick_rate: TICK_RATE,
+            physics: PhysicsConfig::default(),
+            combat: CombatConfig::default(),
+            sumo: SumoConfig::default(),
+            storm: StormConfig::default(),
+            battle_royale: BattleRoyaleConfig::default(),
+            race: RaceConfig::default(),
+            scoring: ScoringConfig::default(),
+        }
+    }
+}
+impl Tunables {
+    /// Length of a simulation step, in seconds
+    pub fn timestep(&self) -> f32 {
+        (1.0 / self.tick_rate) as f32
+    }
+
+    /// Simulation steps in `seconds`, none for negative ones
+    pub fn ticks(&self, seconds: f64) -> u64 {
+        ticks(seconds, self.tick_rate)
+    }
+
+    /// Whether `tick` starts a new second, for what is done once a second
+    pub fn every_second(&self, tick: u64) -> bool {
+        tick.is_multiple_of(self.ticks(1.0).max(1))
+    }
+}
+
+/// Simulation steps in `seconds` at `tick_rate` steps per second, none for negative ones
+pub fn ticks(seconds: f64, tick_rate: f64) -> u64 {
+    (seconds.max(0.0) * tick_rate) as u64
+}
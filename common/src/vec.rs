@@ -14,6 +14,24 @@ impl Vec2 {
     pub const ONE: Self = Self { x: 1.0, y: 1.0 };
 }
 impl Vec2 {
+    pub fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+    /// Whether neither component is NaN or infinite
+    pub fn is_finite(self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+    /// The vector itself if it is finite, zero otherwise
+    pub fn sanitize_or_zero(self) -> Self {
+        if self.is_finite() { self } else { Self::ZERO }
+    }
+}
+impl Vec2 {
+    /// Point in the unit square from the operating system's generator, for effects only. Anything
+    /// that changes the game rolls with the world's [`GameRng`](crate::world::rng::GameRng)
     pub fn random() -> Self {
         use rand::Rng;
         let mut rng = rand::rng();
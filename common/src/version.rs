@@ -1,15 +1,44 @@
 use std::cmp::Ordering;
 use std::fmt::Display;
 
-#[derive(PartialEq)]
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Version of the messages spoken between clients and servers, see [`Version::is_compatible`]
+pub const PROTOCOL_VERSION: Version = Version {
+    major: 1,
+    minor: 0,
+    patch: 0,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
 pub struct Version {
     pub major: u32,
     pub minor: u32,
     pub patch: u32,
 }
-impl From<&str> for Version {
-    fn from(value: &str) -> Self {
-        Version::try_from(value).unwrap()
+impl Version {
+    /// Whether peers speaking these two protocol versions understand each other. Message types
+    /// added in a minor version are skipped by the other side, so the major version has to
+    /// match and the minor ones may be one apart.
+    pub fn is_compatible(&self, other: &Version) -> bool {
+        self.major == other.major && self.minor.abs_diff(other.minor) <= 1
+    }
+}
+impl TryFrom<&str> for Version {
+    type Error = anyhow::Error;
+
+    /// Parses a version written as `major.minor.patch`
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut parts = value.trim().split('.').map(str::parse::<u32>);
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Ok(Self {
+                major,
+                minor,
+                patch,
+            }),
+            _ => Err(anyhow::anyhow!("Invalid version: {value}")),
+        }
     }
 }
 impl Display for Version {
@@ -28,3 +57,18 @@ impl PartialOrd for Version {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_minor_version_apart_is_compatible() {
+        let version = |text: &str| Version::try_from(text).unwrap();
+        assert!(version("1.2.0").is_compatible(&version("1.2.7")));
+        assert!(version("1.2.0").is_compatible(&version("1.3.0")));
+        assert!(version("1.3.0").is_compatible(&version("1.2.0")));
+        assert!(!version("1.1.0").is_compatible(&version("1.3.0")));
+        assert!(!version("1.2.0").is_compatible(&version("2.2.0")));
+    }
+}
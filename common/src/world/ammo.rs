@@ -0,0 +1,229 @@
+//! Ammunition: rounds loaded in each weapon, rounds carried besides, and reloading.
+//!
+//! Shots take a round from the magazine of their weapon, and a player with an empty magazine has
+//! to reload, which moves rounds from the reserve into it over some seconds. Emptying a magazine
+//! starts reloading on its own. Ammo pickups top the reserve up. The server is the only one to
+//! change ammo, clients see it in snapshots.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    combat::{CombatConfig, ProjectileKind},
+    entities::Entities,
+};
+
+/// Ammo of one weapon
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(deny_unknown_fields)]
+pub struct WeaponAmmo {
+    /// Rounds a full magazine holds
+    pub magazine: u32,
+    /// Rounds carried besides the magazine when spawning, and the most that can be carried
+    pub reserve: u32,
+    /// Seconds a reload takes
+    pub reload_time: f32,
+}
+
+/// Tunables for ammo, part of the combat tunables
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct AmmoConfig {
+    pub bullet: WeaponAmmo,
+    pub ricochet: WeaponAmmo,
+    pub piercing: WeaponAmmo,
+    pub explosive: WeaponAmmo,
+    /// Magazines of every weapon an ammo pickup adds to the reserve
+    pub pickup_magazines: u32,
+}
+impl Default for AmmoConfig {
+    fn default() -> Self {
+        let weapon = |magazine, reserve, reload_time| WeaponAmmo {
+            magazine,
+            reserve,
+            reload_time,
+        };
+        Self {
+            bullet: weapon(12, 48, 1.2),
+            ricochet: weapon(8, 32, 1.5),
+            piercing: weapon(5, 20, 2.0),
+            explosive: weapon(2, 6, 2.5),
+            pickup_magazines: 2,
+        }
+    }
+}
+impl AmmoConfig {
+    pub fn weapon(&self, kind: ProjectileKind) -> &WeaponAmmo {
+        match kind {
+            ProjectileKind::Bullet => &self.bullet,
+            ProjectileKind::Ricochet => &self.ricochet,
+            ProjectileKind::Piercing => &self.piercing,
+            ProjectileKind::Explosive => &self.explosive,
+        }
+    }
+}
+
+/// Rounds a player has for one weapon
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default, Decode, Encode)]
+pub struct Magazine {
+    pub loaded: u32,
+    pub reserve: u32,
+}
+
+/// A reload under way
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+pub struct Reload {
+    pub kind: ProjectileKind,
+    /// Seconds until the magazine is full
+    pub left: f32,
+}
+
+/// Ammo a player carries, empty until the server hands out [`Ammo::full`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default, Decode, Encode)]
+pub struct Ammo {
+    /// One per weapon, in the order of [`ProjectileKind::ALL`]
+    pub magazines: [Magazine; ProjectileKind::ALL.len()],
+    pub reloading: Option<Reload>,
+}
+impl Ammo {
+    /// Loaded magazines and a full reserve for every weapon, as players spawn with
+    pub fn full(config: &AmmoConfig) -> Self {
+        Self {
+            magazines: ProjectileKind::ALL.map(|kind| {
+                let weapon = config.weapon(kind);
+                Magazine {
+                    loaded: weapon.magazine,
+                    reserve: weapon.reserve,
+                }
+            }),
+            reloading: None,
+        }
+    }
+
+    pub fn magazine(&self, kind: ProjectileKind) -> &Magazine {
+        &self.magazines[kind as usize]
+    }
+
+    fn magazine_mut(&mut self, kind: ProjectileKind) -> &mut Magazine {
+        &mut self.magazines[kind as usize]
+    }
+
+    /// Whether a shot of `kind` would go off, which it does not while reloading
+    pub fn can_fire(&self, kind: ProjectileKind) -> bool {
+        self.reloading.is_none() && self.magazine(kind).loaded > 0
+    }
+
+    /// Takes a round for a shot of `kind`, returning false if there is none to take. The last
+    /// round starts a reload.
+    pub fn fire(&mut self, kind: ProjectileKind, config: &AmmoConfig) -> bool {
+        if !self.can_fire(kind) {
+            return false;
+        }
+        let magazine = self.magazine_mut(kind);
+        magazine.loaded -= 1;
+        if magazine.loaded == 0 {
+            self.start_reload(kind, config);
+        }
+        true
+    }
+
+    /// Starts reloading `kind`, unless something is already reloading, its magazine is full or
+    /// there is nothing to reload it with. Returns whether it started.
+    pub fn start_reload(&mut self, kind: ProjectileKind, config: &AmmoConfig) -> bool {
+        let weapon = config.weapon(kind);
+        let magazine = self.magazine(kind);
+        if self.reloading.is_some() || magazine.loaded >= weapon.magazine || magazine.reserve == 0 {
+            return false;
+        }
+        self.reloading = Some(Reload {
+            kind,
+            left: weapon.reload_time,
+        });
+        true
+    }
+
+    /// Counts down the reload under way, filling the magazine from the reserve once it is done
+    pub fn update(&mut self, dt: f32, config: &AmmoConfig) {
+        let Some(reload) = &mut self.reloading else {
+            return;
+        };
+        reload.left -= dt;
+        if reload.left > 0.0 {
+            return;
+        }
+        let kind = reload.kind;
+        self.reloading = None;
+        let size = config.weapon(kind).magazine;
+        let magazine = self.magazine_mut(kind);
+        let moved = size.saturating_sub(magazine.loaded).min(magazine.reserve);
+        magazine.loaded += moved;
+        magazine.reserve -= moved;
+    }
+
+    /// Whether every weapon has as many rounds in reserve as can be carried
+    pub fn reserves_full(&self, config: &AmmoConfig) -> bool {
+        ProjectileKind::ALL
+            .into_iter()
+            .all(|kind| self.magazine(kind).reserve >= config.weapon(kind).reserve)
+    }
+
+    /// Adds `magazines` magazines of every weapon to the reserve, up to what can be carried.
+    /// Returns false if the reserve was already full.
+    pub fn refill(&mut self, magazines: u32, config: &AmmoConfig) -> bool {
+        let mut added = false;
+        for kind in ProjectileKind::ALL {
+            let weapon = config.weapon(kind);
+            let magazine = self.magazine_mut(kind);
+            let added_rounds = weapon.magazine.saturating_mul(magazines);
+            let reserve = magazine
+                .reserve
+                .saturating_add(added_rounds)
+                .min(weapon.reserve);
+            added |= reserve > magazine.reserve;
+            magazine.reserve = magazine.reserve.max(reserve);
+        }
+        added
+    }
+}
+
+impl Entities {
+    /// Moves every reload under way along by one step
+    pub fn update_ammo(&mut self, dt: f32, config: &CombatConfig) {
+        for player in self.players.values_mut().filter(|player| player.is_alive()) {
+            player.ammo.update(dt, &config.ammo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shots_empty_the_magazine_then_reload_refills_it() {
+        let config = AmmoConfig::default();
+        let kind = ProjectileKind::Explosive;
+        let mut ammo = Ammo::full(&config);
+
+        assert!(ammo.fire(kind, &config));
+        // The last round starts a reload, during which no weapon fires
+        assert!(ammo.fire(kind, &config));
+        assert_eq!(ammo.reloading.map(|reload| reload.kind), Some(kind));
+        assert!(!ammo.fire(kind, &config));
+        assert!(!ammo.fire(ProjectileKind::Bullet, &config));
+
+        ammo.update(config.explosive.reload_time + 0.01, &config);
+        assert_eq!(
+            *ammo.magazine(kind),
+            Magazine {
+                loaded: 2,
+                reserve: 4
+            }
+        );
+        assert!(ammo.fire(ProjectileKind::Bullet, &config));
+
+        // Pickups fill the reserve up to what can be carried and no further
+        assert!(ammo.refill(1, &config));
+        assert_eq!(ammo.magazine(kind).reserve, 6);
+        assert!(!ammo.refill(1, &config));
+    }
+}
@@ -0,0 +1,60 @@
+//! Circular play area that shrinks over a round, used by knockout modes and the storm.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::vec::Vec2;
+
+/// Tunables for the sumo mode
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct SumoConfig {
+    /// Radius of the arena when a round starts
+    pub start_radius: f32,
+    /// Radius the arena shrinks down to
+    pub end_radius: f32,
+    /// Seconds the arena takes to shrink
+    pub shrink_time: f32,
+    /// Seconds between the end of a round and the start of the next
+    pub round_delay: f32,
+    /// Players needed to start a round
+    pub min_players: usize,
+}
+impl Default for SumoConfig {
+    fn default() -> Self {
+        Self {
+            start_radius: 1.5,
+            end_radius: 0.2,
+            shrink_time: 60.0,
+            round_delay: 5.0,
+            min_players: 2,
+        }
+    }
+}
+
+/// Bounds of the arena, shrinking linearly between two simulation ticks. Clients get it once per
+/// round and work out the current radius from the tick of their latest snapshot.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+pub struct Arena {
+    pub center: Vec2,
+    pub start_radius: f32,
+    pub end_radius: f32,
+    pub start_tick: u64,
+    pub end_tick: u64,
+}
+impl Arena {
+    pub fn radius(&self, tick: u64) -> f32 {
+        if tick >= self.end_tick {
+            return self.end_radius;
+        }
+        if tick <= self.start_tick {
+            return self.start_radius;
+        }
+        let span = (self.end_tick - self.start_tick) as f32;
+        let progress = (tick - self.start_tick) as f32 / span;
+        self.start_radius + (self.end_radius - self.start_radius) * progress
+    }
+
+    pub fn contains(&self, pos: Vec2, tick: u64) -> bool {
+        (pos - self.center).length() <= self.radius(tick)
+    }
+}
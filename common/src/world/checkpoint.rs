@@ -0,0 +1,159 @@
+//! Checkpoints laid out along a track, which players pass in order to complete laps, on their own
+//! in time trials or against each other in races.
+//!
+//! The first checkpoint of a map is the start and finish line: reaching it starts a lap, and
+//! reaching it again after every other checkpoint in order completes it. Checkpoints passed out of
+//! order do not count, so cutting across the track gains nothing.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::vec::Vec2;
+
+/// Tunables for the race mode
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct RaceConfig {
+    /// Laps to complete to finish a race
+    pub laps: u32,
+    /// Seconds everyone else has left to finish once the winner did
+    pub finish_time: f32,
+    /// Seconds between the end of a race and the start of the next
+    pub round_delay: f32,
+    /// Players needed to start a race
+    pub min_players: usize,
+}
+impl Default for RaceConfig {
+    fn default() -> Self {
+        Self {
+            laps: 3,
+            finish_time: 30.0,
+            round_delay: 5.0,
+            min_players: 2,
+        }
+    }
+}
+
+/// Circle a player has to enter to pass the checkpoint
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(deny_unknown_fields)]
+pub struct Checkpoint {
+    pub pos: Vec2,
+    pub radius: f32,
+}
+impl Checkpoint {
+    pub fn contains(&self, pos: Vec2) -> bool {
+        (pos - self.pos).length() <= self.radius
+    }
+}
+
+/// What a player did by reaching the checkpoint it was heading for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Passed {
+    /// Crossed the start line without a lap running
+    Start,
+    /// Passed the checkpoint at this index, other than the start
+    Checkpoint(usize),
+    /// Crossed the start line at the end of a lap that took this many ticks, starting the next
+    Lap(u64),
+}
+
+/// Where a player is along the track
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LapProgress {
+    /// Index of the checkpoint to reach next
+    next: usize,
+    /// Tick the running lap started at, if any
+    lap_start: Option<u64>,
+}
+impl LapProgress {
+    /// Index of the checkpoint to reach next
+    pub fn next(&self) -> usize {
+        self.next
+    }
+
+    /// Tick the running lap started at, if any
+    pub fn lap_start(&self) -> Option<u64> {
+        self.lap_start
+    }
+
+    /// Checkpoints passed in the running lap out of `count`, counting the start line
+    pub fn passed(&self, count: usize) -> usize {
+        match (self.lap_start, self.next) {
+            (None, _) => 0,
+            (Some(_), 0) => count,
+            (Some(_), next) => next,
+        }
+    }
+
+    /// Moves on when `pos` is within the checkpoint to reach next. A track needs at least two
+    /// checkpoints, with fewer nobody ever starts a lap.
+    pub fn advance(&mut self, pos: Vec2, checkpoints: &[Checkpoint], tick: u64) -> Option<Passed> {
+        if checkpoints.len() < 2 || !checkpoints.get(self.next)?.contains(pos) {
+            return None;
+        }
+        let passed = match (self.next, self.lap_start) {
+            (0, Some(start)) => Passed::Lap(tick - start),
+            (0, None) => Passed::Start,
+            (index, _) => Passed::Checkpoint(index),
+        };
+        if self.next == 0 {
+            self.lap_start = Some(tick);
+        }
+        self.next = (self.next + 1) % checkpoints.len();
+        Some(passed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three checkpoints a unit apart along the x axis
+    fn track() -> Vec<Checkpoint> {
+        (0..3)
+            .map(|i| Checkpoint {
+                pos: Vec2 {
+                    x: i as f32,
+                    y: 0.0,
+                },
+                radius: 0.25,
+            })
+            .collect()
+    }
+
+    fn at(x: f32) -> Vec2 {
+        Vec2 { x, y: 0.0 }
+    }
+
+    #[test]
+    fn laps_go_through_every_checkpoint_in_order() {
+        let track = track();
+        let mut progress = LapProgress::default();
+        // Nothing counts before the start line
+        assert_eq!(progress.advance(at(1.0), &track, 5), None);
+        assert_eq!(progress.advance(at(0.0), &track, 10), Some(Passed::Start));
+        assert_eq!(progress.lap_start(), Some(10));
+        // Skipping ahead does not count
+        assert_eq!(progress.advance(at(2.0), &track, 20), None);
+        assert_eq!(
+            progress.advance(at(1.0), &track, 30),
+            Some(Passed::Checkpoint(1))
+        );
+        assert_eq!(
+            progress.advance(at(2.0), &track, 40),
+            Some(Passed::Checkpoint(2))
+        );
+        assert_eq!(progress.next(), 0);
+        assert_eq!(progress.passed(track.len()), 3);
+        assert_eq!(progress.advance(at(0.0), &track, 55), Some(Passed::Lap(45)));
+        assert_eq!(progress.passed(track.len()), 1);
+        assert_eq!(progress.lap_start(), Some(55));
+    }
+
+    #[test]
+    fn tracks_need_two_checkpoints() {
+        let track = &track()[..1];
+        let mut progress = LapProgress::default();
+        assert_eq!(progress.advance(at(0.0), track, 0), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Cheap hashes of simulation state, to tell when two simulations that should agree do not.
+//!
+//! The server can log the [`entities`] hash of every tick, so two runs with the same seed can be
+//! compared tick by tick. A predicting client hashes its own player with [`player`] and compares
+//! it with the one the server sent back. Floats are hashed by their bits, so any difference counts,
+//! however small.
+use crate::vec::Vec2;
+
+use super::entities::{Entities, Player};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a over the values written to it, the same on every platform
+pub struct Checksum(u64);
+impl Default for Checksum {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+impl Checksum {
+    pub fn write_u64(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u64(value.to_bits() as u64);
+    }
+
+    pub fn write_vec(&mut self, value: Vec2) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hash of the motion of a player, which is what a client predicts
+pub fn player(player: &Player) -> u64 {
+    let mut checksum = Checksum::default();
+    checksum.write_vec(player.pos);
+    checksum.write_vec(player.vel);
+    checksum.finish()
+}
+
+/// Hash of every player and projectile, players taken in order of id so it does not depend on
+/// how the map happens to be laid out
+pub fn entities(entities: &Entities) -> u64 {
+    let mut checksum = Checksum::default();
+    let mut ids: Vec<_> = entities.players.keys().copied().collect();
+    ids.sort_unstable();
+    for id in ids {
+        let player = &entities.players[&id];
+        checksum.write_u64(id);
+        checksum.write_vec(player.pos);
+        checksum.write_vec(player.vel);
+        checksum.write_f32(player.health);
+        checksum.write_f32(player.armor);
+    }
+    for projectile in &entities.projectiles {
+        checksum.write_u64(projectile.owner);
+        checksum.write_vec(projectile.pos);
+        checksum.write_vec(projectile.vel);
+    }
+    checksum.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, world::ammo::Ammo};
+
+    use super::*;
+
+    fn crowd(ids: impl Iterator<Item = u64>) -> Entities {
+        let players = ids.map(|id| {
+            let player = Player {
+                username: id.to_string(),
+                color: Color::WHITE,
+                pos: Vec2 {
+                    x: id as f32,
+                    y: 0.0,
+                },
+                vel: Vec2::ZERO,
+                last_input_seq: 0,
+                input_ticks: 0,
+                health: 100.0,
+                armor: 0.0,
+                respawn_in: 0.0,
+                sprite: None,
+                ammo: Ammo::default(),
+            };
+            (id, player)
+        });
+        Entities {
+            players: players.collect(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hashes_follow_state_not_layout() {
+        let a = crowd(0..32);
+        let mut b = crowd((0..32).rev());
+        assert_eq!(entities(&a), entities(&b));
+
+        let before = player(&b.players[&7]);
+        b.players.get_mut(&7).unwrap().pos.y += f32::EPSILON;
+        assert_ne!(player(&b.players[&7]), before);
+        assert_ne!(entities(&a), entities(&b));
+    }
+}
@@ -0,0 +1,690 @@
+//! Projectiles, damage and respawning.
+//!
+//! Projectiles move in the shared simulation so clients can draw them between snapshots, but
+//! only the server decides what they hit and who dies. Each [`ProjectileKind`] is a weapon with
+//! its own behavior: bouncing off walls, piercing through players or exploding on impact.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    physics,
+    vec::Vec2,
+    world::{
+        ammo::{Ammo, AmmoConfig},
+        entities::{Entities, Player},
+        environment::Environment,
+        grid::SpatialGrid,
+    },
+};
+
+/// Tunables for shooting and dying
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct CombatConfig {
+    pub max_health: f32,
+    /// Health a projectile takes from the player it hits
+    pub projectile_damage: f32,
+    pub projectile_speed: f32,
+    /// Seconds a projectile flies before disappearing
+    pub projectile_lifetime: f32,
+    pub projectile_radius: f32,
+    /// Distance a hit player is pushed along the projectile's path
+    pub knockback: f32,
+    /// Seconds a player must wait between two shots
+    pub fire_cooldown: f32,
+    /// Seconds a dead player waits before coming back
+    pub respawn_delay: f32,
+    /// Times a ricochet projectile bounces off walls before it is spent
+    pub ricochet_bounces: u32,
+    /// Fraction of its speed a ricochet projectile keeps on every bounce
+    pub restitution: f32,
+    /// Players a piercing projectile passes through before it stops at the next one
+    pub pierce_count: u32,
+    /// Reach of an explosion, damage falling off towards its edge
+    pub blast_radius: f32,
+    /// Damage at the center of an explosion
+    pub blast_damage: f32,
+    /// Distance a player at the center of an explosion is thrown
+    pub blast_knockback: f32,
+    pub max_armor: f32,
+    /// Fraction of damage the armor takes instead of health while it lasts
+    pub armor_absorption: f32,
+    /// Armor an armor pickup gives
+    pub armor_pickup: f32,
+    /// Seconds a pickup takes to come back once taken
+    pub pickup_respawn: f32,
+    /// Magazines, reserves and reloads of every weapon
+    pub ammo: AmmoConfig,
+}
+impl Default for CombatConfig {
+    fn default() -> Self {
+        Self {
+            max_health: 100.0,
+            projectile_damage: 25.0,
+            projectile_speed: 2.0,
+            projectile_lifetime: 1.5,
+            projectile_radius: 0.01,
+            knockback: 0.0,
+            fire_cooldown: 0.3,
+            respawn_delay: 3.0,
+            ricochet_bounces: 3,
+            restitution: 0.8,
+            pierce_count: 2,
+            blast_radius: 0.2,
+            blast_damage: 60.0,
+            blast_knockback: 0.1,
+            max_armor: 100.0,
+            armor_absorption: 0.5,
+            armor_pickup: 50.0,
+            pickup_respawn: 20.0,
+            ammo: AmmoConfig::default(),
+        }
+    }
+}
+
+/// Weapons a player can fire, each shooting its own kind of projectile
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default, Decode, Encode)]
+pub enum ProjectileKind {
+    #[default]
+    Bullet,
+    /// Bounces off walls
+    Ricochet,
+    /// Goes through players
+    Piercing,
+    /// Damages everyone around where it lands
+    Explosive,
+}
+impl ProjectileKind {
+    pub const ALL: [ProjectileKind; 4] = [
+        ProjectileKind::Bullet,
+        ProjectileKind::Ricochet,
+        ProjectileKind::Piercing,
+        ProjectileKind::Explosive,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectileKind::Bullet => "bullet",
+            ProjectileKind::Ricochet => "ricochet",
+            ProjectileKind::Piercing => "piercing",
+            ProjectileKind::Explosive => "explosive",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub struct Projectile {
+    /// Player that fired it, who gets the kill
+    pub owner: u64,
+    pub kind: ProjectileKind,
+    pub pos: Vec2,
+    pub vel: Vec2,
+    /// Seconds left before it disappears
+    pub ttl: f32,
+    /// Times it bounced off a wall
+    pub bounces: u32,
+    /// Players it already went through, who it cannot hit again
+    pub pierced: Vec<u64>,
+}
+
+/// Outcome of a projectile's step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flight {
+    Flying,
+    /// Ran out of time
+    Expired,
+    /// Hit a wall it could not bounce off
+    Impact,
+}
+
+impl Projectile {
+    /// Moves the projectile by one step, bouncing it off walls if it can.
+    pub fn update(&mut self, dt: f32, config: &CombatConfig, environment: &Environment) -> Flight {
+        let radius = config.projectile_radius;
+        self.vel = physics::attract(self.pos, self.vel, &environment.attractors, dt);
+        self.pos = physics::integrate(self.pos, self.vel, dt);
+        self.ttl -= dt;
+        if self.ttl <= 0.0 {
+            return Flight::Expired;
+        }
+        let colliders = environment.colliders_near(self.pos, radius);
+        if !colliders
+            .iter()
+            .any(|object| physics::overlaps(self.pos, radius, object))
+        {
+            return Flight::Flying;
+        }
+        if self.kind == ProjectileKind::Ricochet && self.bounces < config.ricochet_bounces {
+            let bounced =
+                physics::bounce(self.pos, self.vel, radius, &colliders, config.restitution);
+            if let Some((pos, vel)) = bounced {
+                self.pos = pos;
+                self.vel = vel;
+                self.bounces += 1;
+                return Flight::Flying;
+            }
+        }
+        Flight::Impact
+    }
+}
+
+/// Where an explosive projectile went off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Blast {
+    /// Player that fired it, who gets the kills
+    pub owner: u64,
+    pub pos: Vec2,
+}
+
+/// A player that died
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Death {
+    pub victim: u64,
+    /// Player whose projectile did it, if any
+    pub killer: Option<u64>,
+    /// Kind of projectile that did it, if any
+    pub weapon: Option<ProjectileKind>,
+}
+
+/// What a player lost to some damage
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Damage {
+    /// Taken by the armor
+    pub armor: f32,
+    pub health: f32,
+    /// Whether it killed the player
+    pub fatal: bool,
+}
+
+/// Damage a player took from a projectile or an explosion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub victim: u64,
+    /// Player who fired, who gets the kill
+    pub attacker: u64,
+    /// Kind of projectile that hit, or went off nearby
+    pub weapon: ProjectileKind,
+    pub damage: Damage,
+}
+impl Hit {
+    pub fn death(&self) -> Option<Death> {
+        self.damage.fatal.then_some(Death {
+            victim: self.victim,
+            killer: Some(self.attacker),
+            weapon: Some(self.weapon),
+        })
+    }
+}
+
+impl Player {
+    /// Takes `amount` of damage, armor soaking up its share before health. The dead wait the
+    /// respawn delay before coming back.
+    pub fn take_damage(&mut self, amount: f32, config: &CombatConfig) -> Damage {
+        if !self.is_alive() {
+            return Damage::default();
+        }
+        let armor = (amount * config.armor_absorption.clamp(0.0, 1.0)).min(self.armor);
+        self.armor -= armor;
+        let health = amount - armor;
+        self.health -= health;
+        let fatal = !self.is_alive();
+        if fatal {
+            self.vel = Vec2::ZERO;
+            self.armor = 0.0;
+            self.respawn_in = config.respawn_delay;
+        }
+        Damage {
+            armor,
+            health,
+            fatal,
+        }
+    }
+}
+
+impl Entities {
+    /// Fires a projectile from a living player towards `dir`, taking a round of its ammo. Returns
+    /// false if nothing was fired, such as when the magazine is empty. The caller enforces the
+    /// cooldown.
+    pub fn shoot(
+        &mut self,
+        owner: u64,
+        dir: Vec2,
+        kind: ProjectileKind,
+        config: &CombatConfig,
+        player_radius: f32,
+    ) -> bool {
+        let Some(player) = self
+            .players
+            .get_mut(&owner)
+            .filter(|player| player.is_alive())
+        else {
+            return false;
+        };
+        let length = dir.length();
+        if length == 0.0 || !length.is_finite() || !player.ammo.fire(kind, &config.ammo) {
+            return false;
+        }
+        let dir = dir / length;
+        self.projectiles.push(Projectile {
+            owner,
+            kind,
+            // Starts just outside the shooter so it does not hit them
+            pos: player.pos + dir * (player_radius + config.projectile_radius) * 1.01,
+            vel: dir * config.projectile_speed,
+            ttl: config.projectile_lifetime,
+            bounces: 0,
+            pierced: Vec::new(),
+        });
+        true
+    }
+
+    /// Moves every projectile by one step, removing the spent ones, and returns where explosive
+    /// projectiles hit a wall.
+    pub fn update_projectiles(
+        &mut self,
+        dt: f32,
+        config: &CombatConfig,
+        environment: &Environment,
+    ) -> Vec<Blast> {
+        let mut blasts = Vec::new();
+        self.projectiles.retain_mut(|projectile| {
+            match projectile.update(dt, config, environment) {
+                Flight::Flying => true,
+                Flight::Impact if projectile.kind == ProjectileKind::Explosive => {
+                    blasts.push(Blast {
+                        owner: projectile.owner,
+                        pos: projectile.pos,
+                    });
+                    false
+                }
+                Flight::Impact | Flight::Expired => false,
+            }
+        });
+        blasts
+    }
+
+    /// Applies the damage of every projectile touching a living player, removing those
+    /// projectiles unless they pierce through, and returns the hits. Explosive projectiles go off
+    /// instead, and are added to `blasts` for [`Entities::explode`].
+    pub fn resolve_hits(
+        &mut self,
+        config: &CombatConfig,
+        player_radius: f32,
+        blasts: &mut Vec<Blast>,
+    ) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        let reach = player_radius + config.projectile_radius;
+        let corner = Vec2 { x: reach, y: reach };
+        let mut grid = SpatialGrid::new(2.0 * reach);
+        for (id, player) in &self.players {
+            grid.insert(player.pos, *id);
+        }
+        self.projectiles.retain_mut(|projectile| {
+            // Of the players touching it, the one with the lowest id is hit
+            let players = &self.players;
+            let target = grid
+                .query(projectile.pos - corner, projectile.pos + corner)
+                .filter(|(pos, id)| {
+                    players[id].is_alive()
+                        && !projectile.pierced.contains(id)
+                        && (*pos - projectile.pos).length() < reach
+                })
+                .map(|(_, id)| id)
+                .min();
+            let Some(id) = target else {
+                return true;
+            };
+            let Some(player) = self.players.get_mut(&id) else {
+                return true;
+            };
+            if projectile.kind == ProjectileKind::Explosive {
+                blasts.push(Blast {
+                    owner: projectile.owner,
+                    pos: projectile.pos,
+                });
+                return false;
+            }
+            let speed = projectile.vel.length();
+            if speed > 0.0 {
+                let from = player.pos;
+                player.pos += projectile.vel * (config.knockback / speed);
+                grid.relocate(from, player.pos, id);
+            }
+            hits.push(Hit {
+                victim: id,
+                attacker: projectile.owner,
+                weapon: projectile.kind,
+                damage: player.take_damage(config.projectile_damage, config),
+            });
+            let pierces = projectile.kind == ProjectileKind::Piercing
+                && (projectile.pierced.len() as u32) < config.pierce_count;
+            if pierces {
+                projectile.pierced.push(id);
+            }
+            pierces
+        });
+        hits
+    }
+
+    /// Damages and pushes away every living player caught in the blasts, less the farther they
+    /// are from the center, and returns the hits. Walls between a blast and a player shield them
+    /// from it.
+    pub fn explode(
+        &mut self,
+        blasts: &[Blast],
+        config: &CombatConfig,
+        environment: &Environment,
+    ) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        for blast in blasts {
+            let walls = environment.colliders_near(blast.pos, config.blast_radius);
+            for (id, player) in self.players.iter_mut() {
+                let offset = player.pos - blast.pos;
+                let distance = offset.length();
+                if !player.is_alive() || distance >= config.blast_radius {
+                    continue;
+                }
+                let shielded = walls
+                    .iter()
+                    .any(|wall| physics::segment_hits(blast.pos, player.pos, wall));
+                if shielded {
+                    continue;
+                }
+                let falloff = 1.0 - distance / config.blast_radius;
+                if distance > 0.0 {
+                    player.pos += offset * (config.blast_knockback * falloff / distance);
+                }
+                hits.push(Hit {
+                    victim: *id,
+                    attacker: blast.owner,
+                    // Only explosive projectiles go off
+                    weapon: ProjectileKind::Explosive,
+                    damage: player.take_damage(config.blast_damage * falloff, config),
+                });
+            }
+        }
+        hits
+    }
+
+    /// Counts down dead players' respawn timers, bringing them back at `spawn` points with full
+    /// health. Returns the ids of the players that came back.
+    pub fn respawn(
+        &mut self,
+        dt: f32,
+        config: &CombatConfig,
+        mut spawn: impl FnMut() -> Vec2,
+    ) -> Vec<u64> {
+        let mut respawned = Vec::new();
+        for (id, player) in self.players.iter_mut() {
+            if player.is_alive() {
+                continue;
+            }
+            player.respawn_in -= dt;
+            if player.respawn_in <= 0.0 {
+                player.health = config.max_health;
+                player.armor = 0.0;
+                player.respawn_in = 0.0;
+                player.ammo = Ammo::full(&config.ammo);
+                player.pos = spawn();
+                player.vel = Vec2::ZERO;
+                respawned.push(*id);
+            }
+        }
+        respawned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        world::environment::{OBJECT_COLOR, Object},
+    };
+
+    const RADIUS: f32 = 0.05;
+
+    fn player(x: f32) -> Player {
+        Player {
+            username: String::new(),
+            color: Color::WHITE,
+            pos: Vec2 { x, y: 0.0 },
+            vel: Vec2::ZERO,
+            last_input_seq: 0,
+            input_ticks: 0,
+            health: 100.0,
+            armor: 0.0,
+            respawn_in: 0.0,
+            sprite: None,
+            ammo: Ammo::full(&AmmoConfig::default()),
+        }
+    }
+
+    fn duel() -> Entities {
+        Entities {
+            players: [(1, player(0.0)), (2, player(0.5))].into(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        }
+    }
+
+    /// Shoots from player 1 towards +x until the projectile is gone
+    fn shoot_kind(
+        entities: &mut Entities,
+        kind: ProjectileKind,
+        config: &CombatConfig,
+        environment: &Environment,
+    ) -> Vec<Death> {
+        entities.shoot(1, Vec2 { x: 1.0, y: 0.0 }, kind, config, RADIUS);
+        let mut deaths = Vec::new();
+        for _ in 0..1000 {
+            let mut blasts = entities.update_projectiles(0.01, config, environment);
+            let hits = entities.resolve_hits(config, RADIUS, &mut blasts);
+            deaths.extend(hits.iter().filter_map(Hit::death));
+            let hits = entities.explode(&blasts, config, environment);
+            deaths.extend(hits.iter().filter_map(Hit::death));
+            if entities.projectiles.is_empty() {
+                return deaths;
+            }
+        }
+        panic!("projectile never landed");
+    }
+
+    fn shoot_once(entities: &mut Entities, config: &CombatConfig) -> Vec<Death> {
+        shoot_kind(
+            entities,
+            ProjectileKind::Bullet,
+            config,
+            &Environment::default(),
+        )
+    }
+
+    #[test]
+    fn projectiles_damage_then_kill() {
+        let config = CombatConfig::default();
+        let mut entities = duel();
+        for _ in 0..3 {
+            assert!(shoot_once(&mut entities, &config).is_empty());
+        }
+        assert_eq!(entities.players[&2].health, 25.0);
+        assert_eq!(entities.players[&1].health, 100.0);
+
+        let deaths = shoot_once(&mut entities, &config);
+        assert_eq!(
+            deaths,
+            vec![Death {
+                victim: 2,
+                killer: Some(1),
+                weapon: Some(ProjectileKind::Bullet),
+            }]
+        );
+        assert!(!entities.players[&2].is_alive());
+    }
+
+    #[test]
+    fn dead_players_respawn_after_the_delay() {
+        let config = CombatConfig {
+            projectile_damage: 100.0,
+            ..CombatConfig::default()
+        };
+        let mut entities = duel();
+        shoot_once(&mut entities, &config);
+
+        let spawn = Vec2 { x: 3.0, y: 3.0 };
+        assert!(
+            entities
+                .respawn(config.respawn_delay / 2.0, &config, || spawn)
+                .is_empty()
+        );
+        assert_eq!(
+            entities.respawn(config.respawn_delay / 2.0, &config, || spawn),
+            vec![2]
+        );
+        let respawned = &entities.players[&2];
+        assert_eq!(respawned.health, config.max_health);
+        assert_eq!(respawned.pos, spawn);
+    }
+
+    #[test]
+    fn piercing_goes_through_players() {
+        let config = CombatConfig::default();
+        let mut entities = duel();
+        entities.players.insert(3, player(1.0));
+        let environment = Environment::default();
+        shoot_kind(
+            &mut entities,
+            ProjectileKind::Piercing,
+            &config,
+            &environment,
+        );
+        assert_eq!(entities.players[&2].health, 75.0);
+        assert_eq!(entities.players[&3].health, 75.0);
+    }
+
+    #[test]
+    fn ricochets_bounce_back_off_walls() {
+        let config = CombatConfig {
+            projectile_lifetime: 10.0,
+            ..CombatConfig::default()
+        };
+        let mut entities = Entities {
+            players: [(1, player(0.0))].into(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        };
+        let environment = Environment {
+            objects: vec![Object {
+                pos: Vec2 { x: 0.5, y: -1.0 },
+                size: Vec2 { x: 0.1, y: 2.0 },
+                color: OBJECT_COLOR,
+                sprite: None,
+            }],
+            ..Environment::default()
+        };
+        // Fired straight at a wall, it comes back at the shooter
+        shoot_kind(
+            &mut entities,
+            ProjectileKind::Ricochet,
+            &config,
+            &environment,
+        );
+        assert_eq!(entities.players[&1].health, 75.0);
+
+        entities.players.get_mut(&1).unwrap().health = 100.0;
+        shoot_kind(&mut entities, ProjectileKind::Bullet, &config, &environment);
+        assert_eq!(entities.players[&1].health, 100.0);
+    }
+
+    #[test]
+    fn explosions_hurt_everyone_nearby() {
+        let config = CombatConfig::default();
+        let mut entities = duel();
+        entities.players.insert(3, player(0.6));
+        entities.players.insert(4, player(2.0));
+        let deaths = shoot_kind(
+            &mut entities,
+            ProjectileKind::Explosive,
+            &config,
+            &Environment::default(),
+        );
+        assert!(deaths.is_empty());
+        assert!(entities.players[&2].health < 100.0);
+        assert!(entities.players[&3].health < 100.0);
+        assert!(entities.players[&2].health < entities.players[&3].health);
+        assert_eq!(entities.players[&4].health, 100.0);
+        assert_eq!(entities.players[&1].health, 100.0);
+    }
+
+    #[test]
+    fn walls_shield_from_explosions() {
+        let config = CombatConfig::default();
+        let mut entities = Entities {
+            players: [(1, player(0.0)), (2, player(0.1)), (3, player(-0.1))].into(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        };
+        let environment = Environment {
+            objects: vec![Object {
+                pos: Vec2 { x: 0.03, y: -1.0 },
+                size: Vec2 { x: 0.02, y: 2.0 },
+                color: OBJECT_COLOR,
+                sprite: None,
+            }],
+            ..Environment::default()
+        };
+        let blast = Blast {
+            owner: 1,
+            pos: Vec2::ZERO,
+        };
+        entities.explode(&[blast], &config, &environment);
+
+        let shielded = &entities.players[&2];
+        assert_eq!(shielded.health, 100.0);
+        assert_eq!(shielded.pos.x, 0.1);
+        let exposed = &entities.players[&3];
+        assert_eq!(exposed.health, 100.0 - config.blast_damage * 0.5);
+        assert!(exposed.pos.x < -0.1);
+    }
+
+    #[test]
+    fn armor_takes_its_share_first() {
+        let config = CombatConfig::default();
+        let mut entities = duel();
+        entities.players.get_mut(&2).unwrap().armor = 10.0;
+
+        shoot_once(&mut entities, &config);
+        // Half of 25 is more than the armor has left, so health takes the other 15
+        let target = &entities.players[&2];
+        assert_eq!((target.armor, target.health), (0.0, 85.0));
+
+        entities.players.get_mut(&2).unwrap().armor = 50.0;
+        let mut blasts = Vec::new();
+        entities.shoot(
+            1,
+            Vec2 { x: 1.0, y: 0.0 },
+            ProjectileKind::Bullet,
+            &config,
+            RADIUS,
+        );
+        let mut reported = Vec::new();
+        while !entities.projectiles.is_empty() {
+            entities.update_projectiles(0.01, &config, &Environment::default());
+            reported.extend(entities.resolve_hits(&config, RADIUS, &mut blasts));
+        }
+        assert_eq!(
+            reported,
+            vec![Hit {
+                victim: 2,
+                attacker: 1,
+                weapon: ProjectileKind::Bullet,
+                damage: Damage {
+                    armor: 12.5,
+                    health: 12.5,
+                    fatal: false
+                }
+            }]
+        );
+        let target = &entities.players[&2];
+        assert_eq!((target.armor, target.health), (37.5, 72.5));
+    }
+}
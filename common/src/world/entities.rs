@@ -1,32 +1,133 @@
 //! This module defines entities, a movable object in this world
 use std::collections::HashMap;
 
-use crate::{color::Color, vec::Vec2};
+use crate::{
+    color::Color,
+    physics::{self, PhysicsConfig},
+    vec::Vec2,
+    world::{
+        ammo::Ammo, combat::Projectile, environment::Environment, grid::SpatialGrid,
+        pickups::Pickup, sprite::SpriteId,
+    },
+};
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
+/// Players are never pushed further than this many radii in a step
+pub const PUSH_REACH: f32 = 3.0;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
 pub struct Player {
     pub username: String,
     pub color: Color,
     pub pos: Vec2,
     pub vel: Vec2,
+    /// Sequence number of the last client input the server applied to this player
+    pub last_input_seq: u64,
+    /// Simulation steps run since that input was applied
+    pub input_ticks: u32,
+    /// The player is dead at zero or less
+    pub health: f32,
+    /// Soaks up part of the damage before health, see [`Player::take_damage`]
+    pub armor: f32,
+    /// Seconds until a dead player comes back
+    pub respawn_in: f32,
+    /// Drawn in place of the triangle when the client has it
+    #[serde(default)]
+    pub sprite: Option<SpriteId>,
+    /// Rounds of every weapon, see [`Ammo`]
+    #[serde(default)]
+    pub ammo: Ammo,
 }
 impl Player {
-    fn update(&mut self, dt: f32) {
-        self.pos.x += self.vel.x * dt;
-        self.pos.y += self.vel.y * dt;
+    pub fn is_alive(&self) -> bool {
+        self.health > 0.0
+    }
+
+    /// Advances the player by one simulation step, dead players stay where they fell.
+    pub fn update(&mut self, dt: f32, config: &PhysicsConfig, environment: &Environment) {
+        if !self.is_alive() {
+            self.input_ticks = self.input_ticks.saturating_add(1);
+            return;
+        }
+        self.vel = physics::attract(self.pos, self.vel, &environment.attractors, dt);
+        // Anything the player can reach during the step
+        let reach = config.player_radius + self.vel.length() * dt;
+        (self.pos, self.vel) = physics::step(
+            self.pos,
+            self.vel,
+            config.player_radius,
+            config,
+            &environment.colliders_near(self.pos, reach),
+            dt,
+        );
+        self.input_ticks = self.input_ticks.saturating_add(1);
     }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
 pub struct Entities {
     pub players: HashMap<u64, Player>,
+    pub projectiles: Vec<Projectile>,
+    pub pickups: Vec<Pickup>,
 }
 impl Entities {
-    pub fn update(&mut self, dt: f32) {
-        for (_, player) in &mut self.players {
-            player.update(dt);
+    /// Sets the walking direction a client sent as input `seq`, the server moving the player from
+    /// there at no more than the maximum speed. Returns false and leaves the player untouched if
+    /// a newer input was already applied, or if the player is dead.
+    pub fn apply_input(&mut self, id: u64, seq: u64, dir: Vec2, config: &PhysicsConfig) -> bool {
+        let Some(player) = self.players.get_mut(&id) else {
+            return false;
+        };
+        if seq <= player.last_input_seq || !player.is_alive() {
+            return false;
+        }
+        player.vel = physics::walk_velocity(dir, config);
+        player.last_input_seq = seq;
+        player.input_ticks = 0;
+        true
+    }
+
+    pub fn update(&mut self, dt: f32, config: &PhysicsConfig, environment: &Environment) {
+        for player in self.players.values_mut() {
+            player.update(dt, config, environment);
+        }
+        if config.player_collision {
+            // Only bodies less than two radii apart push each other
+            let reach = 2.0 * config.player_radius;
+            let corner = Vec2 { x: reach, y: reach };
+            let mut grid = SpatialGrid::new(reach);
+            for (id, pos) in self.bodies() {
+                grid.insert(pos, id);
+            }
+            for (id, player) in self.players.iter_mut() {
+                let mut near: Vec<_> = grid
+                    .query(player.pos - corner, player.pos + corner)
+                    .map(|(pos, id)| (id, pos))
+                    .collect();
+                // In the same order as all bodies, so pushes add up the same way
+                near.sort_by_key(|(id, _)| *id);
+                player.pos = physics::push_apart(
+                    *id,
+                    player.pos,
+                    config.player_radius,
+                    &near,
+                    config,
+                    &environment.colliders_near(player.pos, PUSH_REACH * config.player_radius),
+                    dt,
+                );
+            }
         }
     }
+
+    /// Id and position of every player, sorted by id so pushes add up the same way everywhere
+    pub fn bodies(&self) -> Vec<(u64, Vec2)> {
+        let mut bodies: Vec<_> = self
+            .players
+            .iter()
+            .map(|(id, player)| (*id, player.pos))
+            .collect();
+        bodies.sort_by_key(|(id, _)| *id);
+        bodies
+    }
 }
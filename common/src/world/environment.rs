@@ -1,16 +1,203 @@
+//! Static parts of the world: the tile map players walk on and objects placed on top of it.
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
-use crate::vec::Vec2;
+use crate::{
+    color::Color,
+    vec::Vec2,
+    world::{checkpoint::Checkpoint, sprite::SpriteId},
+};
 
 /// Describes the entire game environment.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default, Decode, Encode)]
 pub struct Environment {
+    pub tiles: TileMap,
+    /// Free-standing boxes, on top of the tiles
     pub objects: Vec<Object>,
+    /// Wells pulling moving things in, or pushing them away
+    #[serde(default)]
+    pub attractors: Vec<Attractor>,
+    /// Track of time trials and races, in the order it is driven, see [`checkpoint`]
+    ///
+    /// [`checkpoint`]: crate::world::checkpoint
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+}
+impl Environment {
+    /// Every box a circle of `radius` at `pos` could touch, solid tiles and objects alike
+    pub fn colliders_near(&self, pos: Vec2, radius: f32) -> Vec<Object> {
+        let mut colliders = self.objects.clone();
+        colliders.extend(self.tiles.solid_near(pos, radius));
+        colliders
+    }
 }
 
+/// Color of objects that do not pick one
+pub const OBJECT_COLOR: Color = Color {
+    r: 0.5,
+    g: 0.5,
+    b: 0.55,
+};
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
 pub struct Object {
     pub pos: Vec2,
     pub size: Vec2,
-}
\ No newline at end of file
+    #[serde(default = "object_color")]
+    pub color: Color,
+    /// Drawn stretched over the object in place of its color when the client has it
+    #[serde(default)]
+    pub sprite: Option<SpriteId>,
+}
+
+fn object_color() -> Color {
+    OBJECT_COLOR
+}
+
+/// Gravity well bending the path of players and projectiles that come within `radius` of it, see
+/// [`physics::attract`](crate::physics::attract)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(deny_unknown_fields)]
+pub struct Attractor {
+    pub pos: Vec2,
+    /// Acceleration towards the center at the center, in world units per second squared. A
+    /// negative strength pushes away instead.
+    pub strength: f32,
+    /// Distance at which the pull has faded to nothing
+    pub radius: f32,
+}
+
+/// What a tile id stands for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub struct TileKind {
+    pub color: Color,
+    /// Whether players collide with the tile
+    pub solid: bool,
+}
+
+/// Grid of square tiles, each cell holding an index into `kinds`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub struct TileMap {
+    /// World position of the bottom left corner of the grid
+    pub origin: Vec2,
+    pub tile_size: f32,
+    pub width: u32,
+    pub height: u32,
+    /// Tile ids row by row, starting with the bottom row
+    pub tiles: Vec<u16>,
+    pub kinds: Vec<TileKind>,
+    /// Where players can appear when they join
+    pub spawn_points: Vec<Vec2>,
+    /// Where armor pickups lie
+    pub pickup_points: Vec<Vec2>,
+    /// Where ammo pickups lie
+    #[serde(default)]
+    pub ammo_points: Vec<Vec2>,
+}
+impl Default for TileMap {
+    fn default() -> Self {
+        Self {
+            origin: Vec2::ZERO,
+            tile_size: 1.0,
+            width: 0,
+            height: 0,
+            tiles: Vec::new(),
+            kinds: Vec::new(),
+            spawn_points: Vec::new(),
+            pickup_points: Vec::new(),
+            ammo_points: Vec::new(),
+        }
+    }
+}
+impl TileMap {
+    /// Id of a cell with no tile
+    pub const EMPTY: u16 = u16::MAX;
+
+    /// Kind of the tile at a grid cell, `None` outside the grid or for an unknown id
+    pub fn kind(&self, x: u32, y: u32) -> Option<&TileKind> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let id = *self.tiles.get((y * self.width + x) as usize)?;
+        self.kinds.get(id as usize)
+    }
+
+    /// Box covered by a grid cell
+    pub fn cell_box(&self, x: u32, y: u32) -> Object {
+        Object {
+            pos: Vec2 {
+                x: self.origin.x + x as f32 * self.tile_size,
+                y: self.origin.y + y as f32 * self.tile_size,
+            },
+            size: Vec2 {
+                x: self.tile_size,
+                y: self.tile_size,
+            },
+            color: self.kind(x, y).map_or(OBJECT_COLOR, |kind| kind.color),
+            sprite: None,
+        }
+    }
+
+    /// Boxes of the solid tiles overlapping the square around a circle
+    pub fn solid_near(&self, pos: Vec2, radius: f32) -> impl Iterator<Item = Object> + '_ {
+        let cells = |center: f32, origin: f32, len: u32| {
+            let first = ((center - radius - origin) / self.tile_size).floor();
+            let last = ((center + radius - origin) / self.tile_size).floor();
+            let first = first.clamp(0.0, len as f32) as u32;
+            let last = (last + 1.0).clamp(0.0, len as f32) as u32;
+            first..last
+        };
+        let xs = cells(pos.x, self.origin.x, self.width);
+        let ys = cells(pos.y, self.origin.y, self.height);
+        ys.flat_map(move |y| xs.clone().map(move |x| (x, y)))
+            .filter(|&(x, y)| self.kind(x, y).is_some_and(|kind| kind.solid))
+            .map(|(x, y)| self.cell_box(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x2 grid with a solid tile in the bottom left corner and one in the top right
+    fn map() -> TileMap {
+        TileMap {
+            origin: Vec2 { x: -1.0, y: 0.0 },
+            tile_size: 0.5,
+            width: 3,
+            height: 2,
+            tiles: vec![1, 0, 0, 0, TileMap::EMPTY, 1],
+            kinds: vec![
+                TileKind {
+                    color: Color::BLACK,
+                    solid: false,
+                },
+                TileKind {
+                    color: Color::WHITE,
+                    solid: true,
+                },
+            ],
+            spawn_points: Vec::new(),
+            pickup_points: Vec::new(),
+            ammo_points: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn only_nearby_solid_tiles_collide() {
+        let map = map();
+        let near_bottom_left: Vec<_> = map.solid_near(Vec2 { x: -0.4, y: 0.2 }, 0.15).collect();
+        assert_eq!(near_bottom_left, vec![map.cell_box(0, 0)]);
+
+        let between: Vec<_> = map.solid_near(Vec2 { x: -0.25, y: 0.5 }, 0.1).collect();
+        assert!(between.is_empty());
+    }
+
+    #[test]
+    fn outside_the_grid_has_no_tiles() {
+        let map = map();
+        assert_eq!(map.kind(3, 0), None);
+        assert_eq!(map.kind(1, 1), None);
+        assert_eq!(map.solid_near(Vec2 { x: 5.0, y: 5.0 }, 1.0).count(), 0);
+    }
+}
@@ -0,0 +1,264 @@
+//! Spatial partitioning of the entities, so what is near something can be found without looking at
+//! everything.
+//!
+//! [`SpatialGrid`] sorts positions into square cells. Looking up a box or a circle only visits the
+//! cells it overlaps, which with cells as large as the circle is at most nine. Items that move are
+//! [`relocated`](SpatialGrid::relocate) from their old position to their new one.
+//!
+//! The simulation uses grids as the broadphase of collisions, finding the players a player pushes
+//! or a projectile hits among those around it. The server builds one [`EntityGrid`] per snapshot
+//! and cuts a smaller snapshot out of it for every client with [`Entities::within`].
+use std::collections::HashMap;
+
+use crate::{vec::Vec2, world::entities::Entities};
+
+/// Items sorted by position into square cells
+#[derive(Clone, Debug)]
+pub struct SpatialGrid<T> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(Vec2, T)>>,
+}
+impl<T: Copy + PartialEq> SpatialGrid<T> {
+    /// Empty grid of cells `cell_size` wide, in world units
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(f32::EPSILON),
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, pos: Vec2, item: T) {
+        self.cells
+            .entry(self.cell(pos))
+            .or_default()
+            .push((pos, item));
+    }
+
+    /// Takes `item` out of the grid, given where it was inserted. Returns whether it was there.
+    pub fn remove(&mut self, pos: Vec2, item: T) -> bool {
+        let cell = self.cell(pos);
+        let Some(items) = self.cells.get_mut(&cell) else {
+            return false;
+        };
+        let Some(i) = items.iter().position(|(_, other)| *other == item) else {
+            return false;
+        };
+        items.swap_remove(i);
+        if items.is_empty() {
+            self.cells.remove(&cell);
+        }
+        true
+    }
+
+    /// Moves `item` from `from`, where it was inserted, to `to`
+    pub fn relocate(&mut self, from: Vec2, to: Vec2, item: T) {
+        self.remove(from, item);
+        self.insert(to, item);
+    }
+
+    /// Items and their positions in the box from `min` to `max`, edges included
+    pub fn query(&self, min: Vec2, max: Vec2) -> impl Iterator<Item = (Vec2, T)> + '_ {
+        let (low, high) = (self.cell(min), self.cell(max));
+        (low.0..=high.0)
+            .flat_map(move |x| (low.1..=high.1).map(move |y| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |(pos, _)| {
+                (min.x..=max.x).contains(&pos.x) && (min.y..=max.y).contains(&pos.y)
+            })
+            .copied()
+    }
+
+    /// Items within `radius` of `center`
+    pub fn within(&self, center: Vec2, radius: f32) -> impl Iterator<Item = T> + '_ {
+        let corner = Vec2 {
+            x: radius,
+            y: radius,
+        };
+        self.query(center - corner, center + corner)
+            .filter(move |(pos, _)| (*pos - center).length() <= radius)
+            .map(|(_, item)| item)
+    }
+
+    fn cell(&self, pos: Vec2) -> (i32, i32) {
+        (
+            (pos.x / self.cell_size).floor() as i32,
+            (pos.y / self.cell_size).floor() as i32,
+        )
+    }
+}
+
+/// An entity of a snapshot, by player id or by index into the projectiles or pickups
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityRef {
+    Player(u64),
+    Projectile(usize),
+    Pickup(usize),
+}
+
+pub type EntityGrid = SpatialGrid<EntityRef>;
+
+impl Entities {
+    /// Where every entity is, in cells `cell_size` wide
+    pub fn grid(&self, cell_size: f32) -> EntityGrid {
+        let mut grid = SpatialGrid::new(cell_size);
+        for (id, player) in &self.players {
+            grid.insert(player.pos, EntityRef::Player(*id));
+        }
+        for (i, projectile) in self.projectiles.iter().enumerate() {
+            grid.insert(projectile.pos, EntityRef::Projectile(i));
+        }
+        for (i, pickup) in self.pickups.iter().enumerate() {
+            grid.insert(pickup.pos, EntityRef::Pickup(i));
+        }
+        grid
+    }
+
+    /// The entities within `radius` of `center`, found through `grid` built from these entities.
+    /// Projectiles and pickups keep their order.
+    pub fn within(&self, grid: &EntityGrid, center: Vec2, radius: f32) -> Entities {
+        let mut players = HashMap::new();
+        let (mut projectiles, mut pickups) = (Vec::new(), Vec::new());
+        for entity in grid.within(center, radius) {
+            match entity {
+                EntityRef::Player(id) => {
+                    if let Some(player) = self.players.get(&id) {
+                        players.insert(id, player.clone());
+                    }
+                }
+                EntityRef::Projectile(i) => projectiles.push(i),
+                EntityRef::Pickup(i) => pickups.push(i),
+            }
+        }
+        projectiles.sort_unstable();
+        pickups.sort_unstable();
+        Entities {
+            players,
+            projectiles: projectiles
+                .into_iter()
+                .filter_map(|i| self.projectiles.get(i).cloned())
+                .collect(),
+            pickups: pickups
+                .into_iter()
+                .filter_map(|i| self.pickups.get(i).cloned())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        world::{
+            ammo::Ammo,
+            entities::Player,
+            pickups::{Pickup, PickupKind},
+        },
+    };
+
+    fn at(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+
+    fn player(pos: Vec2) -> Player {
+        Player {
+            username: String::from("player"),
+            color: Color::WHITE,
+            pos,
+            vel: Vec2::ZERO,
+            last_input_seq: 0,
+            input_ticks: 0,
+            health: 100.0,
+            armor: 0.0,
+            respawn_in: 0.0,
+            sprite: None,
+            ammo: Ammo::default(),
+        }
+    }
+
+    #[test]
+    fn only_what_is_in_range_is_found() {
+        let mut grid = SpatialGrid::new(1.0);
+        for (i, pos) in [at(0.2, 0.2), at(0.9, 0.0), at(-0.5, -0.5), at(3.0, 3.0)]
+            .into_iter()
+            .enumerate()
+        {
+            grid.insert(pos, i);
+        }
+        let mut found: Vec<_> = grid.within(at(0.0, 0.0), 1.0).collect();
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1, 2]);
+        assert_eq!(grid.within(at(3.0, 2.5), 0.6).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn boxes_include_their_edges_across_cells() {
+        let mut grid = SpatialGrid::new(1.0);
+        // On cell borders, on either side of zero and on the box's edges
+        for (i, pos) in [at(0.0, 0.0), at(-1.0, -1.0), at(1.0, -0.5), at(-0.001, 2.0)]
+            .into_iter()
+            .enumerate()
+        {
+            grid.insert(pos, i);
+        }
+        let mut found: Vec<_> = grid
+            .query(at(-1.0, -1.0), at(1.0, 1.0))
+            .map(|(_, i)| i)
+            .collect();
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1, 2]);
+        assert_eq!(
+            grid.query(at(-0.5, 1.5), at(0.0, 2.0)).collect::<Vec<_>>(),
+            vec![(at(-0.001, 2.0), 3)]
+        );
+        assert_eq!(grid.query(at(0.1, 0.1), at(0.9, 0.9)).count(), 0);
+    }
+
+    #[test]
+    fn moved_items_are_found_where_they_went() {
+        let mut grid = SpatialGrid::new(0.5);
+        let (mut a, b) = (at(0.1, 0.1), at(0.2, 0.1));
+        grid.insert(a, 'a');
+        grid.insert(b, 'b');
+        // Crosses a few cells one step at a time
+        for _ in 0..10 {
+            let next = a + at(0.3, 0.0);
+            grid.relocate(a, next, 'a');
+            a = next;
+        }
+        assert_eq!(
+            grid.within(at(0.0, 0.0), 0.5).collect::<Vec<_>>(),
+            vec!['b']
+        );
+        assert_eq!(grid.within(a, 0.1).collect::<Vec<_>>(), vec!['a']);
+
+        assert!(grid.remove(b, 'b'));
+        assert!(!grid.remove(b, 'b'));
+        // Only found where it was inserted
+        assert!(!grid.remove(b, 'a'));
+        assert_eq!(
+            grid.within(at(0.0, 0.0), 10.0).collect::<Vec<_>>(),
+            vec!['a']
+        );
+    }
+
+    #[test]
+    fn snapshots_are_cut_down_to_the_area() {
+        let pickup = |x| Pickup {
+            pos: at(x, 0.0),
+            respawn_in: 0.0,
+            kind: PickupKind::default(),
+        };
+        let entities = Entities {
+            players: HashMap::from([(1, player(at(0.0, 0.0))), (2, player(at(5.0, 0.0)))]),
+            projectiles: Vec::new(),
+            pickups: vec![pickup(0.5), pickup(5.0), pickup(-0.5)],
+        };
+        let grid = entities.grid(2.0);
+        let near = entities.within(&grid, at(0.0, 0.0), 2.0);
+        assert_eq!(near.players.keys().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(near.pickups, vec![pickup(0.5), pickup(-0.5)]);
+    }
+}
@@ -1,32 +1,94 @@
 //! This module defines the world structure and its components, including players and the environment.
 //! It provides the [`World`] struct, which contains the game state, including entities and their
 //! properties. The world can be updated with player movements and other game logic.
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
-use bincode::{Decode, Encode};
+use anyhow::Result;
+use bincode::{Decode, Encode, config};
 use serde::{Deserialize, Serialize};
 
+pub mod ammo;
+pub mod arena;
+pub mod checkpoint;
+pub mod checksum;
+pub mod combat;
 pub mod entities;
 pub mod environment;
+pub mod grid;
+pub mod pickups;
+pub mod rng;
+pub mod scoreboard;
+pub mod sprite;
+pub mod zone;
 
+use crate::tunables::Tunables;
+use combat::Blast;
 use entities::Entities;
 use environment::Environment;
+use rng::GameRng;
+use scoreboard::Scoreboard;
 
 /// The main game world that contains the environment and entities (players).
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
 pub struct GameWorld {
     pub environment: Environment,
     pub entities: Entities,
+    /// Number of simulation steps run so far
+    pub tick: u64,
+    pub tunables: Tunables,
+    pub scoreboard: Scoreboard,
+    /// Decides spawn points and anything else left to chance, see [`rng`]
+    pub rng: GameRng,
 }
 impl GameWorld {
     pub fn new() -> Self {
         Self {
-            environment: Environment {
-                objects: Vec::new(),
-            },
+            environment: Environment::default(),
             entities: Entities {
                 players: HashMap::new(),
+                projectiles: Vec::new(),
+                pickups: Vec::new(),
             },
+            tick: 0,
+            tunables: Tunables::default(),
+            scoreboard: Scoreboard::default(),
+            rng: GameRng::default(),
         }
     }
-}
\ No newline at end of file
+
+    /// Writes the whole world to a file, replacing it if it exists.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Reads a world written by [`GameWorld::save`].
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// The whole world as bytes, for storage other than a file
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::encode_to_vec(self, config::standard())?)
+    }
+
+    /// Reads a world from the bytes of [`GameWorld::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (world, _) = bincode::decode_from_slice(bytes, config::standard())?;
+        Ok(world)
+    }
+
+    /// Advances every entity by one simulation step, returning where explosive projectiles hit
+    /// a wall.
+    pub fn update(&mut self, dt: f32) -> Vec<Blast> {
+        self.entities
+            .update(dt, &self.tunables.physics, &self.environment);
+        self.entities
+            .update_projectiles(dt, &self.tunables.combat, &self.environment)
+    }
+}
+impl Default for GameWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,156 @@
+//! Armor and ammo pickups lying around the map, taken by walking over them and back after a
+//! while.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    vec::Vec2,
+    world::{combat::CombatConfig, entities::Entities},
+};
+
+/// What a pickup gives
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default, Decode, Encode)]
+pub enum PickupKind {
+    /// [`CombatConfig::armor_pickup`] of armor
+    #[default]
+    Armor,
+    /// [`AmmoConfig::pickup_magazines`](crate::world::ammo::AmmoConfig::pickup_magazines) of
+    /// every weapon
+    Ammo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+pub struct Pickup {
+    pub pos: Vec2,
+    /// Seconds until it can be taken again, available at zero
+    pub respawn_in: f32,
+    #[serde(default)]
+    pub kind: PickupKind,
+}
+impl Pickup {
+    /// Available pickups of `kind` at every point
+    pub fn at(points: &[Vec2], kind: PickupKind) -> Vec<Pickup> {
+        points
+            .iter()
+            .map(|&pos| Pickup {
+                pos,
+                respawn_in: 0.0,
+                kind,
+            })
+            .collect()
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.respawn_in <= 0.0
+    }
+}
+
+impl Entities {
+    /// Counts down taken pickups and gives what they hold to living players touching an available
+    /// one, unless they already have as much as they can carry. Returns who took one.
+    pub fn collect_pickups(
+        &mut self,
+        dt: f32,
+        config: &CombatConfig,
+        player_radius: f32,
+    ) -> Vec<u64> {
+        let mut collected = Vec::new();
+        for pickup in &mut self.pickups {
+            if !pickup.is_available() {
+                pickup.respawn_in = (pickup.respawn_in - dt).max(0.0);
+                continue;
+            }
+            let taker = self.players.iter_mut().find(|(_, player)| {
+                player.is_alive()
+                    && (player.pos - pickup.pos).length() < player_radius + PICKUP_RADIUS
+                    && match pickup.kind {
+                        PickupKind::Armor => player.armor < config.max_armor,
+                        PickupKind::Ammo => !player.ammo.reserves_full(&config.ammo),
+                    }
+            });
+            if let Some((id, player)) = taker {
+                match pickup.kind {
+                    PickupKind::Armor => {
+                        player.armor = (player.armor + config.armor_pickup).min(config.max_armor)
+                    }
+                    PickupKind::Ammo => {
+                        player
+                            .ammo
+                            .refill(config.ammo.pickup_magazines, &config.ammo);
+                    }
+                }
+                pickup.respawn_in = config.pickup_respawn;
+                collected.push(*id);
+            }
+        }
+        collected
+    }
+}
+
+/// Size of a pickup, for touching and drawing it
+pub const PICKUP_RADIUS: f32 = 0.03;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        world::{ammo::Ammo, entities::Player},
+    };
+
+    fn player() -> Player {
+        Player {
+            username: String::new(),
+            color: Color::WHITE,
+            pos: Vec2::ZERO,
+            vel: Vec2::ZERO,
+            last_input_seq: 0,
+            input_ticks: 0,
+            health: 100.0,
+            armor: 0.0,
+            respawn_in: 0.0,
+            sprite: None,
+            ammo: Ammo::full(&CombatConfig::default().ammo),
+        }
+    }
+
+    #[test]
+    fn pickups_give_armor_then_come_back() {
+        let config = CombatConfig::default();
+        let mut entities = Entities {
+            players: [(1, player())].into(),
+            projectiles: Vec::new(),
+            pickups: Pickup::at(&[Vec2::ZERO], PickupKind::Armor),
+        };
+
+        assert_eq!(entities.collect_pickups(0.1, &config, 0.05), vec![1]);
+        assert_eq!(entities.players[&1].armor, config.armor_pickup);
+        assert!(!entities.pickups[0].is_available());
+
+        // Gone until it respawns
+        assert!(entities.collect_pickups(0.1, &config, 0.05).is_empty());
+        entities.collect_pickups(config.pickup_respawn, &config, 0.05);
+        assert_eq!(entities.collect_pickups(0.1, &config, 0.05), vec![1]);
+        assert_eq!(entities.players[&1].armor, config.armor_pickup * 2.0);
+    }
+
+    #[test]
+    fn ammo_pickups_wait_for_room_in_the_reserve() {
+        let config = CombatConfig::default();
+        let mut entities = Entities {
+            players: [(1, player())].into(),
+            projectiles: Vec::new(),
+            pickups: Pickup::at(&[Vec2::ZERO], PickupKind::Ammo),
+        };
+
+        assert!(entities.collect_pickups(0.1, &config, 0.05).is_empty());
+        let magazine = &mut entities.players.get_mut(&1).unwrap().ammo.magazines[0];
+        magazine.reserve = 0;
+        assert_eq!(entities.collect_pickups(0.1, &config, 0.05), vec![1]);
+        let reserve = entities.players[&1].ammo.magazines[0].reserve;
+        assert_eq!(
+            reserve,
+            config.ammo.bullet.magazine * config.ammo.pickup_magazines
+        );
+    }
+}
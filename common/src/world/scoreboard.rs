@@ -0,0 +1,233 @@
+//! Kills, assists and deaths of every player, kept by the server and sent to clients when they
+//! change.
+//!
+//! The server also keeps a [`DamageLog`] of who recently hurt whom, so that everyone who helped
+//! with a kill besides the killer gets an assist.
+use std::collections::HashMap;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::tunables::ticks;
+
+/// Tunables for scoring
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringConfig {
+    /// Seconds before a death that damage still counts towards an assist
+    pub assist_window: f32,
+    /// Damage a player must have dealt within the window to get an assist
+    pub assist_min_damage: f32,
+}
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            assist_window: 5.0,
+            assist_min_damage: 20.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default, Decode, Encode)]
+pub struct Score {
+    pub kills: u32,
+    pub assists: u32,
+    pub deaths: u32,
+    /// Rating the server keeps across matches, once it looked the player up
+    pub rating: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default, Decode, Encode)]
+pub struct Scoreboard {
+    pub scores: HashMap<u64, Score>,
+}
+impl Scoreboard {
+    /// Counts a death, a kill for whoever caused it unless the victim did, and an assist for
+    /// every player in `assists`.
+    pub fn record_death(&mut self, victim: u64, killer: Option<u64>, assists: &[u64]) {
+        self.scores.entry(victim).or_default().deaths += 1;
+        if let Some(killer) = killer.filter(|killer| *killer != victim) {
+            self.scores.entry(killer).or_default().kills += 1;
+        }
+        for assister in assists {
+            self.scores.entry(*assister).or_default().assists += 1;
+        }
+    }
+
+    /// Forgets a player that left.
+    pub fn remove(&mut self, id: u64) {
+        self.scores.remove(&id);
+    }
+
+    /// Score of a player, nothing yet if it never killed or died
+    pub fn score(&self, id: u64) -> Score {
+        self.scores.get(&id).copied().unwrap_or_default()
+    }
+
+    /// Orders `ids` by their scores, most kills first, then most assists and fewest deaths
+    /// breaking ties.
+    pub fn rank(&self, ids: impl IntoIterator<Item = u64>) -> Vec<(u64, Score)> {
+        use std::cmp::Reverse;
+        let mut ranking: Vec<_> = ids.into_iter().map(|id| (id, self.score(id))).collect();
+        ranking.sort_by_key(|(id, score)| {
+            (
+                Reverse(score.kills),
+                Reverse(score.assists),
+                score.deaths,
+                *id,
+            )
+        });
+        ranking
+    }
+}
+
+/// Damage one player dealt to another at some tick
+#[derive(Clone, Copy, Debug)]
+struct Contribution {
+    attacker: u64,
+    amount: f32,
+    tick: u64,
+}
+
+/// Recent damage taken by every player, kept by the server to credit assists
+#[derive(Clone, Debug, Default)]
+pub struct DamageLog {
+    taken: HashMap<u64, Vec<Contribution>>,
+}
+impl DamageLog {
+    /// Remembers that `attacker` dealt `amount` to `victim` at `tick`, forgetting whatever the
+    /// victim took too long ago to still count. The server simulates `tick_rate` steps per second.
+    pub fn record(
+        &mut self,
+        victim: u64,
+        attacker: u64,
+        amount: f32,
+        tick: u64,
+        config: &ScoringConfig,
+        tick_rate: f64,
+    ) {
+        if attacker == victim || amount <= 0.0 {
+            return;
+        }
+        let window = Self::window(config, tick_rate);
+        let taken = self.taken.entry(victim).or_default();
+        taken.retain(|contribution| contribution.tick + window >= tick);
+        taken.push(Contribution {
+            attacker,
+            amount,
+            tick,
+        });
+    }
+
+    /// Players other than the killer who dealt the victim enough damage in the window before
+    /// its death at `tick`, by id. The victim starts with a clean log afterwards.
+    pub fn assists(
+        &mut self,
+        victim: u64,
+        killer: Option<u64>,
+        tick: u64,
+        config: &ScoringConfig,
+        tick_rate: f64,
+    ) -> Vec<u64> {
+        let window = Self::window(config, tick_rate);
+        let mut dealt: HashMap<u64, f32> = HashMap::new();
+        for contribution in self.taken.remove(&victim).unwrap_or_default() {
+            if contribution.tick + window >= tick && Some(contribution.attacker) != killer {
+                *dealt.entry(contribution.attacker).or_default() += contribution.amount;
+            }
+        }
+        let mut assists: Vec<_> = dealt
+            .into_iter()
+            .filter(|(_, amount)| *amount >= config.assist_min_damage)
+            .map(|(id, _)| id)
+            .collect();
+        assists.sort_unstable();
+        assists
+    }
+
+    /// Forgets the players `keep` rejects, such as ones that left, both as victims and as
+    /// attackers.
+    pub fn retain(&mut self, mut keep: impl FnMut(u64) -> bool) {
+        self.taken.retain(|victim, _| keep(*victim));
+        for taken in self.taken.values_mut() {
+            taken.retain(|contribution| keep(contribution.attacker));
+        }
+    }
+
+    /// Length of the assist window in ticks
+    fn window(config: &ScoringConfig, tick_rate: f64) -> u64 {
+        ticks(config.assist_window as f64, tick_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::details::TICK_RATE;
+
+    #[test]
+    fn kills_and_deaths_are_ranked() {
+        let mut scoreboard = Scoreboard::default();
+        scoreboard.record_death(2, Some(1), &[]);
+        scoreboard.record_death(3, Some(1), &[4]);
+        scoreboard.record_death(1, Some(3), &[]);
+        // Dying to the world or to yourself is not a kill
+        scoreboard.record_death(3, None, &[]);
+        scoreboard.record_death(2, Some(2), &[]);
+
+        let ranking = scoreboard.rank([4, 3, 2, 1]);
+        let ids: Vec<u64> = ranking.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![1, 3, 4, 2]);
+        assert_eq!(
+            ranking[0].1,
+            Score {
+                kills: 2,
+                assists: 0,
+                deaths: 1,
+                rating: None
+            }
+        );
+        assert_eq!(
+            scoreboard.score(4),
+            Score {
+                kills: 0,
+                assists: 1,
+                deaths: 0,
+                rating: None
+            }
+        );
+        assert_eq!(
+            scoreboard.score(2),
+            Score {
+                kills: 0,
+                assists: 0,
+                deaths: 2,
+                rating: None
+            }
+        );
+    }
+
+    #[test]
+    fn recent_damage_earns_assists() {
+        let config = ScoringConfig::default();
+        let window = (config.assist_window as f64 * TICK_RATE) as u64;
+        let mut log = DamageLog::default();
+        log.record(1, 2, 30.0, 0, &config, TICK_RATE);
+        // Not enough damage on its own, but it adds up
+        log.record(1, 3, 10.0, 10, &config, TICK_RATE);
+        log.record(1, 3, 10.0, 20, &config, TICK_RATE);
+        log.record(1, 4, 50.0, 10, &config, TICK_RATE);
+        // Hurting yourself does not count
+        log.record(1, 1, 50.0, 10, &config, TICK_RATE);
+
+        // Too long ago for player 2, and the killer gets the kill instead
+        assert_eq!(
+            log.assists(1, Some(4), window + 5, &config, TICK_RATE),
+            vec![3]
+        );
+        assert!(
+            log.assists(1, Some(4), window + 5, &config, TICK_RATE)
+                .is_empty()
+        );
+    }
+}
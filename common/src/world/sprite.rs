@@ -0,0 +1,18 @@
+//! Sprites that players and objects can be drawn as instead of flat shapes.
+use std::fmt::Display;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Names a sprite image. The client looks it up as `<id>.png` in its sprites directory and falls
+/// back to the flat shape when there is no such file.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Decode, Encode,
+)]
+#[serde(transparent)]
+pub struct SpriteId(pub u16);
+impl Display for SpriteId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
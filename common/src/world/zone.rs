@@ -0,0 +1,293 @@
+//! Safe zone that closes in over a match in phases, hurting anyone caught outside it.
+//!
+//! Battle royale style modes start a [`Storm`] with the match and advance it every tick. Clients
+//! only get the current [`Zone`] and work out its radius and timer from their latest snapshot.
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tunables::ticks,
+    vec::Vec2,
+    world::{
+        arena::Arena,
+        combat::{CombatConfig, Death},
+        entities::Entities,
+    },
+};
+
+/// One step of the storm's schedule
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct StormPhase {
+    /// Seconds the zone holds still before shrinking
+    pub delay: f32,
+    /// Seconds the zone takes to shrink
+    pub shrink_time: f32,
+    /// Radius the zone shrinks down to
+    pub radius: f32,
+    /// Health lost per second outside the zone
+    pub damage: f32,
+}
+impl Default for StormPhase {
+    fn default() -> Self {
+        Self {
+            delay: 30.0,
+            shrink_time: 20.0,
+            radius: 1.0,
+            damage: 5.0,
+        }
+    }
+}
+
+/// Tunables for the storm
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct StormConfig {
+    pub center: Vec2,
+    /// Radius of the zone when a match starts
+    pub start_radius: f32,
+    /// Phases played in order, the zone keeps its last radius once they are over
+    pub phases: Vec<StormPhase>,
+}
+impl Default for StormConfig {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            start_radius: 3.0,
+            phases: vec![
+                StormPhase {
+                    delay: 30.0,
+                    shrink_time: 20.0,
+                    radius: 2.0,
+                    damage: 2.0,
+                },
+                StormPhase {
+                    delay: 20.0,
+                    shrink_time: 15.0,
+                    radius: 1.0,
+                    damage: 5.0,
+                },
+                StormPhase {
+                    delay: 15.0,
+                    shrink_time: 10.0,
+                    radius: 0.3,
+                    damage: 10.0,
+                },
+            ],
+        }
+    }
+}
+
+/// Tunables for the battle royale mode
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+#[serde(default, deny_unknown_fields)]
+pub struct BattleRoyaleConfig {
+    /// Seconds between the end of a match and the start of the next
+    pub round_delay: f32,
+    /// Players needed to start a match
+    pub min_players: usize,
+}
+impl Default for BattleRoyaleConfig {
+    fn default() -> Self {
+        Self {
+            round_delay: 5.0,
+            min_players: 2,
+        }
+    }
+}
+
+/// Current phase of the storm as sent to clients
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Decode, Encode)]
+pub struct Zone {
+    /// Safe area, holding still until its `start_tick` and then shrinking until its `end_tick`
+    pub area: Arena,
+    /// Health lost per second outside the area
+    pub damage: f32,
+}
+
+/// Runs the storm's schedule on the server
+#[derive(Clone, Debug)]
+pub struct Storm {
+    phases: Vec<StormPhase>,
+    /// Index of the phase `zone` belongs to
+    phase: usize,
+    zone: Zone,
+    /// Simulation steps per second, to turn the phases' seconds into ticks
+    tick_rate: f64,
+}
+impl Storm {
+    /// Starts the first phase at `tick`, the server simulating `tick_rate` steps per second.
+    pub fn start(config: &StormConfig, tick: u64, tick_rate: f64) -> Self {
+        let radius = config.start_radius;
+        let mut storm = Self {
+            phases: config.phases.clone(),
+            phase: 0,
+            zone: Zone {
+                area: Arena {
+                    center: config.center,
+                    start_radius: radius,
+                    end_radius: radius,
+                    start_tick: tick,
+                    end_tick: tick,
+                },
+                damage: 0.0,
+            },
+            tick_rate,
+        };
+        if let Some(phase) = storm.phases.first() {
+            storm.zone = Self::phase_zone(phase, storm.zone.area, tick, tick_rate);
+        }
+        storm
+    }
+
+    /// Zone that shrinks from where `previous` ended, following `phase`
+    fn phase_zone(phase: &StormPhase, previous: Arena, tick: u64, tick_rate: f64) -> Zone {
+        let ticks = |seconds: f32| ticks(seconds as f64, tick_rate);
+        let start_tick = tick + ticks(phase.delay);
+        Zone {
+            area: Arena {
+                center: previous.center,
+                start_radius: previous.end_radius,
+                end_radius: phase.radius,
+                start_tick,
+                end_tick: start_tick + ticks(phase.shrink_time),
+            },
+            damage: phase.damage,
+        }
+    }
+
+    pub fn zone(&self) -> Zone {
+        self.zone
+    }
+
+    /// Moves on to the next phase once the current one finished shrinking, returning whether the
+    /// zone changed.
+    pub fn advance(&mut self, tick: u64) -> bool {
+        let Some(next) = self.phases.get(self.phase + 1) else {
+            return false;
+        };
+        if tick < self.zone.area.end_tick {
+            return false;
+        }
+        self.phase += 1;
+        self.zone = Self::phase_zone(next, self.zone.area, tick, self.tick_rate);
+        true
+    }
+
+    /// Damages every living player outside the zone for a step of `dt` seconds, returning the
+    /// ones that died. Armor and respawning work as for any other damage.
+    pub fn hurt(
+        &self,
+        entities: &mut Entities,
+        tick: u64,
+        dt: f32,
+        config: &CombatConfig,
+    ) -> Vec<Death> {
+        let mut deaths = Vec::new();
+        for (id, player) in entities.players.iter_mut() {
+            if !player.is_alive() || self.zone.area.contains(player.pos, tick) {
+                continue;
+            }
+            if player.take_damage(self.zone.damage * dt, config).fatal {
+                deaths.push(Death {
+                    victim: *id,
+                    killer: None,
+                    weapon: None,
+                });
+            }
+        }
+        deaths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        details::TICK_RATE,
+        world::{ammo::Ammo, entities::Player},
+    };
+
+    fn config() -> StormConfig {
+        StormConfig {
+            center: Vec2::ZERO,
+            start_radius: 2.0,
+            phases: vec![
+                StormPhase {
+                    delay: 1.0,
+                    shrink_time: 1.0,
+                    radius: 1.0,
+                    damage: 10.0,
+                },
+                StormPhase {
+                    delay: 0.0,
+                    shrink_time: 2.0,
+                    radius: 0.5,
+                    damage: 50.0,
+                },
+            ],
+        }
+    }
+
+    fn ticks(seconds: f64) -> u64 {
+        (seconds * TICK_RATE) as u64
+    }
+
+    #[test]
+    fn phases_shrink_in_turn() {
+        let mut storm = Storm::start(&config(), 0, TICK_RATE);
+        let first = storm.zone().area;
+        assert_eq!(first.radius(ticks(0.5)), 2.0);
+        assert_eq!(first.radius(ticks(1.5)), 1.5);
+
+        assert!(!storm.advance(ticks(1.5)));
+        assert!(storm.advance(ticks(2.0)));
+        let second = storm.zone();
+        assert_eq!(second.damage, 50.0);
+        assert_eq!(second.area.radius(ticks(2.0)), 1.0);
+        assert_eq!(second.area.radius(ticks(5.0)), 0.5);
+
+        // The last radius holds once the schedule is over
+        assert!(!storm.advance(ticks(10.0)));
+        assert_eq!(storm.zone(), second);
+    }
+
+    #[test]
+    fn only_players_outside_get_hurt() {
+        let player = |x: f32| Player {
+            username: String::new(),
+            color: Color::WHITE,
+            pos: Vec2 { x, y: 0.0 },
+            vel: Vec2::ZERO,
+            last_input_seq: 0,
+            input_ticks: 0,
+            health: 15.0,
+            armor: 0.0,
+            respawn_in: 0.0,
+            sprite: None,
+            ammo: Ammo::default(),
+        };
+        let mut entities = Entities {
+            players: [(1, player(0.0)), (2, player(3.0))].into(),
+            projectiles: Vec::new(),
+            pickups: Vec::new(),
+        };
+        let storm = Storm::start(&config(), 0, TICK_RATE);
+        let combat = CombatConfig::default();
+
+        assert!(storm.hurt(&mut entities, 0, 1.0, &combat).is_empty());
+        assert_eq!(entities.players[&2].health, 5.0);
+        let deaths = storm.hurt(&mut entities, 0, 1.0, &combat);
+        assert_eq!(
+            deaths,
+            vec![Death {
+                victim: 2,
+                killer: None,
+                weapon: None,
+            }]
+        );
+        assert_eq!(entities.players[&2].respawn_in, combat.respawn_delay);
+        assert_eq!(entities.players[&1].health, 15.0);
+    }
+}
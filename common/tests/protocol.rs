@@ -0,0 +1,101 @@
+//! Messages and simulation as another crate sees them, through the public API only.
+use common::{
+    message::{ClientMessage, ServerMessage, frame::Compression},
+    vec::Vec2,
+    version::PROTOCOL_VERSION,
+};
+use test_utils::{
+    assert_close, assert_round_trips, assert_snapshot, transport::FakeTransport,
+    world::WorldBuilder,
+};
+
+#[test]
+fn every_kind_of_message_round_trips() {
+    let world = WorldBuilder::new()
+        .player(1, "one", Vec2::ZERO)
+        .object(Vec2::ONE, Vec2::ONE)
+        .build();
+    assert_round_trips!(ClientMessage::Hello(PROTOCOL_VERSION));
+    assert_round_trips!(ClientMessage::AcceptCompression(Compression::Lz4));
+    assert_round_trips!(ClientMessage::Connect(String::from("name"), String::new()));
+    assert_round_trips!(ClientMessage::Resume(String::from("0123abcd")));
+    assert_round_trips!(ClientMessage::SelectLobby(String::from("duel")));
+    assert_round_trips!(ClientMessage::ProposeSigning([7; 32]));
+    assert_round_trips!(ServerMessage::SigningAgreed([9; 32]));
+    assert_round_trips!(ClientMessage::QualityReport {
+        snapshot_loss: 0.25,
+        fps: 30.0,
+    });
+    assert_round_trips!(ServerMessage::CheckpointReached {
+        player: 3,
+        checkpoint: 2,
+        tick: 600,
+    });
+    assert_round_trips!(ServerMessage::LapCompleted {
+        player: 3,
+        time: 12.5,
+    });
+    assert_round_trips!(ServerMessage::LapTimes(vec![(String::from("name"), 12.5)]));
+    assert_round_trips!(ServerMessage::RaceStarted { laps: 3 });
+    assert_round_trips!(ServerMessage::RaceFinished {
+        player: 3,
+        place: 1,
+        time: 40.0,
+    });
+    assert_round_trips!(ServerMessage::ConnectionAccepted {
+        id: 4,
+        username: String::from("name"),
+        session: String::from("0123abcd"),
+    });
+    assert_round_trips!(ClientMessage::MoveInput {
+        seq: 7,
+        dir: Vec2 { x: 0.5, y: -1.0 },
+    });
+    assert_round_trips!(ServerMessage::UpdateEntities {
+        tick: 3,
+        time: 0.05,
+        entities: world.entities,
+    });
+    assert_round_trips!(ServerMessage::UpdateObjects(world.environment));
+}
+
+#[tokio::test]
+async fn messages_arrive_in_order_over_a_stream() {
+    let (mut client, mut server) = FakeTransport::pair();
+    client
+        .send(&ClientMessage::Hello(PROTOCOL_VERSION))
+        .await
+        .unwrap();
+    client
+        .send(&ClientMessage::Chat(String::from("hi")))
+        .await
+        .unwrap();
+    drop(client);
+
+    let hello = server.recv::<ClientMessage>().await.unwrap();
+    assert_eq!(hello, Some(ClientMessage::Hello(PROTOCOL_VERSION)));
+    let chat = server.recv::<ClientMessage>().await.unwrap();
+    assert_eq!(chat, Some(ClientMessage::Chat(String::from("hi"))));
+    assert_eq!(server.recv::<ClientMessage>().await.unwrap(), None);
+}
+
+#[test]
+fn seeded_worlds_play_out_the_same() {
+    let run = || {
+        let mut world = WorldBuilder::new()
+            .seed(42)
+            .player(1, "one", Vec2 { x: 0.3, y: 0.0 })
+            .attractor(Vec2::ZERO, 2.0, 1.0)
+            .build();
+        for _ in 0..100 {
+            world.update(1.0 / 60.0);
+        }
+        world
+    };
+    let world = run();
+    assert_snapshot!(world, run());
+    // The well pulled the player in from where it stood still
+    let player = &world.entities.players[&1];
+    assert!(player.pos.x < 0.3);
+    assert_close!(player.pos.y, 0.0);
+}
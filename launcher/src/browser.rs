@@ -0,0 +1,60 @@
+//! Server browser listing the favorite servers of a profile, with what each is playing and how
+//! far away it is.
+//!
+//! Every server is asked for its status on a task of its own, see
+//! [`common::discovery::query_status`], and the answers come back as a
+//! [`LauncherEvent::ServerQueried`] handed to [`ServerBrowser::answer`].
+use std::{collections::HashMap, time::Duration};
+
+use common::discovery::{self, ServerStatus};
+
+use crate::events::{EventSender, LauncherEvent};
+
+/// Time a server gets to answer before it is listed as offline
+const QUERY_TIME: Duration = Duration::from_secs(2);
+
+/// What is known about a listed server
+pub enum ServerInfo {
+    /// Asked and waiting for the answer
+    Pending,
+    /// Answered with its status after the given round trip
+    Online(ServerStatus, Duration),
+    /// Could not be reached, for the given reason
+    Offline(String),
+}
+
+#[derive(Default)]
+pub struct ServerBrowser {
+    /// Latest answer of every server asked, by address
+    servers: HashMap<String, ServerInfo>,
+}
+impl ServerBrowser {
+    /// Asks every server at `addrs` for its status again
+    pub fn refresh<'a>(
+        &mut self,
+        events: &EventSender,
+        addrs: impl IntoIterator<Item = &'a String>,
+    ) {
+        for addr in addrs {
+            self.servers.insert(addr.clone(), ServerInfo::Pending);
+            let (addr, events) = (addr.clone(), events.clone());
+            tokio::spawn(async move {
+                let info = match discovery::query_status(addr.as_str(), QUERY_TIME).await {
+                    Ok((status, rtt)) => ServerInfo::Online(status, rtt),
+                    Err(e) => ServerInfo::Offline(e.to_string()),
+                };
+                events.send(LauncherEvent::ServerQueried(addr, info));
+            });
+        }
+    }
+
+    /// Takes in the answer of the server at `addr`
+    pub fn answer(&mut self, addr: String, info: ServerInfo) {
+        self.servers.insert(addr, info);
+    }
+
+    /// What is known about the server at `addr`, `None` if it was never asked
+    pub fn get(&self, addr: &str) -> Option<&ServerInfo> {
+        self.servers.get(addr)
+    }
+}
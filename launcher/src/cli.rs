@@ -0,0 +1,12 @@
+//! Command-line options of the launcher, which otherwise takes everything from its settings.
+use clap::Parser;
+
+/// Command-line arguments for the launcher.
+#[derive(Parser, Debug)]
+#[command(name = "Launcher")]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+    /// Base URL to download builds from instead of the known mirrors, such as a local copy
+    #[arg(long, value_name = "URL")]
+    pub mirror: Option<String>,
+}
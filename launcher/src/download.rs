@@ -0,0 +1,462 @@
+//! Fetching newer client, server and launcher builds from a download mirror.
+//!
+//! A [`Downloader`] holds nothing but the HTTP client and the mirrors to use, so a copy of it can
+//! be moved into a background task while the launcher carries on. Every request fails over to
+//! the next mirror when one times out or answers with an error, see [`mirrors`](crate::mirrors).
+//! Files are written as they arrive, with their progress sent to the UI as
+//! [`LauncherEvent::DownloadProgress`].
+//!
+//! Every archive has its SHA-256 published next to its version file, as written by `sha256sum`.
+//! An archive is only unpacked once it matches, so a download that was cut short or tampered
+//! with never replaces the binaries installed.
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use common::version::Version;
+use reqwest::{Client, Response};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use zip::ZipArchive;
+
+use crate::{
+    Source,
+    events::{EventSender, LauncherEvent},
+    mirrors::Mirrors,
+};
+
+/// Bytes received between two progress reports, so a fast download does not flood the UI
+const PROGRESS_STEP: u64 = 256 * 1024;
+
+/// How far along the download of one file is
+#[derive(Clone, Debug)]
+pub struct DownloadProgress {
+    /// Path of the file on the mirror
+    pub file: String,
+    pub downloaded: u64,
+    /// Size the mirror gave for the file, if it did
+    pub total: Option<u64>,
+}
+impl DownloadProgress {
+    /// Share of the file downloaded, from 0 to 1, when its size is known
+    pub fn fraction(&self) -> Option<f32> {
+        let total = self.total.filter(|total| *total > 0)?;
+        Some((self.downloaded as f64 / total as f64).min(1.0) as f32)
+    }
+}
+
+#[derive(Clone)]
+pub struct Downloader {
+    http: Client,
+    /// Base URLs the relative paths of a [`Source`] are fetched from
+    mirrors: Mirrors,
+    /// Mirror tried first while it works
+    preferred: String,
+}
+impl Downloader {
+    pub fn new(http: Client, mirrors: Mirrors, preferred: &str) -> Self {
+        Self {
+            http,
+            mirrors,
+            preferred: preferred.to_string(),
+        }
+    }
+
+    /// Names of the parts whose version online is newer than the one installed. Parts that are
+    /// not installed or cannot be reached are left out.
+    pub async fn newer_parts(&self, parts: &[(&'static str, Source)]) -> Vec<String> {
+        let mut found = Vec::new();
+        for (name, src) in parts {
+            if let Ok(true) = self.check_for_file_updates(src).await {
+                found.push(name.to_string());
+            }
+        }
+        found
+    }
+
+    /// Version of the build described by `src` online, if it is newer than the one installed
+    pub async fn newer_version(&self, src: &Source) -> Result<Option<Version>> {
+        let local_version = self.read_local_version(src).await?;
+        let remote_version = self.fetch_remote_version(src).await?;
+        match (local_version, remote_version) {
+            (Some(local), Some(remote)) if remote > local => Ok(Some(remote)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Downloads and unpacks the build described by `src` below `target` instead of over the
+    /// installed one, for builds that cannot be replaced while they run
+    pub async fn stage(&self, src: &Source, target: &Path, events: &EventSender) -> Result<()> {
+        let (zip, version) = (target.join(src.zip), target.join(src.version));
+        for path in [&zip, &version] {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+        self.download_verified(src, &zip, events).await?;
+        unpack(&zip).await?;
+        self.download_remote_file(src.version, &version, events)
+            .await?;
+        Ok(())
+    }
+
+    /// Downloads and unpacks every part with a newer version online, returning their names.
+    /// Progress on each file goes to `events`.
+    pub async fn update(
+        &self,
+        parts: &[(&'static str, Source)],
+        events: &EventSender,
+    ) -> Result<Vec<String>> {
+        let mut updated = Vec::new();
+        for (name, src) in parts {
+            if self.check_for_file_updates(src).await? {
+                self.update_file(src, events).await?;
+                updated.push(name.to_string());
+            }
+        }
+        Ok(updated)
+    }
+
+    async fn check_for_file_updates(&self, src: &Source) -> Result<bool> {
+        Ok(self.newer_version(src).await?.is_some())
+    }
+    async fn update_file(&self, src: &Source, events: &EventSender) -> Result<()> {
+        let local_version = self.read_local_version(src).await?;
+        let remote_version = self.fetch_remote_version(src).await?;
+
+        // Update the client version
+        if let (Some(local), Some(remote)) = (local_version, remote_version)
+            && remote > local
+        {
+            // The version file only moves on once the new binaries are in place
+            self.download_verified(src, Path::new(src.zip), events)
+                .await?;
+            unpack(Path::new(src.zip)).await?;
+            self.download_remote_file(src.version, Path::new(src.version), events)
+                .await?;
+        }
+        Ok(())
+    }
+}
+/// File management
+impl Downloader {
+    /// Fetches `relative_path` from `mirror`, an answer with an error status counting as failing
+    async fn get(&self, mirror: &str, relative_path: &str) -> Result<Response> {
+        let url = format!("{mirror}{relative_path}");
+        let response = self.http.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to download file: {url} ({})",
+                response.status()
+            ));
+        }
+        Ok(response)
+    }
+    /// Notes how a request to `mirror` went, passing its result on
+    fn record<T>(&self, mirror: &str, result: Result<T>) -> Result<T> {
+        match &result {
+            Ok(_) => self.mirrors.succeeded(mirror),
+            Err(e) => {
+                eprintln!("Mirror {mirror} failed: {e}");
+                self.mirrors.failed(mirror);
+            }
+        }
+        result
+    }
+    /// Text of the small file at `relative_path`, from the first mirror that has it
+    async fn fetch_text(&self, relative_path: &str) -> Result<String> {
+        let mut last_error = None;
+        for mirror in self.mirrors.order(&self.preferred) {
+            let text = async { Ok(self.get(&mirror, relative_path).await?.text().await?) };
+            match self.record(&mirror, text.await) {
+                Ok(text) => return Ok(text),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No mirror to download from")))
+    }
+    async fn fetch_remote_version(&self, src: &Source) -> Result<Option<Version>> {
+        let text = self.fetch_text(src.version).await?;
+        Ok(Version::try_from(text.trim()).ok())
+    }
+    /// SHA-256 of the archive of `src` as published, in lowercase hex
+    async fn fetch_remote_checksum(&self, src: &Source) -> Result<String> {
+        let text = self.fetch_text(src.checksum).await?;
+        parse_checksum(&text).ok_or_else(|| anyhow::anyhow!("Invalid checksum in {}", src.checksum))
+    }
+    /// Downloads the archive of `src` to `zip_path` and checks it against its published
+    /// checksum, removing it again if they differ
+    async fn download_verified(
+        &self,
+        src: &Source,
+        zip_path: &Path,
+        events: &EventSender,
+    ) -> Result<()> {
+        self.download_remote_file(src.zip, zip_path, events).await?;
+        let verified = match self.fetch_remote_checksum(src).await {
+            Ok(expected) => {
+                let path = zip_path.to_path_buf();
+                tokio::task::spawn_blocking(move || verify(&path, &expected)).await?
+            }
+            Err(e) => Err(e.context(format!("Could not verify {}", src.zip))),
+        };
+        if verified.is_err() {
+            let _ = tokio::fs::remove_file(zip_path).await;
+        }
+        verified
+    }
+    async fn read_local_version(&self, src: &Source) -> Result<Option<Version>> {
+        let text = tokio::fs::read_to_string(src.version).await?;
+        Ok(Version::try_from(text.trim()).ok())
+    }
+    /// Downloads `relative_path` to `output_path`, starting over from the next mirror if one
+    /// fails part of the way
+    async fn download_remote_file(
+        &self,
+        relative_path: &str,
+        output_path: &Path,
+        events: &EventSender,
+    ) -> Result<PathBuf> {
+        let mut last_error = None;
+        for mirror in self.mirrors.order(&self.preferred) {
+            let download = self.download_from(&mirror, relative_path, output_path, events);
+            match self.record(&mirror, download.await) {
+                Ok(path) => return Ok(path),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No mirror to download from")))
+    }
+    async fn download_from(
+        &self,
+        mirror: &str,
+        relative_path: &str,
+        output_path: &Path,
+        events: &EventSender,
+    ) -> Result<PathBuf> {
+        let mut response = self.get(mirror, relative_path).await?;
+        let mut progress = DownloadProgress {
+            file: relative_path.to_string(),
+            downloaded: 0,
+            total: response.content_length(),
+        };
+        events.send(LauncherEvent::DownloadProgress(progress.clone()));
+
+        let path = output_path.to_path_buf();
+        let mut file = tokio::fs::File::create(&path).await?;
+        let mut reported = 0;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+            progress.downloaded += chunk.len() as u64;
+            if progress.downloaded - reported >= PROGRESS_STEP {
+                reported = progress.downloaded;
+                events.send(LauncherEvent::DownloadProgress(progress.clone()));
+            }
+        }
+        file.flush().await?;
+        events.send(LauncherEvent::DownloadProgress(progress));
+        Ok(path)
+    }
+}
+
+/// Unpacks the archive at `zip` into the directory it is in, as archives hold the files that go
+/// next to them the way `package.sh` zips them
+async fn unpack(zip: &Path) -> Result<()> {
+    let destination = zip.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let zip = zip.to_path_buf();
+    tokio::task::spawn_blocking(move || extract(&zip, &destination))
+        .await?
+        .map_err(|e| anyhow::anyhow!("Failed to unzip file: {e}"))
+}
+
+/// The hash of a checksum file as `sha256sum` writes it, which follows the hash with the file
+/// name, in lowercase
+fn parse_checksum(text: &str) -> Option<String> {
+    let hash = text.split_whitespace().next()?;
+    (hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| hash.to_ascii_lowercase())
+}
+
+/// Checks that the file at `path` has the SHA-256 `expected`
+fn verify(path: &Path, expected: &str) -> Result<()> {
+    let actual = sha256(path)?;
+    if actual != expected {
+        anyhow::bail!(
+            "Checksum mismatch for {}: expected {expected}, got {actual}",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// SHA-256 of the file at `path`, in lowercase hex
+fn sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Writes every entry of the archive at `zip_path` below `target`, keeping the executable bit of
+/// binaries on Unix. Entries that would land outside `target` are refused.
+fn extract(zip_path: &Path, target: &Path) -> Result<()> {
+    let mut archive = ZipArchive::new(File::open(zip_path)?)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(relative) = entry.enclosed_name().map(Path::to_path_buf) else {
+            anyhow::bail!(
+                "{} has an entry outside of it: {}",
+                zip_path.display(),
+                entry.name()
+            );
+        };
+        let path = target.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&path)?;
+        io::copy(&mut entry, &mut file)?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use test_utils::files::Scratch;
+    use zip::{ZipWriter, write::FileOptions};
+
+    use super::*;
+    use crate::LauncherApp;
+
+    /// Zips `entries` of a name, a Unix mode and the contents into `path`
+    fn archive(path: &Path, entries: &[(&str, u32, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, mode, contents) in entries {
+            let options = FileOptions::default().unix_permissions(*mode);
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn archives_unpack_into_nested_directories() {
+        let scratch = Scratch::new("extract-nested");
+        let zip = scratch.join("build.zip");
+        archive(
+            &zip,
+            &[
+                ("client", 0o755, "binary"),
+                ("assets/maps/arena.toml", 0o644, "map"),
+            ],
+        );
+        let target = scratch.join("out");
+        extract(&zip, &target).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(target.join("client")).unwrap(),
+            "binary"
+        );
+        assert_eq!(
+            std::fs::read_to_string(target.join("assets/maps/arena.toml")).unwrap(),
+            "map"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn binaries_stay_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let scratch = Scratch::new("extract-mode");
+        let zip = scratch.join("build.zip");
+        archive(
+            &zip,
+            &[("server", 0o755, "binary"), ("version.txt", 0o644, "1.0.0")],
+        );
+        extract(&zip, scratch.path()).unwrap();
+        let mode = |name| {
+            let metadata = std::fs::metadata(scratch.join(name)).unwrap();
+            metadata.permissions().mode() & 0o777
+        };
+        assert_eq!(mode("server"), 0o755);
+        assert_eq!(mode("version.txt"), 0o644);
+    }
+
+    #[test]
+    fn entries_outside_the_target_are_refused() {
+        let scratch = Scratch::new("extract-slip");
+        let zip = scratch.join("build.zip");
+        archive(&zip, &[("../escaped", 0o644, "evil")]);
+        let target = scratch.join("out");
+        let error = extract(&zip, &target).unwrap_err();
+        assert!(error.to_string().contains("has an entry outside of it"));
+        assert!(!scratch.join("escaped").exists());
+    }
+
+    /// SHA-256 of `hello`
+    const HELLO: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn checksums_are_read_as_sha256sum_writes_them() {
+        let line = format!("{}  client.zip\n", HELLO.to_ascii_uppercase());
+        assert_eq!(parse_checksum(&line).as_deref(), Some(HELLO));
+        assert_eq!(parse_checksum(""), None);
+        assert_eq!(parse_checksum("abc123  client.zip"), None);
+        assert_eq!(parse_checksum(&HELLO.replace('2', "g")), None);
+    }
+
+    #[test]
+    fn archives_must_match_their_checksum() {
+        let scratch = Scratch::new("checksum");
+        let zip = scratch.write("client.zip", "hello");
+        verify(&zip, HELLO).unwrap();
+
+        let error = verify(&zip, &HELLO.replace('2', "3")).unwrap_err();
+        assert!(error.to_string().starts_with("Checksum mismatch"));
+        scratch.write("client.zip", "hello, tampered");
+        assert!(verify(&zip, HELLO).is_err());
+    }
+
+    #[tokio::test]
+    async fn archives_unpack_next_to_themselves() {
+        let scratch = Scratch::new("unpack");
+        let zip = scratch.join("build/launcher/launcher.zip");
+        std::fs::create_dir_all(zip.parent().unwrap()).unwrap();
+        archive(&zip, &[("launcher", 0o755, "binary")]);
+        unpack(&zip).await.unwrap();
+        assert!(scratch.join("build/launcher/launcher").exists());
+    }
+
+    #[test]
+    fn published_archives_have_matching_checksums() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        for source in [
+            LauncherApp::CLIENT_SRC,
+            LauncherApp::SERVER_SRC,
+            LauncherApp::LAUNCHER_SRC,
+        ] {
+            let checksum = std::fs::read_to_string(root.join(source.checksum))
+                .unwrap_or_else(|e| panic!("{} is published: {e}", source.checksum));
+            let hash = parse_checksum(&checksum)
+                .unwrap_or_else(|| panic!("{} holds a SHA-256", source.checksum));
+            verify(&root.join(source.zip), &hash).unwrap();
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Results of the work the launcher does in the background, sent back to the UI.
+//!
+//! Tasks are spawned with an [`EventSender`] and report what came of them as a [`LauncherEvent`].
+//! Sending one wakes the UI, which handles every event waiting at the start of its next frame, so
+//! the launcher's state follows what the tasks did rather than what was asked of them.
+use std::net::SocketAddr;
+
+use common::{discovery::ServerStatus, version::Version};
+use eframe::egui::Context;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+use crate::{browser::ServerInfo, download::DownloadProgress};
+
+pub enum LauncherEvent {
+    /// A check for updates finished, naming the parts with a newer version online
+    UpdatesChecked(Vec<String>),
+    /// A check for updates finished, with the version of the launcher online if it is newer
+    LauncherUpdateChecked(Option<Version>),
+    /// More of a file came in while downloading updates
+    DownloadProgress(DownloadProgress),
+    /// Downloading updates finished, naming the parts updated or telling why it failed
+    UpdatesDownloaded(Result<Vec<String>, String>),
+    /// Downloading a newer launcher next to the running one finished, or why it failed
+    LauncherStaged(Result<(), String>),
+    /// What a server told about itself, or why it could not be asked
+    ServerQueried(String, ServerInfo),
+    /// A search of the local network finished with the games it found
+    LanGamesFound(Vec<(SocketAddr, ServerStatus)>),
+}
+
+/// Hands events to the UI, cloned into every task that has something to report
+#[derive(Clone)]
+pub struct EventSender {
+    tx: UnboundedSender<LauncherEvent>,
+    ctx: Context,
+}
+impl EventSender {
+    pub fn send(&self, event: LauncherEvent) {
+        // The receiver only goes away with the launcher, there is nobody left to tell then
+        let _ = self.tx.send(event);
+        self.ctx.request_repaint();
+    }
+}
+
+/// Sender for tasks and the receiver the UI of `ctx` takes their events from
+pub fn channel(ctx: &Context) -> (EventSender, UnboundedReceiver<LauncherEvent>) {
+    let (tx, rx) = unbounded_channel();
+    let sender = EventSender {
+        tx,
+        ctx: ctx.clone(),
+    };
+    (sender, rx)
+}
@@ -0,0 +1,87 @@
+//! Logs of the server and client the launcher starts, and telling when they crash.
+//!
+//! Everything a launched process prints goes to `logs/<name>.log`. Every launch starts a new file
+//! and moves the older ones along to `<name>.1.log`, `<name>.2.log` and so on, keeping the last
+//! [`KEPT_LOGS`]. When a process exits on its own with an error, a [`Crash`] says how and where
+//! its log is, so the player can look at it from the launcher.
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+
+use anyhow::{Context, Result};
+use tokio::process::Command;
+
+/// Where the logs are written, next to the build directory
+pub const LOG_DIR: &str = "logs";
+/// Logs kept per process, counting the one being written
+const KEPT_LOGS: usize = 5;
+/// Most bytes at the end of a log that are shown in the launcher
+const SHOWN_BYTES: u64 = 64 * 1024;
+
+/// Log `age` launches back of the process called `name`, 0 being the latest
+fn log_path(name: &str, age: usize) -> PathBuf {
+    let file = match age {
+        0 => format!("{name}.log"),
+        age => format!("{name}.{age}.log"),
+    };
+    Path::new(LOG_DIR).join(file)
+}
+
+/// Sends the output of `command` to a fresh log for `name`, moving the older logs along.
+/// Returns the path of the new log.
+pub fn capture(command: &mut Command, name: &str) -> Result<PathBuf> {
+    std::fs::create_dir_all(LOG_DIR).with_context(|| format!("Could not create {LOG_DIR}"))?;
+    for age in (0..KEPT_LOGS - 1).rev() {
+        let from = log_path(name, age);
+        if from.exists() {
+            std::fs::rename(&from, log_path(name, age + 1))
+                .with_context(|| format!("Could not rotate {}", from.display()))?;
+        }
+    }
+    let path = log_path(name, 0);
+    let file =
+        File::create(&path).with_context(|| format!("Could not create {}", path.display()))?;
+    command.stdout(file.try_clone()?).stderr(file);
+    Ok(path)
+}
+
+/// The end of the log at `path`, as much as is worth showing
+pub fn tail(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(SHOWN_BYTES)))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// A launched process that exited with an error without being asked to
+#[derive(Clone, Debug)]
+pub struct Crash {
+    /// Which process crashed, such as "Server"
+    pub process: &'static str,
+    /// Exit code, none when it was killed by a signal
+    pub code: Option<i32>,
+    pub log: PathBuf,
+}
+impl Crash {
+    /// A crash of `process` when it exited with `status`, none when it exited cleanly
+    pub fn from_exit(process: &'static str, status: ExitStatus, log: PathBuf) -> Option<Self> {
+        (!status.success()).then(|| Self {
+            process,
+            code: status.code(),
+            log,
+        })
+    }
+
+    pub fn message(&self) -> String {
+        match self.code {
+            Some(code) => format!("{} crashed (exit code {code})", self.process),
+            None => format!("{} crashed (killed by a signal)", self.process),
+        }
+    }
+}
@@ -11,27 +11,87 @@
 //! │   ├── client_version.txt
 //! │   └── server_version.txt
 //! ├── launcher
+//! ├── logs     // What the launched server and client print, see [`logs`]
 //! ├── version.txt
 //!
+//! Player profiles are kept apart from the binaries, in the launcher config directory, see
+//! [`profiles`]. So are the launcher's own [`settings`], such as how often it looks for updates in
+//! the background, see [`updates`], and the address and window size it was last left with.
+//!
+//! Anything that takes a while, such as downloads, update checks and asking servers for their
+//! status, runs on a task of its own and reports back to the UI through [`events`].
+//!
+//! The launcher updates itself too, but only when asked and never while running: a newer build is
+//! downloaded next to it and swapped in when it next starts, see [`self_update`].
+//!
+//! What the launched server and client print is kept in `logs/`, and the launcher says when one of
+//! them crashes, see [`logs`].
+
+mod browser;
+mod cli;
+mod download;
+mod events;
+mod logs;
+mod mirrors;
+mod profiles;
+mod self_update;
+mod settings;
+mod updates;
 
 use anyhow::Result;
+use clap::Parser;
+use common::color::Color;
 use common::details;
-use common::version::Version;
+use common::discovery::{self, ServerStatus};
+use common::version::{PROTOCOL_VERSION, Version};
 use eframe::egui::{self, Context};
 use local_ip_address::local_ip;
 use reqwest::Client;
-use std::{path::PathBuf, process::Stdio};
-use tokio::process::{Child, Command};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::{
+    process::{Child, Command},
+    sync::mpsc::UnboundedReceiver,
+};
 
-/// Different servers that serve game and server binaries
+use crate::{
+    browser::{ServerBrowser, ServerInfo},
+    cli::Cli,
+    download::{DownloadProgress, Downloader},
+    events::{EventSender, LauncherEvent},
+    logs::Crash,
+    mirrors::Mirrors,
+    profiles::Profiles,
+    self_update::SelfUpdate,
+    settings::Settings,
+    updates::UpdateChecker,
+};
+
+/// Seconds between saves of a hosted world, at most this much play is lost when the server stops
+const HOST_AUTOSAVE_INTERVAL: u64 = 10;
+/// Time a restarted server gets to start listening before the client connects again
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Time servers on the local network get to answer a search
+const LAN_SEARCH_TIME: Duration = Duration::from_secs(1);
+/// Time a mirror gets to accept a connection, and then to send more of a file, before the next
+/// one is tried
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Different servers that serve game and server binaries, which one is used is a setting
 const VERSION_SERVERS: [&str; 1] =
     ["https://raw.githubusercontent.com/Larmbs/multiplayer_game/refs/heads/master/"];
 
 /// Server and Client sources are parallel
+#[derive(Clone, Copy)]
 struct Source {
     pub binary: &'static str,
     pub zip: &'static str,
     pub version: &'static str,
+    /// SHA-256 of the zip, see [`download`]
+    pub checksum: &'static str,
 }
 
 #[derive(Default, Clone)]
@@ -42,6 +102,15 @@ enum LauncherState {
     DownloadNeeded,
     DownloadingUpdate,
     CheckingForUpdates,
+    /// The hosted server exited without being asked to
+    ServerStopped,
+}
+
+/// Server the launcher is hosting, remembered so it can be started again the same way
+struct HostSession {
+    addr: String,
+    /// World the server autosaves to and picks up again after a restart
+    world_file: PathBuf,
 }
 
 struct LauncherApp {
@@ -49,127 +118,236 @@ struct LauncherApp {
 
     server_process: Option<Child>,
     client_process: Option<Child>,
+    /// Logs the running server and client write to
+    server_log: Option<PathBuf>,
+    client_log: Option<PathBuf>,
+    /// Last launched process that crashed, until it is launched again
+    crash: Option<Crash>,
+    /// Log shown in a window, with the end of what it says
+    log_view: Option<(PathBuf, String)>,
 
     http: Client,
+    /// Mirrors builds come from, with how each has been doing
+    mirrors: Mirrors,
+    /// Mirror given with `--mirror`, used instead of the known ones
+    mirror_override: Option<String>,
+    /// Every background task reports back through here, see [`events`]
+    events: EventSender,
+    event_rx: UnboundedReceiver<LauncherEvent>,
 
     addr_input: String,
-    update_available: bool,
+    /// Status of the favorite servers
+    browser: ServerBrowser,
+    /// Servers the last search found on the local network
+    lan_games: Vec<(SocketAddr, ServerStatus)>,
+    /// Whether a search of the local network is under way
+    lan_searching: bool,
+    /// Looks for updates when asked to and in the background
+    updates: UpdateChecker,
+    /// File being downloaded, while updates are
+    download_progress: Option<DownloadProgress>,
+    /// Newer launcher found online and what the player made of it
+    self_update: SelfUpdate,
+    /// Why the last update failed, such as a download not matching its checksum
+    update_error: Option<String>,
+
+    profiles: Profiles,
+    settings: Settings,
+
+    host: Option<HostSession>,
+    /// When to launch the client again after a server restart
+    reconnect_at: Option<Instant>,
 }
 impl LauncherApp {
     const CLIENT_SRC: Source = Source {
         binary: "build/client/client",
         zip: "build/client/client.zip",
         version: "build/client/version.txt",
+        checksum: "build/client/sha256.txt",
     };
     const SERVER_SRC: Source = Source {
         binary: "build/server/server",
         zip: "build/server/server.zip",
         version: "build/server/version.txt",
+        checksum: "build/server/sha256.txt",
     };
     const LAUNCHER_SRC: Source = Source {
         binary: "build/launcher/launcher",
         zip: "build/launcher/launcher.zip",
         version: "build/launcher/version.txt",
+        checksum: "build/launcher/sha256.txt",
     };
 
-    async fn new() -> Result<Self> {
-        Ok(Self {
+    fn new(ctx: &Context, cli: Cli, settings: Settings, profiles: Profiles) -> Self {
+        let (events, event_rx) = events::channel(ctx);
+        let mirrors = match &cli.mirror {
+            Some(mirror) => Mirrors::new([mirror.clone()]),
+            None => Mirrors::new(VERSION_SERVERS.map(String::from)),
+        };
+        let http = Client::builder()
+            .connect_timeout(MIRROR_TIMEOUT)
+            .read_timeout(MIRROR_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
             state: LauncherState::Ready,
-            addr_input: String::new(),
+            addr_input: settings.last_address.clone(),
+            browser: ServerBrowser::default(),
+            lan_games: Vec::new(),
+            lan_searching: false,
             server_process: None,
             client_process: None,
-            http: Client::new(),
-            update_available: false,
-        })
-    }
-    async fn check_for_updates(&mut self) -> Result<Vec<String>> {
-        self.state = LauncherState::CheckingForUpdates;
-        let mut updates = Vec::new();
-        if self.check_for_file_updates(&Self::CLIENT_SRC).await? {
-            updates.push(String::from("Client needs an update"));
-        }
-        if self.check_for_file_updates(&Self::SERVER_SRC).await? {
-            updates.push(String::from("Server needs an update"));
-        }
-        Ok(updates)
-    }
-    async fn update(&mut self) -> Result<()> {
-        self.state = LauncherState::CheckingForUpdates;
-        if self.check_for_file_updates(&Self::CLIENT_SRC).await? {
-            self.state = LauncherState::DownloadingUpdate;
-            self.update_file(&Self::CLIENT_SRC).await?;
-        }
-        if self.check_for_file_updates(&Self::SERVER_SRC).await? {
-            self.state = LauncherState::DownloadingUpdate;
-            self.update_file(&Self::SERVER_SRC).await?;
+            server_log: None,
+            client_log: None,
+            crash: None,
+            log_view: None,
+            http,
+            mirrors,
+            mirror_override: cli.mirror,
+            events,
+            event_rx,
+            updates: UpdateChecker::new(settings.update_check_interval()),
+            download_progress: None,
+            self_update: SelfUpdate::default(),
+            update_error: None,
+            profiles,
+            settings,
+            host: None,
+            reconnect_at: None,
         }
-        self.state = LauncherState::Ready;
-        Ok(())
     }
-    async fn check_for_file_updates(&self, src: &Source) -> Result<bool> {
-        let local_version = self.read_local_version(src).await?;
-        let remote_version = self.fetch_remote_version(src).await?;
-        match (local_version, remote_version) {
-            (Some(local), Some(remote)) if remote > local => Ok(true),
-            _ => Ok(false),
-        }
+
+    /// Parts of the game the update checks look at, by name
+    fn update_parts() -> Vec<(&'static str, Source)> {
+        vec![("Client", Self::CLIENT_SRC), ("Server", Self::SERVER_SRC)]
     }
-    async fn update_file(&self, src: &Source) -> Result<()> {
-        let local_version = self.read_local_version(src).await?;
-        let remote_version = self.fetch_remote_version(src).await?;
 
-        // Update the client version
-        if let (Some(local), Some(remote)) = (local_version, remote_version) {
-            if remote > local {
-                self.download_remote_file(src.zip, src.zip).await?;
-                self.download_remote_file(src.version, src.version).await?;
-                self.unzip_file(src.zip).await?;
+    /// Takes in what the background tasks reported since the last frame
+    fn handle_events(&mut self) {
+        while let Ok(event) = self.event_rx.try_recv() {
+            match event {
+                LauncherEvent::UpdatesChecked(found) => {
+                    let found = self.updates.finish(found);
+                    // Background checks leave whatever else the launcher is doing alone
+                    if matches!(
+                        self.state,
+                        LauncherState::Ready
+                            | LauncherState::CheckingForUpdates
+                            | LauncherState::DownloadNeeded
+                    ) {
+                        self.state = if found {
+                            LauncherState::DownloadNeeded
+                        } else {
+                            LauncherState::Ready
+                        };
+                        if found && self.settings.auto_download {
+                            self.download_updates();
+                        }
+                    }
+                }
+                LauncherEvent::LauncherUpdateChecked(Some(version)) => {
+                    self.self_update.found(version)
+                }
+                LauncherEvent::LauncherUpdateChecked(None) => {}
+                LauncherEvent::LauncherStaged(result) => {
+                    if !matches!(self.state, LauncherState::DownloadingUpdate) {
+                        self.download_progress = None;
+                    }
+                    let SelfUpdate::Downloading(version) = self.self_update else {
+                        continue;
+                    };
+                    self.self_update = match result {
+                        Ok(()) => SelfUpdate::Ready(version),
+                        Err(e) => {
+                            eprintln!("Launcher update failed: {e}");
+                            self.update_error = Some(format!("Launcher update failed: {e}"));
+                            // Offered again by the next check
+                            SelfUpdate::Idle
+                        }
+                    };
+                }
+                LauncherEvent::DownloadProgress(progress) => {
+                    self.download_progress = Some(progress)
+                }
+                LauncherEvent::UpdatesDownloaded(Ok(_)) => {
+                    self.download_progress = None;
+                    self.updates.found.clear();
+                    self.state = LauncherState::Ready;
+                }
+                LauncherEvent::UpdatesDownloaded(Err(e)) => {
+                    self.download_progress = None;
+                    eprintln!("Update failed: {e}");
+                    self.update_error = Some(format!("Update failed: {e}"));
+                    self.state = LauncherState::Failed;
+                }
+                LauncherEvent::ServerQueried(addr, info) => self.browser.answer(addr, info),
+                LauncherEvent::LanGamesFound(games) => {
+                    self.lan_games = games;
+                    self.lan_searching = false;
+                }
             }
         }
-        Ok(())
     }
-}
-/// File management
-impl LauncherApp {
-    async fn fetch_remote_version(&self, src: &Source) -> Result<Option<Version>> {
-        let url = format!("{}{}", VERSION_SERVERS[0], src.version);
-        let text = self.http.get(&url).send().await?.text().await?;
-        Ok(Version::try_from(text.trim()).ok())
-    }
-    async fn read_local_version(&self, src: &Source) -> Result<Option<Version>> {
-        let text = tokio::fs::read_to_string(src.version).await?;
-        Ok(Version::try_from(text.trim()).ok())
-    }
-    async fn download_remote_file(
-        &self,
-        relative_path: &str,
-        output_path: &str,
-    ) -> Result<PathBuf> {
-        let url = format!("{}{}", VERSION_SERVERS[0], relative_path);
-        let response = self.http.get(&url).send().await?;
-        if response.status().is_success() {
-            let bytes = response.bytes().await?;
-            let path = PathBuf::from(output_path);
-            tokio::fs::write(&path, bytes).await?;
-            Ok(path)
-        } else {
-            Err(anyhow::anyhow!("Failed to download file: {}", url))
-        }
+    /// Fetches builds from the mirror picked in the settings, or the one given on the command line,
+    /// failing over to the others
+    fn downloader(&self) -> Downloader {
+        let preferred = match &self.mirror_override {
+            Some(mirror) => mirror,
+            None => self.settings.mirror(),
+        };
+        Downloader::new(self.http.clone(), self.mirrors.clone(), preferred)
     }
-    async fn unzip_file(&self, zip_path: &str) -> Result<()> {
-        let output = Command::new("unzip")
-            .arg(zip_path)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output()
-            .await?;
-        if output.status.success() {
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!(
-                "Failed to unzip file: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ))
+
+    /// Downloads what the last check found on a task of its own
+    fn download_updates(&mut self) {
+        self.state = LauncherState::DownloadingUpdate;
+        self.update_error = None;
+        self.updates.dismiss_toast();
+        let (downloader, events) = (self.downloader(), self.events.clone());
+        tokio::spawn(async move {
+            let result = downloader.update(&Self::update_parts(), &events).await;
+            events.send(LauncherEvent::UpdatesDownloaded(
+                result.map_err(|e| format!("{e:#}")),
+            ));
+        });
+    }
+
+    /// Downloads the newer launcher next to the running one on a task of its own
+    fn stage_launcher(&mut self, version: Version) {
+        self.self_update = SelfUpdate::Downloading(version);
+        self.update_error = None;
+        let (downloader, events) = (self.downloader(), self.events.clone());
+        tokio::spawn(async move {
+            let result = downloader
+                .stage(
+                    &Self::LAUNCHER_SRC,
+                    Path::new(self_update::STAGING_DIR),
+                    &events,
+                )
+                .await;
+            events.send(LauncherEvent::LauncherStaged(
+                result.map_err(|e| format!("{e:#}")),
+            ));
+        });
+    }
+
+    /// Bar filling up as the current file downloads, or just counting bytes when the mirror did
+    /// not say how large the file is
+    fn progress_bar(&self) -> egui::ProgressBar {
+        const MB: f64 = 1024.0 * 1024.0;
+        let Some(progress) = &self.download_progress else {
+            return egui::ProgressBar::new(0.0).animate(true);
+        };
+        let downloaded = progress.downloaded as f64 / MB;
+        match (progress.fraction(), progress.total) {
+            (Some(fraction), Some(total)) => egui::ProgressBar::new(fraction).text(format!(
+                "{}: {downloaded:.1} / {:.1} MB",
+                progress.file,
+                total as f64 / MB
+            )),
+            _ => egui::ProgressBar::new(0.0)
+                .animate(true)
+                .text(format!("{}: {downloaded:.1} MB", progress.file)),
         }
     }
 }
@@ -179,35 +357,73 @@ impl LauncherApp {
         if addr.is_empty() {
             return Err(anyhow::anyhow!("Address cannot be empty"));
         }
-        if let Some(child) = Command::new(Self::CLIENT_SRC.binary)
-            .args([addr])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .ok()
-        {
+        self.spawn_client(addr)?;
+        self.remember_address(addr);
+        Ok(())
+    }
+
+    /// Starts the client on a server of its own, which it runs inside itself
+    fn launch_single_player(&mut self) -> Result<()> {
+        self.spawn_client("--single-player")
+    }
+
+    /// Starts the client with `target`, an address or how to play without one, and the selected
+    /// profile
+    fn spawn_client(&mut self, target: &str) -> Result<()> {
+        let profile_args = self.profiles.selected().client_args()?;
+        let mut command = Command::new(Self::CLIENT_SRC.binary);
+        command.arg(target).args(profile_args);
+        let log = logs::capture(&mut command, "client")?;
+        if let Ok(child) = command.spawn() {
             self.client_process = Some(child);
+            self.client_log = Some(log);
+            self.clear_crash("Client");
             Ok(())
         } else {
             Err(anyhow::anyhow!("Failed to launch client"))
         }
     }
 
-    fn launch_server(&mut self, addr: &str) -> Result<()> {
-        if addr.is_empty() {
-            return Err(anyhow::anyhow!("Address cannot be empty"));
+    /// Keeps the address of the server joined for the next time the launcher starts
+    fn remember_address(&mut self, addr: &str) {
+        if self.settings.last_address == addr {
+            return;
         }
-        if let Some(child) = Command::new(Self::SERVER_SRC.binary)
-            .args([addr])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .ok()
-        {
-            self.client_process = Some(child);
+        self.settings.last_address = addr.to_string();
+        self.save_settings();
+    }
+
+    /// Starts the server of the host session
+    fn launch_server(&mut self) -> Result<()> {
+        let Some(host) = &self.host else {
+            return Err(anyhow::anyhow!("Not hosting a server"));
+        };
+        let mut command = Command::new(Self::SERVER_SRC.binary);
+        command
+            .arg(&host.addr)
+            .arg("--world-file")
+            .arg(&host.world_file)
+            .arg("--discovery")
+            .args(["--autosave-interval", &HOST_AUTOSAVE_INTERVAL.to_string()]);
+        let log = logs::capture(&mut command, "server")?;
+        if let Ok(child) = command.spawn() {
+            self.server_process = Some(child);
+            self.server_log = Some(log);
+            self.clear_crash("Server");
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Failed to launch client"))
+            Err(anyhow::anyhow!("Failed to launch server"))
+        }
+    }
+
+    /// Forgets a crash of `process`, once it is launched again
+    fn clear_crash(&mut self, process: &str) {
+        if self
+            .crash
+            .as_ref()
+            .is_some_and(|crash| crash.process == process)
+        {
+            self.crash = None;
         }
     }
     fn process_terminate(&mut self) {
@@ -221,11 +437,492 @@ impl LauncherApp {
         self.server_process = None;
     }
 }
+/// Hosting a server and bringing it back when it stops
+impl LauncherApp {
+    /// Hosts a new session at `addr` and joins it
+    fn host(&mut self, addr: &str) -> Result<()> {
+        if addr.is_empty() {
+            return Err(anyhow::anyhow!("Address cannot be empty"));
+        }
+        let dir = profiles::config_dir();
+        std::fs::create_dir_all(&dir)?;
+        let world_file = dir.join("hosted_world.bin");
+        // Only restarts carry on from the saved world, a new session starts afresh
+        if world_file.exists() {
+            std::fs::remove_file(&world_file)?;
+        }
+        self.host = Some(HostSession {
+            addr: addr.to_string(),
+            world_file,
+        });
+        self.launch_server()?;
+        self.launch_client(addr)
+    }
+
+    /// Starts the stopped server again with the same settings and world, then reconnects the
+    /// client. The server puts players back where they were by username.
+    fn restart_host(&mut self) -> Result<()> {
+        if let Some(child) = &mut self.client_process {
+            let _ = child.start_kill();
+        }
+        self.client_process = None;
+        self.launch_server()?;
+        self.reconnect_at = Some(Instant::now() + RECONNECT_DELAY);
+        self.state = LauncherState::Ready;
+        Ok(())
+    }
+
+    /// Notices when the hosted server or the client exits, keeping how if it crashed, and
+    /// reconnects the client once a restarted server had time to start.
+    fn supervise(&mut self) -> Result<()> {
+        if let Some(child) = &mut self.server_process
+            && let Ok(Some(status)) = child.try_wait()
+        {
+            eprintln!("Server stopped unexpectedly: {status}");
+            self.server_process = None;
+            self.reconnect_at = None;
+            self.state = LauncherState::ServerStopped;
+            if let Some(log) = self.server_log.take() {
+                self.crash = Crash::from_exit("Server", status, log).or(self.crash.take());
+            }
+        }
+        if let Some(child) = &mut self.client_process
+            && let Ok(Some(status)) = child.try_wait()
+        {
+            self.client_process = None;
+            if let Some(log) = self.client_log.take() {
+                self.crash = Crash::from_exit("Client", status, log).or(self.crash.take());
+            }
+        }
+        if let Some(at) = self.reconnect_at
+            && Instant::now() >= at
+        {
+            self.reconnect_at = None;
+            if let Some(addr) = self.host.as_ref().map(|host| host.addr.clone()) {
+                self.launch_client(&addr)?;
+            }
+        }
+        Ok(())
+    }
+}
+/// Managing player profiles
+impl LauncherApp {
+    fn save_profiles(&mut self) {
+        if let Err(e) = self.profiles.save() {
+            self.state = LauncherState::Failed;
+            eprintln!("{e}");
+        }
+    }
+
+    fn profile_ui(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            let names: Vec<String> = self.profiles.names().map(String::from).collect();
+            let mut selected = self.profiles.selected_index();
+            egui::ComboBox::from_label("Profile")
+                .selected_text(&names[selected])
+                .show_ui(ui, |ui| {
+                    for (index, name) in names.iter().enumerate() {
+                        ui.selectable_value(&mut selected, index, name);
+                    }
+                });
+            if selected != self.profiles.selected_index() {
+                self.profiles.select(selected);
+                changed = true;
+            }
+            if ui.button("➕ New").clicked() {
+                self.profiles.add();
+                changed = true;
+            }
+            if ui
+                .add_enabled(names.len() > 1, egui::Button::new("🗑 Delete"))
+                .clicked()
+            {
+                self.profiles.remove_selected();
+                changed = true;
+            }
+        });
+
+        ui.collapsing("Edit Profile", |ui| {
+            let profile = self.profiles.selected_mut();
+            ui.horizontal(|ui| {
+                ui.label("Profile Name:");
+                changed |= ui.text_edit_singleline(&mut profile.name).changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Username:");
+                changed |= ui.text_edit_singleline(&mut profile.username).changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Color:");
+                let color = Color::from_hex(&profile.color).unwrap_or(Color::WHITE);
+                let mut rgb = [color.r, color.g, color.b].map(|c| (c * 255.0).round() as u8);
+                if ui.color_edit_button_srgb(&mut rgb).changed() {
+                    let [r, g, b] = rgb.map(|c| c as f32 / 255.0);
+                    profile.color = Color { r, g, b }.to_hex();
+                    changed = true;
+                }
+            });
+        });
+
+        if changed {
+            self.save_profiles();
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save() {
+            self.state = LauncherState::Failed;
+            eprintln!("{e}");
+        }
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Settings", |ui| {
+            let mut changed = ui
+                .checkbox(
+                    &mut self.settings.check_for_updates,
+                    "Check for updates in the background",
+                )
+                .changed();
+            ui.add_enabled_ui(self.settings.check_for_updates, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Every");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut self.settings.update_check_minutes)
+                                .range(1..=24 * 60),
+                        )
+                        .changed();
+                    ui.label("minutes");
+                });
+                changed |= ui
+                    .checkbox(
+                        &mut self.settings.auto_download,
+                        "Download updates without asking",
+                    )
+                    .changed();
+            });
+            if let Some(mirror) = &self.mirror_override {
+                ui.label(format!("Download mirror: {mirror} (from --mirror)"));
+            } else {
+                let mirror = self.settings.mirror();
+                egui::ComboBox::from_label("Download mirror")
+                    .selected_text(mirror)
+                    .show_ui(ui, |ui| {
+                        // Mirrors that failed lately are marked, they are tried last for now
+                        for (server, health) in self.mirrors.health() {
+                            let label = if health.is_failing() {
+                                format!("⚠ {server}")
+                            } else {
+                                server.clone()
+                            };
+                            let option = ui.selectable_label(server == mirror, label);
+                            let option = if health.failures > 0 {
+                                option.on_hover_text(format!(
+                                    "{} failed requests in a row",
+                                    health.failures
+                                ))
+                            } else {
+                                option
+                            };
+                            if option.clicked() {
+                                self.settings.mirror = server;
+                                changed = true;
+                            }
+                        }
+                    });
+            }
+            if changed {
+                self.updates.schedule(self.settings.update_check_interval());
+                self.save_settings();
+            }
+        });
+    }
+
+    /// Notice in the corner of the window about updates a check found, which goes away by itself
+    fn update_toast(&mut self, ctx: &Context) {
+        let Some(text) = self.updates.toast() else {
+            return;
+        };
+        egui::Area::new(egui::Id::new("update_toast"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("📦 {text}"));
+                        if ui.small_button("✖").clicked() {
+                            self.updates.dismiss_toast();
+                        }
+                    });
+                });
+            });
+    }
+
+    /// Asks whether to download a newer launcher, and once it is downloaded whether to restart
+    /// into it now or on the next start
+    /// Opens the log at `path` in a window
+    fn view_log(&mut self, path: PathBuf) {
+        let text = logs::tail(&path).unwrap_or_else(|e| format!("{e:#}"));
+        self.log_view = Some((path, text));
+    }
+
+    /// Window with the end of the log being viewed, if any
+    fn log_window(&mut self, ctx: &Context) {
+        let Some((path, text)) = &self.log_view else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new(path.display().to_string())
+            .open(&mut open)
+            .default_size([500.0, 300.0])
+            .show(ctx, |ui| {
+                egui::ScrollArea::both()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| ui.monospace(text));
+            });
+        if !open {
+            self.log_view = None;
+        }
+    }
+
+    fn self_update_dialog(&mut self, ctx: &Context) {
+        let (title, text) = match self.self_update {
+            SelfUpdate::Offered(version) => (
+                "Launcher Update",
+                format!("Version {version} of the launcher is available. Download it now?"),
+            ),
+            SelfUpdate::Downloading(version) => (
+                "Launcher Update",
+                format!("Downloading version {version} of the launcher..."),
+            ),
+            SelfUpdate::Ready(version) => (
+                "Launcher Update Ready",
+                format!(
+                    "Version {version} of the launcher is downloaded. Restart now to use it, \
+                     or it is used the next time the launcher starts."
+                ),
+            ),
+            SelfUpdate::Idle | SelfUpdate::Declined(_) | SelfUpdate::Waiting(_) => return,
+        };
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(text);
+                ui.add_space(5.0);
+                match self.self_update {
+                    SelfUpdate::Offered(version) => {
+                        ui.horizontal(|ui| {
+                            if ui.button("⬇ Update").clicked() {
+                                self.stage_launcher(version);
+                            }
+                            if ui.button("Later").clicked() {
+                                self.self_update = SelfUpdate::Declined(version);
+                            }
+                        });
+                    }
+                    SelfUpdate::Downloading(_) => {
+                        ui.add(self.progress_bar());
+                    }
+                    SelfUpdate::Ready(version) => {
+                        ui.horizontal(|ui| {
+                            if ui.button("🔄 Restart Now").clicked() {
+                                match self_update::apply_staged(&Self::LAUNCHER_SRC) {
+                                    Ok(true) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                                    Ok(false) => self.self_update = SelfUpdate::Idle,
+                                    Err(e) => {
+                                        eprintln!("Could not apply the launcher update: {e}");
+                                        self.self_update = SelfUpdate::Waiting(version);
+                                    }
+                                }
+                            }
+                            if ui.button("On Next Start").clicked() {
+                                self.self_update = SelfUpdate::Waiting(version);
+                            }
+                        });
+                    }
+                    _ => {}
+                }
+            });
+    }
+
+    /// Lists the favorite servers of the selected profile with their status and a Join button.
+    /// Servers are asked for their status when they first show up and on refresh.
+    fn browser_ui(&mut self, ui: &mut egui::Ui) {
+        let favorites = self.profiles.selected().favorite_servers.clone();
+        if favorites.is_empty() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Favorite Servers:");
+            if ui.small_button("🔄 Refresh").clicked() {
+                self.browser.refresh(&self.events, &favorites);
+            }
+        });
+        let unasked: Vec<String> = favorites
+            .iter()
+            .filter(|addr| self.browser.get(addr).is_none())
+            .cloned()
+            .collect();
+        self.browser.refresh(&self.events, &unasked);
+
+        let mut join = None;
+        let mut remove = None;
+        egui::Grid::new("favorite_servers")
+            .striped(true)
+            .show(ui, |ui| {
+                for (index, addr) in favorites.iter().enumerate() {
+                    let (name, players, latency, joinable) = match self.browser.get(addr) {
+                        Some(ServerInfo::Online(status, rtt)) => (
+                            status.name.clone(),
+                            format!("{}/{}", status.players, status.max_players),
+                            format!("{} ms", rtt.as_millis()),
+                            PROTOCOL_VERSION.is_compatible(&status.version),
+                        ),
+                        Some(ServerInfo::Offline(reason)) => {
+                            ui.label(addr).on_hover_text(reason);
+                            (String::new(), String::from("offline"), String::new(), false)
+                        }
+                        Some(ServerInfo::Pending) | None => {
+                            ui.label(addr);
+                            (String::new(), String::from("…"), String::new(), false)
+                        }
+                    };
+                    // Clicking a server that answered fills in its address
+                    if !name.is_empty()
+                        && ui
+                            .selectable_label(false, &name)
+                            .on_hover_text(addr)
+                            .clicked()
+                    {
+                        self.addr_input = addr.clone();
+                    }
+                    ui.label(players);
+                    ui.label(latency);
+                    if ui
+                        .add_enabled(joinable, egui::Button::new("🎮 Join"))
+                        .clicked()
+                    {
+                        join = Some(addr.clone());
+                    }
+                    if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                        remove = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some(addr) = join
+            && let Err(e) = self.launch_client(&addr)
+        {
+            self.state = LauncherState::Failed;
+            eprintln!("{e}");
+        }
+        if let Some(index) = remove {
+            self.profiles.selected_mut().favorite_servers.remove(index);
+            self.save_profiles();
+        }
+    }
+
+    /// Lists the games found on the local network, clicking one fills in its address
+    fn lan_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let searching = self.lan_searching;
+            if ui
+                .add_enabled(!searching, egui::Button::new("📡 Find LAN games"))
+                .clicked()
+            {
+                self.lan_searching = true;
+                let events = self.events.clone();
+                tokio::spawn(async move {
+                    let games = discovery::find_servers(LAN_SEARCH_TIME)
+                        .await
+                        .unwrap_or_else(|e| {
+                            eprintln!("LAN search failed: {e}");
+                            Vec::new()
+                        });
+                    events.send(LauncherEvent::LanGamesFound(games));
+                });
+            }
+            if searching {
+                ui.spinner();
+            }
+        });
+        for (addr, status) in &self.lan_games {
+            let lock = if status.password { " 🔒" } else { "" };
+            let text = format!(
+                "{}{lock}  {}/{}  {}  {addr}",
+                status.name, status.players, status.max_players, status.mode
+            );
+            let compatible = PROTOCOL_VERSION.is_compatible(&status.version);
+            let mut button = ui
+                .add_enabled(compatible, egui::Button::new(text))
+                .on_disabled_hover_text(format!("Runs protocol version {}", status.version));
+            // Only worth showing when the server hosts more than its main lobby
+            if status.lobbies.len() > 1 {
+                let lobbies: Vec<_> = status
+                    .lobbies
+                    .iter()
+                    .map(|lobby| match lobby.max_players {
+                        Some(max) => format!("{}  {}/{max}", lobby.name, lobby.players),
+                        None => format!("{}  {}", lobby.name, lobby.players),
+                    })
+                    .collect();
+                button = button.on_hover_text(lobbies.join("\n"));
+            }
+            if button.clicked() {
+                self.addr_input = addr.to_string();
+            }
+        }
+    }
+
+    /// Adds the address in the address field to the favorites of the selected profile
+    fn add_favorite(&mut self) {
+        let addr = self.addr_input.trim().to_string();
+        let favorites = &mut self.profiles.selected_mut().favorite_servers;
+        if !addr.is_empty() && !favorites.contains(&addr) {
+            favorites.push(addr);
+            self.save_profiles();
+        }
+    }
+}
 /// Rendering the UI
 impl eframe::App for LauncherApp {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         use egui::{Align, Button, Layout, RichText, Separator};
 
+        if let Err(e) = self.supervise() {
+            self.state = LauncherState::Failed;
+            eprintln!("{e}");
+        }
+        // Keep checking on the server and client even when nobody moves the mouse
+        if self.server_process.is_some()
+            || self.client_process.is_some()
+            || self.reconnect_at.is_some()
+        {
+            ctx.request_repaint_after(Duration::from_millis(500));
+        }
+        self.handle_events();
+        self.updates.tick(
+            self.settings.update_check_interval(),
+            ctx,
+            &self.events,
+            self.downloader(),
+            Self::update_parts,
+            Self::LAUNCHER_SRC,
+        );
+        // Saved when the launcher closes, see `on_exit`
+        if let Some(rect) = ctx.input(|input| input.viewport().inner_rect) {
+            self.settings.window_size = Some([rect.width(), rect.height()]);
+        }
+        self.update_toast(ctx);
+        self.self_update_dialog(ctx);
+        self.log_window(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(Layout::top_down(Align::Center), |ui| {
                 ui.add_space(10.0);
@@ -236,11 +933,24 @@ impl eframe::App for LauncherApp {
                 );
 
                 ui.add_space(15.0);
+                self.profile_ui(ui);
+                self.browser_ui(ui);
+                self.settings_ui(ui);
+
+                ui.add_space(10.0);
                 ui.horizontal(|ui| {
                     ui.label("Server Address:");
                     ui.text_edit_singleline(&mut self.addr_input)
                         .on_hover_text("127.0.0.1");
+                    if ui
+                        .button("⭐")
+                        .on_hover_text("Add to favorite servers")
+                        .clicked()
+                    {
+                        self.add_favorite();
+                    }
                 });
+                self.lan_ui(ui);
 
                 ui.add_space(10.0);
                 ui.add(Separator::default());
@@ -249,140 +959,73 @@ impl eframe::App for LauncherApp {
                 if ui
                     .add(Button::new("🎮 Join").min_size([150.0, 30.0].into()))
                     .clicked()
+                    && let Err(e) = self.launch_client(&self.addr_input.clone())
                 {
-                    if self.client_process.is_none() || true {
-                        if let Err(e) = self.launch_client(&self.addr_input.clone()) {
-                            self.state = LauncherState::Failed;
-                            eprintln!("{e}");
-                        }
-                    }
+                    self.state = LauncherState::Failed;
+                    eprintln!("{e}");
                 }
                 if ui
                     .add(Button::new("🖥 Host").min_size([150.0, 30.0].into()))
                     .clicked()
+                    && self.server_process.is_none()
                 {
-                    if self.server_process.is_none() {
-                        let ip = &format!("{}:8000", local_ip().unwrap().to_string());
-                        if let Err(e) = self.launch_server(ip) {
-                            self.state = LauncherState::Failed;
-                            eprintln!("{e}");
-                        }
-                        if let Err(e) = self.launch_client(ip) {
-                            self.state = LauncherState::Failed;
-                            eprintln!("{e}");
-                        }
+                    let ip = &format!("{}:8000", local_ip().unwrap());
+                    if let Err(e) = self.host(ip) {
+                        self.state = LauncherState::Failed;
+                        eprintln!("{e}");
                     }
                 }
                 if ui
                     .add(Button::new("👤 Single Player").min_size([150.0, 30.0].into()))
                     .clicked()
+                    && let Err(e) = self.launch_single_player()
                 {
-                    if self.server_process.is_none() {
-                        if let Err(e) = self.launch_server("127.0.0.1:8000") {
-                            self.state = LauncherState::Failed;
-                            eprintln!("{e}");
-                        }
-                        if let Err(e) = self.launch_client("127.0.0.1:8000") {
-                            self.state = LauncherState::Failed;
-                            eprintln!("{e}");
-                        }
-                    }
+                    self.state = LauncherState::Failed;
+                    eprintln!("{e}");
                 }
 
                 ui.add_space(20.0);
                 ui.add(Separator::default());
                 ui.add_space(10.0);
 
-                // Check for Updates
-                if ui
-                    .add(Button::new("🔍 Check for Updates").min_size([180.0, 30.0].into()))
-                    .clicked()
-                {
+                // Check for Updates, with a badge counting what the last check found
+                let downloading = matches!(self.state, LauncherState::DownloadingUpdate);
+                let check = ui.add_enabled(
+                    !self.updates.checking() && !downloading,
+                    Button::new("🔍 Check for Updates").min_size([180.0, 30.0].into()),
+                );
+                if !self.updates.found.is_empty() {
+                    let center = check.rect.right_top();
+                    ui.painter()
+                        .circle_filled(center, 8.0, egui::Color32::from_rgb(220, 50, 50));
+                    ui.painter().text(
+                        center,
+                        egui::Align2::CENTER_CENTER,
+                        self.updates.found.len().to_string(),
+                        egui::FontId::proportional(11.0),
+                        egui::Color32::WHITE,
+                    );
+                }
+                if check.clicked() {
                     self.state = LauncherState::CheckingForUpdates;
-                    let ctx_clone = ctx.clone();
-                    // Clone only the fields needed for the async call
-                    let http = self.http.clone();
-                    let client_src = Self::CLIENT_SRC;
-                    let server_src = Self::SERVER_SRC;
-
-                    tokio::spawn(async move {
-                        // Perform the update check asynchronously
-                        let mut updates = Vec::new();
-                        // Check client
-                        if let Ok(local_version) =
-                            tokio::fs::read_to_string(client_src.version).await
-                        {
-                            let url = format!("{}{}", VERSION_SERVERS[0], client_src.version);
-                            if let Ok(response) = http.get(&url).send().await {
-                                if let Ok(text) = response.text().await {
-                                    if let (Ok(local), Ok(remote)) = (
-                                        Version::try_from(local_version.trim()),
-                                        Version::try_from(text.trim()),
-                                    ) {
-                                        if remote > local {
-                                            updates.push(String::from("Client needs an update"));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        // Check server
-                        if let Ok(local_version) =
-                            tokio::fs::read_to_string(server_src.version).await
-                        {
-                            let url = format!("{}{}", VERSION_SERVERS[0], server_src.version);
-                            if let Ok(response) = http.get(&url).send().await {
-                                if let Ok(text) = response.text().await {
-                                    if let (Ok(local), Ok(remote)) = (
-                                        Version::try_from(local_version.trim()),
-                                        Version::try_from(text.trim()),
-                                    ) {
-                                        if remote > local {
-                                            updates.push(String::from("Server needs an update"));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        // Print updates found
-                        if !updates.is_empty() {
-                            println!("Updates found: {:?}", updates);
-                        }
-                        ctx_clone.request_repaint();
-                    });
-                    self.update_available = true; // Set flag so Download button appears
-                    self.state = LauncherState::Ready;
+                    self.updates.start(
+                        &self.events,
+                        self.downloader(),
+                        Self::update_parts(),
+                        Self::LAUNCHER_SRC,
+                    );
                 }
 
                 // If update found, show Download button
-                if self.update_available {
-                    if ui
-                        .add(Button::new("⬇ Download Updates").min_size([180.0, 30.0].into()))
+                if !self.updates.found.is_empty()
+                    && ui
+                        .add_enabled(
+                            !downloading,
+                            Button::new("⬇ Download Updates").min_size([180.0, 30.0].into()),
+                        )
                         .clicked()
-                    {
-                        self.state = LauncherState::DownloadingUpdate;
-                        let ctx_clone = ctx.clone();
-                        // Clone only the fields needed for the async call
-                        let mut app_clone = LauncherApp {
-                            state: self.state.clone(),
-                            server_process: None,
-                            client_process: None,
-                            http: self.http.clone(),
-                            addr_input: self.addr_input.clone(),
-                            update_available: self.update_available,
-                        };
-                        // Spawn the update task
-                        tokio::spawn(async move {
-                            if let Err(e) = app_clone.update().await {
-                                eprintln!("Update failed: {e}");
-                                app_clone.state = LauncherState::Failed;
-                            } else {
-                                app_clone.update_available = false;
-                                app_clone.state = LauncherState::Ready;
-                            }
-                            ctx_clone.request_repaint();
-                        });
-                    }
+                {
+                    self.download_updates();
                 }
 
                 ui.add_space(15.0);
@@ -394,11 +1037,42 @@ impl eframe::App for LauncherApp {
                     LauncherState::DownloadNeeded => "📦 Update Available",
                     LauncherState::DownloadingUpdate => "⬇ Downloading Update...",
                     LauncherState::CheckingForUpdates => "🔍 Checking for Updates...",
+                    LauncherState::ServerStopped => "⚠ Server Stopped Unexpectedly",
                 };
                 ui.label(RichText::new(status_text).strong());
+                if let Some(error) = &self.update_error {
+                    ui.colored_label(egui::Color32::from_rgb(220, 50, 50), error);
+                }
+                if let Some(crash) = &self.crash {
+                    let log = crash.log.clone();
+                    ui.colored_label(egui::Color32::from_rgb(220, 50, 50), crash.message());
+                    if ui.button("📄 View Log").clicked() {
+                        self.view_log(log);
+                    }
+                }
+
+                if downloading {
+                    ui.add(self.progress_bar());
+                }
+
+                if matches!(self.state, LauncherState::ServerStopped)
+                    && ui
+                        .add(Button::new("🔄 Restart Server").min_size([180.0, 30.0].into()))
+                        .clicked()
+                    && let Err(e) = self.restart_host()
+                {
+                    self.state = LauncherState::Failed;
+                    eprintln!("{e}");
+                }
             });
         });
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Err(e) = self.settings.save() {
+            eprintln!("{e}");
+        }
+    }
 }
 impl Drop for LauncherApp {
     fn drop(&mut self) {
@@ -408,12 +1082,30 @@ impl Drop for LauncherApp {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let options = eframe::NativeOptions::default();
-    let launcher = LauncherApp::new().await?;
+    let cli = Cli::parse();
+    // A launcher downloaded last time takes over before anything else happens
+    match self_update::apply_staged(&LauncherApp::LAUNCHER_SRC) {
+        Ok(true) => return Ok(()),
+        Ok(false) => {}
+        Err(e) => eprintln!("Could not apply the launcher update: {e}"),
+    }
+    let settings = Settings::load()?;
+    let profiles = Profiles::load()?;
+    let mut options = eframe::NativeOptions::default();
+    if let Some(size) = settings.window_size {
+        options.viewport = options.viewport.with_inner_size(size);
+    }
     if let Err(e) = eframe::run_native(
         &format!("{} Launcher", details::GAME_NAME),
         options,
-        Box::new(|_cc| Ok(Box::new(launcher))),
+        Box::new(|cc| {
+            Ok(Box::new(LauncherApp::new(
+                &cc.egui_ctx,
+                cli,
+                settings,
+                profiles,
+            )))
+        }),
     ) {
         eprintln!("Failed to launch GUI: {e}");
     }
@@ -0,0 +1,93 @@
+//! Health of the download mirrors, so a mirror that is down does not hold up every download.
+//!
+//! Every request goes to the mirror picked in the settings first and on to the next one when it
+//! times out or answers with an error. [`Mirrors`] is shared by every [`Downloader`] and remembers
+//! how each mirror fared, so mirrors that keep failing are tried after the ones that work until
+//! they answer again. `--mirror` replaces the known mirrors with one of its own.
+//!
+//! [`Downloader`]: crate::download::Downloader
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Time a mirror that failed is tried after the others, before it gets its turn again
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+/// How requests to one mirror went lately
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MirrorHealth {
+    /// Requests in a row that failed, reset by one that works
+    pub failures: u32,
+    pub last_failure: Option<Instant>,
+}
+impl MirrorHealth {
+    /// Whether the mirror failed recently enough to be tried last
+    pub fn is_failing(&self) -> bool {
+        self.failures > 0
+            && self
+                .last_failure
+                .is_some_and(|at| at.elapsed() < FAILURE_COOLDOWN)
+    }
+}
+
+struct Mirror {
+    url: String,
+    health: MirrorHealth,
+}
+
+/// Mirrors builds can be downloaded from, with how each has been doing
+#[derive(Clone)]
+pub struct Mirrors {
+    mirrors: Arc<Mutex<Vec<Mirror>>>,
+}
+impl Mirrors {
+    pub fn new(urls: impl IntoIterator<Item = String>) -> Self {
+        let mirrors = urls
+            .into_iter()
+            .map(|url| Mirror {
+                url,
+                health: MirrorHealth::default(),
+            })
+            .collect();
+        Self {
+            mirrors: Arc::new(Mutex::new(mirrors)),
+        }
+    }
+
+    /// Base URLs in the order to try them: `preferred` first and mirrors failing lately last,
+    /// keeping the known order otherwise
+    pub fn order(&self, preferred: &str) -> Vec<String> {
+        let mirrors = self.mirrors.lock().unwrap();
+        let mut order: Vec<_> = mirrors.iter().collect();
+        order.sort_by_key(|mirror| (mirror.health.is_failing(), mirror.url != preferred));
+        order.into_iter().map(|mirror| mirror.url.clone()).collect()
+    }
+
+    /// Every mirror with how it has been doing, in the known order
+    pub fn health(&self) -> Vec<(String, MirrorHealth)> {
+        let mirrors = self.mirrors.lock().unwrap();
+        mirrors
+            .iter()
+            .map(|mirror| (mirror.url.clone(), mirror.health))
+            .collect()
+    }
+
+    pub fn succeeded(&self, url: &str) {
+        self.update(url, |health| *health = MirrorHealth::default());
+    }
+
+    pub fn failed(&self, url: &str) {
+        self.update(url, |health| {
+            health.failures += 1;
+            health.last_failure = Some(Instant::now());
+        });
+    }
+
+    fn update(&self, url: &str, f: impl FnOnce(&mut MirrorHealth)) {
+        let mut mirrors = self.mirrors.lock().unwrap();
+        if let Some(mirror) = mirrors.iter_mut().find(|mirror| mirror.url == url) {
+            f(&mut mirror.health);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Player profiles kept by the launcher, each with the name to play under, a preferred color
+//! and a list of favorite servers. They are stored as TOML in the launcher config directory:
+//!
+//! ```toml
+//! selected = "Main"
+//!
+//! [[profiles]]
+//! name = "Main"
+//! username = "Newbie"
+//! color = "#f2a255"
+//! favorite_servers = ["127.0.0.1:8000"]
+//! ```
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use common::{color::Color, details};
+use serde::{Deserialize, Serialize};
+
+const FILE_NAME: &str = "profiles.toml";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Profile {
+    /// Name the profile is listed under in the launcher
+    pub name: String,
+    /// Name to play under
+    pub username: String,
+    /// Preferred player color as `#rrggbb`
+    pub color: String,
+    #[serde(default)]
+    pub favorite_servers: Vec<String>,
+}
+impl Profile {
+    pub fn new(name: String, color: Color) -> Self {
+        Self {
+            name,
+            username: String::from(details::DEFAULT_USERNAME),
+            color: color.to_hex(),
+            favorite_servers: Vec::new(),
+        }
+    }
+
+    /// Arguments passing the profile on to the client
+    pub fn client_args(&self) -> Result<Vec<String>> {
+        let color = Color::from_hex(&self.color)
+            .with_context(|| format!("Profile `{}` has no valid color", self.name))?;
+        let username = match self.username.trim() {
+            "" => details::DEFAULT_USERNAME,
+            username => username,
+        };
+        Ok(vec![
+            String::from("--username"),
+            String::from(username),
+            String::from("--color"),
+            color.to_hex(),
+        ])
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ProfilesFile {
+    selected: String,
+    profiles: Vec<Profile>,
+}
+
+/// Every profile and which of them is selected, along with the file they are saved to
+#[derive(Clone)]
+pub struct Profiles {
+    profiles: Vec<Profile>,
+    selected: usize,
+    path: PathBuf,
+}
+impl Profiles {
+    /// Reads the profiles from the launcher config directory, starting with a single default
+    /// profile when there is no file yet.
+    pub fn load() -> Result<Self> {
+        Self::load_from(config_dir().join(FILE_NAME))
+    }
+
+    fn load_from(path: PathBuf) -> Result<Self> {
+        let file = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .with_context(|| format!("Could not read profiles from {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ProfilesFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut profiles = file.profiles;
+        if profiles.is_empty() {
+            profiles.push(Profile::new(String::from("Default"), Color::for_player(1)));
+        }
+        let selected = profiles
+            .iter()
+            .position(|profile| profile.name == file.selected)
+            .unwrap_or(0);
+        Ok(Self {
+            profiles,
+            selected,
+            path,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = ProfilesFile {
+            selected: self.selected().name.clone(),
+            profiles: self.profiles.clone(),
+        };
+        std::fs::write(&self.path, toml::to_string_pretty(&file)?)
+            .with_context(|| format!("Could not save profiles to {}", self.path.display()))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|profile| profile.name.as_str())
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> &Profile {
+        &self.profiles[self.selected]
+    }
+
+    pub fn selected_mut(&mut self) -> &mut Profile {
+        &mut self.profiles[self.selected]
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.profiles.len() {
+            self.selected = index;
+        }
+    }
+
+    /// Adds a profile with an unused name and selects it
+    pub fn add(&mut self) {
+        let name = (self.profiles.len() + 1..)
+            .map(|n| format!("Profile {n}"))
+            .find(|name| self.names().all(|existing| existing != name))
+            .unwrap_or_default();
+        let color = Color::for_player(self.profiles.len() as u64 + 1);
+        self.profiles.push(Profile::new(name, color));
+        self.selected = self.profiles.len() - 1;
+    }
+
+    /// Removes the selected profile, unless it is the last one
+    pub fn remove_selected(&mut self) {
+        if self.profiles.len() > 1 {
+            self.profiles.remove(self.selected);
+            self.selected = self.selected.min(self.profiles.len() - 1);
+        }
+    }
+}
+
+/// Directory the launcher keeps its settings in, following the conventions of the platform
+pub fn config_dir() -> PathBuf {
+    let app = details::GAME_NAME.to_lowercase().replace(' ', "_");
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join("Library")
+                .join("Application Support")
+        })
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.unwrap_or_default().join(app).join("launcher")
+}
@@ -0,0 +1,107 @@
+//! Updating the launcher itself.
+//!
+//! A running program cannot safely overwrite its own binary, and Windows refuses to. So a newer
+//! launcher is downloaded and unpacked into [`STAGING_DIR`] first, and swapped in the next time
+//! the launcher starts: [`apply_staged`] runs before anything else, moves the running binary
+//! aside, puts the staged one in its place and starts it. Players are asked before anything is
+//! downloaded, and once it is ready whether to restart right away or let it wait for the next
+//! start.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+use common::version::Version;
+
+use crate::Source;
+
+/// Where a downloaded launcher waits to be swapped in, laid out like the install it goes into
+pub const STAGING_DIR: &str = "build/launcher/staged";
+
+/// Where a launcher update stands, shown as a dialog while it needs an answer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SelfUpdate {
+    #[default]
+    Idle,
+    /// A newer version is online, asking whether to download it
+    Offered(Version),
+    /// Not wanted, this version is not offered again until the launcher restarts
+    Declined(Version),
+    Downloading(Version),
+    /// Staged, asking whether to restart into it now
+    Ready(Version),
+    /// Staged, swapped in on the next start
+    Waiting(Version),
+}
+impl SelfUpdate {
+    /// Takes the version a check found online, offering it unless it was already dealt with
+    pub fn found(&mut self, version: Version) {
+        let known = match self {
+            SelfUpdate::Idle => None,
+            SelfUpdate::Offered(known)
+            | SelfUpdate::Declined(known)
+            | SelfUpdate::Downloading(known)
+            | SelfUpdate::Ready(known)
+            | SelfUpdate::Waiting(known) => Some(*known),
+        };
+        if known.is_none_or(|known| version > known) {
+            *self = SelfUpdate::Offered(version);
+        }
+    }
+}
+
+/// Staged binary of the launcher described by `src`
+fn staged_binary(src: &Source) -> PathBuf {
+    Path::new(STAGING_DIR).join(src.binary)
+}
+
+/// Swaps a staged launcher in for the running one and starts it with the same arguments. Returns
+/// whether it did, in which case the running launcher should exit right away.
+pub fn apply_staged(src: &Source) -> Result<bool> {
+    let current = std::env::current_exe().context("Could not find the running launcher")?;
+    let aside = current.with_extension("old");
+    // Left over from the last swap, when it could not be removed while it ran
+    let _ = std::fs::remove_file(&aside);
+
+    let staged = staged_binary(src);
+    if !staged.is_file() {
+        return Ok(false);
+    }
+    std::fs::rename(&current, &aside)
+        .with_context(|| format!("Could not move {} aside", current.display()))?;
+    if let Err(e) = install(&staged, &current) {
+        // Put the old launcher back rather than leave none at all
+        let _ = std::fs::rename(&aside, &current);
+        return Err(e);
+    }
+    let staged_version = Path::new(STAGING_DIR).join(src.version);
+    if staged_version.is_file() {
+        std::fs::copy(&staged_version, src.version)
+            .with_context(|| format!("Could not update {}", src.version))?;
+    }
+    std::fs::remove_dir_all(STAGING_DIR)
+        .with_context(|| format!("Could not clear {STAGING_DIR}"))?;
+
+    restart()?;
+    Ok(true)
+}
+
+/// Moves the staged binary to `target`, copying it when they are on different file systems
+fn install(staged: &Path, target: &Path) -> Result<()> {
+    if std::fs::rename(staged, target).is_err() {
+        std::fs::copy(staged, target)
+            .with_context(|| format!("Could not install {}", target.display()))?;
+    }
+    Ok(())
+}
+
+/// Starts the launcher binary again with the arguments this one got, for the caller to exit after
+fn restart() -> Result<()> {
+    let current = std::env::current_exe().context("Could not find the running launcher")?;
+    Command::new(&current)
+        .args(std::env::args_os().skip(1))
+        .spawn()
+        .with_context(|| format!("Could not start {}", current.display()))?;
+    Ok(())
+}
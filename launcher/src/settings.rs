@@ -0,0 +1,88 @@
+//! Launcher settings, stored as TOML in the launcher config directory next to the profiles:
+//!
+//! ```toml
+//! check_for_updates = true
+//! update_check_minutes = 60
+//! auto_download = false
+//! mirror = "https://raw.githubusercontent.com/Larmbs/multiplayer_game/refs/heads/master/"
+//! last_address = "127.0.0.1:8000"
+//! window_size = [420.0, 640.0]
+//! ```
+//!
+//! What the launcher was last left with, the address typed in and the size of the window, is kept
+//! here too so it comes back the same way. Usernames belong to [`profiles`](crate::profiles).
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{VERSION_SERVERS, profiles::config_dir};
+
+const FILE_NAME: &str = "settings.toml";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Whether updates are looked for at startup and then every `update_check_minutes`
+    pub check_for_updates: bool,
+    pub update_check_minutes: u64,
+    /// Whether updates a background check finds are downloaded without asking
+    pub auto_download: bool,
+    /// Server builds are downloaded from, one of the known mirrors
+    pub mirror: String,
+    /// Server address the launcher last joined or hosted
+    pub last_address: String,
+    /// Inner size of the window when the launcher was last closed
+    pub window_size: Option<[f32; 2]>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            check_for_updates: true,
+            update_check_minutes: 60,
+            auto_download: false,
+            mirror: String::from(VERSION_SERVERS[0]),
+            last_address: String::new(),
+            window_size: None,
+            path: PathBuf::new(),
+        }
+    }
+}
+impl Settings {
+    /// Reads the settings from the launcher config directory, the defaults when there is no
+    /// file yet.
+    pub fn load() -> Result<Self> {
+        let path = config_dir().join(FILE_NAME);
+        let settings = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .with_context(|| format!("Could not read settings from {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, ..settings })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Could not save settings to {}", self.path.display()))
+    }
+
+    /// Time between background update checks, `None` when they are turned off
+    pub fn update_check_interval(&self) -> Option<Duration> {
+        self.check_for_updates
+            .then(|| Duration::from_secs(self.update_check_minutes.max(1) * 60))
+    }
+
+    /// Base URL to download builds from, the first mirror when the one saved is no longer known
+    pub fn mirror(&self) -> &'static str {
+        VERSION_SERVERS
+            .iter()
+            .find(|mirror| **mirror == self.mirror)
+            .unwrap_or(&VERSION_SERVERS[0])
+    }
+}
@@ -0,0 +1,113 @@
+//! Looking for newer client, server and launcher builds, when asked to and every so often in the
+//! background.
+//!
+//! Checks run as tasks of their own so the launcher stays responsive, and report back with a
+//! [`LauncherEvent::UpdatesChecked`] handed to [`UpdateChecker::finish`]. A newer launcher is
+//! reported on its own, as [`LauncherEvent::LauncherUpdateChecked`], since it is offered with a
+//! dialog rather than downloaded with the rest, see [`crate::self_update`].
+use std::time::{Duration, Instant};
+
+use eframe::egui::Context;
+
+use crate::{
+    Source,
+    download::Downloader,
+    events::{EventSender, LauncherEvent},
+};
+
+/// Time the notice about found updates stays up
+const TOAST_TIME: Duration = Duration::from_secs(8);
+
+pub struct UpdateChecker {
+    /// Whether a check is on its way
+    checking: bool,
+    /// When the next background check is due, never while they are turned off
+    next_check: Option<Instant>,
+    /// Parts with a newer version online, as of the last check
+    pub found: Vec<String>,
+    /// When the notice about found updates goes away
+    toast_until: Option<Instant>,
+}
+impl UpdateChecker {
+    /// Checker whose first background check is due right away, unless `interval` turns them off
+    pub fn new(interval: Option<Duration>) -> Self {
+        Self {
+            checking: false,
+            next_check: interval.map(|_| Instant::now()),
+            found: Vec::new(),
+            toast_until: None,
+        }
+    }
+
+    /// Puts the next background check `interval` from now, for when the settings change
+    pub fn schedule(&mut self, interval: Option<Duration>) {
+        self.next_check = interval.map(|interval| Instant::now() + interval);
+    }
+
+    /// Looks for updates of `parts` and of the `launcher` unless a check is already running
+    pub fn start(
+        &mut self,
+        events: &EventSender,
+        downloader: Downloader,
+        parts: Vec<(&'static str, Source)>,
+        launcher: Source,
+    ) {
+        if self.checking {
+            return;
+        }
+        self.checking = true;
+        let events = events.clone();
+        tokio::spawn(async move {
+            let found = downloader.newer_parts(&parts).await;
+            events.send(LauncherEvent::UpdatesChecked(found));
+            // A launcher installed without a version file is left alone, like the other parts
+            let launcher = downloader.newer_version(&launcher).await.ok().flatten();
+            events.send(LauncherEvent::LauncherUpdateChecked(launcher));
+        });
+    }
+
+    /// Starts a background check when one is due, and has the UI woken up for the next one
+    pub fn tick(
+        &mut self,
+        interval: Option<Duration>,
+        ctx: &Context,
+        events: &EventSender,
+        downloader: Downloader,
+        parts: impl FnOnce() -> Vec<(&'static str, Source)>,
+        launcher: Source,
+    ) {
+        let now = Instant::now();
+        if self.next_check.is_some_and(|due| now >= due) {
+            self.start(events, downloader, parts(), launcher);
+            self.schedule(interval);
+        }
+        for wake in [self.next_check, self.toast_until].into_iter().flatten() {
+            ctx.request_repaint_after(wake.saturating_duration_since(now));
+        }
+    }
+
+    /// Takes the result of a finished check, returning whether it found updates
+    pub fn finish(&mut self, found: Vec<String>) -> bool {
+        self.checking = false;
+        if !found.is_empty() && found != self.found {
+            self.toast_until = Some(Instant::now() + TOAST_TIME);
+        }
+        self.found = found;
+        !self.found.is_empty()
+    }
+
+    /// Whether a check is on its way
+    pub fn checking(&self) -> bool {
+        self.checking
+    }
+
+    /// Text of the notice about found updates, while it is up
+    pub fn toast(&self) -> Option<String> {
+        let until = self.toast_until?;
+        (Instant::now() < until).then(|| format!("Updates available: {}", self.found.join(", ")))
+    }
+
+    pub fn dismiss_toast(&mut self) {
+        self.toast_until = None;
+    }
+}
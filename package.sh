@@ -26,12 +26,16 @@ cargo build --bin "$SERVER_DIR"
 echo "Building launcher..."
 cargo build --bin "$LAUNCHER_DIR"
 
+# Every archive gets its `sha256sum` in sha256.txt next to it, the launcher checks it before
+# installing anything
+
 # Copy files and create zip for client
 mkdir -p "$OUTPUT_DIR/client/"
 cp "$CLIENT_BUILD" "$OUTPUT_DIR/client/"
 cp "$CLIENT_DIR/version.txt" "$OUTPUT_DIR/client/"
 cd "$OUTPUT_DIR/client"
 zip -r ./client.zip ./client*
+sha256sum client.zip > sha256.txt
 cd - > /dev/null
 
 # Copy files and create zip for server
@@ -40,6 +44,7 @@ cp "$SERVER_BUILD" "$OUTPUT_DIR/server/"
 cp "$SERVER_DIR/version.txt" "$OUTPUT_DIR/server/"
 cd "$OUTPUT_DIR/server"
 zip -r ./server.zip ./server*
+sha256sum server.zip > sha256.txt
 cd - > /dev/null
 
 mkdir -p "$OUTPUT_DIR/launcher/"
@@ -47,6 +52,7 @@ cp "$LAUNCHER_BUILD" "$OUTPUT_DIR/launcher/"
 cp "$LAUNCHER_DIR/version.txt" "$OUTPUT_DIR/launcher/"
 cd "$OUTPUT_DIR/launcher"
 zip -r ./launcher.zip ./launcher*
+sha256sum launcher.zip > sha256.txt
 cd - > /dev/null
 
 echo "✅ Build and packaging complete. Output in $OUTPUT_DIR"
@@ -0,0 +1,157 @@
+//! Usernames and addresses the server refuses, kept across restarts in the `--ban-list` file or
+//! the database, see [`crate::storage`].
+//!
+//! The file lists both kinds, and is written again whenever an operator bans or unbans someone:
+//!
+//! ```toml
+//! usernames = ["griefer"]
+//! addresses = ["203.0.113.7"]
+//! ```
+//!
+//! Connections from a banned address are dropped as soon as they are accepted, while a banned
+//! username is told so when it asks to join.
+use std::{collections::BTreeSet, fmt, net::IpAddr, sync::Arc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+
+/// Someone to refuse
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ban {
+    Username(String),
+    Address(IpAddr),
+}
+impl Ban {
+    /// An address if `text` reads as one, a username otherwise
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        match text.parse() {
+            Ok(addr) => Ban::Address(addr),
+            Err(_) => Ban::Username(text.to_string()),
+        }
+    }
+}
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ban::Username(username) => write!(f, "username {username}"),
+            Ban::Address(addr) => write!(f, "address {addr}"),
+        }
+    }
+}
+
+/// Every ban, as written to the ban list file
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bans {
+    pub usernames: BTreeSet<String>,
+    pub addresses: BTreeSet<IpAddr>,
+}
+
+pub struct BanList {
+    bans: Bans,
+    /// Where the bans are kept, they only last until shutdown if it keeps none
+    storage: Arc<dyn Storage>,
+}
+impl BanList {
+    /// Reads the bans of earlier runs, starting with none if there are none yet.
+    pub fn load(storage: Arc<dyn Storage>) -> Result<Self> {
+        Ok(Self {
+            bans: storage.load_bans()?,
+            storage,
+        })
+    }
+
+    pub fn refuses_address(&self, addr: IpAddr) -> bool {
+        self.bans.addresses.contains(&addr)
+    }
+
+    pub fn refuses_username(&self, username: &str) -> bool {
+        self.bans.usernames.contains(username.trim())
+    }
+
+    /// Every ban, usernames first
+    pub fn entries(&self) -> impl Iterator<Item = Ban> + '_ {
+        let usernames = self.bans.usernames.iter().cloned().map(Ban::Username);
+        usernames.chain(self.bans.addresses.iter().copied().map(Ban::Address))
+    }
+
+    /// Adds a ban and stores the bans. Returns whether it was new.
+    pub fn ban(&mut self, ban: Ban) -> Result<bool> {
+        let added = match ban {
+            Ban::Username(username) => self.bans.usernames.insert(username),
+            Ban::Address(addr) => self.bans.addresses.insert(addr),
+        };
+        if added {
+            self.storage.save_bans(&self.bans)?;
+        }
+        Ok(added)
+    }
+
+    /// Lifts a ban and stores the bans. Returns whether there was one.
+    pub fn unban(&mut self, ban: &Ban) -> Result<bool> {
+        let removed = match ban {
+            Ban::Username(username) => self.bans.usernames.remove(username),
+            Ban::Address(addr) => self.bans.addresses.remove(addr),
+        };
+        if removed {
+            self.storage.save_bans(&self.bans)?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::Parser;
+    use test_utils::files::Scratch;
+
+    use super::*;
+    use crate::{cli::ServerConfig, storage::FileStorage};
+
+    fn storage(ban_list: &Path) -> Arc<dyn Storage> {
+        let config =
+            ServerConfig::parse_from(["server", "--ban-list", &ban_list.to_string_lossy()]);
+        Arc::new(FileStorage::from_config(&config))
+    }
+
+    #[test]
+    fn bans_read_as_addresses_or_usernames() {
+        assert_eq!(
+            Ban::parse(" 203.0.113.7 "),
+            Ban::Address("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(Ban::parse("::1"), Ban::Address("::1".parse().unwrap()));
+        assert_eq!(
+            Ban::parse("griefer"),
+            Ban::Username(String::from("griefer"))
+        );
+    }
+
+    #[test]
+    fn bans_last_across_restarts() {
+        let scratch = Scratch::new("bans");
+        let path = scratch.join("bans.toml");
+        let addr: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let mut bans = BanList::load(storage(&path)).unwrap();
+        assert!(bans.ban(Ban::Username(String::from("griefer"))).unwrap());
+        assert!(bans.ban(Ban::Address(addr)).unwrap());
+        assert!(!bans.ban(Ban::Address(addr)).unwrap());
+
+        let mut bans = BanList::load(storage(&path)).unwrap();
+        assert!(bans.refuses_username(" griefer "));
+        assert!(bans.refuses_address(addr));
+        assert!(!bans.refuses_username("someone"));
+        assert_eq!(bans.entries().count(), 2);
+
+        assert!(bans.unban(&Ban::Username(String::from("griefer"))).unwrap());
+        assert!(!bans.unban(&Ban::Username(String::from("griefer"))).unwrap());
+        let bans = BanList::load(storage(&path)).unwrap();
+        assert_eq!(bans.entries().collect::<Vec<_>>(), [Ban::Address(addr)]);
+    }
+}
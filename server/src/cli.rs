@@ -1,7 +1,12 @@
 //! This file is part of the multiplayer game project.
 //! It defines the command-line interface (CLI) for the game server, allowing users to specify
 //! the server address, configuration options, and other parameters when starting the server.
+use std::{net::SocketAddr, path::PathBuf};
+
 use clap::Parser;
+use common::message::Transport;
+
+use crate::mode::GameMode;
 
 #[derive(Debug, Parser)]
 #[command(name = "Server")]
@@ -17,9 +22,183 @@ pub struct ServerConfig {
     #[arg(long, default_value = "New Server")]
     pub server_name: String,
 
+    /// Message of the day, shown to players as they join after the welcome
+    #[arg(long)]
+    pub motd: Option<String>,
+
     #[arg(long)]
     pub password: Option<String>,
 
     #[arg(long, default_value_t = 10)]
     pub max_clients: usize,
+
+    /// Slots out of `max_clients` that only players on the priority list can take
+    #[arg(long, default_value_t = 0)]
+    pub reserved_slots: usize,
+
+    /// Username allowed to take a reserved slot, can be given several times
+    #[arg(long, value_name = "USERNAME")]
+    pub priority: Vec<String>,
+
+    /// File banned usernames and addresses are kept in, bans only last until shutdown without one
+    #[arg(long, value_name = "PATH")]
+    pub ban_list: Option<PathBuf>,
+
+    /// Queues players that join while the server is full instead of turning them away
+    #[arg(long)]
+    pub join_queue: bool,
+
+    /// Lists the server among the launcher's LAN games, answering the discovery probes sent from
+    /// the local network
+    #[arg(long)]
+    pub discovery: bool,
+
+    /// Sends every message as it is, instead of compressing large ones for clients that accept it
+    #[arg(long)]
+    pub no_compression: bool,
+
+    /// Lets clients join that cannot sign their messages, such as older ones. Those are turned
+    /// away otherwise, as anyone on the network could send messages in their name
+    #[arg(long)]
+    pub allow_unsigned: bool,
+
+    /// Seconds without hearing from a client before it is disconnected
+    #[arg(long, default_value_t = 10)]
+    pub client_timeout: u64,
+
+    /// Seconds a client whose connection dropped can come back as the same player, with its id,
+    /// place and score
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub session_grace: u64,
+
+    /// Players that left kept to be put back where they were when they join again, those that
+    /// left longest ago are forgotten first
+    #[arg(long, value_name = "COUNT", default_value_t = 1000)]
+    pub offline_players: usize,
+
+    /// Protocol clients connect with, `tcp` or `udp`
+    #[arg(long, default_value_t = Transport::Tcp)]
+    pub transport: Transport,
+
+    /// PEM file of the certificate chain to encrypt TCP connections with, clients then have to
+    /// connect with `--tls`
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM file of the private key of `--tls-cert`
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Rules the server plays by
+    #[arg(long, value_enum, default_value_t = GameMode::FreeForAll)]
+    pub mode: GameMode,
+
+    /// Hosts another lobby by this name besides `main`, can be given several times. Lobbies play
+    /// the same map and mode in worlds of their own, clients pick one when they join
+    #[arg(long = "lobby", value_name = "NAME")]
+    pub lobbies: Vec<String>,
+
+    /// Players each lobby holds. Once every lobby is full another is opened for the next player,
+    /// and closed again when everyone has left it
+    #[arg(long)]
+    pub lobby_size: Option<usize>,
+
+    /// Map file whose `[tunables]` table overrides the default gameplay settings
+    #[arg(long)]
+    pub map: Option<PathBuf>,
+
+    /// File environment edits made at runtime are kept in, defaults to the map path with an
+    /// `.edits.toml` extension
+    #[arg(long, value_name = "PATH", requires = "map")]
+    pub map_edits: Option<PathBuf>,
+
+    /// File the world is loaded from at startup and saved to when the server stops
+    #[arg(long, value_name = "PATH")]
+    pub world_file: Option<PathBuf>,
+
+    /// Seeds spawn points and other rolls so runs can be repeated, a random seed or the one the
+    /// world file was saved with if not given
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Writes a checksum of the world every tick, so runs with the same seed can be compared to
+    /// find the tick where they went apart
+    #[arg(long, value_name = "PATH")]
+    pub hash_log: Option<PathBuf>,
+
+    /// Seconds between automatic saves of the world, never if not given. Needs a world file or
+    /// database to save to
+    #[arg(long, value_name = "SECS")]
+    pub autosave_interval: Option<u64>,
+
+    /// File player ratings and lifetime stats are kept in, they only last until shutdown without
+    /// one
+    #[arg(long, value_name = "PATH")]
+    pub stats_file: Option<PathBuf>,
+
+    /// SQLite database to keep stats, bans and the world in instead of their files, only for
+    /// servers built with the `sqlite` feature
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stats_file", "ban_list", "world_file"])]
+    pub database: Option<PathBuf>,
+
+    /// Rating players lose for every full day they stay away
+    #[arg(long, value_name = "POINTS", default_value_t = 0.0)]
+    pub rating_decay: f64,
+
+    /// Lowest rating decay can bring a player down to
+    #[arg(long, value_name = "POINTS", default_value_t = crate::stats::INITIAL_RATING)]
+    pub rating_decay_floor: f64,
+
+    /// Address to serve the operator dashboard on, such as `127.0.0.1:8080`. It has no login, so
+    /// it should only be reachable by operators
+    #[arg(long, value_name = "ADDR")]
+    pub dashboard_addr: Option<SocketAddr>,
+
+    /// Port to serve metrics on for Prometheus to scrape, at `/metrics` on every interface
+    #[arg(long, value_name = "PORT")]
+    pub metrics_port: Option<u16>,
+
+    /// Directory crash reports and the world at the time are written to when something inside
+    /// the server goes wrong
+    #[arg(long, value_name = "DIR", default_value = "diagnostics")]
+    pub diagnostics_dir: PathBuf,
+
+    /// Simulation steps per second, sent to clients so their prediction steps alike. Same as
+    /// `--set tick_rate=HZ`, 60 unless the map says otherwise
+    #[arg(long, value_name = "HZ")]
+    pub tick_rate: Option<f64>,
+
+    /// Snapshots of the world sent to clients per second, every simulation step if not given.
+    /// Deaths, hits and other events are still sent on the step they happen
+    #[arg(long, value_name = "HZ")]
+    pub broadcast_rate: Option<f64>,
+
+    /// Sends each client only the players, projectiles and pickups within this distance of its
+    /// player, in world units, instead of the whole world. Clients then only know of the players
+    /// near them
+    #[arg(long, value_name = "UNITS")]
+    pub interest_radius: Option<f32>,
+
+    /// Overrides a tunable, taking priority over the map, e.g. `--set physics.friction=0.5`
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+
+    /// Prints the resolved tunables and where each value came from, then exits
+    #[arg(long)]
+    pub dump_config: bool,
+}
+impl ServerConfig {
+    /// Settings of a server played on alone, kept out of the list of LAN games
+    pub fn single_player() -> Self {
+        Self::parse_from(["server", "--server-name", "Single Player"])
+    }
+
+    /// Name of the map file without its extension, empty when playing without one
+    pub fn map_name(&self) -> String {
+        self.map
+            .as_deref()
+            .and_then(|map| map.file_stem())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
 }
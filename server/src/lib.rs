@@ -0,0 +1,15 @@
+//! The game server as a library, for programs that run one inside themselves, such as the client
+//! playing single player, see [`Server::run_in_background`]. The `server` binary runs one on its
+//! own.
+mod bans;
+pub mod cli;
+mod loot;
+mod map;
+mod mode;
+mod patch;
+mod server;
+mod stats;
+mod storage;
+pub mod tunables;
+
+pub use crate::server::Server;
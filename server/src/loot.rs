@@ -0,0 +1,225 @@
+//! Pickups spawned from loot tables in the map file's `[loot]` section.
+//!
+//! A table weighs what a spawn can hold, with `nothing` leaving it empty for another respawn time.
+//! Each spawn rolls its table at startup and again whenever it comes back after being taken, so
+//! what lies where changes over a match. A spawn is either one `pos` or every tile drawn with a
+//! legend `tile` character, and waits `respawn` seconds after being taken, the combat
+//! `pickup_respawn` unless it says otherwise:
+//!
+//! ```toml
+//! [loot.tables.supplies]
+//! armor = 3.0
+//! ammo = 1.0
+//! nothing = 1.0
+//!
+//! [[loot.spawns]]
+//! table = "supplies"
+//! pos = { x = 0.5, y = 0.0 }
+//! respawn = 30.0
+//!
+//! [[loot.spawns]]
+//! table = "supplies"
+//! tile = "L"
+//! ```
+//!
+//! These come on top of the fixed armor and ammo pickups of the legend's `pickup` and `ammo`
+//! flags, which always hold the same.
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use common::{
+    vec::Vec2,
+    world::{
+        pickups::{Pickup, PickupKind},
+        rng::GameRng,
+    },
+};
+use serde::Deserialize;
+
+use crate::{cli::ServerConfig, map};
+
+/// The part of a map file read here
+#[derive(Deserialize)]
+struct MapFile {
+    #[serde(default)]
+    loot: LootSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct LootSection {
+    #[serde(default)]
+    tables: BTreeMap<String, LootTable>,
+    #[serde(default)]
+    spawns: Vec<SpawnEntry>,
+}
+
+/// Relative chances of what a spawn holds
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct LootTable {
+    armor: f32,
+    ammo: f32,
+    nothing: f32,
+}
+impl LootTable {
+    fn weights(&self) -> [(Option<PickupKind>, f32); 3] {
+        [
+            (Some(PickupKind::Armor), self.armor),
+            (Some(PickupKind::Ammo), self.ammo),
+            (None, self.nothing),
+        ]
+    }
+
+    /// What a spawn holds this time, none when it stays empty
+    fn roll(&self, rng: &mut GameRng) -> Option<PickupKind> {
+        let weights = self.weights();
+        let total: f32 = weights.iter().map(|(_, weight)| weight).sum();
+        let mut left = rng.next_f32() * total;
+        for (kind, weight) in weights {
+            if left < weight {
+                return kind;
+            }
+            left -= weight;
+        }
+        // Rounding can leave a sliver past the last weight
+        weights
+            .into_iter()
+            .rev()
+            .find(|(_, weight)| *weight > 0.0)?
+            .0
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpawnEntry {
+    table: String,
+    pos: Option<Vec2>,
+    tile: Option<char>,
+    respawn: Option<f32>,
+}
+
+/// A place loot appears, with the pickup it is in the world
+struct Spawn {
+    table: LootTable,
+    /// Seconds it stays empty after being taken, the combat config's when none
+    respawn: Option<f32>,
+    /// Index of its pickup among the world's
+    pickup: usize,
+    /// Whether its pickup could be taken as of the last update
+    available: bool,
+}
+
+/// Rolls what the loot spawns of a map hold
+#[derive(Default)]
+pub struct Loot {
+    spawns: Vec<Spawn>,
+}
+impl Loot {
+    /// Reads the loot section of the configured map, no loot without a map or section. The spawns'
+    /// pickups are added to `pickups` with their first roll, those rolling nothing waiting their
+    /// respawn time, `default_respawn` unless they have their own.
+    pub fn from_config(
+        config: &ServerConfig,
+        default_respawn: f32,
+        pickups: &mut Vec<Pickup>,
+        rng: &mut GameRng,
+    ) -> Result<Self> {
+        let Some(path) = &config.map else {
+            return Ok(Self::default());
+        };
+        Self::load(path, default_respawn, pickups, rng)
+            .map_err(|e| anyhow!("In map {}: {e}", path.display()))
+    }
+
+    fn load(
+        path: &Path,
+        default_respawn: f32,
+        pickups: &mut Vec<Pickup>,
+        rng: &mut GameRng,
+    ) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let section = toml::from_str::<MapFile>(&text)?.loot;
+        for (name, table) in &section.tables {
+            let weights = table.weights().map(|(_, weight)| weight);
+            if weights
+                .iter()
+                .any(|weight| !(*weight >= 0.0 && weight.is_finite()))
+            {
+                bail!("Loot table `{name}` has a weight that is not 0 or more");
+            }
+            if weights.iter().all(|weight| *weight == 0.0) {
+                bail!("Loot table `{name}` has nothing to roll");
+            }
+        }
+        let centers = if section.spawns.iter().any(|spawn| spawn.tile.is_some()) {
+            map::tile_centers(path)?
+        } else {
+            BTreeMap::new()
+        };
+
+        let mut loot = Self::default();
+        for entry in section.spawns {
+            let table = *section
+                .tables
+                .get(&entry.table)
+                .ok_or_else(|| anyhow!("No loot table `{}`", entry.table))?;
+            if entry
+                .respawn
+                .is_some_and(|secs| secs < 0.0 || !secs.is_finite())
+            {
+                bail!("Loot respawn times cannot be below 0");
+            }
+            let points = match (entry.pos, entry.tile) {
+                (Some(pos), None) => vec![pos],
+                (None, Some(c)) => centers.get(&c).cloned().unwrap_or_default(),
+                _ => bail!("A loot spawn needs either a `pos` or a `tile`"),
+            };
+            for pos in points {
+                let kind = table.roll(rng);
+                loot.spawns.push(Spawn {
+                    table,
+                    respawn: entry.respawn,
+                    pickup: pickups.len(),
+                    available: kind.is_some(),
+                });
+                pickups.push(Pickup {
+                    pos,
+                    respawn_in: match kind {
+                        Some(_) => 0.0,
+                        None => entry.respawn.unwrap_or(default_respawn),
+                    },
+                    kind: kind.unwrap_or_default(),
+                });
+            }
+        }
+        Ok(loot)
+    }
+
+    pub fn spawn_count(&self) -> usize {
+        self.spawns.len()
+    }
+
+    /// Follows the spawns' pickups after they were collected: those just taken wait their own
+    /// respawn time, those just back roll their table anew. `default_respawn` is the combat
+    /// config's.
+    pub fn update(&mut self, pickups: &mut [Pickup], default_respawn: f32, rng: &mut GameRng) {
+        for spawn in &mut self.spawns {
+            let Some(pickup) = pickups.get_mut(spawn.pickup) else {
+                continue;
+            };
+            match (spawn.available, pickup.is_available()) {
+                (true, false) => {
+                    pickup.respawn_in = spawn.respawn.unwrap_or(default_respawn);
+                }
+                (false, true) => match spawn.table.roll(rng) {
+                    Some(kind) => pickup.kind = kind,
+                    None => pickup.respawn_in = spawn.respawn.unwrap_or(default_respawn),
+                },
+                _ => {}
+            }
+            spawn.available = pickup.is_available();
+        }
+    }
+}
@@ -3,14 +3,16 @@
 //! address and configuration, and runs the server to handle client connections and game logic.
 use anyhow::Result;
 use clap::Parser;
-
-mod cli;
-mod server;
+use server::{Server, cli::Cli, tunables::ResolvedTunables};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = cli::Cli::parse();
-    let mut server = server::Server::init(&cli.address, cli.config).await?;
+    let cli = Cli::parse();
+    if cli.config.dump_config {
+        println!("{}", ResolvedTunables::from_config(&cli.config)?.dump());
+        return Ok(());
+    }
+    let mut server = Server::init(&cli.address, cli.config).await?;
     println!(
         "Started server, listening on {}.",
         server.get_address().unwrap()
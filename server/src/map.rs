@@ -0,0 +1,266 @@
+//! Reading the environment out of a map file.
+//!
+//! The tile grid is drawn as text, one string per row with the top row first, and a legend tells
+//! what each character stands for:
+//!
+//! ```toml
+//! [tiles]
+//! size = 0.25
+//! origin = { x = -1.0, y = -0.5 }
+//! rows = [
+//!     "########",
+//!     "#..S...#",
+//!     "########",
+//! ]
+//!
+//! [tiles.legend]
+//! "#" = { color = { r = 0.4, g = 0.4, b = 0.45 }, solid = true }
+//! "." = { color = { r = 0.1, g = 0.1, b = 0.1 } }
+//! "S" = { color = { r = 0.1, g = 0.1, b = 0.1 }, spawn = true }
+//! "A" = { color = { r = 0.1, g = 0.1, b = 0.1 }, pickup = true }
+//! "M" = { color = { r = 0.1, g = 0.1, b = 0.1 }, ammo = true }
+//! ```
+//!
+//! A space leaves the cell empty. Free-standing boxes go in an `[[objects]]` array next to it, each
+//! with a `pos`, a `size` and optionally a `color` and a `sprite` id to draw it with.
+//!
+//! Pickups rolled from loot tables go in a `[loot]` section, see [`crate::loot`].
+//!
+//! Gravity wells go in an `[[attractors]]` array, each with a `pos`, a `radius` and a `strength`
+//! that pulls players and projectiles towards it, or pushes them away when negative:
+//!
+//! ```toml
+//! [[attractors]]
+//! pos = { x = 0.0, y = 0.0 }
+//! radius = 0.6
+//! strength = 2.0
+//! ```
+//!
+//! The track of time trials and races goes in a `[[checkpoints]]` array in the order it is driven, each
+//! with a `pos` and a `radius`. The first one is the start and finish line, see
+//! [`checkpoint`](common::world::checkpoint):
+//!
+//! ```toml
+//! [[checkpoints]]
+//! pos = { x = -0.8, y = 0.0 }
+//! radius = 0.15
+//! ```
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use common::{
+    color::Color,
+    vec::Vec2,
+    world::{
+        checkpoint::Checkpoint,
+        environment::{Attractor, Environment, Object, TileKind, TileMap},
+    },
+};
+use serde::Deserialize;
+
+/// Parts of a map file describing the environment, the rest is read elsewhere
+#[derive(Deserialize)]
+struct MapFile {
+    tiles: Option<TilesSection>,
+    #[serde(default)]
+    objects: Vec<Object>,
+    #[serde(default)]
+    attractors: Vec<Attractor>,
+    #[serde(default)]
+    checkpoints: Vec<Checkpoint>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TilesSection {
+    size: f32,
+    #[serde(default = "zero")]
+    origin: Vec2,
+    rows: Vec<String>,
+    legend: BTreeMap<String, LegendEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LegendEntry {
+    color: Color,
+    #[serde(default)]
+    solid: bool,
+    /// Players appear in the middle of these tiles
+    #[serde(default)]
+    spawn: bool,
+    /// An armor pickup lies in the middle of these tiles
+    #[serde(default)]
+    pickup: bool,
+    /// An ammo pickup lies in the middle of these tiles
+    #[serde(default)]
+    ammo: bool,
+}
+
+fn zero() -> Vec2 {
+    Vec2::ZERO
+}
+
+/// Reads the tiles, objects, attractors and checkpoints of a map file
+pub fn load_environment(path: &Path) -> Result<Environment> {
+    let map = read(path)?;
+    let tiles = match map.tiles {
+        Some(tiles) => build_tiles(tiles).map_err(|e| anyhow!("In map {}: {e}", path.display()))?,
+        None => TileMap::default(),
+    };
+    for attractor in &map.attractors {
+        if !(attractor.radius > 0.0 && attractor.radius.is_finite()) {
+            bail!(
+                "In map {}: attractor radius must be positive",
+                path.display()
+            );
+        }
+        if !attractor.strength.is_finite() || !attractor.pos.is_finite() {
+            bail!(
+                "In map {}: attractor at {:?} is not finite",
+                path.display(),
+                attractor.pos
+            );
+        }
+    }
+    for checkpoint in &map.checkpoints {
+        let finite = checkpoint.radius.is_finite() && checkpoint.pos.is_finite();
+        if !(finite && checkpoint.radius > 0.0) {
+            bail!(
+                "In map {}: checkpoint at {:?} must have a finite position and a positive radius",
+                path.display(),
+                checkpoint.pos
+            );
+        }
+    }
+    Ok(Environment {
+        tiles,
+        objects: map.objects,
+        attractors: map.attractors,
+        checkpoints: map.checkpoints,
+    })
+}
+
+fn read(path: &Path) -> Result<MapFile> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read map {}: {e}", path.display()))?;
+    toml::from_str(&text).map_err(|e| anyhow!("Invalid map {}: {e}", path.display()))
+}
+
+fn build_tiles(section: TilesSection) -> Result<TileMap> {
+    if section.size <= 0.0 {
+        bail!("Tile size must be positive");
+    }
+
+    let mut ids = BTreeMap::new();
+    let mut kinds = Vec::new();
+    let mut spawn_ids = Vec::new();
+    let mut pickup_ids = Vec::new();
+    let mut ammo_ids = Vec::new();
+    for (key, entry) in section.legend {
+        let mut chars = key.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            bail!("Legend key `{key}` must be a single character");
+        };
+        if c == ' ' {
+            bail!("A space always means an empty cell and cannot be in the legend");
+        }
+        let id = kinds.len() as u16;
+        ids.insert(c, id);
+        if entry.spawn {
+            spawn_ids.push(id);
+        }
+        if entry.pickup {
+            pickup_ids.push(id);
+        }
+        if entry.ammo {
+            ammo_ids.push(id);
+        }
+        kinds.push(TileKind {
+            color: entry.color,
+            solid: entry.solid,
+        });
+    }
+
+    let height = section.rows.len() as u32;
+    let width = section
+        .rows
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+
+    // Rows are written top first but stored bottom first, short rows are padded with empty cells
+    let mut tiles = Vec::with_capacity((width * height) as usize);
+    for row in section.rows.iter().rev() {
+        let mut len = 0;
+        for c in row.chars() {
+            let id = match c {
+                ' ' => TileMap::EMPTY,
+                c => *ids
+                    .get(&c)
+                    .ok_or_else(|| anyhow!("Tile `{c}` is not in the legend"))?,
+            };
+            tiles.push(id);
+            len += 1;
+        }
+        tiles.extend(std::iter::repeat_n(TileMap::EMPTY, width as usize - len));
+    }
+
+    let mut map = TileMap {
+        origin: section.origin,
+        tile_size: section.size,
+        width,
+        height,
+        tiles,
+        kinds,
+        spawn_points: Vec::new(),
+        pickup_points: Vec::new(),
+        ammo_points: Vec::new(),
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let id = map.tiles[(y * width + x) as usize];
+            let center = cell_center(&map, x, y);
+            if spawn_ids.contains(&id) {
+                map.spawn_points.push(center);
+            }
+            if pickup_ids.contains(&id) {
+                map.pickup_points.push(center);
+            }
+            if ammo_ids.contains(&id) {
+                map.ammo_points.push(center);
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Middle of every tile of the map file at `path`, by the legend character it is drawn with
+pub fn tile_centers(path: &Path) -> Result<BTreeMap<char, Vec<Vec2>>> {
+    let Some(section) = read(path)?.tiles else {
+        return Ok(BTreeMap::new());
+    };
+    // Tile kinds are numbered in the order of the legend, as built
+    let chars: Vec<char> = section
+        .legend
+        .keys()
+        .filter_map(|key| key.chars().next())
+        .collect();
+    let map = build_tiles(section).map_err(|e| anyhow!("In map {}: {e}", path.display()))?;
+    let mut centers = BTreeMap::<char, Vec<Vec2>>::new();
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let id = map.tiles[(y * map.width + x) as usize];
+            if let Some(&c) = chars.get(id as usize) {
+                centers.entry(c).or_default().push(cell_center(&map, x, y));
+            }
+        }
+    }
+    Ok(centers)
+}
+
+fn cell_center(map: &TileMap, x: u32, y: u32) -> Vec2 {
+    let cell = map.cell_box(x, y);
+    cell.pos + cell.size / 2.0
+}
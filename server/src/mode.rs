@@ -0,0 +1,583 @@
+//! Game modes, which add rules on top of the simulation every tick.
+//!
+//! A mode also comes with its own defaults for the tunables, applied between the built-in
+//! defaults and the map so maps and server settings can still adjust them.
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+use common::{
+    message::ServerMessage,
+    vec::Vec2,
+    world::{
+        GameWorld,
+        ammo::Ammo,
+        arena::Arena,
+        checkpoint::{Checkpoint, LapProgress, Passed},
+        entities::Player,
+        zone::Storm,
+    },
+};
+use toml::Table;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GameMode {
+    /// Everyone shoots everyone and respawns after dying
+    #[default]
+    FreeForAll,
+    /// Players knock each other out of a shrinking arena, the last one standing wins the round
+    Sumo,
+    /// Single life matches in a storm that closes in, the last one standing wins
+    BattleRoyale,
+    /// Laps around the map's checkpoints against the clock, with the fastest ones on a leaderboard
+    TimeTrial,
+    /// Everyone starts on the line at once, the first to complete the laps wins
+    Race,
+}
+impl GameMode {
+    /// Name of the mode as given to `--mode`, which is how clients and the launcher know it
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Tunables the mode changes from the built-in defaults
+    pub fn tunables(self) -> Table {
+        match self {
+            GameMode::FreeForAll => Table::new(),
+            GameMode::Sumo => toml::toml! {
+                [physics]
+                player_collision = true
+                [combat]
+                projectile_damage = 0.0
+                knockback = 0.15
+            },
+            GameMode::BattleRoyale => Table::new(),
+            // Nobody can knock a player off a run
+            GameMode::TimeTrial | GameMode::Race => toml::toml! {
+                [combat]
+                projectile_damage = 0.0
+                knockback = 0.0
+                blast_damage = 0.0
+                blast_knockback = 0.0
+            },
+        }
+    }
+
+    pub fn rules(self) -> Box<dyn Rules> {
+        match self {
+            GameMode::FreeForAll => Box::new(FreeForAll),
+            GameMode::Sumo => Box::new(Sumo::default()),
+            GameMode::BattleRoyale => Box::new(BattleRoyale::default()),
+            GameMode::TimeTrial => Box::new(TimeTrial::default()),
+            GameMode::Race => Box::new(Race::default()),
+        }
+    }
+}
+
+/// Rules run by the server after each simulation step
+pub trait Rules: Send {
+    /// Applies the rules to the world, returning the messages to send to every player.
+    fn update(&mut self, world: &mut GameWorld) -> Vec<ServerMessage>;
+
+    /// Whether ratings change with every kill, for modes without rounds. Otherwise they change
+    /// with the placements announced when a round is over.
+    fn rates_kills(&self) -> bool {
+        false
+    }
+}
+
+/// Nothing beyond the shared combat rules
+pub struct FreeForAll;
+impl Rules for FreeForAll {
+    fn update(&mut self, _world: &mut GameWorld) -> Vec<ServerMessage> {
+        Vec::new()
+    }
+
+    fn rates_kills(&self) -> bool {
+        true
+    }
+}
+
+/// Progress of a last-player-standing mode, `A` being the play area of a running round
+#[derive(Default)]
+enum Round<A> {
+    /// Not enough players yet
+    #[default]
+    Waiting,
+    Running {
+        area: A,
+        /// Players that started the round, anyone else watches until the next one
+        contestants: HashSet<u64>,
+        /// Contestants that are out, in the order they went out
+        fallen: Vec<u64>,
+    },
+    /// Someone won, the next round starts at `next_tick`
+    Over { next_tick: u64 },
+}
+
+/// Brings every player back at a spawn point with full health and ammo.
+fn revive_all(world: &mut GameWorld) {
+    for player in world.entities.players.values_mut() {
+        player.health = world.tunables.combat.max_health;
+        player.armor = 0.0;
+        player.ammo = Ammo::full(&world.tunables.combat.ammo);
+        player.respawn_in = 0.0;
+        player.vel = Vec2::ZERO;
+        player.pos = world.rng.spawn_position(&world.environment.tiles);
+    }
+    world.entities.projectiles.clear();
+}
+
+/// Keeps a player out until the next round.
+fn eliminate(player: &mut Player) {
+    player.health = player.health.min(0.0);
+    player.vel = Vec2::ZERO;
+    player.respawn_in = f32::INFINITY;
+}
+
+/// Notes the contestants that went out since the last step, then ends the round once at most one
+/// is standing, announcing the winner and everyone's placement. Returns the tick the next round
+/// starts at.
+fn finish_round(
+    contestants: &HashSet<u64>,
+    fallen: &mut Vec<u64>,
+    world: &GameWorld,
+    round_delay: f32,
+    messages: &mut Vec<ServerMessage>,
+) -> Option<u64> {
+    // Contestants that left get no placement
+    fallen.retain(|id| contestants.contains(id));
+    let mut standing = Vec::new();
+    let mut out = Vec::new();
+    for id in contestants.iter().copied() {
+        if world.entities.players[&id].is_alive() {
+            standing.push(id);
+        } else if !fallen.contains(&id) {
+            out.push(id);
+        }
+    }
+    // Players going out on the same step are told apart by id so placements stay deterministic
+    out.sort_unstable();
+    fallen.extend(out);
+    if standing.len() > 1 {
+        return None;
+    }
+    let winner = standing.first().copied();
+    match winner {
+        Some(id) => println!("Player {id} won the round"),
+        None => println!("Nobody won the round"),
+    }
+    let placements = standing.into_iter().chain(fallen.iter().rev().copied());
+    messages.push(ServerMessage::RoundOver {
+        winner,
+        placements: placements.collect(),
+    });
+    Some(world.tick + world.tunables.ticks(round_delay as f64))
+}
+
+#[derive(Default)]
+pub struct Sumo {
+    round: Round<Arena>,
+}
+impl Sumo {
+    /// Brings everyone back in the arena and starts shrinking it.
+    fn start_round(&mut self, world: &mut GameWorld) -> Vec<ServerMessage> {
+        let config = world.tunables.sumo;
+        let arena = Arena {
+            center: Vec2::ZERO,
+            start_radius: config.start_radius,
+            end_radius: config.end_radius,
+            start_tick: world.tick,
+            end_tick: world.tick + world.tunables.ticks(config.shrink_time as f64),
+        };
+        revive_all(world);
+
+        let contestants = world.entities.players.keys().copied().collect();
+        println!("Sumo round started");
+        self.round = Round::Running {
+            area: arena,
+            contestants,
+            fallen: Vec::new(),
+        };
+        vec![ServerMessage::UpdateArena(Some(arena))]
+    }
+}
+impl Rules for Sumo {
+    fn update(&mut self, world: &mut GameWorld) -> Vec<ServerMessage> {
+        let config = world.tunables.sumo;
+        let mut messages = Vec::new();
+        match &mut self.round {
+            Round::Waiting => {
+                if world.entities.players.len() >= config.min_players.max(1) {
+                    return self.start_round(world);
+                }
+            }
+            Round::Running {
+                area: arena,
+                contestants,
+                fallen,
+            } => {
+                contestants.retain(|id| world.entities.players.contains_key(id));
+                for (id, player) in world.entities.players.iter_mut() {
+                    if !player.is_alive() {
+                        continue;
+                    }
+                    // Late joiners sit the round out, leaving the arena eliminates
+                    let joined_late = !contestants.contains(id);
+                    if joined_late || !arena.contains(player.pos, world.tick) {
+                        eliminate(player);
+                        if !joined_late {
+                            println!("Player {id} was knocked out");
+                            messages.push(ServerMessage::PlayerDied {
+                                victim: *id,
+                                killer: None,
+                                assists: Vec::new(),
+                                weapon: None,
+                            });
+                        }
+                    }
+                }
+
+                if let Some(next_tick) = finish_round(
+                    contestants,
+                    fallen,
+                    world,
+                    config.round_delay,
+                    &mut messages,
+                ) {
+                    self.round = Round::Over { next_tick };
+                } else if world.tunables.every_second(world.tick) {
+                    // Sent again now and then for players who joined after the round started
+                    messages.push(ServerMessage::UpdateArena(Some(*arena)));
+                }
+            }
+            Round::Over { next_tick } => {
+                if world.tick >= *next_tick {
+                    if world.entities.players.len() >= config.min_players.max(1) {
+                        return self.start_round(world);
+                    }
+                    revive_all(world);
+                    self.round = Round::Waiting;
+                    messages.push(ServerMessage::UpdateArena(None));
+                }
+            }
+        }
+        messages
+    }
+}
+
+#[derive(Default)]
+pub struct BattleRoyale {
+    round: Round<Storm>,
+}
+impl BattleRoyale {
+    /// Brings everyone back and starts a new storm.
+    fn start_round(&mut self, world: &mut GameWorld) -> Vec<ServerMessage> {
+        let storm = Storm::start(&world.tunables.storm, world.tick, world.tunables.tick_rate);
+        let zone = storm.zone();
+        revive_all(world);
+
+        let contestants = world.entities.players.keys().copied().collect();
+        println!("Battle royale match started");
+        self.round = Round::Running {
+            area: storm,
+            contestants,
+            fallen: Vec::new(),
+        };
+        vec![ServerMessage::UpdateStorm(Some(zone))]
+    }
+}
+impl Rules for BattleRoyale {
+    fn update(&mut self, world: &mut GameWorld) -> Vec<ServerMessage> {
+        let config = world.tunables.battle_royale;
+        let mut messages = Vec::new();
+        match &mut self.round {
+            Round::Waiting => {
+                if world.entities.players.len() >= config.min_players.max(1) {
+                    return self.start_round(world);
+                }
+            }
+            Round::Running {
+                area: storm,
+                contestants,
+                fallen,
+            } => {
+                contestants.retain(|id| world.entities.players.contains_key(id));
+                let changed = storm.advance(world.tick);
+
+                let dt = world.tunables.timestep();
+                for death in storm.hurt(&mut world.entities, world.tick, dt, &world.tunables.combat)
+                {
+                    println!("Player {} was caught in the storm", death.victim);
+                    messages.push(ServerMessage::PlayerDied {
+                        victim: death.victim,
+                        killer: None,
+                        assists: Vec::new(),
+                        weapon: death.weapon,
+                    });
+                }
+                for (id, player) in world.entities.players.iter_mut() {
+                    // Late joiners sit the match out and nobody comes back before the next one
+                    if !contestants.contains(id) || !player.is_alive() {
+                        eliminate(player);
+                    }
+                }
+
+                if let Some(next_tick) = finish_round(
+                    contestants,
+                    fallen,
+                    world,
+                    config.round_delay,
+                    &mut messages,
+                ) {
+                    self.round = Round::Over { next_tick };
+                } else if changed || world.tunables.every_second(world.tick) {
+                    messages.push(ServerMessage::UpdateStorm(Some(storm.zone())));
+                }
+            }
+            Round::Over { next_tick } => {
+                if world.tick >= *next_tick {
+                    if world.entities.players.len() >= config.min_players.max(1) {
+                        return self.start_round(world);
+                    }
+                    revive_all(world);
+                    self.round = Round::Waiting;
+                    messages.push(ServerMessage::UpdateStorm(None));
+                }
+            }
+        }
+        messages
+    }
+}
+
+/// Rows of the lap leaderboard sent to players
+const LEADERBOARD_SIZE: usize = 10;
+
+/// Everyone drives laps of the map's checkpoints on their own, as often as they like
+#[derive(Default)]
+pub struct TimeTrial {
+    progress: HashMap<u64, LapProgress>,
+    /// Fastest lap of everyone who completed one, in seconds by username, kept while the server
+    /// runs so leaving does not take a player off the leaderboard
+    best: HashMap<String, f32>,
+}
+impl TimeTrial {
+    fn leaderboard(&self) -> ServerMessage {
+        let mut times: Vec<_> = self
+            .best
+            .iter()
+            .map(|(username, time)| (username.clone(), *time))
+            .collect();
+        times.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        times.truncate(LEADERBOARD_SIZE);
+        ServerMessage::LapTimes(times)
+    }
+}
+impl Rules for TimeTrial {
+    fn update(&mut self, world: &mut GameWorld) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+        let mut improved = false;
+        let players = &world.entities.players;
+        self.progress.retain(|id, _| players.contains_key(id));
+        for (id, player) in players {
+            let progress = self.progress.entry(*id).or_default();
+            // Dying ends the lap, the next one starts from the line again
+            if !player.is_alive() {
+                *progress = LapProgress::default();
+                continue;
+            }
+            let checkpoint = progress.next() as u32;
+            let passed = progress.advance(player.pos, &world.environment.checkpoints, world.tick);
+            if let Some(Passed::Lap(ticks)) = passed {
+                let time = (ticks as f64 / world.tunables.tick_rate) as f32;
+                println!("{} completed a lap in {time:.2}s", player.username);
+                messages.push(ServerMessage::LapCompleted { player: *id, time });
+                let best = self.best.entry(player.username.clone()).or_insert(time);
+                if time <= *best {
+                    *best = time;
+                    improved = true;
+                }
+            }
+            if passed.is_some() {
+                messages.push(ServerMessage::CheckpointReached {
+                    player: *id,
+                    checkpoint,
+                    tick: world.tick,
+                });
+            }
+        }
+        // Sent again now and then for players who joined since
+        if improved || (world.tunables.every_second(world.tick) && !self.best.is_empty()) {
+            messages.push(self.leaderboard());
+        }
+        messages
+    }
+}
+
+/// Progress of a race
+#[derive(Default)]
+enum RaceState {
+    /// Not enough players yet, or no track
+    #[default]
+    Waiting,
+    Running {
+        start_tick: u64,
+        /// Players that started the race, anyone else watches until the next one
+        racers: HashMap<u64, Racer>,
+        /// Racers that completed every lap, in the order they did
+        finished: Vec<u64>,
+        /// Tick the race ends at whoever is left, once someone finished
+        end_tick: Option<u64>,
+    },
+    /// The race is over, the next one starts at `next_tick`
+    Over { next_tick: u64 },
+}
+
+#[derive(Default)]
+struct Racer {
+    progress: LapProgress,
+    laps: u32,
+}
+
+#[derive(Default)]
+pub struct Race {
+    state: RaceState,
+}
+impl Race {
+    /// Brings everyone back on the start line and starts the clock.
+    fn start_race(&mut self, world: &mut GameWorld, start: Checkpoint) -> Vec<ServerMessage> {
+        revive_all(world);
+        for player in world.entities.players.values_mut() {
+            player.pos = start.pos;
+        }
+
+        let racers = world
+            .entities
+            .players
+            .keys()
+            .map(|id| (*id, Racer::default()))
+            .collect();
+        println!("Race started");
+        self.state = RaceState::Running {
+            start_tick: world.tick,
+            racers,
+            finished: Vec::new(),
+            end_tick: None,
+        };
+        vec![ServerMessage::RaceStarted {
+            laps: world.tunables.race.laps,
+        }]
+    }
+
+    /// Start line of the track, when there is one and enough players to race on it
+    fn ready(world: &GameWorld) -> Option<Checkpoint> {
+        let enough = world.entities.players.len() >= world.tunables.race.min_players.max(1);
+        let checkpoints = &world.environment.checkpoints;
+        (enough && checkpoints.len() >= 2).then(|| checkpoints[0])
+    }
+}
+impl Rules for Race {
+    fn update(&mut self, world: &mut GameWorld) -> Vec<ServerMessage> {
+        let config = world.tunables.race;
+        let mut messages = Vec::new();
+        match &mut self.state {
+            RaceState::Waiting => {
+                if let Some(start) = Self::ready(world) {
+                    return self.start_race(world, start);
+                }
+            }
+            RaceState::Running {
+                start_tick,
+                racers,
+                finished,
+                end_tick,
+            } => {
+                racers.retain(|id, _| world.entities.players.contains_key(id));
+                finished.retain(|id| racers.contains_key(id));
+                let checkpoints = &world.environment.checkpoints;
+                let tick_rate = world.tunables.tick_rate;
+                for (id, player) in world.entities.players.iter_mut() {
+                    // Late joiners sit the race out
+                    let Some(racer) = racers.get_mut(id) else {
+                        eliminate(player);
+                        continue;
+                    };
+                    if racer.laps >= config.laps {
+                        continue;
+                    }
+                    let checkpoint = racer.progress.next() as u32;
+                    let passed = racer.progress.advance(player.pos, checkpoints, world.tick);
+                    if let Some(Passed::Lap(ticks)) = passed {
+                        racer.laps += 1;
+                        let time = (ticks as f64 / tick_rate) as f32;
+                        messages.push(ServerMessage::LapCompleted { player: *id, time });
+                        if racer.laps >= config.laps {
+                            finished.push(*id);
+                            let time = ((world.tick - *start_tick) as f64 / tick_rate) as f32;
+                            let place = finished.len() as u32;
+                            println!("{} finished the race in {time:.2}s", player.username);
+                            messages.push(ServerMessage::RaceFinished {
+                                player: *id,
+                                place,
+                                time,
+                            });
+                            end_tick.get_or_insert(
+                                world.tick + world.tunables.ticks(config.finish_time as f64),
+                            );
+                            continue;
+                        }
+                    }
+                    if passed.is_some() {
+                        messages.push(ServerMessage::CheckpointReached {
+                            player: *id,
+                            checkpoint,
+                            tick: world.tick,
+                        });
+                    }
+                }
+
+                let everyone_finished = finished.len() == racers.len();
+                if everyone_finished || end_tick.is_some_and(|end| world.tick >= end) {
+                    // Whoever did not finish is placed by how far they got
+                    let count = checkpoints.len();
+                    let mut rest: Vec<_> = racers
+                        .iter()
+                        .filter(|(id, _)| !finished.contains(id))
+                        .map(|(id, racer)| {
+                            let distance =
+                                racer.laps as usize * count + racer.progress.passed(count);
+                            (*id, distance)
+                        })
+                        .collect();
+                    rest.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                    let winner = finished.first().copied();
+                    match winner {
+                        Some(id) => println!("Player {id} won the race"),
+                        None => println!("Nobody finished the race"),
+                    }
+                    let placements = finished
+                        .iter()
+                        .copied()
+                        .chain(rest.into_iter().map(|r| r.0));
+                    messages.push(ServerMessage::RoundOver {
+                        winner,
+                        placements: placements.collect(),
+                    });
+                    let next_tick = world.tick + world.tunables.ticks(config.round_delay as f64);
+                    self.state = RaceState::Over { next_tick };
+                }
+            }
+            RaceState::Over { next_tick } => {
+                if world.tick >= *next_tick {
+                    if let Some(start) = Self::ready(world) {
+                        return self.start_race(world, start);
+                    }
+                    revive_all(world);
+                    self.state = RaceState::Waiting;
+                }
+            }
+        }
+        messages
+    }
+}
@@ -0,0 +1,134 @@
+//! Environment edits made while the server runs.
+//!
+//! The map file provides the pristine environment and every edit is kept as a patch on top of
+//! it, listing the objects that were added and the map objects that were removed. The patch is
+//! written to its own file after each change so live tweaks survive a restart, while the map
+//! itself is never touched and can be restored at any time.
+use std::path::{Path, PathBuf};
+
+use crate::{cli::ServerConfig, map};
+use anyhow::{Result, anyhow, bail};
+use common::world::environment::{Environment, Object};
+use serde::{Deserialize, Serialize};
+
+/// Changes made to the base environment
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct Patch {
+    added: Vec<Object>,
+    removed: Vec<Object>,
+}
+
+/// Base environment of the map along with the edits made to it
+pub struct EnvironmentPatch {
+    base: Environment,
+    patch: Patch,
+    /// File the patch is kept in, edits only last until shutdown without one
+    path: Option<PathBuf>,
+}
+impl EnvironmentPatch {
+    /// Reads the map's objects and the edits saved for it by an earlier run. Without a map, the
+    /// environment of a saved world stands in for it.
+    pub fn from_config(config: &ServerConfig, saved: Option<Environment>) -> Result<Self> {
+        let base = match &config.map {
+            Some(path) => map::load_environment(path)?,
+            None => saved.unwrap_or_default(),
+        };
+        // Edits are kept next to the map unless told otherwise, without a map the world file has them
+        let path = config.map_edits.clone().or_else(|| {
+            config
+                .map
+                .as_ref()
+                .map(|map| map.with_extension("edits.toml"))
+        });
+
+        let patch = match &path {
+            Some(path) if path.exists() => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Failed to read map edits {}: {e}", path.display()))?;
+                toml::from_str(&text)
+                    .map_err(|e| anyhow!("Invalid map edits in {}: {e}", path.display()))?
+            }
+            _ => Patch::default(),
+        };
+        Ok(Self { base, patch, path })
+    }
+
+    /// The map's environment with the edits applied, only objects are ever edited
+    pub fn environment(&self) -> Environment {
+        let mut removed = self.patch.removed.clone();
+        let mut objects = Vec::new();
+        for object in &self.base.objects {
+            // Each removal only takes out one copy of an object the map repeats
+            match removed.iter().position(|r| r == object) {
+                Some(index) => {
+                    removed.swap_remove(index);
+                }
+                None => objects.push(object.clone()),
+            }
+        }
+        objects.extend(self.patch.added.iter().cloned());
+        Environment {
+            tiles: self.base.tiles.clone(),
+            objects,
+            attractors: self.base.attractors.clone(),
+            checkpoints: self.base.checkpoints.clone(),
+        }
+    }
+
+    /// Number of objects added and removed compared to the map
+    pub fn counts(&self) -> (usize, usize) {
+        (self.patch.added.len(), self.patch.removed.len())
+    }
+
+    pub fn add(&mut self, object: Object) -> Result<()> {
+        self.patch.added.push(object);
+        self.save()
+    }
+
+    /// Removes the object at `index` in [`Self::environment`] and returns it.
+    pub fn remove(&mut self, index: usize) -> Result<Object> {
+        let environment = self.environment();
+        let Some(object) = environment.objects.get(index).cloned() else {
+            bail!(
+                "No object at index {index}, there are {}",
+                environment.objects.len()
+            );
+        };
+        // Added objects come after the map's, taking one back undoes the addition
+        let base_len = environment.objects.len() - self.patch.added.len();
+        if index >= base_len {
+            self.patch.added.remove(index - base_len);
+        } else {
+            self.patch.removed.push(object.clone());
+        }
+        self.save()?;
+        Ok(object)
+    }
+
+    /// Drops every edit, going back to the pristine map.
+    pub fn reset(&mut self) -> Result<()> {
+        self.patch = Patch::default();
+        self.save()
+    }
+
+    /// Where the edits are kept, if anywhere
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.patch == Patch::default() {
+            // Nothing to keep, so a later run starts from the pristine map
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        std::fs::write(path, toml::to_string(&self.patch)?)
+            .map_err(|e| anyhow!("Failed to write map edits {}: {e}", path.display()))
+    }
+}
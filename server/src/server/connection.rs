@@ -0,0 +1,228 @@
+//! Transport independent connections to clients, so handles do not care whether a client talks
+//! over TCP, TCP encrypted with TLS or UDP.
+use anyhow::{Result, bail};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::mpsc::{UnboundedSender, unbounded_channel},
+};
+use tokio_rustls::{Accept, TlsAcceptor};
+
+use super::metrics::Metrics;
+
+use common::message::{
+    ClientMessage, Message, MessageStream, ServerMessage, Transport,
+    auth::{SessionKey, Verifier},
+    frame::{self, Compression, Frame},
+    udp::{self, MAX_DATAGRAM_SIZE, UdpConnection},
+};
+
+/// Accepts new client connections over the configured transport
+pub struct Listener {
+    socket: Socket,
+    /// Handed to every connection, which counts what it sends and receives
+    metrics: Arc<Metrics>,
+}
+
+enum Socket {
+    /// Connections are encrypted when there is an acceptor
+    Tcp {
+        listener: TcpListener,
+        tls: Option<TlsAcceptor>,
+    },
+    /// A single socket shared by every client, datagrams are routed to handles by sender address
+    Udp {
+        socket: Arc<UdpSocket>,
+        peers: HashMap<SocketAddr, UnboundedSender<Vec<u8>>>,
+    },
+}
+impl Listener {
+    pub async fn bind<T: ToSocketAddrs>(
+        addr: T,
+        transport: Transport,
+        tls: Option<TlsAcceptor>,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let socket = match transport {
+            Transport::Tcp => Socket::Tcp {
+                listener: TcpListener::bind(addr).await?,
+                tls,
+            },
+            Transport::Udp if tls.is_some() => bail!("Only TCP connections can use TLS"),
+            Transport::Udp => Socket::Udp {
+                socket: Arc::new(UdpSocket::bind(addr).await?),
+                peers: HashMap::new(),
+            },
+        };
+        Ok(Self { socket, metrics })
+    }
+
+    /// Waits for a new client. Over UDP this also forwards datagrams of known clients to their
+    /// handles, so it has to be polled continuously.
+    pub async fn accept(&mut self) -> Result<(Connection, SocketAddr)> {
+        let metrics = self.metrics.clone();
+        match &mut self.socket {
+            Socket::Tcp { listener, tls } => {
+                let (stream, addr) = listener.accept().await?;
+                // The handshake is left to the handle, so a slow client holds up no one else
+                let link = match tls {
+                    Some(acceptor) => Link::Handshake(Box::new(acceptor.accept(stream))),
+                    None => Link::stream(stream),
+                };
+                Ok((Connection::new(link, metrics), addr))
+            }
+            Socket::Udp { socket, peers } => {
+                let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+                loop {
+                    let (len, addr) = socket.recv_from(&mut buffer).await?;
+                    let datagram = buffer[..len].to_vec();
+
+                    // A failed send means the handle has finished, so the sender is new again
+                    if let Some(tx) = peers.get(&addr)
+                        && tx.send(datagram.clone()).is_ok()
+                    {
+                        continue;
+                    }
+                    if !udp::opens_connection(&datagram) {
+                        continue;
+                    }
+
+                    let (tx, rx) = unbounded_channel();
+                    let _ = tx.send(datagram);
+                    peers.insert(addr, tx);
+                    let connection = UdpConnection::accept(socket.clone(), addr, rx);
+                    let link = Link::Udp(Box::new(connection));
+                    return Ok((Connection::new(link, metrics), addr));
+                }
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.socket {
+            Socket::Tcp { listener, .. } => listener.local_addr().ok(),
+            Socket::Udp { socket, .. } => socket.local_addr().ok(),
+        }
+    }
+}
+
+enum Link {
+    Stream {
+        stream: Box<dyn MessageStream>,
+        /// Bytes received from the client that have not been decoded yet
+        read_buf: Vec<u8>,
+    },
+    /// TLS connection whose handshake is not done yet
+    Handshake(Box<Accept<TcpStream>>),
+    Udp(Box<UdpConnection>),
+}
+impl Link {
+    fn stream(stream: impl MessageStream + 'static) -> Self {
+        Link::Stream {
+            stream: Box::new(stream),
+            read_buf: Vec::new(),
+        }
+    }
+}
+
+/// Connection to a single client
+pub struct Connection {
+    link: Link,
+    /// How large messages are compressed, none until the client says it reads compressed frames
+    compression: Option<Compression>,
+    /// Checks the signature of every message once the client agreed to sign them
+    verifier: Option<Verifier>,
+    metrics: Arc<Metrics>,
+}
+impl Connection {
+    fn new(link: Link, metrics: Arc<Metrics>) -> Self {
+        Self {
+            link,
+            compression: None,
+            verifier: None,
+            metrics,
+        }
+    }
+
+    /// Finishes the TLS handshake of an encrypted connection, nothing can be sent or received
+    /// before. Other connections are ready from the start.
+    pub async fn establish(&mut self) -> Result<()> {
+        let Link::Handshake(accept) = &mut self.link else {
+            return Ok(());
+        };
+        let stream = accept.await?;
+        self.link = Link::stream(stream);
+        Ok(())
+    }
+
+    /// Waits for the next message from the client, a closed connection reads as a disconnect.
+    /// Messages that are not signed once they should be are dropped. Cancelling it does not lose
+    /// any message.
+    pub async fn recv(&mut self) -> Result<ClientMessage> {
+        loop {
+            let frame = match &mut self.link {
+                Link::Stream { stream, read_buf } => frame::read_frame(stream, read_buf).await?,
+                Link::Handshake(_) => bail!("The TLS handshake is not done"),
+                Link::Udp(connection) => connection.recv_frame().await?,
+            };
+            let Some(frame) = frame else {
+                return Ok(ClientMessage::Disconnect);
+            };
+            if let Some(verifier) = &mut self.verifier
+                && let Err(e) = verifier.verify(&frame)
+            {
+                eprintln!("Dropped a client message: {e}");
+                continue;
+            }
+            match ClientMessage::decode(&frame)? {
+                Frame::Message(msg, _) => {
+                    self.metrics.record_received(msg.name());
+                    return Ok(msg);
+                }
+                Frame::Unknown { type_id, .. } => {
+                    eprintln!("Skipped a message of unknown type {type_id}");
+                }
+                Frame::Incomplete => bail!("Truncated message"),
+            }
+        }
+    }
+
+    pub async fn send(&mut self, msg: &ServerMessage) -> Result<()> {
+        let frame = match self.compression {
+            Some(compression) => msg.encode_compressed(compression)?,
+            None => msg.encode()?,
+        };
+        let len = frame.len();
+        match &mut self.link {
+            Link::Stream { stream, .. } => {
+                stream.write_all(&frame).await?;
+                stream.flush().await?;
+            }
+            Link::Handshake(_) => bail!("The TLS handshake is not done"),
+            Link::Udp(connection) => connection.send_frame(frame, msg.delivery()).await?,
+        }
+        self.metrics.record_sent(msg.name(), len);
+        Ok(())
+    }
+
+    /// Acknowledges what the client sent before the connection is dropped, so a client leaving
+    /// over UDP does not wait for an ack of its goodbye. Nothing to do over TCP.
+    pub async fn acknowledge(&mut self) -> Result<()> {
+        match &mut self.link {
+            Link::Udp(connection) => connection.acknowledge().await,
+            Link::Stream { .. } | Link::Handshake(_) => Ok(()),
+        }
+    }
+
+    /// Compresses large messages from now on
+    pub fn compress(&mut self, compression: Compression) {
+        self.compression = Some(compression);
+    }
+
+    /// Only accepts messages signed with `key` from now on
+    pub fn verify(&mut self, key: SessionKey) {
+        self.verifier = Some(Verifier::new(key));
+    }
+}
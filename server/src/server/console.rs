@@ -0,0 +1,250 @@
+//! Admin console read from the server's standard input.
+//!
+//! Every line is parsed into an [`AdminCommand`] and handed to the server loop, so a host can
+//! manage a running server without restarting it.
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{Error, Result, anyhow, bail};
+use common::{
+    vec::Vec2,
+    world::environment::{OBJECT_COLOR, Object},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::mpsc::UnboundedSender,
+};
+
+use super::ServerCommand;
+use crate::bans::Ban;
+
+pub const HELP: &str = "\
+Commands:
+  list                  show connected clients and their round trips
+  kick <id>             disconnect a client
+  ban <id>              disconnect a client and refuse its address
+  ban-name <username>   refuse a username, disconnecting whoever plays under it
+  ban-ip <address>      refuse an address, disconnecting the clients connected from it
+  unban <username|address>
+                        lift a ban
+  bans                  show the banned usernames and addresses
+  redirect <id|all> <address> [token]
+                        send players to another server, giving the token as password
+  broadcast <text>      announce something to every player
+  save [path]           write the world to a file, the world file by default
+  objects               list the environment objects
+  add <x> <y> <w> <h>   add an object to the environment
+  remove <index>        remove an environment object
+  reset-map             undo every environment edit
+  dump-config           show the tunables in use and where each value came from
+  stop                  disconnect everyone and shut down
+  help                  show this message";
+
+/// Commands a host can type into the console
+#[derive(Debug, PartialEq)]
+pub enum AdminCommand {
+    List,
+    Kick(u64),
+    /// Kicks a client and refuses new connections from its address
+    Ban(u64),
+    /// Refuses a username or address, kicking the clients it matches
+    AddBan(Ban),
+    RemoveBan(Ban),
+    Bans,
+    /// Sends a player, or every player without an id, to another server
+    Redirect {
+        id: Option<u64>,
+        address: String,
+        token: String,
+    },
+    Broadcast(String),
+    /// Saves to the given file, or to the world file
+    Save(Option<PathBuf>),
+    Objects,
+    AddObject(Object),
+    RemoveObject(usize),
+    ResetMap,
+    /// Prints the resolved tunables, as `--dump-config` does at startup
+    DumpConfig,
+    Stop,
+    Help,
+}
+impl FromStr for AdminCommand {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        let command = match name {
+            "list" => AdminCommand::List,
+            "kick" => AdminCommand::Kick(
+                args.parse()
+                    .map_err(|_| anyhow!("Usage: kick <id>, got `{args}`"))?,
+            ),
+            "ban" => AdminCommand::Ban(
+                args.parse()
+                    .map_err(|_| anyhow!("Usage: ban <id>, got `{args}`"))?,
+            ),
+            "ban-name" if args.is_empty() => bail!("Usage: ban-name <username>"),
+            "ban-name" => AdminCommand::AddBan(Ban::Username(args.to_string())),
+            "ban-ip" => AdminCommand::AddBan(Ban::Address(
+                args.parse()
+                    .map_err(|_| anyhow!("Usage: ban-ip <address>, got `{args}`"))?,
+            )),
+            "unban" if args.is_empty() => bail!("Usage: unban <username|address>"),
+            "unban" => AdminCommand::RemoveBan(Ban::parse(args)),
+            "bans" => AdminCommand::Bans,
+            "redirect" => parse_redirect(args)?,
+            "broadcast" if args.is_empty() => bail!("Usage: broadcast <text>"),
+            "broadcast" => AdminCommand::Broadcast(args.to_string()),
+            "save" if args.is_empty() => AdminCommand::Save(None),
+            "save" => AdminCommand::Save(Some(PathBuf::from(args))),
+            "objects" => AdminCommand::Objects,
+            "add" => AdminCommand::AddObject(parse_object(args)?),
+            "remove" => AdminCommand::RemoveObject(
+                args.parse()
+                    .map_err(|_| anyhow!("Usage: remove <index>, got `{args}`"))?,
+            ),
+            "reset-map" => AdminCommand::ResetMap,
+            "dump-config" => AdminCommand::DumpConfig,
+            "stop" => AdminCommand::Stop,
+            "help" => AdminCommand::Help,
+            _ => bail!("Unknown command `{name}`, type `help` for a list"),
+        };
+        Ok(command)
+    }
+}
+
+/// Parses who to send where, `<id|all> <address> [token]`
+fn parse_redirect(args: &str) -> Result<AdminCommand> {
+    let usage = || anyhow!("Usage: redirect <id|all> <address> [token], got `{args}`");
+    let mut words = args.split_whitespace();
+    let id = match words.next().ok_or_else(usage)? {
+        "all" => None,
+        id => Some(id.parse().map_err(|_| usage())?),
+    };
+    let address = words.next().ok_or_else(usage)?.to_string();
+    let token = words.next().unwrap_or_default().to_string();
+    if words.next().is_some() {
+        return Err(usage());
+    }
+    Ok(AdminCommand::Redirect { id, address, token })
+}
+
+/// Parses the position and size of an object, `x y width height`
+fn parse_object(args: &str) -> Result<Object> {
+    let numbers: Vec<f32> = args
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| anyhow!("Usage: add <x> <y> <w> <h>, got `{args}`"))?;
+    let [x, y, w, h] = numbers[..] else {
+        bail!("Usage: add <x> <y> <w> <h>, got `{args}`");
+    };
+    Ok(Object {
+        pos: Vec2 { x, y },
+        size: Vec2 { x: w, y: h },
+        color: OBJECT_COLOR,
+        sprite: None,
+    })
+}
+
+/// Reads commands from stdin until it is closed, which leaves the server running.
+pub fn spawn(tx: UnboundedSender<ServerCommand>) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(command) => {
+                    if tx.send(ServerCommand::Admin(command)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<AdminCommand> {
+        line.parse()
+    }
+
+    #[test]
+    fn commands_take_their_arguments() {
+        assert_eq!(parse("  kick 7 ").unwrap(), AdminCommand::Kick(7));
+        assert_eq!(
+            parse("ban-ip 203.0.113.7").unwrap(),
+            AdminCommand::AddBan(Ban::Address("203.0.113.7".parse().unwrap()))
+        );
+        assert_eq!(
+            parse("unban griefer").unwrap(),
+            AdminCommand::RemoveBan(Ban::Username(String::from("griefer")))
+        );
+        assert_eq!(
+            parse("broadcast Restarting  soon").unwrap(),
+            AdminCommand::Broadcast(String::from("Restarting  soon"))
+        );
+        assert_eq!(parse("save").unwrap(), AdminCommand::Save(None));
+        assert_eq!(
+            parse("save backup.bin").unwrap(),
+            AdminCommand::Save(Some(PathBuf::from("backup.bin")))
+        );
+        assert_eq!(parse("dump-config").unwrap(), AdminCommand::DumpConfig);
+        assert_eq!(
+            parse("add 1 2 3 4").unwrap(),
+            AdminCommand::AddObject(Object {
+                pos: Vec2 { x: 1.0, y: 2.0 },
+                size: Vec2 { x: 3.0, y: 4.0 },
+                color: OBJECT_COLOR,
+                sprite: None,
+            })
+        );
+    }
+
+    #[test]
+    fn redirects_go_to_one_player_or_all() {
+        assert_eq!(
+            parse("redirect all 10.0.0.2:8000").unwrap(),
+            AdminCommand::Redirect {
+                id: None,
+                address: String::from("10.0.0.2:8000"),
+                token: String::new(),
+            }
+        );
+        assert_eq!(
+            parse("redirect 3 10.0.0.2:8000 secret").unwrap(),
+            AdminCommand::Redirect {
+                id: Some(3),
+                address: String::from("10.0.0.2:8000"),
+                token: String::from("secret"),
+            }
+        );
+        assert!(parse("redirect 3").is_err());
+        assert!(parse("redirect 3 10.0.0.2:8000 secret extra").is_err());
+    }
+
+    #[test]
+    fn bad_lines_say_how_to_use_the_command() {
+        for line in [
+            "kick",
+            "kick me",
+            "ban-ip host",
+            "add 1 2 3",
+            "broadcast",
+            "remove x",
+        ] {
+            let error = parse(line).unwrap_err().to_string();
+            assert!(error.starts_with("Usage:"), "`{line}` gave `{error}`");
+        }
+        let error = parse("launch").unwrap_err().to_string();
+        assert!(error.contains("Unknown command `launch`"));
+    }
+}
@@ -0,0 +1,346 @@
+//! Web page operators can manage the server from, served when it starts with `--dashboard-addr`.
+//!
+//! The page asks `/state` every second for the clients, the recent chat and where the players are,
+//! and `/map` now and then for the boxes of the map. Its buttons post to `/kick`, `/ban` and
+//! `/broadcast`, which become the same [`AdminCommand`]s the console sends, so both manage the
+//! server the same way. The state comes from the server loop too, asked for with
+//! [`ServerCommand::Dashboard`].
+//!
+//! Posts must carry the [`ACTION_HEADER`] the page sends. Browsers only let other sites send a
+//! custom header after asking the dashboard, which never agrees, so a page the operator happens
+//! to visit cannot post to it. There is no login though, anyone who can reach the dashboard can
+//! kick and ban players. It should only listen where the operators alone can reach it, such as
+//! `127.0.0.1`.
+use std::{net::SocketAddr, time::Duration};
+
+use anyhow::{Result, anyhow, bail};
+use common::{
+    color::Color,
+    message::MAX_CHAT_LENGTH,
+    vec::Vec2,
+    world::{GameWorld, environment::Object},
+};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc::UnboundedSender, oneshot},
+    time,
+};
+
+use super::{ServerCommand, console::AdminCommand, simulation::World};
+
+const PAGE: &str = include_str!("dashboard.html");
+/// Longest the head of a request may be, and its body
+const MAX_HEAD: usize = 16 * 1024;
+const MAX_BODY: usize = 4 * 1024;
+/// Header every post must have, with the value `1`
+const ACTION_HEADER: &str = "X-Dashboard";
+/// Time a browser gets to send its request before the connection is dropped
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the page shows besides the map
+#[derive(Serialize)]
+pub struct DashboardState {
+    pub server_name: String,
+    pub mode: String,
+    pub map: String,
+    pub clients: Vec<ClientState>,
+    /// Recent chat and announcements, oldest first
+    pub chat: Vec<ChatLine>,
+}
+
+/// A connected client, and its player once it plays
+#[derive(Serialize)]
+pub struct ClientState {
+    pub id: u64,
+    pub username: Option<String>,
+    pub addr: String,
+    /// Playing, connecting or where it stands in the queue
+    pub state: String,
+    pub rtt_ms: Option<f64>,
+    pub player: Option<PlayerState>,
+}
+
+#[derive(Serialize)]
+pub struct PlayerState {
+    pub pos: Vec2,
+    pub color: Color,
+    pub health: f32,
+    pub armor: f32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ChatLine {
+    /// Unix milliseconds
+    pub timestamp: u64,
+    pub sender: String,
+    pub text: String,
+}
+
+/// Serves the dashboard at `addr` in the background, a dashboard that cannot listen leaves the
+/// server running without one.
+pub fn spawn(addr: SocketAddr, tx: UnboundedSender<ServerCommand>, world: World) {
+    tokio::spawn(async move {
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Could not serve the dashboard on {addr}: {e}");
+                return;
+            }
+        };
+        println!("Dashboard on http://{addr}");
+        while let Ok((stream, _)) = listener.accept().await {
+            let (tx, world) = (tx.clone(), world.clone());
+            tokio::spawn(async move {
+                let _ = time::timeout(REQUEST_TIMEOUT, serve(stream, &tx, &world)).await;
+            });
+        }
+    });
+}
+
+/// Answers one request and closes the connection
+async fn serve(
+    mut stream: TcpStream,
+    tx: &UnboundedSender<ServerCommand>,
+    world: &World,
+) -> Result<()> {
+    let response = match read_request(&mut stream).await {
+        Ok(request) if !from_page(&request) => Response::text(
+            "403 Forbidden",
+            format!("Posts need the {ACTION_HEADER} header"),
+        ),
+        Ok(request) => match route(&request, tx, world).await {
+            Ok(response) => response,
+            Err(e) => Response::text("400 Bad Request", e.to_string()),
+        },
+        Err(e) => Response::text("400 Bad Request", e.to_string()),
+    };
+    stream.write_all(&response.encode()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Whether the request may have come from another site: posts change things, so only the page
+/// itself may send them
+fn from_page(request: &Request) -> bool {
+    request.method != "POST" || request.header(ACTION_HEADER) == Some("1")
+}
+
+async fn route(
+    request: &Request,
+    tx: &UnboundedSender<ServerCommand>,
+    world: &World,
+) -> Result<Response> {
+    let Request {
+        method, path, body, ..
+    } = request;
+    let admin = |command| {
+        tx.send(ServerCommand::Admin(command))
+            .map_err(|_| anyhow!("The server is stopping"))?;
+        Ok(Response::text("200 OK", String::from("OK")))
+    };
+    let id = || {
+        body.trim()
+            .parse()
+            .map_err(|_| anyhow!("Expected a client id, got `{body}`"))
+    };
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/") => Ok(Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: PAGE.to_string(),
+        }),
+        ("GET", "/state") => {
+            let (reply, state) = oneshot::channel();
+            tx.send(ServerCommand::Dashboard(reply))
+                .map_err(|_| anyhow!("The server is stopping"))?;
+            Response::json(&state.await?)
+        }
+        ("GET", "/map") => {
+            let world = world.snapshot().await;
+            Response::json(&map_boxes(
+                &world.ok_or_else(|| anyhow!("The server is stopping"))?,
+            ))
+        }
+        ("POST", "/kick") => admin(AdminCommand::Kick(id()?)),
+        ("POST", "/ban") => admin(AdminCommand::Ban(id()?)),
+        ("POST", "/broadcast") => {
+            let text: String = body.trim().chars().take(MAX_CHAT_LENGTH).collect();
+            if text.is_empty() {
+                bail!("Nothing to broadcast");
+            }
+            admin(AdminCommand::Broadcast(text))
+        }
+        _ => Ok(Response::text(
+            "404 Not Found",
+            format!("No {method} {path}"),
+        )),
+    }
+}
+
+/// Every box drawn on the map, tiles and objects alike
+fn map_boxes(world: &GameWorld) -> Vec<Object> {
+    let tiles = &world.environment.tiles;
+    let cells = (0..tiles.height).flat_map(|y| (0..tiles.width).map(move |x| (x, y)));
+    cells
+        .filter(|&(x, y)| tiles.kind(x, y).is_some())
+        .map(|(x, y)| tiles.cell_box(x, y))
+        .chain(world.environment.objects.iter().cloned())
+        .collect()
+}
+
+/// An HTTP request, as much of it as the dashboard and the metrics look at
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Names and values, in the order they came
+    headers: Vec<(String, String)>,
+    pub body: String,
+}
+impl Request {
+    /// Value of the first header called `name`, whatever its case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads a request, refusing one whose head or body is too long
+pub async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> Result<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_HEAD {
+            bail!("Request too long");
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            bail!("Connection closed mid request");
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: String::new(),
+    };
+    let length = request
+        .header("Content-Length")
+        .map(str::parse::<usize>)
+        .transpose()
+        .map_err(|_| anyhow!("Bad Content-Length"))?
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        bail!("Request body too long, the most is {MAX_BODY} bytes");
+    }
+    let end = head_end
+        .checked_add(length)
+        .ok_or_else(|| anyhow!("Bad Content-Length"))?;
+    while buffer.len() < end {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            bail!("Connection closed mid request");
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    request.body = String::from_utf8_lossy(&buffer[head_end..end]).into_owned();
+    Ok(request)
+}
+
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+impl Response {
+    pub fn text(status: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+
+    fn json(value: &impl Serialize) -> Result<Self> {
+        Ok(Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body: serde_json::to_string(value)?,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )
+        .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(request: &str) -> Result<Request> {
+        read_request(&mut request.as_bytes()).await
+    }
+
+    #[tokio::test]
+    async fn requests_are_read_with_their_headers_and_body() {
+        let request = read("POST /kick HTTP/1.1\r\nx-dashboard: 1\r\nContent-Length: 2\r\n\r\n12")
+            .await
+            .unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/kick")
+        );
+        assert_eq!(request.header("X-Dashboard"), Some("1"));
+        assert_eq!(request.body, "12");
+    }
+
+    #[tokio::test]
+    async fn oversized_requests_are_refused() {
+        let huge = format!(
+            "POST /ban HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        );
+        assert!(read(&huge).await.is_err());
+        let long = format!(
+            "POST /broadcast HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        let error = read(&long).await.err().unwrap();
+        assert!(error.to_string().contains("body too long"));
+    }
+
+    #[tokio::test]
+    async fn only_the_page_can_post() {
+        let form = "POST /kick HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 1\r\n\r\n1";
+        assert!(!from_page(&read(form).await.unwrap()));
+        let page = "POST /kick HTTP/1.1\r\nX-Dashboard: 1\r\nContent-Length: 1\r\n\r\n1";
+        assert!(from_page(&read(page).await.unwrap()));
+        assert!(from_page(
+            &read("GET /state HTTP/1.1\r\n\r\n").await.unwrap()
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+//! Diagnostics written when something inside the server goes wrong.
+//!
+//! The server remembers the last commands it handled, and when a client handle or the simulation
+//! panics it writes them to a report together with the world as it was. The world goes into a file
+//! of its own that `--world-file` loads, so the failure can be reproduced from the real state.
+//!
+//! With `--hash-log` the server also writes a [`checksum`] of the world every tick. Two runs with
+//! the same seed and inputs should write the same file, and the first line that differs is the
+//! tick where they went apart.
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt::{self, Write},
+    fs::File,
+    io::{self, BufWriter, Write as _},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use common::{
+    message::ServerMessage,
+    time,
+    world::{GameWorld, checksum},
+};
+
+use super::{ServerCommand, console::AdminCommand};
+
+/// Commands kept for the report, older ones are forgotten
+const HISTORY_LENGTH: usize = 256;
+
+/// The most recent commands the server loop handled
+pub struct CommandHistory {
+    started: Instant,
+    entries: VecDeque<(f64, String)>,
+}
+impl CommandHistory {
+    pub fn new(started: Instant) -> Self {
+        Self {
+            started,
+            entries: VecDeque::with_capacity(HISTORY_LENGTH),
+        }
+    }
+
+    pub fn record(&mut self, command: &ServerCommand) {
+        if let Some(entry) = describe(command) {
+            self.push(entry);
+        }
+    }
+
+    fn push(&mut self, entry: String) {
+        if self.entries.len() == HISTORY_LENGTH {
+            self.entries.pop_front();
+        }
+        let secs = self.started.elapsed().as_secs_f64();
+        self.entries.push_back((secs, entry));
+    }
+}
+
+/// One line about a command, leaving out the snapshots sent every tick, and the round trips timed
+/// and dashboard refreshes asked for every second
+fn describe(command: &ServerCommand) -> Option<String> {
+    Some(match command {
+        ServerCommand::Broadcast {
+            msg: ServerMessage::UpdateEntities { .. },
+            ..
+        }
+        | ServerCommand::RoundTrip { .. }
+        | ServerCommand::Dashboard(_)
+        | ServerCommand::Status(_) => return None,
+        ServerCommand::Broadcast { lobby, msg } => {
+            format!("Broadcast {} to lobby {lobby}", variant_name(msg))
+        }
+        ServerCommand::Join {
+            id,
+            username,
+            lobby: Some(lobby),
+            ..
+        } => format!("Join {id} as {username:?} in lobby {lobby:?}"),
+        ServerCommand::Join { id, username, .. } => format!("Join {id} as {username:?}"),
+        ServerCommand::ClientDisconnected { id, .. } => format!("Client {id} disconnected"),
+        ServerCommand::Resume { id, .. } => format!("Client {id} resumes a session"),
+        ServerCommand::HandlePanicked { id, message } => {
+            format!("Handle of client {id} panicked: {message}")
+        }
+        ServerCommand::QueryStatus { id } => format!("Client {id} asked for the status"),
+        ServerCommand::Autosave => String::from("Autosave"),
+        ServerCommand::Admin(AdminCommand::AddObject(object)) => {
+            format!(
+                "Admin AddObject at {:?} sized {:?}",
+                object.pos, object.size
+            )
+        }
+        ServerCommand::Admin(command) => format!("Admin {command:?}"),
+    })
+}
+
+/// Name of a message without its fields, which can be as large as the whole map
+fn variant_name(msg: &ServerMessage) -> String {
+    let text = format!("{msg:?}");
+    let end = text.find([' ', '(', '{']).unwrap_or(text.len());
+    text[..end].to_string()
+}
+
+/// Lines of `tick checksum` for every simulation step
+pub struct HashLog {
+    file: BufWriter<File>,
+}
+impl HashLog {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Could not create hash log {}", path.display()))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, world: &GameWorld) -> io::Result<()> {
+        let hash = checksum::entities(&world.entities);
+        writeln!(self.file, "{} {hash:016x}", world.tick)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Text of a panic, for the panics that carry one
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(text) = payload.downcast_ref::<&str>() {
+        text.to_string()
+    } else if let Some(text) = payload.downcast_ref::<String>() {
+        text.clone()
+    } else {
+        String::from("no message")
+    }
+}
+
+/// Writes a report of why and what the server was doing to `dir`, with the world next to it if
+/// there is one. Returns the path of the report.
+pub fn dump(
+    dir: &Path,
+    reason: &str,
+    world: Option<&GameWorld>,
+    history: &CommandHistory,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let stamp = time::unix_millis();
+    let report_path = dir.join(format!("crash-{stamp}.txt"));
+
+    let mut report = String::new();
+    writeln!(report, "Reason: {reason}")?;
+    writeln!(report, "Time: {stamp} ms since the Unix epoch")?;
+    match world {
+        Some(world) => {
+            let world_path = dir.join(format!("crash-{stamp}.world"));
+            world.save(&world_path)?;
+            describe_world(&mut report, world, &world_path)?;
+        }
+        None => writeln!(report, "World: gone along with its simulation")?,
+    }
+
+    writeln!(report, "\nRecent commands, oldest first:")?;
+    for (secs, entry) in &history.entries {
+        writeln!(report, "  [{secs:>10.3}s] {entry}")?;
+    }
+
+    std::fs::write(&report_path, report)?;
+    Ok(report_path)
+}
+
+/// What the report says about the world saved at `world_path`
+fn describe_world(report: &mut String, world: &GameWorld, world_path: &Path) -> fmt::Result {
+    writeln!(report, "Tick: {}", world.tick)?;
+    writeln!(
+        report,
+        "Checksum: {:016x}",
+        checksum::entities(&world.entities)
+    )?;
+    writeln!(
+        report,
+        "World: {}, start a server with --world-file on a copy of it to reproduce",
+        world_path.display()
+    )?;
+
+    writeln!(report, "\nPlayers:")?;
+    let mut players: Vec<_> = world.entities.players.iter().collect();
+    players.sort_by_key(|(id, _)| **id);
+    for (id, player) in players {
+        writeln!(
+            report,
+            "  {id} {:?} at {:?} moving {:?}, health {} armor {}",
+            player.username, player.pos, player.vel, player.health, player.armor
+        )?;
+    }
+    writeln!(
+        report,
+        "{} projectile(s), {} pickup(s), {} object(s)",
+        world.entities.projectiles.len(),
+        world.entities.pickups.len(),
+        world.environment.objects.len()
+    )?;
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Answers the probes clients broadcast to find games on the local network, see
+//! [`common::discovery`].
+//!
+//! Only servers started with `--discovery` answer, and only probes from loopback, private and
+//! link-local addresses, each at most once per [`ANSWER_INTERVAL`]. The status is much larger
+//! than a probe, so answering anyone would let a spoofed probe aim it at a third party.
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use common::{
+    discovery::{DISCOVERY_PORT, LobbyStatus, PROBE, ServerStatus},
+    version::PROTOCOL_VERSION,
+};
+use tokio::{
+    net::UdpSocket,
+    sync::{mpsc::UnboundedSender, oneshot},
+};
+
+use super::ServerCommand;
+use crate::cli::ServerConfig;
+
+/// Shortest time between two answers to the same address
+const ANSWER_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Answers probes in the background with the status the server loop gives. Only one server per
+/// machine can take the discovery port, the others carry on without being found.
+pub fn spawn(tx: UnboundedSender<ServerCommand>) {
+    tokio::spawn(async move {
+        if let Err(e) = answer_probes(&tx).await {
+            eprintln!("Not answering LAN discovery: {e}");
+        }
+    });
+}
+
+/// What the server tells clients that look for it, with the players of `lobbies` playing on
+/// `port`
+pub fn status(server_config: &ServerConfig, port: u16, lobbies: Vec<LobbyStatus>) -> ServerStatus {
+    ServerStatus {
+        name: server_config.server_name.clone(),
+        port,
+        players: lobbies.iter().map(|lobby| lobby.players).sum(),
+        max_players: server_config.max_clients as u32,
+        mode: server_config.mode.name(),
+        password: server_config.password.is_some(),
+        version: PROTOCOL_VERSION,
+        lobbies,
+    }
+}
+
+async fn answer_probes(tx: &UnboundedSender<ServerCommand>) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))
+        .await
+        .map_err(|e| anyhow!("Could not listen on port {DISCOVERY_PORT}: {e}"))?;
+    let mut buffer = [0; 64];
+    let mut answered: HashMap<IpAddr, Instant> = HashMap::new();
+    loop {
+        let (len, from) = socket.recv_from(&mut buffer).await?;
+        if &buffer[..len] != PROBE || !on_local_network(from.ip()) {
+            continue;
+        }
+        let now = Instant::now();
+        answered.retain(|_, at| now - *at < ANSWER_INTERVAL);
+        if answered.contains_key(&from.ip()) {
+            continue;
+        }
+        answered.insert(from.ip(), now);
+        let (reply, status) = oneshot::channel();
+        tx.send(ServerCommand::Status(reply))
+            .map_err(|_| anyhow!("The server stopped"))?;
+        let Ok(status) = status.await else {
+            break Ok(());
+        };
+        // A probe that cannot be answered is only one client not finding us
+        let _ = socket.send_to(&status.encode()?, from).await;
+    }
+}
+
+/// Whether `ip` can be on the same network as us, as anyone on the LAN is
+fn on_local_network(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_loopback(),
+    }
+}
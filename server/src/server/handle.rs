@@ -1,35 +1,65 @@
 //! Handles the client connections and communication with the server.
 use anyhow::Result;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use tokio::{
-    net::TcpStream,
     select,
     sync::{
-        Mutex,
         mpsc::{UnboundedReceiver, UnboundedSender},
+        oneshot,
     },
+    time::{Instant, interval},
 };
 
-use super::ServerCommand;
+use super::{
+    Resumed, ServerCommand,
+    connection::Connection,
+    lobby::{LobbyId, MAIN_LOBBY, Placement},
+    quality::Quality,
+    simulation::{Joined, World, WorldCommand},
+};
 use crate::cli::ServerConfig;
-use common::world::{GameWorld, entities::Player};
 use common::{
     color::Color,
-    message::{ClientMessage, ServerMessage},
-    vec::Vec2,
+    message::{
+        ClientMessage, MAX_CHAT_LENGTH, ServerMessage,
+        auth::{self, PublicKey, SessionKey},
+    },
+    physics, time,
+    version::{PROTOCOL_VERSION, Version},
 };
 
+/// Time between two pings sent to a client
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// ClientHandle manages a single client connection, processing messages and updating the game state.
 /// It handles incoming messages from the client, updates the world state, and sends responses back to
 pub struct ClientHandle {
-    /// TCP stream for communication with the client
-    stream: TcpStream,
+    /// Connection to the client, over whichever transport the server uses
+    connection: Connection,
 
     /// Unique identifier for the client
     client_id: u64,
     /// Whether the client has been accepted and is allowed to interact with the server
     accepted: bool,
+    /// Protocol version the client said hello with, it may only connect once it did
+    version: Option<Version>,
+    /// Name the client asked to play under, replaced by the one the server assigns when it lets
+    /// the client join
+    username: Option<String>,
+    /// Color the client asked for, the player gets one picked from its id otherwise
+    color: Option<Color>,
+    /// Our half of the key exchange the client proposed and the key both ends derived from it,
+    /// until the client is accepted and has to sign its messages with it
+    signing: Option<(PublicKey, SessionKey)>,
+    /// When anything was last received from the client
+    last_seen: Instant,
+    /// When the heartbeat the client has not answered yet was sent
+    ping_sent: Option<Instant>,
+    /// When the server started, pongs carry the time since
+    started: Instant,
+    /// Which of the lobby's snapshots the client gets
+    quality: Quality,
 
     // Reference to server config variables
     server_config: Arc<ServerConfig>,
@@ -40,80 +70,349 @@ pub struct ClientHandle {
     /// Receives a server message to send to the client
     rx: UnboundedReceiver<ServerMessage>,
 
-    world: Arc<Mutex<GameWorld>>,
+    /// Lobby the client asked to play in, if any
+    requested_lobby: Option<String>,
+    /// Told where the server placed the client, before it is accepted
+    placed: Option<oneshot::Receiver<Placement>>,
+    /// Told whether the session the client asked to resume is still valid
+    resuming: Option<oneshot::Receiver<Option<Resumed>>>,
+    /// Lobby the client plays in, and its world
+    lobby: LobbyId,
+    world: World,
 }
 
 impl ClientHandle {
     pub fn new(
         client_id: u64,
         server_config: Arc<ServerConfig>,
-        stream: TcpStream,
+        connection: Connection,
         tx: UnboundedSender<ServerCommand>,
         rx: UnboundedReceiver<ServerMessage>,
-        world: Arc<Mutex<GameWorld>>,
+        world: World,
+        started: Instant,
     ) -> Self {
         Self {
             server_config,
             client_id,
-            stream,
+            connection,
             tx,
             rx,
             world,
+            lobby: MAIN_LOBBY,
+            requested_lobby: None,
+            placed: None,
+            resuming: None,
             accepted: false,
+            version: None,
+            username: None,
+            color: None,
+            signing: None,
+            last_seen: Instant::now(),
+            ping_sent: None,
+            started,
+            quality: Quality::new(),
         }
     }
 
     /// Handles the client connection, processing messages and updating the world state.
     pub async fn handle(&mut self) -> Result<()> {
-        let mut buffer = [0; 1024];
+        let result = self.process().await;
+
+        // However the connection ended, the player should not linger in the world
+        let (player, score) = self.world.leave(self.client_id).await;
+        let _ = self.tx.send(ServerCommand::ClientDisconnected {
+            id: self.client_id,
+            player,
+            score,
+        });
+        result
+    }
+
+    /// Adds the player to the world once the server has made room for it, unless the server
+    /// already put back where the player was last time. The client is told the `session` to
+    /// resume should its connection drop.
+    async fn accept(&mut self, session: String) {
+        let username = self.username.clone().unwrap_or_default();
+        // Nothing to play in once the simulation stopped, the server is going down
+        let Some(Joined {
+            tunables,
+            environment,
+            scoreboard,
+        }) = self.world.join(self.client_id, username, self.color).await
+        else {
+            return;
+        };
+
+        // The client signs what it sends from the moment it reads our half of the exchange
+        if let Some((public_key, key)) = self.signing.take() {
+            let _ = self
+                .connection
+                .send(&ServerMessage::SigningAgreed(public_key))
+                .await;
+            self.connection.verify(key);
+        }
+        let _ = self
+            .connection
+            .send(&ServerMessage::ConnectionAccepted {
+                id: self.client_id,
+                username: self.username.clone().unwrap_or_default(),
+                session,
+            })
+            .await;
+        let _ = self
+            .connection
+            .send(&ServerMessage::UpdateTunables(tunables))
+            .await;
+        let _ = self
+            .connection
+            .send(&ServerMessage::UpdateObjects(environment))
+            .await;
+        let _ = self
+            .connection
+            .send(&ServerMessage::UpdateScoreboard(scoreboard))
+            .await;
+
+        self.accepted = true;
+    }
+
+    /// Asks the server to let the client play as `username` in the lobby it asked for
+    fn join(&mut self, username: String) {
+        let (placed, placement) = oneshot::channel();
+        self.placed = Some(placement);
+        let _ = self.tx.send(ServerCommand::Join {
+            id: self.client_id,
+            username,
+            lobby: self.requested_lobby.clone(),
+            placed,
+        });
+    }
+
+    /// Whether the client asked to join, under a new name or a session it resumes
+    fn joining(&self) -> bool {
+        self.username.is_some() || self.resuming.is_some()
+    }
+
+    async fn process(&mut self) -> Result<()> {
+        let timeout = Duration::from_secs(self.server_config.client_timeout);
+        let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+
+        match tokio::time::timeout(timeout, self.connection.establish()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                println!("Client {} failed the TLS handshake: {e}", self.client_id);
+                return Err(e);
+            }
+            Err(_) => {
+                println!(
+                    "Client {} timed out during the TLS handshake",
+                    self.client_id
+                );
+                return Ok(());
+            }
+        }
 
         loop {
             select! {
-                client_message = ClientMessage::read_from_tcp_stream(&mut self.stream, &mut buffer) => {
-                    match client_message? {
-                        ClientMessage::Ping =>{
-                            let _ = self.tx.send(ServerCommand::Broadcast(ServerMessage::Ping));
+                client_message = self.connection.recv() => {
+                    let client_message = client_message?;
+                    self.last_seen = Instant::now();
+                    match client_message {
+                        ClientMessage::Ping { client_time } => {
+                            let _ = self.connection.send(&ServerMessage::Pong {
+                                client_time,
+                                server_time: self.started.elapsed().as_secs_f64(),
+                            }).await;
+                        },
+                        ClientMessage::Pong => {
+                            if let Some(sent) = self.ping_sent.take() {
+                                let _ = self.tx.send(ServerCommand::RoundTrip { id: self.client_id, rtt: sent.elapsed() });
+                            }
+                        },
+                        ClientMessage::Hello(version) => {
+                            if !PROTOCOL_VERSION.is_compatible(&version) {
+                                println!("Client {} speaks protocol {version}, this server {PROTOCOL_VERSION}", self.client_id);
+                                let _ = self.connection.send(&ServerMessage::IncompatibleVersion(PROTOCOL_VERSION)).await;
+                                break;
+                            }
+                            self.version = Some(version);
                         },
                         ClientMessage::Connect(username, password) => {
-                            // Check if the password is correct
-                            if self.server_config.password.is_none() || password == self.server_config.password.clone().unwrap() {
-                                // Create a new player and add it to the world
-                                let new_player = Player {
-                                    username,
-                                    color: Color::random(), // Default color
-                                    pos: Vec2::ZERO,
-                                    vel: Vec2::ZERO,
-                                };
-                                let mut world = self.world.lock().await;
-                                world.entities.players.insert(self.client_id, new_player);
-
-                                let _ = ServerMessage::ConnectionAccepted(self.client_id).write_to_tcp_stream(&mut self.stream).await;
-                                let _ = self.tx.send(ServerCommand::UpdateEntities);
-
-                                self.accepted = true;
+                            if self.joining() {
+                                continue;
+                            }
+                            // Clients that never said hello predate the handshake
+                            if self.version.is_none() {
+                                println!("Client {} did not say which protocol it speaks", self.client_id);
+                                let _ = self.connection.send(&ServerMessage::IncompatibleVersion(PROTOCOL_VERSION)).await;
+                                break;
                             }
+                            if self.signing.is_none() && !self.server_config.allow_unsigned {
+                                println!("Client {} does not sign its messages", self.client_id);
+                                let _ = self.connection.send(&ServerMessage::IncompatibleVersion(PROTOCOL_VERSION)).await;
+                                break;
+                            }
+                            // A wrong password ends the connection, so the client is not left waiting
+                            if self.server_config.password.as_ref().is_some_and(|expected| *expected != password) {
+                                println!("Client {} gave a wrong password", self.client_id);
+                                let _ = self.connection.send(&ServerMessage::PasswordFailed).await;
+                                break;
+                            }
+                            // The server answers with ConnectionAccepted once there is room
+                            self.username = Some(username.clone());
+                            self.join(username);
                         },
-                        ClientMessage::NotifyUpdatePlayer(player) =>{
-                            // Update the player in the world state
-                            let mut world = self.world.lock().await;
-                            world.entities.players.insert(self.client_id, player);
-
-                            // Broadcast updated players to all clients
-                            let _ = self.tx.send(ServerCommand::UpdateEntities);
+                        ClientMessage::Resume(session) => {
+                            if self.joining() {
+                                continue;
+                            }
+                            if self.version.is_none() {
+                                println!("Client {} did not say which protocol it speaks", self.client_id);
+                                let _ = self.connection.send(&ServerMessage::IncompatibleVersion(PROTOCOL_VERSION)).await;
+                                break;
+                            }
+                            if self.signing.is_none() && !self.server_config.allow_unsigned {
+                                println!("Client {} does not sign its messages", self.client_id);
+                                let _ = self.connection.send(&ServerMessage::IncompatibleVersion(PROTOCOL_VERSION)).await;
+                                break;
+                            }
+                            // The password was checked when the session began. The server answers
+                            // once the session's previous connection, if still open, has closed,
+                            // which the loop waits for alongside everything else
+                            let (reply, resumed) = oneshot::channel();
+                            self.resuming = Some(resumed);
+                            let _ = self.tx.send(ServerCommand::Resume { id: self.client_id, session, reply });
+                        },
+                        ClientMessage::MoveInput { seq, dir } => {
+                            if !self.accepted {
+                                continue;
+                            }
+                            if !dir.is_finite() {
+                                println!("Client {} sent a movement that is not a number", self.client_id);
+                                continue;
+                            }
+                            // The moved player reaches the lobby's clients with the next snapshot
+                            let dir = physics::clamp_to_unit(dir);
+                            self.world.send(WorldCommand::Input { id: self.client_id, seq, dir });
+                        },
+                        ClientMessage::NotifyShot { dir, kind } => {
+                            if self.accepted {
+                                self.world.send(WorldCommand::Shoot { id: self.client_id, dir, kind });
+                            }
+                        },
+                        ClientMessage::Reload { kind } => {
+                            if self.accepted {
+                                self.world.send(WorldCommand::Reload { id: self.client_id, kind });
+                            }
+                        },
+                        ClientMessage::Chat(text) => {
+                            // Relay the trimmed line to everyone in the lobby, ignoring clients that
+                            // never joined
+                            let text: String = text.trim().chars().take(MAX_CHAT_LENGTH).collect();
+                            if self.accepted && !text.is_empty() {
+                                println!("[chat] {}: {}", self.client_id, text);
+                                let _ = self.tx.send(ServerCommand::Broadcast {
+                                    lobby: self.lobby,
+                                    msg: ServerMessage::ChatBroadcast {
+                                        sender_id: self.client_id,
+                                        text,
+                                        timestamp: time::unix_millis(),
+                                    },
+                                });
+                            }
+                        },
+                        ClientMessage::QueryStatus => {
+                            // The server answers through our channel, which ends the connection
+                            let _ = self.tx.send(ServerCommand::QueryStatus { id: self.client_id });
+                        },
+                        ClientMessage::PreferColor(color) => {
+                            self.color = color.clamped();
+                        },
+                        ClientMessage::SelectLobby(name) => {
+                            // The lobby cannot change once the client asked to join
+                            if self.joining() {
+                                continue;
+                            }
+                            // The server tells the client if there is no such lobby when it joins
+                            self.requested_lobby = Some(name);
+                        },
+                        ClientMessage::AcceptCompression(compression) => {
+                            if !self.server_config.no_compression {
+                                self.connection.compress(compression);
+                            }
+                        },
+                        ClientMessage::QualityReport { snapshot_loss, fps } => {
+                            if let Some(divisor) = self.quality.report(snapshot_loss, fps) {
+                                println!("Client {} gets one snapshot in {divisor}, losing {:.0}% at {fps:.0} fps", self.client_id, snapshot_loss * 100.0);
+                            }
+                        },
+                        ClientMessage::ProposeSigning(peer) => {
+                            // The key cannot change once the client asked to join
+                            if self.joining() {
+                                continue;
+                            }
+                            match auth::respond(&peer) {
+                                Ok(agreed) => self.signing = Some(agreed),
+                                Err(e) => {
+                                    println!("Client {} proposed signing with a bad key: {e}", self.client_id);
+                                    break;
+                                }
+                            }
                         },
                         ClientMessage::Disconnect => {
-                            let mut world = self.world.lock().await;
-                            world.entities.players.remove(&self.client_id);
-
-                            let _ = self.tx.send(ServerCommand::UpdateEntities);
+                            let _ = self.connection.acknowledge().await;
                             break;
-                        },
+                        }
+                    }
+                }
+                resumed = resumed(&mut self.resuming), if self.resuming.is_some() => {
+                    self.resuming = None;
+                    match resumed {
+                        Ok(Some(Resumed { id, username, lobby })) => {
+                            self.client_id = id;
+                            self.username = Some(username.clone());
+                            self.requested_lobby = Some(lobby);
+                            self.join(username);
+                        }
+                        _ => {
+                            let _ = self.connection.send(&ServerMessage::SessionExpired).await;
+                        }
                     }
                 }
                 Some(msg) = self.rx.recv() => {
-                    if self.accepted {
-                        let _ = msg.write_to_tcp_stream(&mut self.stream).await;
+                    match msg {
+                        ServerMessage::ConnectionAccepted { username, session, .. } => {
+                            // The server places the client before accepting it
+                            if let Some(placed) = self.placed.take()
+                                && let Ok(Placement { lobby, world }) = placed.await
+                            {
+                                self.lobby = lobby;
+                                self.world = world;
+                            }
+                            self.username = Some(username);
+                            self.accept(session).await;
+                        }
+                        ServerMessage::UpdateEntities { .. } => {
+                            if self.quality.forward(self.rx.len()) {
+                                let _ = self.connection.send(&msg).await;
+                            }
+                        }
+                        ServerMessage::ServerFull | ServerMessage::Banned | ServerMessage::Disconnect | ServerMessage::Redirect { .. } | ServerMessage::Status(_) => {
+                            let _ = self.connection.send(&msg).await;
+                            break;
+                        }
+                        msg => {
+                            let _ = self.connection.send(&msg).await;
+                        }
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if self.last_seen.elapsed() > timeout {
+                        println!("Client {} timed out", self.client_id);
+                        break;
                     }
+                    // An unanswered heartbeat is forgotten, a lost pong only costs one sample
+                    self.ping_sent = Some(Instant::now());
+                    let _ = self.connection.send(&ServerMessage::Ping).await;
                 }
             }
         }
@@ -121,3 +420,13 @@ impl ClientHandle {
         Ok(())
     }
 }
+
+/// Waits for the answer to a session the client asked to resume
+async fn resumed(
+    resuming: &mut Option<oneshot::Receiver<Option<Resumed>>>,
+) -> Result<Option<Resumed>, oneshot::error::RecvError> {
+    match resuming {
+        Some(resumed) => resumed.await,
+        None => std::future::pending().await,
+    }
+}
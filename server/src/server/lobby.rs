@@ -0,0 +1,82 @@
+//! Lobbies, the independent worlds one server hosts.
+//!
+//! Every lobby plays the same map and mode in a world of its own, owned and stepped by a
+//! [`Simulation`] of its own. The listener, the slots and the join queue are shared: clients pick a lobby by name
+//! with [`ClientMessage::SelectLobby`] before they join, and are placed by the server otherwise.
+//! Snapshots, events and chat only go to the clients in the lobby they happened in.
+//!
+//! Besides `main`, operators name lobbies that stay open with `--lobby`. With `--lobby-size` each
+//! lobby holds that many players, and when all of them are full the server opens another for the
+//! next player, closing it again once everyone has left. Status queries list the lobbies and how
+//! full they are, so a matchmaker can send players where there is room.
+//!
+//! The main lobby is the one the world file is loaded into, and the one the hash log, the
+//! dashboard's map and crash reports of the server as a whole follow.
+//!
+//! [`ClientMessage::SelectLobby`]: common::message::ClientMessage::SelectLobby
+//! [`Simulation`]: super::simulation::Simulation
+use anyhow::Result;
+use common::{
+    tunables::Tunables,
+    world::{
+        GameWorld,
+        environment::Environment,
+        pickups::{Pickup, PickupKind},
+        rng::GameRng,
+    },
+};
+use tokio::sync::oneshot;
+
+use super::simulation::World;
+use crate::{cli::ServerConfig, loot::Loot};
+
+/// Id of the lobby every server hosts
+pub const MAIN_LOBBY: LobbyId = 0;
+/// Name of the main lobby
+pub const MAIN_LOBBY_NAME: &str = "main";
+
+/// Lobbies are numbered as they open, ids are not used again
+pub type LobbyId = u64;
+
+/// One of the worlds the server hosts
+pub struct Lobby {
+    pub name: String,
+    pub world: World,
+    /// Opened because the others were full, and closed again once empty
+    pub automatic: bool,
+    /// Stops the lobby's simulation, once it runs
+    pub stop: Option<oneshot::Sender<()>>,
+}
+
+/// Where the server placed a joining client, told to its handle before the client is accepted
+pub struct Placement {
+    pub lobby: LobbyId,
+    pub world: World,
+}
+
+/// Fresh world of a lobby, with the map's pickups and those rolled from its loot tables
+pub fn world(
+    config: &ServerConfig,
+    tunables: Tunables,
+    environment: Environment,
+    tick: u64,
+    mut rng: GameRng,
+) -> Result<(GameWorld, Loot)> {
+    let tiles = &environment.tiles;
+    let mut entities = GameWorld::new().entities;
+    entities.pickups = Pickup::at(&tiles.pickup_points, PickupKind::Armor);
+    entities
+        .pickups
+        .extend(Pickup::at(&tiles.ammo_points, PickupKind::Ammo));
+    let respawn = tunables.combat.pickup_respawn;
+    let loot = Loot::from_config(config, respawn, &mut entities.pickups, &mut rng)?;
+    let world = GameWorld {
+        tunables,
+        environment,
+        entities,
+        tick,
+        rng,
+        ..GameWorld::new()
+    };
+    Ok((world, loot))
+}
@@ -0,0 +1,178 @@
+//! Numbers for monitoring, served in the Prometheus text format at `/metrics` when the server
+//! starts with `--metrics-port`:
+//!
+//! - `game_server_tick_duration_seconds`, a histogram of how long simulation steps take, over
+//!   every lobby
+//! - `game_server_sent_bytes_total`, bytes sent to clients, whose rate is the outgoing bandwidth
+//! - `game_server_connected_clients`, clients connected whether they play, wait or just connected
+//! - `game_server_messages_sent_total` and `game_server_messages_received_total`, messages by
+//!   `type`
+//!
+//! They are counted whether or not anyone scrapes them. Like the dashboard, the endpoint answers
+//! anyone who can reach it, but only ever reads.
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{io::AsyncWriteExt, net::TcpListener, time};
+
+use super::dashboard::{REQUEST_TIMEOUT, Response, read_request};
+
+/// Upper bounds of the tick duration buckets, in seconds. A tick at 60 Hz has 16.7 ms
+const TICK_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.0167, 0.025, 0.05];
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Ticks that took up to each of [`TICK_BUCKETS`], and all of them
+    tick_buckets: [AtomicU64; TICK_BUCKETS.len()],
+    ticks: AtomicU64,
+    tick_nanos: AtomicU64,
+    sent_bytes: AtomicU64,
+    connected_clients: AtomicU64,
+    sent: Mutex<BTreeMap<&'static str, u64>>,
+    received: Mutex<BTreeMap<&'static str, u64>>,
+}
+impl Metrics {
+    pub fn record_tick(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, bound) in self.tick_buckets.iter().zip(TICK_BUCKETS) {
+            if secs <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.tick_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// A message of type `name` went out to a client in `bytes` bytes
+    pub fn record_sent(&self, name: &'static str, bytes: usize) {
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        Self::count(&self.sent, name);
+    }
+
+    pub fn record_received(&self, name: &'static str) {
+        Self::count(&self.received, name);
+    }
+
+    pub fn set_connected_clients(&self, count: usize) {
+        self.connected_clients
+            .store(count as u64, Ordering::Relaxed);
+    }
+
+    fn count(counts: &Mutex<BTreeMap<&'static str, u64>>, name: &'static str) {
+        let mut counts = counts.lock().unwrap_or_else(|e| e.into_inner());
+        *counts.entry(name).or_default() += 1;
+    }
+
+    /// Every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed);
+
+        out.push_str("# HELP game_server_tick_duration_seconds Time a simulation step takes\n");
+        out.push_str("# TYPE game_server_tick_duration_seconds histogram\n");
+        for (bucket, bound) in self.tick_buckets.iter().zip(TICK_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "game_server_tick_duration_seconds_bucket{{le=\"{bound}\"}} {}",
+                load(bucket)
+            );
+        }
+        let ticks = load(&self.ticks);
+        let _ = writeln!(
+            out,
+            "game_server_tick_duration_seconds_bucket{{le=\"+Inf\"}} {ticks}"
+        );
+        let _ = writeln!(
+            out,
+            "game_server_tick_duration_seconds_sum {}",
+            load(&self.tick_nanos) as f64 / 1e9
+        );
+        let _ = writeln!(out, "game_server_tick_duration_seconds_count {ticks}");
+
+        out.push_str("# HELP game_server_sent_bytes_total Bytes sent to clients\n");
+        out.push_str("# TYPE game_server_sent_bytes_total counter\n");
+        let _ = writeln!(
+            out,
+            "game_server_sent_bytes_total {}",
+            load(&self.sent_bytes)
+        );
+
+        out.push_str("# HELP game_server_connected_clients Clients connected to the server\n");
+        out.push_str("# TYPE game_server_connected_clients gauge\n");
+        let _ = writeln!(
+            out,
+            "game_server_connected_clients {}",
+            load(&self.connected_clients)
+        );
+
+        for (metric, help, counts) in [
+            (
+                "game_server_messages_sent_total",
+                "Messages sent to clients",
+                &self.sent,
+            ),
+            (
+                "game_server_messages_received_total",
+                "Messages received from clients",
+                &self.received,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {metric} {help}");
+            let _ = writeln!(out, "# TYPE {metric} counter");
+            let counts = counts.lock().unwrap_or_else(|e| e.into_inner());
+            for (name, count) in counts.iter() {
+                let _ = writeln!(out, "{metric}{{type=\"{name}\"}} {count}");
+            }
+        }
+        out
+    }
+}
+
+/// Serves the metrics on `port` of every interface in the background, metrics that cannot be
+/// served leave the server running without them.
+pub fn spawn(port: u16, metrics: Arc<Metrics>) {
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    tokio::spawn(async move {
+        let listener = match TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Could not serve metrics on {addr}: {e}");
+                return;
+            }
+        };
+        println!("Metrics on http://{addr}/metrics");
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                let serve = async {
+                    let response = match read_request(&mut stream).await {
+                        Ok(request) if request.method == "GET" && request.path == "/metrics" => {
+                            Response {
+                                status: "200 OK",
+                                content_type: "text/plain; version=0.0.4; charset=utf-8",
+                                body: metrics.render(),
+                            }
+                        }
+                        Ok(request) => Response::text(
+                            "404 Not Found",
+                            format!("No {} {}", request.method, request.path),
+                        ),
+                        Err(e) => Response::text("400 Bad Request", e.to_string()),
+                    };
+                    stream.write_all(&response.encode()).await?;
+                    stream.shutdown().await
+                };
+                let _ = time::timeout(REQUEST_TIMEOUT, serve).await;
+            });
+        }
+    });
+}
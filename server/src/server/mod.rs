@@ -1,148 +1,1491 @@
 //! Server module for handling multiplayer game connections, world state, and client communication.
 //!
-//! This module provides the [`Server`] struct, which manages TCP connections from clients,
-//! maintains the shared game world state, and broadcasts updates to all connected clients.
+//! This module provides the [`Server`] struct, which manages TCP or UDP connections from clients
+//! and broadcasts updates to all connected clients. The game world belongs to its simulation
+//! task, which the client handles and the server loop send commands to, see [`simulation`].
 //! It uses asynchronous Tokio primitives for concurrency and message passing between the server
 //! and client handlers. The server supports a configurable maximum number of clients and
-//! periodically updates and synchronizes the world state.
+//! periodically updates and synchronizes the world state. Hosts can manage it while it runs
+//! through the admin console on stdin, and stop it with Ctrl-C or SIGTERM, which tells the
+//! clients, writes the world and stats and waits for the client handles to finish, see
+//! [`Server::run`]. When a client handle or the simulation panics, the state of the server is
+//! written to the diagnostics directory, see [`diagnostics`]. With `--discovery`, clients on the
+//! local network find the server without its address, see [`discovery`]. With an interest radius,
+//! clients are only sent the entities near their player, found through the grid of
+//! [`common::world::grid`]. Operators can also manage it from a browser, see [`dashboard`]. One
+//! server can host several worlds at once, each with its own players and simulation, see
+//! [`lobby`].
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use std::{
-    net::SocketAddr,
+    collections::{BTreeMap, HashMap, VecDeque},
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
     sync::{
         Arc,
         atomic::{AtomicU64, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
-    net::{TcpListener, ToSocketAddrs},
+    net::ToSocketAddrs,
     select,
     sync::{
         Mutex,
         mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        oneshot,
     },
+    task::{self as tokio_task, AbortHandle, JoinHandle, JoinSet},
     time,
 };
 
+mod connection;
+mod console;
+mod dashboard;
+mod diagnostics;
+mod discovery;
 mod handle;
+mod lobby;
+mod metrics;
+mod offline;
+mod quality;
+mod session;
+mod signals;
+mod simulation;
 
-use crate::cli::ServerConfig;
-use common::{message::ServerMessage, world::GameWorld};
+use crate::{
+    bans::{Ban, BanList},
+    cli::ServerConfig,
+    loot::Loot,
+    patch::EnvironmentPatch,
+    stats::{DecayConfig, StatsStore},
+    storage::{self, Record, Storage},
+    tunables::ResolvedTunables,
+};
+use common::{
+    discovery::{LobbyStatus, ServerStatus},
+    message::{MAX_USERNAME_LENGTH, ServerMessage, announcement::Announcement, tls},
+    time as unix_time,
+    vec::Vec2,
+    world::{
+        GameWorld,
+        entities::{Entities, Player},
+        rng::GameRng,
+        scoreboard::Score,
+    },
+};
+use connection::{Connection, Listener};
+use console::AdminCommand;
+use dashboard::{ChatLine, ClientState, DashboardState, PlayerState};
+use diagnostics::{CommandHistory, HashLog};
 use handle::ClientHandle;
+use lobby::{Lobby, LobbyId, MAIN_LOBBY, MAIN_LOBBY_NAME, Placement};
+use metrics::Metrics;
+use offline::OfflinePlayers;
+use session::{DroppedSession, Sessions};
+use simulation::{Finished, Simulation, World, WorldCommand};
 
 /// Commands that the server can execute that a handle would otherwise not.
 enum ServerCommand {
-    Broadcast(ServerMessage),
-    UpdateEntities,
+    /// Sends a message to the clients playing in `lobby`
+    Broadcast { lobby: LobbyId, msg: ServerMessage },
+    /// A client gave the right password and wants to play, in the lobby it named if any. Where
+    /// it is placed is told to `placed` before it is accepted
+    Join {
+        id: u64,
+        username: String,
+        lobby: Option<String>,
+        placed: oneshot::Sender<Placement>,
+    },
+    /// A client handle has finished, so the client is forgotten. Its player is kept for when it
+    /// comes back, and its score for when it resumes the session
+    ClientDisconnected {
+        id: u64,
+        player: Option<Player>,
+        score: Option<Score>,
+    },
+    /// A client wants to play on as the player of a dropped connection, told who that is or none
+    /// when the session is unknown or expired
+    Resume {
+        id: u64,
+        session: String,
+        reply: oneshot::Sender<Option<Resumed>>,
+    },
+    /// A client handle panicked before it could clean up after itself
+    HandlePanicked { id: u64, message: String },
+    /// A client asked what the server is playing instead of joining
+    QueryStatus { id: u64 },
+    /// LAN discovery asks what to answer probes with
+    Status(oneshot::Sender<ServerStatus>),
+    /// A client answered a heartbeat after `rtt`
+    RoundTrip { id: u64, rtt: Duration },
+    /// Time to write the world file
+    Autosave,
+    /// Typed by the host in the admin console, or clicked in the dashboard
+    Admin(AdminCommand),
+    /// The dashboard asks what to show
+    Dashboard(oneshot::Sender<DashboardState>),
+}
+
+/// Who a client that resumed a session plays as
+struct Resumed {
+    id: u64,
+    username: String,
+    /// Name of the lobby the player was in, it goes back there if it is still open and has room
+    lobby: String,
+}
+
+/// A client waiting for the connection whose session it resumes to close
+struct PendingResume {
+    id: u64,
+    session: String,
+    reply: oneshot::Sender<Option<Resumed>>,
+}
+
+/// Name given to clients that join without one
+const DEFAULT_USERNAME: &str = "Player";
+
+/// Chat lines and announcements kept for the dashboard
+const CHAT_LOG_LENGTH: usize = 100;
+
+/// Longest the server waits for clients to be told it is stopping
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+
+/// What the server keeps about a connected client
+struct ClientInfo {
+    /// Id of the client, shared with what watches its handle. It changes when the client
+    /// resumes a session
+    id: Arc<AtomicU64>,
+    /// Sends a message to the client's handle
+    tx: UnboundedSender<ServerMessage>,
+    addr: SocketAddr,
+    /// Name the client asked to join with, if it got that far
+    username: Option<String>,
+    /// Lobby the client plays in, the main one until it is placed
+    lobby: LobbyId,
+    /// Lobby the client asked to play in, until it is placed
+    requested_lobby: Option<String>,
+    /// Tells the client's handle where it was placed
+    placed: Option<oneshot::Sender<Placement>>,
+    /// Score of the session the client resumed, put back once it is placed
+    resumed_score: Option<Score>,
+    /// Whether the client has joined the game, only playing clients receive broadcasts
+    playing: bool,
+    /// Whether the client is on the priority list and may use the reserved slots
+    priority: bool,
+    /// Token of the client's session, once it was accepted or resumed one
+    session: Option<String>,
+    /// Last round trip to the client, once it answered a heartbeat
+    rtt: Option<Duration>,
+    /// Finishes once the client's handle did, and aborts the handle
+    task: JoinHandle<()>,
+    abort: AbortHandle,
 }
 
 /// Server struct that deploys handles for each client connection and manages the game world.
 pub struct Server {
-    listener: TcpListener,
+    listener: Listener,
 
     /* Identification and settings */
     player_id_counter: Arc<AtomicU64>,
     server_config: Arc<ServerConfig>,
 
     /* Communication between server and client handles */
-    client_txs: Arc<Mutex<Vec<UnboundedSender<ServerMessage>>>>,
+    /// Every connected client, registered when accepted and removed when its handle finishes
+    clients: HashMap<u64, ClientInfo>,
+    /// Clients that asked to join while the server was full, in arrival order
+    queue: VecDeque<u64>,
     command_rx: UnboundedReceiver<ServerCommand>,
     command_tx: UnboundedSender<ServerCommand>, // Used for copying to handles
 
-    world: Arc<Mutex<GameWorld>>,
+    /// Worlds the server hosts, by id
+    lobbies: BTreeMap<LobbyId, Lobby>,
+    /// Id of the next lobby to open
+    next_lobby: LobbyId,
+    /// Steps the world of each lobby, the task ids tell which lobby a finished one stepped
+    simulations: JoinSet<Finished>,
+    simulation_lobbies: HashMap<tokio_task::Id, LobbyId>,
+    /// Map objects and the edits made to them, the world of every lobby holds the result
+    environment: EnvironmentPatch,
+    /// World of the main lobby, the pickups rolled from the map's loot tables for it and the
+    /// commands sent to it, handed to its simulation when it starts
+    main_world: Option<(GameWorld, Loot, UnboundedReceiver<WorldCommand>)>,
+    /// Gameplay settings every lobby plays by, with where each came from
+    tunables: ResolvedTunables,
+    /// Shared by the simulations of the lobbies
+    stats: Arc<Mutex<StatsStore>>,
+    /// Taken by the simulation of the main lobby
+    hash_log: Option<HashLog>,
+    tick_rate: f64,
+    /// Simulation steps and snapshots have their own rates, every so many steps get a snapshot
+    snapshot_every: u64,
+    /// Players that left or were loaded from the world file, put back where they were when they
+    /// join again
+    offline_players: OfflinePlayers,
+    /// Sessions of dropped connections, for their clients to resume
+    sessions: Sessions,
+    /// Clients resuming a session whose connection is still closing, by the id of that connection
+    resuming: HashMap<u64, PendingResume>,
+    /// When the server started, snapshots carry the time since
+    started: Instant,
+    /// Recent commands, written out along with the world when something goes wrong
+    history: CommandHistory,
+    /// Recent chat and announcements, for the dashboard
+    chat_log: VecDeque<ChatLine>,
+    /// Usernames and addresses refused, by an operator or the ban list file
+    bans: BanList,
+    /// Where the stats, bans and world are kept
+    storage: Arc<dyn Storage>,
+    /// Counted by the connections and simulations, served with `--metrics-port`
+    metrics: Arc<Metrics>,
 }
 
 impl Server {
     pub async fn init<T: ToSocketAddrs>(addr: T, server_config: ServerConfig) -> Result<Self> {
-        let listener = TcpListener::bind(addr).await?;
+        let tls = match (&server_config.tls_cert, &server_config.tls_key) {
+            (Some(cert), Some(key)) => Some(tls::acceptor(cert, key)?),
+            _ => None,
+        };
+        let metrics = Arc::new(Metrics::default());
+        let listener = Listener::bind(addr, server_config.transport, tls, metrics.clone()).await?;
         let (tx, rx) = unbounded_channel();
+        let tunables = ResolvedTunables::from_config(&server_config)?;
+        for line in tunables.overrides() {
+            println!("Tunable {line}");
+        }
+        let storage = storage::from_config(&server_config)?;
+        if server_config.autosave_interval.is_some() && storage.location(Record::World).is_none() {
+            bail!("Nothing to autosave to, start with --world-file or --database");
+        }
+        let saved = storage.load_world()?;
+        if let (Some(world), Some(location)) = (&saved, storage.location(Record::World)) {
+            println!(
+                "Loaded world from {location} with {} player(s)",
+                world.entities.players.len()
+            );
+        }
+        let environment = EnvironmentPatch::from_config(
+            &server_config,
+            saved.as_ref().map(|world| world.environment.clone()),
+        )?;
+        let tiles = &environment.environment().tiles;
+        if tiles.width * tiles.height > 0 {
+            println!(
+                "Map: {}x{} tiles, {} spawn point(s), {} pickup(s)",
+                tiles.width,
+                tiles.height,
+                tiles.spawn_points.len(),
+                tiles.pickup_points.len() + tiles.ammo_points.len()
+            );
+        }
+        if let (added, removed) = environment.counts()
+            && added + removed > 0
+        {
+            println!("Map edits: {added} object(s) added, {removed} removed");
+        }
+
+        let mut names = vec![MAIN_LOBBY_NAME];
+        for name in &server_config.lobbies {
+            let name = name.trim();
+            if name.is_empty() {
+                bail!("Lobbies need a name");
+            }
+            if names.contains(&name) {
+                bail!("There is more than one lobby called `{name}`");
+            }
+            names.push(name);
+        }
+        if names.len() > 1 {
+            println!("Lobbies: {}", names.join(", "));
+        }
+        if server_config.lobby_size == Some(0) {
+            bail!("Lobbies need room for at least one player");
+        }
+
+        let (tick, rng, players) = match saved {
+            Some(world) => {
+                // Numbered in the order they left, see [`offline`]
+                let mut players: Vec<_> = world.entities.players.into_iter().collect();
+                players.sort_by_key(|(id, _)| *id);
+                (world.tick, world.rng, players)
+            }
+            None => (0, GameRng::default(), Vec::new()),
+        };
+        let offline_players = OfflinePlayers::new(
+            server_config.offline_players,
+            players.into_iter().map(|(_, player)| player),
+        );
+        // A seed given on the command line wins over the one the world was saved with
+        let rng = server_config.seed.map(GameRng::seeded).unwrap_or(rng);
+        let (world, loot) = lobby::world(
+            &server_config,
+            tunables.tunables.clone(),
+            environment.environment(),
+            tick,
+            rng,
+        )?;
+        if loot.spawn_count() > 0 {
+            println!("Loot: {} spawn(s)", loot.spawn_count());
+        }
+        let (handle, commands) = World::channel();
+        let main = Lobby {
+            name: String::from(MAIN_LOBBY_NAME),
+            world: handle,
+            automatic: false,
+            stop: None,
+        };
 
+        let decay = DecayConfig {
+            per_day: server_config.rating_decay,
+            floor: server_config.rating_decay_floor,
+        };
+        let stats = StatsStore::load(storage.clone(), decay)?;
+        if let Some(location) = storage.location(Record::Stats) {
+            println!(
+                "Stats of {} player(s) kept in {location}",
+                stats.player_count()
+            );
+        }
+        let hash_log = server_config
+            .hash_log
+            .as_deref()
+            .map(HashLog::create)
+            .transpose()?;
+        // Simulation steps and snapshots have their own rates, every so many steps get a snapshot
+        let tick_rate = tunables.tunables.tick_rate;
+        let snapshot_every = match server_config.broadcast_rate {
+            Some(rate) if rate > 0.0 => (tick_rate / rate).round().max(1.0) as u64,
+            Some(rate) => bail!("The broadcast rate must be above 0, got {rate}"),
+            None => 1,
+        };
+
+        let bans = BanList::load(storage.clone())?;
+        if let Some(location) = storage.location(Record::Bans) {
+            let count = bans.entries().count();
+            println!("{count} ban(s) kept in {location}");
+        }
+
+        let started = Instant::now();
         Ok(Self {
             server_config: Arc::new(server_config),
             listener,
-            client_txs: Arc::new(Mutex::new(vec![])),
+            clients: HashMap::new(),
+            queue: VecDeque::new(),
             command_rx: rx,
             command_tx: tx,
 
-            world: Arc::new(Mutex::new(GameWorld::new())),
+            lobbies: BTreeMap::from([(MAIN_LOBBY, main)]),
+            next_lobby: MAIN_LOBBY + 1,
+            simulations: JoinSet::new(),
+            simulation_lobbies: HashMap::new(),
+            environment,
+            main_world: Some((world, loot, commands)),
+            tunables,
+            stats: Arc::new(Mutex::new(stats)),
+            hash_log,
+            tick_rate,
+            snapshot_every,
+            offline_players,
+            sessions: Sessions::default(),
+            resuming: HashMap::new(),
             player_id_counter: Arc::new(AtomicU64::new(1)),
+            started,
+            history: CommandHistory::new(started),
+            chat_log: VecDeque::new(),
+            bans,
+            storage,
+            metrics,
         })
     }
 
-    /// Starts the server, accepting connections and handling client messages.
-    /// This method runs indefinitely, processing incoming connections and messages.
+    /// Starts a server on a free port of this machine in the background, for a program that plays
+    /// on it itself, such as the client in single player. Returns the address it listens on and
+    /// the task running it. Unlike [`Server::run`] it reads no admin console, stdin belongs to the
+    /// program.
+    pub async fn run_in_background(
+        server_config: ServerConfig,
+    ) -> Result<(SocketAddr, JoinHandle<Result<()>>)> {
+        let mut server = Self::init((Ipv4Addr::LOCALHOST, 0), server_config).await?;
+        let addr = server
+            .get_address()
+            .ok_or_else(|| anyhow!("The embedded server has no address"))?;
+        Ok((addr, tokio::spawn(async move { server.serve().await })))
+    }
+
+    /// Starts the server, accepting connections and handling client messages along with the admin
+    /// console. Runs until stopped from the console or dashboard, or by SIGINT or SIGTERM, then
+    /// disconnects the clients, writes the world and stats to disk and waits for the client
+    /// handles to finish before returning.
     pub async fn run(&mut self) -> Result<()> {
-        let world = self.world.clone();
-        let command_tx = self.command_tx.clone();
-        tokio::spawn(async move {
-            let mut interval = time::interval(Duration::from_secs_f64(1.0 / 60.0));
-            loop {
-                interval.tick().await;
-
-                {
-                    let mut w = world.lock().await;
-                    w.entities.update(0.05); // advance the world state by 50 ms (or whatever dt)
-                }
-                // Broadcast updated world to clients
-                // (Here you can customize message type accordingly)
-                if let Err(e) = command_tx.send(ServerCommand::Broadcast(
-                    ServerMessage::UpdateEntities(world.lock().await.entities.clone()),
-                )) {
-                    eprintln!("Failed to broadcast world update: {:?}", e);
+        console::spawn(self.command_tx.clone());
+        signals::spawn(self.command_tx.clone());
+        self.serve().await
+    }
+
+    /// Runs the server until it stops, taking commands from anything but the console
+    async fn serve(&mut self) -> Result<()> {
+        if let Some(addr) = self.server_config.dashboard_addr {
+            let world = self.lobbies[&MAIN_LOBBY].world.clone();
+            dashboard::spawn(addr, self.command_tx.clone(), world);
+        }
+        if let Some(port) = self.server_config.metrics_port {
+            metrics::spawn(port, self.metrics.clone());
+        }
+        if self.server_config.discovery {
+            discovery::spawn(self.command_tx.clone());
+        }
+        if let Some(secs) = self.server_config.autosave_interval {
+            let command_tx = self.command_tx.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(Duration::from_secs(secs.max(1)));
+                interval.tick().await; // The first tick is immediate
+                loop {
+                    interval.tick().await;
+                    if command_tx.send(ServerCommand::Autosave).is_err() {
+                        break;
+                    }
                 }
-            }
-        });
+            });
+        }
 
-        loop {
+        if let Some((world, loot, commands)) = self.main_world.take() {
+            self.start_simulation(MAIN_LOBBY, world, loot, commands);
+        }
+        for name in self.server_config.lobbies.clone() {
+            self.open_lobby(name.trim().to_string(), false).await?;
+        }
+
+        // Worlds of simulations that stopped on their own, saved along with the others
+        let mut worlds = BTreeMap::new();
+        let stopped = loop {
             select! {
                 // Accepts connections and creates new client handles
-                Ok((stream, addr)) = self.listener.accept() => {
+                Ok((connection, addr)) = self.listener.accept() => {
                     println!("New client: {}", addr);
-
-                    if self.client_txs.lock().await.len() < self.server_config.max_clients {
-                        let (tx_to_client, rx_for_client) = unbounded_channel();
-                        let client_command_sender = self.command_tx.clone();
-                        self.client_txs.lock().await.push(tx_to_client.clone());
-
-                        let mut client = ClientHandle::new(
-                            self.player_id_counter.fetch_add(1, Ordering::Relaxed),
-                            self.server_config.clone(),
-                            stream,
-                            client_command_sender,
-                            rx_for_client,
-                            self.world.clone()
-                        );
-
-                        tokio::spawn(async move {
-                            let _ = client.handle().await;
-                        });
-                    }
+                    self.register(connection, addr);
+                }
+                // Nothing can be played without the simulations, so the server stops with any of
+                // them but those of lobbies that were closed
+                Some(result) = self.simulations.join_next_with_id() => {
+                    let (task, result) = match result {
+                        Ok((task, finished)) => (task, Ok(finished)),
+                        Err(e) => (e.id(), Err(e)),
+                    };
+                    let lobby = self.simulation_lobbies.remove(&task).unwrap_or_default();
+                    let Some(name) = self.lobbies.get(&lobby).map(|lobby| lobby.name.clone()) else {
+                        continue;
+                    };
+                    let reason = match result {
+                        Ok(Finished { world, panic }) => {
+                            let reason = match panic {
+                                Some(message) => format!("The simulation of lobby {name} panicked: {message}"),
+                                None => format!("The simulation of lobby {name} stopped"),
+                            };
+                            self.dump_diagnostics(&reason, Some(&world)).await;
+                            worlds.insert(lobby, world);
+                            reason
+                        }
+                        Err(e) => {
+                            let reason = match e.try_into_panic() {
+                                Ok(panic) => format!(
+                                    "The simulation of lobby {name} panicked: {}",
+                                    diagnostics::panic_message(&*panic)
+                                ),
+                                Err(e) => format!("The simulation of lobby {name} stopped: {e}"),
+                            };
+                            self.dump_diagnostics(&reason, None).await;
+                            reason
+                        }
+                    };
+                    break Some(reason);
                 }
                 // Handles commands from server handles
                 Some(cmd) = self.command_rx.recv() => {
+                    self.history.record(&cmd);
                     match cmd {
-                        ServerCommand::Broadcast(msg)=>{
-                            let clients = self.client_txs.lock().await;
-                            for tx in clients.iter() {
-                                let _ = tx.send(msg.clone());
+                        ServerCommand::Broadcast { lobby, msg } => {
+                            self.log_chat(&msg);
+                            self.broadcast(lobby, &msg);
+                        }
+                        ServerCommand::Join { id, username, lobby, placed } => self.join(id, &username, lobby, placed).await,
+                        ServerCommand::ClientDisconnected { id, player, score } => self.unregister(id, player, score).await,
+                        ServerCommand::Resume { id, session, reply } => {
+                            self.resume(PendingResume { id, session, reply }).await;
+                        }
+                        ServerCommand::HandlePanicked { id, message } => self.handle_panicked(id, &message).await,
+                        ServerCommand::QueryStatus { id } => self.answer_status_query(id),
+                        ServerCommand::Status(reply) => {
+                            let _ = reply.send(self.status());
+                        }
+                        ServerCommand::RoundTrip { id, rtt } => {
+                            if let Some(client) = self.clients.get_mut(&id) {
+                                client.rtt = Some(rtt);
                             }
                         }
-                        ServerCommand::UpdateEntities => {
-                            let clients = self.client_txs.lock().await;
-                            let msg = ServerMessage::UpdateEntities(self.world.lock().await.entities.clone());
-                            for tx in clients.iter() {
-                                let _ = tx.send(msg.clone());
+                        ServerCommand::Autosave => {
+                            if let Err(e) = self.save_world(None).await {
+                                eprintln!("Autosave failed: {e}");
                             }
-                        },
+                        }
+                        ServerCommand::Admin(AdminCommand::Stop) => break None,
+                        ServerCommand::Admin(command) => self.admin(command).await,
+                        ServerCommand::Dashboard(reply) => {
+                            let _ = reply.send(self.dashboard_state().await);
+                        }
                     }
                 }
             }
+        };
+
+        self.shutdown(worlds).await;
+        match stopped {
+            Some(reason) => bail!(reason),
+            None => Ok(()),
         }
     }
 }
 impl Server {
+    /// Creates a handle for a new connection and keeps track of the client.
+    /// Whether there is room is decided once the client asks to join.
+    fn register(&mut self, connection: Connection, addr: SocketAddr) {
+        if self.bans.refuses_address(addr.ip()) {
+            // Dropping the connection closes it
+            println!("Refused banned client {addr}");
+            return;
+        }
+        let id = self.player_id_counter.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = unbounded_channel();
+        let mut client = ClientHandle::new(
+            id,
+            self.server_config.clone(),
+            connection,
+            self.command_tx.clone(),
+            rx,
+            self.lobbies[&MAIN_LOBBY].world.clone(),
+            time::Instant::from_std(self.started),
+        );
+        let task = tokio::spawn(async move {
+            let _ = client.handle().await;
+        });
+        let abort = task.abort_handle();
+        // A panicking handle cannot tell the server it is gone, so it is watched from outside
+        let command_tx = self.command_tx.clone();
+        let shared_id = Arc::new(AtomicU64::new(id));
+        let current_id = shared_id.clone();
+        let task = tokio::spawn(async move {
+            if let Err(e) = task.await
+                && e.is_panic()
+            {
+                let message = diagnostics::panic_message(&*e.into_panic());
+                let id = current_id.load(Ordering::Relaxed);
+                let _ = command_tx.send(ServerCommand::HandlePanicked { id, message });
+            }
+        });
+        self.clients.insert(
+            id,
+            ClientInfo {
+                id: shared_id,
+                tx,
+                addr,
+                username: None,
+                lobby: MAIN_LOBBY,
+                requested_lobby: None,
+                placed: None,
+                resumed_score: None,
+                playing: false,
+                priority: false,
+                session: None,
+                rtt: None,
+                task,
+                abort,
+            },
+        );
+        self.metrics.set_connected_clients(self.clients.len());
+    }
+
+    /// Writes what the server was doing when something went wrong to the diagnostics directory,
+    /// along with the world it happened in if there is one.
+    async fn dump_diagnostics(&self, reason: &str, world: Option<&GameWorld>) {
+        eprintln!("{reason}");
+        match diagnostics::dump(
+            &self.server_config.diagnostics_dir,
+            reason,
+            world,
+            &self.history,
+        ) {
+            Ok(path) => eprintln!("Wrote diagnostics to {}", path.display()),
+            Err(e) => eprintln!("Failed to write diagnostics: {e}"),
+        }
+    }
+
+    /// Records the state of the server and cleans up after a client whose handle panicked, the
+    /// other clients play on.
+    async fn handle_panicked(&mut self, id: u64, message: &str) {
+        let lobby = self
+            .clients
+            .get(&id)
+            .map_or(MAIN_LOBBY, |client| client.lobby);
+        let world = self.world_of(lobby).snapshot().await;
+        let reason = format!("Handle of client {id} panicked: {message}");
+        self.dump_diagnostics(&reason, world.as_ref()).await;
+        let (player, score) = self.world_of(lobby).leave(id).await;
+        self.unregister(id, player, score).await;
+    }
+
+    /// Forgets a client whose handle has finished, giving its slot to the queue. Its session is
+    /// kept for the client to resume, along with `score`.
+    async fn unregister(&mut self, id: u64, player: Option<Player>, score: Option<Score>) {
+        let mut left = None;
+        if let Some(client) = self.clients.remove(&id) {
+            self.metrics.set_connected_clients(self.clients.len());
+            left = Some(client.lobby);
+            println!("Client {id} ({}) disconnected", client.addr);
+            let username = client.username.unwrap_or_default();
+            if client.playing {
+                let left = ServerMessage::PlayerLeft {
+                    id,
+                    username: username.clone(),
+                };
+                self.broadcast(client.lobby, &left);
+            }
+            if let Some(session) = client.session {
+                let dropped = DroppedSession {
+                    id,
+                    username,
+                    lobby: self.lobby_name(client.lobby),
+                    score,
+                };
+                let grace = Duration::from_secs(self.server_config.session_grace);
+                self.sessions.keep(session, dropped, grace);
+            }
+        }
+        if let Some(player) = player {
+            self.offline_players.keep(player);
+        }
+        self.queue.retain(|queued| *queued != id);
+        if let Some(pending) = self.resuming.remove(&id) {
+            self.resume(pending).await;
+        }
+        self.admit_queued().await;
+        if let Some(lobby) = left {
+            self.close_if_empty(lobby);
+        }
+    }
+
+    /// Lets a client play on as the player of the session it gave, once the connection that
+    /// had the session is closed. The handle is told the id and name to join with, and the
+    /// client's score goes back on the scoreboard once it is placed.
+    async fn resume(&mut self, pending: PendingResume) {
+        let PendingResume { id, session, reply } = pending;
+        let open = self.clients.iter().find(|(other, client)| {
+            **other != id && client.session.as_deref() == Some(session.as_str())
+        });
+        if let Some((&previous, client)) = open {
+            // Most likely the client noticed the connection broke before the server did
+            let _ = client.tx.send(ServerMessage::Disconnect);
+            self.resuming
+                .insert(previous, PendingResume { id, session, reply });
+            return;
+        }
+        let Some(dropped) = self.sessions.take(&session) else {
+            let _ = reply.send(None);
+            return;
+        };
+        if self.clients.contains_key(&dropped.id) {
+            let _ = reply.send(None);
+            return;
+        }
+        let Some(client) = self.clients.remove(&id) else {
+            return;
+        };
+
+        client.id.store(dropped.id, Ordering::Relaxed);
+        self.clients.insert(
+            dropped.id,
+            ClientInfo {
+                session: Some(session.clone()),
+                resumed_score: dropped.score,
+                ..client
+            },
+        );
+        let resumed = Resumed {
+            id: dropped.id,
+            username: dropped.username.clone(),
+            lobby: dropped.lobby.clone(),
+        };
+        if reply.send(Some(resumed)).is_err() {
+            // The handle is gone and reports itself gone under the id it had
+            if let Some(client) = self.clients.remove(&dropped.id) {
+                client.id.store(id, Ordering::Relaxed);
+                self.clients.insert(id, client);
+            }
+            let grace = Duration::from_secs(self.server_config.session_grace);
+            self.sessions.keep(session, dropped, grace);
+            return;
+        }
+        println!("Client {id} resumed the session of client {}", dropped.id);
+    }
+
+    /// Tells client `id` what the server is playing, and how many are playing it.
+    fn answer_status_query(&self, id: u64) {
+        if let Some(client) = self.clients.get(&id) {
+            let _ = client.tx.send(ServerMessage::Status(self.status()));
+        }
+    }
+
+    /// What the server is playing, with its lobbies and how full they are
+    fn status(&self) -> ServerStatus {
+        let port = self.listener.local_addr().map_or(0, |addr| addr.port());
+        let lobbies = self
+            .lobbies
+            .iter()
+            .map(|(id, lobby)| LobbyStatus {
+                name: lobby.name.clone(),
+                players: self.playing_in(*id).count() as u32,
+                max_players: self.server_config.lobby_size.map(|size| size as u32),
+            })
+            .collect();
+        discovery::status(&self.server_config, port, lobbies)
+    }
+
+    /// Sends a message to every client playing in `lobby`, see [`Server::broadcast_nearby`] for
+    /// snapshots.
+    fn broadcast(&self, lobby: LobbyId, msg: &ServerMessage) {
+        if let Some(radius) = self.server_config.interest_radius
+            && let ServerMessage::UpdateEntities {
+                tick,
+                time,
+                entities,
+            } = msg
+        {
+            self.broadcast_nearby(lobby, *tick, *time, entities, radius);
+            return;
+        }
+        for (_, client) in self.playing_in(lobby) {
+            let _ = client.tx.send(msg.clone());
+        }
+    }
+
+    /// Sends a message to every playing client, whichever lobby they play in.
+    fn broadcast_all(&self, msg: &ServerMessage) {
+        for client in self.clients.values().filter(|client| client.playing) {
+            let _ = client.tx.send(msg.clone());
+        }
+    }
+
+    /// Sends every client playing in `lobby` a snapshot of the entities within `radius` of its
+    /// player, or of all of them while it has no player.
+    fn broadcast_nearby(
+        &self,
+        lobby: LobbyId,
+        tick: u64,
+        time: f64,
+        entities: &Entities,
+        radius: f32,
+    ) {
+        let grid = entities.grid(radius);
+        for (id, client) in self.playing_in(lobby) {
+            let entities = match entities.players.get(id) {
+                Some(player) => entities.within(&grid, player.pos, radius),
+                None => entities.clone(),
+            };
+            let _ = client.tx.send(ServerMessage::UpdateEntities {
+                tick,
+                time,
+                entities,
+            });
+        }
+    }
+
+    /// World of `lobby`, or of the main lobby once it closed
+    fn world_of(&self, lobby: LobbyId) -> &World {
+        let lobby = self
+            .lobbies
+            .get(&lobby)
+            .unwrap_or(&self.lobbies[&MAIN_LOBBY]);
+        &lobby.world
+    }
+
+    fn lobby_name(&self, lobby: LobbyId) -> String {
+        let lobby = self
+            .lobbies
+            .get(&lobby)
+            .unwrap_or(&self.lobbies[&MAIN_LOBBY]);
+        lobby.name.clone()
+    }
+
+    fn find_lobby(&self, name: &str) -> Option<LobbyId> {
+        let name = name.trim();
+        let mut lobbies = self.lobbies.iter();
+        lobbies
+            .find(|(_, lobby)| lobby.name == name)
+            .map(|(id, _)| *id)
+    }
+
+    /// Whether another player fits in `lobby`, they all do without a lobby size
+    fn has_room(&self, lobby: LobbyId) -> bool {
+        self.server_config
+            .lobby_size
+            .is_none_or(|size| self.playing_in(lobby).count() < size)
+    }
+
+    /// Lobby for a joining client: the one it asked for while that has room, otherwise the first
+    /// with room. Once every lobby is full another is opened.
+    async fn place(&mut self, requested: Option<&str>) -> LobbyId {
+        if let Some(name) = requested
+            && let Some(lobby) = self.find_lobby(name)
+        {
+            if self.has_room(lobby) {
+                return lobby;
+            }
+            println!("Lobby {name} is full");
+        }
+        if let Some(&lobby) = self.lobbies.keys().find(|lobby| self.has_room(**lobby)) {
+            return lobby;
+        }
+        let name = (1..)
+            .map(|n| format!("match-{n}"))
+            .find(|name| self.find_lobby(name).is_none())
+            .unwrap();
+        match self.open_lobby(name.clone(), true).await {
+            Ok(lobby) => {
+                println!("Opened lobby {name}, the others are full");
+                lobby
+            }
+            Err(e) => {
+                eprintln!("Could not open another lobby: {e}");
+                MAIN_LOBBY
+            }
+        }
+    }
+
+    /// Opens a lobby with a fresh world and starts its simulation. Each lobby rolls differently
+    /// from the same seed.
+    async fn open_lobby(&mut self, name: String, automatic: bool) -> Result<LobbyId> {
+        let lobby = self.next_lobby;
+        self.next_lobby += 1;
+        let tunables = self.tunables.tunables.clone();
+        let seed = self.server_config.seed.map(|seed| seed.wrapping_add(lobby));
+        let rng = seed.map(GameRng::seeded).unwrap_or_default();
+        let environment = self.environment.environment();
+        let (world, loot) = lobby::world(&self.server_config, tunables, environment, 0, rng)?;
+        let (handle, commands) = World::channel();
+        self.lobbies.insert(
+            lobby,
+            Lobby {
+                name,
+                world: handle,
+                automatic,
+                stop: None,
+            },
+        );
+        self.start_simulation(lobby, world, loot, commands);
+        Ok(lobby)
+    }
+
+    /// Starts stepping the world of `lobby`, which from then on belongs to its simulation. The
+    /// main lobby's simulation keeps the hash log.
+    fn start_simulation(
+        &mut self,
+        lobby: LobbyId,
+        world: GameWorld,
+        loot: Loot,
+        commands: UnboundedReceiver<WorldCommand>,
+    ) {
+        let Some(state) = self.lobbies.get_mut(&lobby) else {
+            return;
+        };
+        let simulation = Simulation {
+            lobby,
+            command_tx: self.command_tx.clone(),
+            started: self.started,
+            rules: self.server_config.mode.rules(),
+            loot,
+            stats: self.stats.clone(),
+            hash_log: if lobby == MAIN_LOBBY {
+                self.hash_log.take()
+            } else {
+                None
+            },
+            tick_rate: self.tick_rate,
+            snapshot_every: self.snapshot_every,
+            metrics: self.metrics.clone(),
+        };
+        let (stop, stopped) = oneshot::channel();
+        state.stop = Some(stop);
+        let task = self
+            .simulations
+            .spawn(simulation.run(world, commands, stopped));
+        self.simulation_lobbies.insert(task.id(), lobby);
+    }
+
+    /// Closes `lobby` if it was opened because the others were full and nobody is left in it.
+    fn close_if_empty(&mut self, lobby: LobbyId) {
+        let automatic = self
+            .lobbies
+            .get(&lobby)
+            .is_some_and(|lobby| lobby.automatic);
+        if !automatic || self.clients.values().any(|client| client.lobby == lobby) {
+            return;
+        }
+        if let Some(lobby) = self.lobbies.remove(&lobby) {
+            println!("Closed lobby {}, everyone left", lobby.name);
+            if let Some(stop) = lobby.stop {
+                let _ = stop.send(());
+            }
+        }
+    }
+
+    /// Clients playing in `lobby`, by id
+    fn playing_in(&self, lobby: LobbyId) -> impl Iterator<Item = (&u64, &ClientInfo)> {
+        self.clients
+            .iter()
+            .filter(move |(_, client)| client.playing && client.lobby == lobby)
+    }
+
+    fn playing_count(&self) -> usize {
+        self.clients
+            .values()
+            .filter(|client| client.playing)
+            .count()
+    }
+
+    /// Number of players the server lets in before turning away a client, players on the priority
+    /// list may also take the reserved slots.
+    fn slots_for(&self, priority: bool) -> usize {
+        if priority {
+            self.server_config.max_clients
+        } else {
+            self.server_config
+                .max_clients
+                .saturating_sub(self.server_config.reserved_slots)
+        }
+    }
+
+    /// Lets a client in if there is room, otherwise queues it or turns it away. It plays in the
+    /// lobby it asked for while that has room.
+    async fn join(
+        &mut self,
+        id: u64,
+        username: &str,
+        lobby: Option<String>,
+        placed: oneshot::Sender<Placement>,
+    ) {
+        if self.bans.refuses_username(username) {
+            println!(
+                "Refused client {id}, the username {} is banned",
+                username.trim()
+            );
+            if let Some(client) = self.clients.get(&id) {
+                let _ = client.tx.send(ServerMessage::Banned);
+            }
+            return;
+        }
+        let lobby = match lobby {
+            Some(name) if self.find_lobby(&name).is_none() => {
+                let names: Vec<_> = self
+                    .lobbies
+                    .values()
+                    .map(|lobby| lobby.name.as_str())
+                    .collect();
+                let text = format!(
+                    "There is no lobby {name}, this server has {}",
+                    names.join(", ")
+                );
+                if let Some(client) = self.clients.get(&id) {
+                    let _ = client
+                        .tx
+                        .send(ServerMessage::Announcement(Announcement::Text(text)));
+                }
+                None
+            }
+            lobby => lobby,
+        };
+        let username = &self.unique_username(id, username);
+        let priority = self
+            .server_config
+            .priority
+            .iter()
+            .any(|name| name == username);
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.username = Some(username.to_string());
+            client.priority = priority;
+            client.requested_lobby = lobby;
+            client.placed = Some(placed);
+        }
+
+        let full = self.playing_count() >= self.slots_for(priority);
+        if full && !self.server_config.join_queue {
+            if let Some(client) = self.clients.get(&id) {
+                let _ = client.tx.send(ServerMessage::ServerFull);
+            }
+            return;
+        }
+        self.queue.push_back(id);
+        self.admit_queued().await;
+    }
+
+    /// Name for client `id` that no other connected client goes by, the requested one with a
+    /// number added if it is taken.
+    fn unique_username(&self, id: u64, requested: &str) -> String {
+        let requested = requested.trim();
+        let requested = if requested.is_empty() {
+            DEFAULT_USERNAME
+        } else {
+            requested
+        };
+        let taken = |name: &str| {
+            self.clients.iter().any(|(other, client)| {
+                *other != id && client.username.as_deref().is_some_and(|used| used == name)
+            })
+        };
+        // Cut long names short, leaving room for the suffix
+        let with_suffix = |suffix: String| {
+            let length = MAX_USERNAME_LENGTH - suffix.chars().count();
+            let name: String = requested.chars().take(length).collect();
+            name.trim_end().to_string() + &suffix
+        };
+        let name = with_suffix(String::new());
+        if !taken(&name) {
+            return name;
+        }
+        (2..)
+            .map(|n| with_suffix(format!(" ({n})")))
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+
+    /// Admits queued clients while there is room and tells the rest where they stand.
+    async fn admit_queued(&mut self) {
+        // Clients further back may still fit if they can use the reserved slots
+        let mut index = 0;
+        while index < self.queue.len() {
+            let id = self.queue[index];
+            let Some(priority) = self.clients.get(&id).map(|client| client.priority) else {
+                self.queue.remove(index);
+                continue;
+            };
+            if self.playing_count() >= self.slots_for(priority) {
+                index += 1;
+                continue;
+            }
+
+            self.queue.remove(index);
+            let requested = self
+                .clients
+                .get_mut(&id)
+                .and_then(|client| client.requested_lobby.take());
+            let lobby = self.place(requested.as_deref()).await;
+            let world = self.world_of(lobby).clone();
+            if let Some(client) = self.clients.get_mut(&id) {
+                client.lobby = lobby;
+                if let Some(placed) = client.placed.take() {
+                    let _ = placed.send(Placement { lobby, world });
+                }
+            }
+            self.restore_player(id);
+            let username = self.clients[&id].username.clone().unwrap_or_default();
+            // Told before the newcomer counts as playing, so it does not hear about itself
+            let joined = ServerMessage::PlayerJoined {
+                id,
+                username: username.clone(),
+            };
+            self.broadcast(lobby, &joined);
+            if let Some(client) = self.clients.get_mut(&id) {
+                let session = Sessions::token();
+                client.session = Some(session.clone());
+                let _ = client.tx.send(ServerMessage::ConnectionAccepted {
+                    id,
+                    username,
+                    session,
+                });
+                let welcome = Announcement::Welcome {
+                    server_name: self.server_config.server_name.clone(),
+                };
+                let _ = client.tx.send(ServerMessage::Announcement(welcome));
+                if let Some(motd) = &self.server_config.motd {
+                    let motd = Announcement::Text(motd.clone());
+                    let _ = client.tx.send(ServerMessage::Announcement(motd));
+                }
+                let _ = client.tx.send(ServerMessage::MatchInfo {
+                    map: self.server_config.map_name(),
+                    mode: self.server_config.mode.name(),
+                });
+                client.playing = true;
+            }
+        }
+        for (index, id) in self.queue.iter().enumerate() {
+            if let Some(client) = self.clients.get(id) {
+                let _ = client
+                    .tx
+                    .send(ServerMessage::QueuePosition(index as u32 + 1));
+            }
+        }
+    }
+
+    /// Runs a command typed into the admin console.
+    async fn admin(&mut self, command: AdminCommand) {
+        match command {
+            AdminCommand::List => {
+                let mut ids: Vec<_> = self.clients.keys().copied().collect();
+                ids.sort();
+                println!("{} client(s) connected", ids.len());
+                for id in ids {
+                    let client = &self.clients[&id];
+                    let state = self.client_state(id);
+                    let username = client.username.as_deref().unwrap_or("-");
+                    let rtt = client
+                        .rtt
+                        .map(|rtt| format!("{:.1} ms", rtt.as_secs_f64() * 1000.0))
+                        .unwrap_or_else(|| String::from("-"));
+                    println!("  {id} {username} {} {state} {rtt}", client.addr);
+                }
+            }
+            AdminCommand::Kick(id) => match self.clients.get(&id) {
+                Some(client) => {
+                    // The handle closes the connection once the client has been told
+                    let _ = client.tx.send(ServerMessage::Disconnect);
+                    println!("Kicked client {id}");
+                }
+                None => println!("No client with id {id}"),
+            },
+            AdminCommand::Ban(id) => match self.clients.get(&id) {
+                Some(client) => self.add_ban(Ban::Address(client.addr.ip())),
+                None => println!("No client with id {id}"),
+            },
+            AdminCommand::AddBan(ban) => self.add_ban(ban),
+            AdminCommand::RemoveBan(ban) => match self.bans.unban(&ban) {
+                Ok(true) => println!("Lifted the ban of {ban}"),
+                Ok(false) => println!("The {ban} is not banned"),
+                Err(e) => println!("Lifted the ban of {ban} until the server stops: {e}"),
+            },
+            AdminCommand::Bans => {
+                let bans: Vec<_> = self.bans.entries().collect();
+                println!("{} ban(s)", bans.len());
+                for ban in bans {
+                    println!("  {ban}");
+                }
+            }
+            AdminCommand::Redirect { id, address, token } => {
+                // Clients still joining only know how to be accepted or turned away
+                let ids: Vec<u64> = match id {
+                    Some(id) if self.clients.get(&id).is_some_and(|client| client.playing) => {
+                        vec![id]
+                    }
+                    Some(id) => {
+                        println!("No playing client with id {id}");
+                        return;
+                    }
+                    None => {
+                        let playing = self.clients.iter().filter(|(_, client)| client.playing);
+                        playing.map(|(id, _)| *id).collect()
+                    }
+                };
+                for id in &ids {
+                    // The handle closes the connection once the client has been told
+                    let _ = self.clients[id].tx.send(ServerMessage::Redirect {
+                        address: address.clone(),
+                        token: token.clone(),
+                    });
+                }
+                println!("Sent {} client(s) to {address}", ids.len());
+            }
+            AdminCommand::Broadcast(text) => {
+                println!("[announcement] {text}");
+                let msg = ServerMessage::Announcement(Announcement::Text(text));
+                self.log_chat(&msg);
+                self.broadcast_all(&msg);
+            }
+            AdminCommand::Save(path) => match self.save_world(path).await {
+                Ok(location) => println!("Saved the world to {location}"),
+                Err(e) => println!("{e}"),
+            },
+            AdminCommand::Objects => {
+                let environment = self.environment.environment();
+                println!("{} object(s)", environment.objects.len());
+                for (index, object) in environment.objects.iter().enumerate() {
+                    println!(
+                        "  {index} at ({}, {}) size ({}, {})",
+                        object.pos.x, object.pos.y, object.size.x, object.size.y
+                    );
+                }
+            }
+            AdminCommand::AddObject(object) => {
+                let result = self.environment.add(object);
+                self.environment_changed("Added an object", result).await;
+            }
+            AdminCommand::RemoveObject(index) => {
+                let result = self.environment.remove(index).map(|_| ());
+                self.environment_changed(&format!("Removed object {index}"), result)
+                    .await;
+            }
+            AdminCommand::ResetMap => {
+                let result = self.environment.reset();
+                self.environment_changed("Reset the map", result).await;
+            }
+            AdminCommand::DumpConfig => println!("{}", self.tunables.dump()),
+            AdminCommand::Stop => {}
+            AdminCommand::Help => println!("{}", console::HELP),
+        }
+    }
+
+    /// Refuses `ban` from now on and sends away the clients it matches.
+    fn add_ban(&mut self, ban: Ban) {
+        match self.bans.ban(ban.clone()) {
+            Ok(true) => println!("Banned {ban}"),
+            Ok(false) => println!("The {ban} was already banned"),
+            Err(e) => println!("Banned {ban} until the server stops: {e}"),
+        }
+        for (id, client) in &self.clients {
+            let banned = match &ban {
+                Ban::Username(username) => client.username.as_ref() == Some(username),
+                Ban::Address(addr) => client.addr.ip() == *addr,
+            };
+            if banned {
+                // The handle closes the connection once the client has been told
+                let _ = client.tx.send(ServerMessage::Banned);
+                println!("Disconnected client {id} ({})", client.addr);
+            }
+        }
+    }
+
+    /// Whether client `id` is playing, and in which lobby when there are several, still
+    /// connecting or waiting in the queue.
+    fn client_state(&self, id: u64) -> String {
+        match self.queue.iter().position(|queued| *queued == id) {
+            Some(index) => format!("queued #{}", index + 1),
+            None => match self.clients.get(&id) {
+                Some(client) if client.playing && self.lobbies.len() > 1 => {
+                    format!("playing in {}", self.lobby_name(client.lobby))
+                }
+                Some(client) if client.playing => String::from("playing"),
+                _ => String::from("connecting"),
+            },
+        }
+    }
+
+    /// Keeps chat lines and announcements for the dashboard, dropping the oldest.
+    fn log_chat(&mut self, msg: &ServerMessage) {
+        let line = match msg {
+            ServerMessage::ChatBroadcast {
+                sender_id,
+                text,
+                timestamp,
+            } => ChatLine {
+                timestamp: *timestamp,
+                sender: self
+                    .clients
+                    .get(sender_id)
+                    .and_then(|client| client.username.clone())
+                    .unwrap_or_else(|| sender_id.to_string()),
+                text: text.clone(),
+            },
+            ServerMessage::Announcement(Announcement::Text(text)) => ChatLine {
+                timestamp: unix_time::unix_millis(),
+                sender: String::from("Server"),
+                text: text.clone(),
+            },
+            _ => return,
+        };
+        if self.chat_log.len() == CHAT_LOG_LENGTH {
+            self.chat_log.pop_front();
+        }
+        self.chat_log.push_back(line);
+    }
+
+    /// Everything the dashboard shows besides the map, which is that of the main lobby along with
+    /// its players.
+    async fn dashboard_state(&self) -> DashboardState {
+        let world = self.lobbies[&MAIN_LOBBY].world.snapshot().await;
+        let players = world
+            .map(|world| world.entities.players)
+            .unwrap_or_default();
+        let mut ids: Vec<_> = self.clients.keys().copied().collect();
+        ids.sort();
+        let clients =
+            ids.into_iter()
+                .map(|id| {
+                    let client = &self.clients[&id];
+                    ClientState {
+                        id,
+                        username: client.username.clone(),
+                        addr: client.addr.to_string(),
+                        state: self.client_state(id),
+                        rtt_ms: client.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+                        player: players.get(&id).filter(|_| client.lobby == MAIN_LOBBY).map(
+                            |player| PlayerState {
+                                pos: player.pos,
+                                color: player.color,
+                                health: player.health,
+                                armor: player.armor,
+                            },
+                        ),
+                    }
+                })
+                .collect();
+        DashboardState {
+            server_name: self.server_config.server_name.clone(),
+            mode: self.server_config.mode.name(),
+            map: self.server_config.map_name(),
+            clients,
+            chat: self.chat_log.iter().cloned().collect(),
+        }
+    }
+
+    /// Puts the edited environment in the world of every lobby and sends it to the players.
+    async fn environment_changed(&mut self, done: &str, result: Result<()>) {
+        match result {
+            // Without an edits file, the world file keeps the environment
+            Ok(())
+                if self.environment.path().is_some()
+                    || self.storage.location(Record::World).is_some() =>
+            {
+                println!("{done}")
+            }
+            Ok(()) => println!("{done}, it lasts until the server stops"),
+            Err(e) => println!("{e}"),
+        }
+        // Even a failed save leaves the edit in place, so the world follows it either way
+        let environment = self.environment.environment();
+        for lobby in self.lobbies.values() {
+            let environment = WorldCommand::SetEnvironment(environment.clone());
+            lobby.world.send(environment);
+        }
+        self.broadcast_all(&ServerMessage::UpdateObjects(environment));
+    }
+
+    /// Puts a joining player back where they were when they left, if they played before, and
+    /// their score if they resumed a session. All lobbies play the same map, so it can be another
+    /// lobby than the one they left.
+    fn restore_player(&mut self, id: u64) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        let (lobby, score) = (client.lobby, client.resumed_score.take());
+        let player = client
+            .username
+            .as_ref()
+            .and_then(|username| self.offline_players.take(username))
+            .map(|player| Player {
+                vel: Vec2::ZERO,
+                last_input_seq: 0,
+                input_ticks: 0,
+                ..player
+            });
+        let world = self.world_of(lobby);
+        world.send(WorldCommand::Restore { id, player, score });
+    }
+
+    /// Writes the world of the main lobby, along with the players of the others and those that
+    /// are offline, to `path` or the storage. Returns where it went.
+    async fn save_world(&self, path: Option<PathBuf>) -> Result<String> {
+        let mut worlds = BTreeMap::new();
+        for (id, lobby) in &self.lobbies {
+            if let Some(world) = lobby.world.snapshot().await {
+                worlds.insert(*id, world);
+            }
+        }
+        self.write_world(path, worlds)
+    }
+
+    /// Writes the main lobby's world out of `worlds` along with the players of the others, see
+    /// [`Server::save_world`].
+    fn write_world(
+        &self,
+        path: Option<PathBuf>,
+        mut worlds: BTreeMap<LobbyId, GameWorld>,
+    ) -> Result<String> {
+        let location = self.storage.location(Record::World);
+        if path.is_none() && location.is_none() {
+            bail!("No world file to save to, give a path or start with --world-file or --database");
+        }
+        let Some(mut world) = worlds.remove(&MAIN_LOBBY) else {
+            bail!("The world of the main lobby is gone, there is nothing to save");
+        };
+        // Ids only last as long as a connection, so players are numbered afresh in the file, in the
+        // order they left with those still playing last
+        let mut players: Vec<_> = self.offline_players.iter().cloned().collect();
+        players.extend(std::mem::take(&mut world.entities.players).into_values());
+        for world in worlds.into_values() {
+            players.extend(world.entities.players.into_values());
+        }
+        world.entities.players = (1..).zip(players).collect();
+
+        match path {
+            Some(path) => {
+                world
+                    .save(&path)
+                    .map_err(|e| anyhow!("Failed to save the world to {}: {e}", path.display()))?;
+                Ok(path.display().to_string())
+            }
+            None => {
+                self.storage.save_world(&world)?;
+                Ok(location.unwrap_or_default())
+            }
+        }
+    }
+
+    /// Tells every client the server is stopping and waits a moment for their handles to finish,
+    /// aborting those that take longer, then stops the simulations and writes their worlds to the
+    /// world file along with `worlds`, those of simulations that stopped on their own.
+    async fn shutdown(&mut self, mut worlds: BTreeMap<LobbyId, GameWorld>) {
+        println!("Stopping server");
+        // Handles waiting to resume a session are let go, so they hear of the shutdown
+        self.resuming.clear();
+        for client in self.clients.values() {
+            let _ = client
+                .tx
+                .send(ServerMessage::Announcement(Announcement::ShuttingDown));
+            let _ = client.tx.send(ServerMessage::Disconnect);
+        }
+        let _ = time::timeout(SHUTDOWN_GRACE, async {
+            while !self.clients.is_empty() {
+                match self.command_rx.recv().await {
+                    Some(ServerCommand::ClientDisconnected { id, player, .. }) => {
+                        if let Some(client) = self.clients.remove(&id) {
+                            let _ = client.task.await;
+                        }
+                        if let Some(player) = player {
+                            self.offline_players.keep(player);
+                        }
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+        })
+        .await;
+        // Players of clients that did not finish in time are still in the world, and saved with it
+        for (id, client) in self.clients.drain() {
+            println!("Client {id} did not disconnect in time");
+            client.abort.abort();
+            let _ = client.task.await;
+        }
+
+        // Those still running write out their stats and hash log once stopped
+        for lobby in self.lobbies.values_mut() {
+            if let Some(stop) = lobby.stop.take() {
+                let _ = stop.send(());
+            }
+        }
+        while let Some(result) = self.simulations.join_next_with_id().await {
+            if let Ok((task, finished)) = result
+                && let Some(lobby) = self.simulation_lobbies.remove(&task)
+                && self.lobbies.contains_key(&lobby)
+            {
+                worlds.insert(lobby, finished.world);
+            }
+        }
+
+        if self.storage.location(Record::World).is_some() {
+            match self.write_world(None, worlds) {
+                Ok(location) => println!("Saved the world to {location}"),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+
     pub fn get_address(&self) -> Option<SocketAddr> {
-        self.listener.local_addr().ok()
+        self.listener.local_addr()
     }
 }
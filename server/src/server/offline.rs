@@ -0,0 +1,99 @@
+//! Players that left, or were loaded from the world file, kept to be put back where they were when
+//! they join again.
+//!
+//! Players are known by their username alone. Whoever joins under a name takes over the position,
+//! health, armor and ammo that name left behind, with nothing to prove they are the player who
+//! left. A session token from [`ClientMessage::Resume`] only brings back the id and score, the rest
+//! comes from here like for any other join.
+//!
+//! At most `--offline-players` are kept, the ones that left longest ago are forgotten first, so
+//! neither memory nor the world file grows with every name that ever played. The world file keeps
+//! them in the order they left.
+//!
+//! [`ClientMessage::Resume`]: common::message::ClientMessage::Resume
+use std::collections::VecDeque;
+
+use common::world::entities::Player;
+
+pub struct OfflinePlayers {
+    /// The player that left longest ago first
+    players: VecDeque<Player>,
+    capacity: usize,
+}
+impl OfflinePlayers {
+    /// Keeps up to `capacity` of `players`, given in the order they left
+    pub fn new(capacity: usize, players: impl IntoIterator<Item = Player>) -> Self {
+        let mut offline = Self {
+            players: VecDeque::new(),
+            capacity,
+        };
+        for player in players {
+            offline.keep(player);
+        }
+        offline
+    }
+
+    /// Keeps a player that just left, in place of any kept under the same name, forgetting the
+    /// one that left longest ago when there is no more room
+    pub fn keep(&mut self, player: Player) {
+        self.take(&player.username);
+        self.players.push_back(player);
+        while self.players.len() > self.capacity {
+            self.players.pop_front();
+        }
+    }
+
+    /// Hands out the player kept under `username`, if any
+    pub fn take(&mut self, username: &str) -> Option<Player> {
+        let index = self
+            .players
+            .iter()
+            .position(|player| player.username == username)?;
+        self.players.remove(index)
+    }
+
+    /// The players kept, the one that left longest ago first
+    pub fn iter(&self) -> impl Iterator<Item = &Player> {
+        self.players.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::vec::Vec2;
+    use test_utils::world::player;
+
+    use super::*;
+
+    fn names(offline: &OfflinePlayers) -> Vec<&str> {
+        offline
+            .iter()
+            .map(|player| player.username.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn the_players_that_left_longest_ago_are_forgotten_first() {
+        let players = ["a", "b", "c"].map(|name| player(name, Vec2::ZERO));
+        let mut offline = OfflinePlayers::new(2, players);
+        assert_eq!(names(&offline), ["b", "c"]);
+
+        offline.keep(player("d", Vec2::ZERO));
+        assert_eq!(names(&offline), ["c", "d"]);
+        assert!(offline.take("a").is_none());
+    }
+
+    #[test]
+    fn leaving_again_replaces_the_player_kept_under_the_name() {
+        let mut offline = OfflinePlayers::new(2, [player("a", Vec2::ZERO)]);
+        offline.keep(player("b", Vec2::ZERO));
+        offline.keep(player("a", Vec2::ONE));
+        assert_eq!(names(&offline), ["b", "a"]);
+
+        // A third player makes room by forgetting `b`, which left before `a` did the second time
+        offline.keep(player("c", Vec2::ZERO));
+        assert_eq!(names(&offline), ["a", "c"]);
+        assert_eq!(offline.take("a").map(|player| player.pos), Some(Vec2::ONE));
+        assert!(offline.take("a").is_none());
+    }
+}
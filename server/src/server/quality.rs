@@ -0,0 +1,82 @@
+//! How many of the lobby's snapshots each client gets.
+//!
+//! Every client starts out with all of them. From its [`ClientMessage::QualityReport`]s, a client
+//! that draws fewer frames than it receives snapshots only gets as many as it can show, and one
+//! that loses many of them gets fewer until the loss goes away. Snapshots also wait in the
+//! client's queue when its connection cannot keep up: past [`MAX_BACKLOG`] messages, new ones are
+//! dropped until the queue drains and the client counts as losing snapshots.
+//!
+//! [`ClientMessage::QualityReport`]: common::message::ClientMessage::QualityReport
+use tokio::time::Instant;
+
+/// Fewest snapshots a client gets, one in this many
+const MAX_DIVISOR: u32 = 6;
+/// Loss above which a client gets fewer snapshots
+const HIGH_LOSS: f32 = 0.1;
+/// Loss below which a client gets more snapshots again
+const LOW_LOSS: f32 = 0.02;
+/// Messages waiting to be sent to a client past which its snapshots are dropped
+pub const MAX_BACKLOG: usize = 64;
+
+pub struct Quality {
+    /// The client gets one snapshot in this many
+    divisor: u32,
+    /// How much the divisor was raised for losses, on top of what the frame rate asks for
+    loss_steps: u32,
+    /// Snapshots of the lobby seen since the last report and since the client started
+    window: u32,
+    seen: u64,
+    /// Whether snapshots were dropped for the backlog since the last report
+    congested: bool,
+    last_report: Instant,
+}
+impl Quality {
+    pub fn new() -> Self {
+        Self {
+            divisor: 1,
+            loss_steps: 0,
+            window: 0,
+            seen: 0,
+            congested: false,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Whether to send the client the lobby's next snapshot, with `backlog` messages waiting
+    /// to be sent to it
+    pub fn forward(&mut self, backlog: usize) -> bool {
+        self.window += 1;
+        self.seen += 1;
+        if backlog > MAX_BACKLOG {
+            self.congested = true;
+            return false;
+        }
+        self.seen.is_multiple_of(self.divisor as u64)
+    }
+
+    /// Takes in a report of the client, returning the new divisor if it changed
+    pub fn report(&mut self, snapshot_loss: f32, fps: f32) -> Option<u32> {
+        let elapsed = self.last_report.elapsed().as_secs_f32();
+        let snapshot_rate = std::mem::take(&mut self.window) as f32 / elapsed.max(f32::EPSILON);
+        self.last_report = Instant::now();
+
+        if std::mem::take(&mut self.congested) || snapshot_loss > HIGH_LOSS {
+            self.loss_steps += 1;
+        } else if snapshot_loss < LOW_LOSS {
+            self.loss_steps = self.loss_steps.saturating_sub(1);
+        }
+        // Snapshots beyond the frame rate are never seen
+        let for_fps = if fps.is_finite() && fps >= 1.0 {
+            (snapshot_rate / fps).floor().max(1.0) as u32
+        } else {
+            1
+        };
+        let divisor = (for_fps + self.loss_steps).min(MAX_DIVISOR);
+        self.loss_steps = self.loss_steps.min(MAX_DIVISOR - 1);
+
+        (divisor != self.divisor).then(|| {
+            self.divisor = divisor;
+            divisor
+        })
+    }
+}
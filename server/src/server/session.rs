@@ -0,0 +1,55 @@
+//! Sessions of dropped connections, so a client whose connection broke can come back as the same
+//! player.
+//!
+//! Every client gets a fresh token with [`ServerMessage::ConnectionAccepted`]. When its connection
+//! closes, the player's id, name, lobby and score are kept under that token for `--session-grace`
+//! seconds, and a client giving the token with [`ClientMessage::Resume`] takes them back. The
+//! player itself waits among the offline players like after any other disconnect.
+//!
+//! [`ServerMessage::ConnectionAccepted`]: common::message::ServerMessage::ConnectionAccepted
+//! [`ClientMessage::Resume`]: common::message::ClientMessage::Resume
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use common::world::scoreboard::Score;
+
+/// What a dropped connection leaves behind for the client to resume
+pub struct DroppedSession {
+    pub id: u64,
+    pub username: String,
+    /// Name of the lobby the player was in, the lobby can close before the session is resumed
+    pub lobby: String,
+    pub score: Option<Score>,
+}
+
+/// Sessions of the connections that closed, by token, with when they expire
+#[derive(Default)]
+pub struct Sessions {
+    dropped: HashMap<String, (DroppedSession, Instant)>,
+}
+impl Sessions {
+    /// New token that cannot be guessed from the others
+    pub fn token() -> String {
+        format!("{:032x}", rand::random::<u128>())
+    }
+
+    /// Keeps the session `token` of a closed connection for `grace`
+    pub fn keep(&mut self, token: String, session: DroppedSession, grace: Duration) {
+        self.forget_expired();
+        self.dropped
+            .insert(token, (session, Instant::now() + grace));
+    }
+
+    /// Hands out the session `token` if its grace period is not over, it can only be resumed once
+    pub fn take(&mut self, token: &str) -> Option<DroppedSession> {
+        self.forget_expired();
+        self.dropped.remove(token).map(|(session, _)| session)
+    }
+
+    fn forget_expired(&mut self) {
+        let now = Instant::now();
+        self.dropped.retain(|_, (_, expires)| *expires > now);
+    }
+}
@@ -0,0 +1,46 @@
+//! Stops the server when the process is asked to, by Ctrl-C or a service manager.
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{ServerCommand, console::AdminCommand};
+
+/// Stops the server gracefully on the first SIGINT or SIGTERM, the same way the console's `stop`
+/// does. A second one exits at once, for when stopping hangs.
+pub fn spawn(tx: UnboundedSender<ServerCommand>) {
+    tokio::spawn(async move {
+        let Some(signal) = next_signal().await else {
+            return;
+        };
+        println!("Received {signal}, stopping (again to stop at once)");
+        if tx.send(ServerCommand::Admin(AdminCommand::Stop)).is_err() {
+            return;
+        }
+        if let Some(signal) = next_signal().await {
+            eprintln!("Received {signal} again, stopping at once");
+            std::process::exit(1);
+        }
+    });
+}
+
+/// Waits for the next signal asking the process to stop and names it, none when signals cannot be
+/// listened for.
+#[cfg(unix)]
+async fn next_signal() -> Option<&'static str> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            eprintln!("Could not listen for SIGTERM: {e}");
+            return tokio::signal::ctrl_c().await.ok().map(|()| "SIGINT");
+        }
+    };
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.ok().map(|()| "SIGINT"),
+        _ = terminate.recv() => Some("SIGTERM"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_signal() -> Option<&'static str> {
+    tokio::signal::ctrl_c().await.ok().map(|()| "Ctrl-C")
+}
@@ -0,0 +1,455 @@
+//! The simulation of a lobby, the only owner of its world.
+//!
+//! Nothing else holds the [`GameWorld`] of a lobby. Client handles and the server loop send its
+//! [`Simulation`] typed [`WorldCommand`]s through a [`World`], which it applies between ticks in the
+//! order they arrive, answering those that ask for something through a oneshot channel. What
+//! clients should hear about goes to the server loop as broadcasts to the lobby.
+//!
+//! A panic while stepping the world or applying a command stops the simulation, which hands the
+//! world back as it was for the crash report, see [`Finished`].
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::{
+    color::Color,
+    message::ServerMessage,
+    time as unix_time,
+    tunables::Tunables,
+    vec::Vec2,
+    world::{
+        GameWorld,
+        ammo::Ammo,
+        combat::{Hit, ProjectileKind},
+        entities::Player,
+        environment::Environment,
+        scoreboard::{DamageLog, Score, Scoreboard},
+    },
+};
+use tokio::{
+    select,
+    sync::{
+        Mutex,
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        oneshot,
+    },
+    time,
+};
+
+use super::{
+    ServerCommand,
+    diagnostics::{self, HashLog},
+    lobby::LobbyId,
+    metrics::Metrics,
+};
+use crate::{loot::Loot, mode::Rules, stats::StatsStore};
+
+/// What can be done to the world of a lobby
+pub enum WorldCommand {
+    /// Adds the player of a client the server accepted, unless it was restored, and answers with
+    /// what the client needs to start playing
+    Join {
+        id: u64,
+        username: String,
+        color: Option<Color>,
+        reply: oneshot::Sender<Joined>,
+    },
+    /// Puts back the player and score a client had before it joins
+    Restore {
+        id: u64,
+        player: Option<Player>,
+        score: Option<Score>,
+    },
+    Input {
+        id: u64,
+        seq: u64,
+        dir: Vec2,
+    },
+    /// Fires unless the player's last shot is within the cooldown
+    Shoot {
+        id: u64,
+        dir: Vec2,
+        kind: ProjectileKind,
+    },
+    Reload {
+        id: u64,
+        kind: ProjectileKind,
+    },
+    /// Takes the player of a client that left out of the world, answering with it and its score
+    Leave {
+        id: u64,
+        reply: oneshot::Sender<(Option<Player>, Option<Score>)>,
+    },
+    SetEnvironment(Environment),
+    /// Answers with a copy of the whole world
+    Snapshot(oneshot::Sender<GameWorld>),
+}
+
+/// What a client is sent when it starts playing in a world
+pub struct Joined {
+    pub tunables: Tunables,
+    pub environment: Environment,
+    pub scoreboard: Scoreboard,
+}
+
+/// Sends commands to the simulation of a lobby. Once the simulation stopped, commands are dropped
+/// and questions go unanswered.
+#[derive(Clone)]
+pub struct World {
+    tx: UnboundedSender<WorldCommand>,
+}
+impl World {
+    /// A world whose simulation takes its commands from the returned receiver
+    pub fn channel() -> (Self, UnboundedReceiver<WorldCommand>) {
+        let (tx, rx) = unbounded_channel();
+        (Self { tx }, rx)
+    }
+
+    pub fn send(&self, command: WorldCommand) {
+        let _ = self.tx.send(command);
+    }
+
+    /// Sends the command `ask` makes and waits for its answer
+    async fn ask<T>(&self, ask: impl FnOnce(oneshot::Sender<T>) -> WorldCommand) -> Option<T> {
+        let (reply, answer) = oneshot::channel();
+        self.send(ask(reply));
+        answer.await.ok()
+    }
+
+    pub async fn join(&self, id: u64, username: String, color: Option<Color>) -> Option<Joined> {
+        self.ask(|reply| WorldCommand::Join {
+            id,
+            username,
+            color,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn leave(&self, id: u64) -> (Option<Player>, Option<Score>) {
+        let left = self.ask(|reply| WorldCommand::Leave { id, reply }).await;
+        left.unwrap_or_default()
+    }
+
+    pub async fn snapshot(&self) -> Option<GameWorld> {
+        self.ask(WorldCommand::Snapshot).await
+    }
+}
+
+/// What a simulation leaves behind when it stops
+pub struct Finished {
+    pub world: GameWorld,
+    /// What the panic that stopped it said, none if it was told to stop
+    pub panic: Option<String>,
+}
+
+/// Steps the world of a lobby and has its snapshots and events broadcast to the lobby
+pub struct Simulation {
+    pub lobby: LobbyId,
+    pub command_tx: UnboundedSender<ServerCommand>,
+    /// When the server started, snapshots carry the time since
+    pub started: Instant,
+    pub rules: Box<dyn Rules>,
+    pub loot: Loot,
+    /// Shared by the lobbies, ratings carry over from one to the other
+    pub stats: Arc<Mutex<StatsStore>>,
+    pub hash_log: Option<HashLog>,
+    pub tick_rate: f64,
+    /// Simulation steps and snapshots have their own rates, every so many steps get a snapshot
+    pub snapshot_every: u64,
+    /// Where the time each step takes is counted
+    pub metrics: Arc<Metrics>,
+}
+impl Simulation {
+    /// Steps `world` every tick and applies `commands` in between until `stop` fires or something
+    /// panics, then writes what is left of the stats and hash log.
+    pub async fn run(
+        mut self,
+        mut world: GameWorld,
+        mut commands: UnboundedReceiver<WorldCommand>,
+        mut stop: oneshot::Receiver<()>,
+    ) -> Finished {
+        let mut interval = time::interval(Duration::from_secs_f64(1.0 / self.tick_rate));
+        let mut damage_log = DamageLog::default();
+        // When each player last fired, shots closer together than the cooldown are ignored
+        let mut last_shots = HashMap::new();
+        let panic = loop {
+            let done = select! {
+                _ = interval.tick() => {
+                    let stats = self.stats.clone();
+                    let mut stats = stats.lock().await;
+                    let started = Instant::now();
+                    let stepped = panic::catch_unwind(AssertUnwindSafe(|| {
+                        self.step(&mut world, &mut stats, &mut damage_log)
+                    }));
+                    self.metrics.record_tick(started.elapsed());
+                    stepped
+                }
+                Some(command) = commands.recv() => panic::catch_unwind(AssertUnwindSafe(|| {
+                    self.apply(&mut world, command, &mut last_shots)
+                })),
+                _ = &mut stop => break None,
+            };
+            if let Err(payload) = done {
+                break Some(diagnostics::panic_message(&*payload));
+            }
+        };
+
+        // Whatever changed since the last write would be lost otherwise
+        if let Err(e) = self.stats.lock().await.save() {
+            eprintln!("{e}");
+        }
+        if let Some(log) = &mut self.hash_log
+            && let Err(e) = log.flush()
+        {
+            eprintln!("Could not write the hash log: {e}");
+        }
+        Finished { world, panic }
+    }
+
+    /// Carries out a command sent to the world
+    fn apply(
+        &self,
+        w: &mut GameWorld,
+        command: WorldCommand,
+        last_shots: &mut HashMap<u64, Instant>,
+    ) {
+        match command {
+            WorldCommand::Join {
+                id,
+                username,
+                color,
+                reply,
+            } => {
+                let spawn = w.rng.spawn_position(&w.environment.tiles);
+                let combat = &w.tunables.combat;
+                w.entities.players.entry(id).or_insert_with(|| Player {
+                    username,
+                    color: color.unwrap_or_else(|| Color::for_player(id)),
+                    pos: spawn,
+                    vel: Vec2::ZERO,
+                    last_input_seq: 0,
+                    input_ticks: 0,
+                    health: combat.max_health,
+                    armor: 0.0,
+                    respawn_in: 0.0,
+                    sprite: None,
+                    ammo: Ammo::full(&combat.ammo),
+                });
+                let _ = reply.send(Joined {
+                    tunables: w.tunables.clone(),
+                    environment: w.environment.clone(),
+                    scoreboard: w.scoreboard.clone(),
+                });
+            }
+            WorldCommand::Restore { id, player, score } => {
+                if let Some(player) = player {
+                    w.entities.players.insert(id, player);
+                }
+                if let Some(score) = score {
+                    w.scoreboard.scores.insert(id, score);
+                    self.broadcast(ServerMessage::UpdateScoreboard(w.scoreboard.clone()));
+                }
+            }
+            WorldCommand::Input { id, seq, dir } => {
+                // Only the direction is taken from the client, the tick moves the player from
+                // there. Inputs that arrive out of order are ignored, and the sequence is echoed
+                // back in the next snapshot so the client can reconcile. Snapshots only go out
+                // every `snapshot_every` ticks, however often clients send input
+                let physics = w.tunables.physics;
+                w.entities.apply_input(id, seq, dir, &physics);
+            }
+            WorldCommand::Shoot { id, dir, kind } => {
+                let combat = w.tunables.combat;
+                let cooldown = Duration::from_secs_f32(combat.fire_cooldown.max(0.0));
+                let ready = last_shots
+                    .get(&id)
+                    .is_none_or(|shot: &Instant| shot.elapsed() >= cooldown);
+                let radius = w.tunables.physics.player_radius;
+                // A dry shot does not start the cooldown
+                if ready && w.entities.shoot(id, dir, kind, &combat, radius) {
+                    last_shots.insert(id, Instant::now());
+                }
+            }
+            WorldCommand::Reload { id, kind } => {
+                let ammo = w.tunables.combat.ammo;
+                if let Some(player) = w.entities.players.get_mut(&id).filter(|p| p.is_alive()) {
+                    player.ammo.start_reload(kind, &ammo);
+                }
+            }
+            WorldCommand::Leave { id, reply } => {
+                last_shots.remove(&id);
+                let player = w.entities.players.remove(&id);
+                let score = w.scoreboard.scores.get(&id).copied();
+                if score.is_some() {
+                    w.scoreboard.remove(id);
+                    self.broadcast(ServerMessage::UpdateScoreboard(w.scoreboard.clone()));
+                }
+                let _ = reply.send((player, score));
+            }
+            WorldCommand::SetEnvironment(environment) => w.environment = environment,
+            WorldCommand::Snapshot(reply) => {
+                let _ = reply.send(w.clone());
+            }
+        }
+    }
+
+    /// Advances the world by one tick and broadcasts what happened
+    fn step(&mut self, w: &mut GameWorld, stats: &mut StatsStore, damage_log: &mut DamageLog) {
+        let tick_rate = self.tick_rate;
+        let dt = (1.0 / tick_rate) as f32;
+        // Advance by exactly one tick so clients can predict the same motion
+        w.tick += 1;
+        let mut blasts = w.update(dt);
+
+        let combat = w.tunables.combat;
+        let radius = w.tunables.physics.player_radius;
+        let mut hits = w.entities.resolve_hits(&combat, radius, &mut blasts);
+        hits.extend(w.entities.explode(&blasts, &combat, &w.environment));
+        let scoring = w.tunables.scoring;
+        let players = &w.entities.players;
+        damage_log.retain(|id| players.contains_key(&id));
+        for hit in &hits {
+            let amount = hit.damage.armor + hit.damage.health;
+            damage_log.record(
+                hit.victim,
+                hit.attacker,
+                amount,
+                w.tick,
+                &scoring,
+                tick_rate,
+            );
+        }
+        let deaths: Vec<_> = hits.iter().filter_map(Hit::death).collect();
+        w.entities.collect_pickups(dt, &combat, radius);
+        let respawn = combat.pickup_respawn;
+        self.loot
+            .update(&mut w.entities.pickups, respawn, &mut w.rng);
+        w.entities.update_ammo(dt, &combat);
+        let explosions = blasts.iter().map(|blast| ServerMessage::ExplosionEvent {
+            owner: blast.owner,
+            pos: blast.pos,
+            radius: combat.blast_radius,
+        });
+        let hits = hits.iter().map(|hit| ServerMessage::PlayerHit {
+            victim: hit.victim,
+            attacker: hit.attacker,
+            armor: hit.damage.armor,
+            health: hit.damage.health,
+        });
+        let events: Vec<_> = explosions.chain(hits).collect();
+        let (tiles, rng) = (&w.environment.tiles, &mut w.rng);
+        w.entities
+            .respawn(dt, &combat, || rng.spawn_position(tiles));
+        let mut announcements = self.rules.update(w);
+
+        // Deaths come from combat and from the mode's own rules, and anyone who
+        // recently hurt the victim besides the killer gets an assist
+        let mut deaths: Vec<_> = deaths
+            .into_iter()
+            .map(|death| ServerMessage::PlayerDied {
+                victim: death.victim,
+                killer: death.killer,
+                assists: Vec::new(),
+                weapon: death.weapon,
+            })
+            .collect();
+        let mut changed = false;
+        let now = unix_time::unix_millis() / 1000;
+        let name = |id: &u64| w.entities.players.get(id).map(|p| p.username.as_str());
+        for msg in deaths.iter_mut().chain(&mut announcements) {
+            match msg {
+                ServerMessage::PlayerDied {
+                    victim,
+                    killer,
+                    assists,
+                    ..
+                } => {
+                    *assists = damage_log.assists(*victim, *killer, w.tick, &scoring, tick_rate);
+                    w.scoreboard.record_death(*victim, *killer, assists);
+                    if let Some(victim) = name(victim) {
+                        let killer = killer.as_ref().and_then(name);
+                        stats.record_death(victim, killer, now);
+                        if let Some(killer) = killer
+                            && self.rules.rates_kills()
+                        {
+                            stats.rate_kill(killer, victim, now);
+                        }
+                    }
+                    changed = true;
+                }
+                ServerMessage::RoundOver { winner, placements } => {
+                    let winner = winner.as_ref().and_then(name);
+                    let placements: Vec<_> = placements.iter().filter_map(name).collect();
+                    stats.record_match(winner, &placements, now);
+                }
+                _ => {}
+            }
+        }
+        // Ratings of players that just joined or just played
+        for (id, player) in &w.entities.players {
+            let rating = Some(stats.rating(&player.username, now));
+            let score = w.scoreboard.scores.entry(*id).or_default();
+            if score.rating != rating {
+                score.rating = rating;
+                changed = true;
+            }
+        }
+        if w.tunables.every_second(w.tick)
+            && let Err(e) = stats.save()
+        {
+            eprintln!("{e}");
+        }
+        if let Some(log) = &mut self.hash_log {
+            let mut written = log.record(w);
+            if w.tunables.every_second(w.tick) {
+                written = written.and_then(|()| log.flush());
+            }
+            if let Err(e) = written {
+                eprintln!("Could not write the hash log: {e}");
+                self.hash_log = None;
+            }
+        }
+
+        for death in deaths {
+            if let ServerMessage::PlayerDied { victim, killer, .. } = &death {
+                match killer {
+                    Some(killer) => println!("Player {victim} was killed by {killer}"),
+                    None => println!("Player {victim} died"),
+                }
+            }
+            self.broadcast(death);
+        }
+        for msg in events.into_iter().chain(announcements) {
+            self.broadcast(msg);
+        }
+        if changed {
+            self.broadcast(ServerMessage::UpdateScoreboard(w.scoreboard.clone()));
+        }
+        // Broadcast updated world to clients
+        if w.tick.is_multiple_of(self.snapshot_every) {
+            self.broadcast(self.snapshot(w));
+        }
+    }
+
+    fn snapshot(&self, w: &GameWorld) -> ServerMessage {
+        ServerMessage::UpdateEntities {
+            tick: w.tick,
+            time: self.started.elapsed().as_secs_f64(),
+            entities: w.entities.clone(),
+        }
+    }
+
+    fn broadcast(&self, msg: ServerMessage) {
+        let lobby = self.lobby;
+        if let Err(e) = self
+            .command_tx
+            .send(ServerCommand::Broadcast { lobby, msg })
+        {
+            eprintln!("Failed to broadcast world update: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Ratings and lifetime stats of every player, by username, kept across restarts in the stats file
+//! or the database, see [`crate::storage`].
+//!
+//! Ratings work like Elo: finishing a round above another contestant counts as beating them, and
+//! in modes without rounds every kill counts as beating the victim. Operators can have the
+//! ratings of players who stay away decay a little every day.
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+
+/// Rating of a player the first time they play
+pub const INITIAL_RATING: f64 = 1000.0;
+/// Most rating a player can win or lose in a single game
+const K_FACTOR: f64 = 32.0;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayerStats {
+    pub rating: f64,
+    /// Rounds played to the end
+    pub matches: u32,
+    pub wins: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    /// Unix seconds up to which decay has been applied, about when the player last played
+    pub last_played: u64,
+}
+impl Default for PlayerStats {
+    fn default() -> Self {
+        Self {
+            rating: INITIAL_RATING,
+            matches: 0,
+            wins: 0,
+            kills: 0,
+            deaths: 0,
+            last_played: 0,
+        }
+    }
+}
+
+/// How ratings decay while players stay away
+#[derive(Clone, Copy, Debug)]
+pub struct DecayConfig {
+    /// Rating lost per full day without playing
+    pub per_day: f64,
+    /// Decay never takes a rating below this
+    pub floor: f64,
+}
+
+pub struct StatsStore {
+    players: BTreeMap<String, PlayerStats>,
+    decay: DecayConfig,
+    /// Where the stats are kept, they only last until shutdown if it keeps none
+    storage: Arc<dyn Storage>,
+    /// Whether there are changes the storage does not have yet
+    dirty: bool,
+}
+impl StatsStore {
+    /// Reads the stats saved by an earlier run, starting afresh if there are none yet.
+    pub fn load(storage: Arc<dyn Storage>, decay: DecayConfig) -> Result<Self> {
+        Ok(Self {
+            players: storage.load_stats()?,
+            decay,
+            storage,
+            dirty: false,
+        })
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Stores the stats if anything changed since they were last stored.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.storage.save_stats(&self.players)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Stats of a player as of `now` in unix seconds, with decay applied for every full day since
+    /// they last played. New players start at the initial rating.
+    fn stats(&mut self, username: &str, now: u64) -> &mut PlayerStats {
+        let decay = self.decay;
+        let stats = self.players.entry(username.to_owned()).or_insert_with(|| {
+            self.dirty = true;
+            PlayerStats {
+                last_played: now,
+                ..PlayerStats::default()
+            }
+        });
+        let days = now.saturating_sub(stats.last_played) / SECS_PER_DAY;
+        if days > 0 {
+            if stats.rating > decay.floor {
+                let decayed = stats.rating - decay.per_day.max(0.0) * days as f64;
+                stats.rating = decayed.max(decay.floor);
+            }
+            stats.last_played += days * SECS_PER_DAY;
+            self.dirty = true;
+        }
+        stats
+    }
+
+    /// Current rating of a player, rounded for showing
+    pub fn rating(&mut self, username: &str, now: u64) -> i32 {
+        self.stats(username, now).rating.round() as i32
+    }
+
+    /// Counts a death towards the lifetime stats, and a kill for whoever caused it unless the
+    /// victim did.
+    pub fn record_death(&mut self, victim: &str, killer: Option<&str>, now: u64) {
+        self.stats(victim, now).deaths += 1;
+        if let Some(killer) = killer.filter(|killer| *killer != victim) {
+            self.stats(killer, now).kills += 1;
+        }
+        self.dirty = true;
+    }
+
+    /// Rates a kill as the killer beating the victim.
+    pub fn rate_kill(&mut self, killer: &str, victim: &str, now: u64) {
+        if killer != victim {
+            self.rate(&[killer, victim], now);
+        }
+    }
+
+    /// Rates the end of a round, `placements` going from first place to the first one out. Every
+    /// contestant counts as beating everyone placed below them.
+    pub fn record_match(&mut self, winner: Option<&str>, placements: &[&str], now: u64) {
+        for username in placements {
+            let stats = self.stats(username, now);
+            stats.matches += 1;
+            stats.wins += (winner == Some(*username)) as u32;
+            stats.last_played = now;
+        }
+        self.rate(placements, now);
+    }
+
+    /// Moves ratings as for a game where each player beat everyone after them, split so that a
+    /// game never moves a rating by more than the K factor.
+    fn rate(&mut self, placements: &[&str], now: u64) {
+        if placements.len() < 2 {
+            return;
+        }
+        let ratings: Vec<f64> = placements
+            .iter()
+            .map(|username| self.stats(username, now).rating)
+            .collect();
+        let k = K_FACTOR / (placements.len() - 1) as f64;
+        for (i, username) in placements.iter().enumerate() {
+            let change: f64 = ratings
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(j, other)| {
+                    let score = if i < j { 1.0 } else { 0.0 };
+                    k * (score - expected_score(ratings[i], *other))
+                })
+                .sum();
+            let stats = self.stats(username, now);
+            stats.rating += change;
+            stats.last_played = now;
+        }
+        self.dirty = true;
+    }
+}
+
+/// Chance a player rated `rating` beats one rated `other`
+fn expected_score(rating: f64, other: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((other - rating) / 400.0))
+}
@@ -0,0 +1,99 @@
+//! Records kept in a file each: stats and bans as TOML, the world as bincode.
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Result, anyhow};
+use common::world::GameWorld;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use super::{Record, Storage};
+use crate::{bans::Bans, cli::ServerConfig, stats::PlayerStats};
+
+/// What is written to the stats file
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct StatsFile {
+    players: BTreeMap<String, PlayerStats>,
+}
+
+pub struct FileStorage {
+    stats: Option<PathBuf>,
+    bans: Option<PathBuf>,
+    world: Option<PathBuf>,
+}
+impl FileStorage {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            stats: config.stats_file.clone(),
+            bans: config.ban_list.clone(),
+            world: config.world_file.clone(),
+        }
+    }
+}
+impl Storage for FileStorage {
+    fn location(&self, record: Record) -> Option<String> {
+        let path = match record {
+            Record::Stats => &self.stats,
+            Record::Bans => &self.bans,
+            Record::World => &self.world,
+        };
+        path.as_ref().map(|path| path.display().to_string())
+    }
+
+    fn load_stats(&self) -> Result<BTreeMap<String, PlayerStats>> {
+        let file: StatsFile = read_toml(&self.stats, "stats")?;
+        Ok(file.players)
+    }
+
+    fn save_stats(&self, players: &BTreeMap<String, PlayerStats>) -> Result<()> {
+        let file = StatsFile {
+            players: players.clone(),
+        };
+        write_toml(&self.stats, "stats", &file)
+    }
+
+    fn load_bans(&self) -> Result<Bans> {
+        read_toml(&self.bans, "bans")
+    }
+
+    fn save_bans(&self, bans: &Bans) -> Result<()> {
+        write_toml(&self.bans, "bans", bans)
+    }
+
+    fn load_world(&self) -> Result<Option<GameWorld>> {
+        match &self.world {
+            Some(path) if path.exists() => GameWorld::load(path)
+                .map(Some)
+                .map_err(|e| anyhow!("Failed to load world {}: {e}", path.display())),
+            _ => Ok(None),
+        }
+    }
+
+    fn save_world(&self, world: &GameWorld) -> Result<()> {
+        let Some(path) = &self.world else {
+            return Ok(());
+        };
+        world
+            .save(path)
+            .map_err(|e| anyhow!("Failed to save the world to {}: {e}", path.display()))
+    }
+}
+
+/// Reads the TOML file of `what`, starting afresh if there is none or it does not exist yet.
+fn read_toml<T: DeserializeOwned + Default>(path: &Option<PathBuf>, what: &str) -> Result<T> {
+    match path {
+        Some(path) if path.exists() => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("Failed to read {what} {}: {e}", path.display()))?;
+            toml::from_str(&text).map_err(|e| anyhow!("Invalid {what} in {}: {e}", path.display()))
+        }
+        _ => Ok(T::default()),
+    }
+}
+
+fn write_toml<T: Serialize>(path: &Option<PathBuf>, what: &str, value: &T) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    std::fs::write(path, toml::to_string(value)?)
+        .map_err(|e| anyhow!("Failed to write {what} {}: {e}", path.display()))
+}
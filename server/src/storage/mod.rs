@@ -0,0 +1,56 @@
+//! Where the server keeps what outlasts a run: player stats, bans and the world.
+//!
+//! The rest of the server only sees the [`Storage`] trait. By default each record goes to the file
+//! given for it with `--stats-file`, `--ban-list` and `--world-file`, see [`FileStorage`]. Servers
+//! built with the `sqlite` feature can keep all of them in one database with `--database`
+//! instead, see [`sqlite`], which suits deployments that outgrow flat files.
+mod file;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Result;
+#[cfg(not(feature = "sqlite"))]
+use anyhow::bail;
+use common::world::GameWorld;
+
+use crate::{bans::Bans, cli::ServerConfig, stats::PlayerStats};
+pub use file::FileStorage;
+
+/// The kinds of records a storage keeps
+#[derive(Clone, Copy, Debug)]
+pub enum Record {
+    Stats,
+    Bans,
+    World,
+}
+
+/// Somewhere the server's records are kept. Loading a record that was never saved gives an empty
+/// one, and saving a record the storage does not keep does nothing.
+pub trait Storage: Send + Sync {
+    /// Where `record` is kept, none if it only lasts until shutdown
+    fn location(&self, record: Record) -> Option<String>;
+
+    /// Stats of every player, by username
+    fn load_stats(&self) -> Result<BTreeMap<String, PlayerStats>>;
+    fn save_stats(&self, players: &BTreeMap<String, PlayerStats>) -> Result<()>;
+
+    fn load_bans(&self) -> Result<Bans>;
+    fn save_bans(&self, bans: &Bans) -> Result<()>;
+
+    /// World saved by an earlier run, if there was one
+    fn load_world(&self) -> Result<Option<GameWorld>>;
+    fn save_world(&self, world: &GameWorld) -> Result<()>;
+}
+
+/// Storage the command line asks for
+pub fn from_config(config: &ServerConfig) -> Result<Arc<dyn Storage>> {
+    match &config.database {
+        #[cfg(feature = "sqlite")]
+        Some(path) => Ok(Arc::new(sqlite::SqliteStorage::open(path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        Some(_) => bail!("This server was built without the `sqlite` feature, --database needs it"),
+        None => Ok(Arc::new(FileStorage::from_config(config))),
+    }
+}
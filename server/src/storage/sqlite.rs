@@ -0,0 +1,173 @@
+//! Every record in one SQLite database, for servers built with the `sqlite` feature.
+//!
+//! Stats and bans get a row each, so the database can be queried and edited with any SQLite tool
+//! while the server is stopped. The world is one row holding the bytes of
+//! [`GameWorld::to_bytes`].
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Result, anyhow};
+use common::world::GameWorld;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Record, Storage};
+use crate::{bans::Bans, stats::PlayerStats};
+
+/// Tables are created the first time the server opens the database
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS stats (
+        username TEXT PRIMARY KEY,
+        rating REAL NOT NULL,
+        matches INTEGER NOT NULL,
+        wins INTEGER NOT NULL,
+        kills INTEGER NOT NULL,
+        deaths INTEGER NOT NULL,
+        last_played INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS banned_usernames (username TEXT PRIMARY KEY);
+    CREATE TABLE IF NOT EXISTS banned_addresses (address TEXT PRIMARY KEY);
+    CREATE TABLE IF NOT EXISTS world (id INTEGER PRIMARY KEY CHECK (id = 0), data BLOB NOT NULL);
+";
+
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+    path: PathBuf,
+}
+impl SqliteStorage {
+    /// Opens the database, creating it if it does not exist yet.
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .map_err(|e| anyhow!("Failed to open the database {}: {e}", path.display()))?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|e| anyhow!("Failed to set up the database {}: {e}", path.display()))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // A panic elsewhere leaves the connection as usable as it was
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+impl Storage for SqliteStorage {
+    fn location(&self, _record: Record) -> Option<String> {
+        Some(self.path.display().to_string())
+    }
+
+    fn load_stats(&self) -> Result<BTreeMap<String, PlayerStats>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT username, rating, matches, wins, kills, deaths, last_played FROM stats",
+        )?;
+        let rows = statement.query_map([], |row| {
+            let stats = PlayerStats {
+                rating: row.get(1)?,
+                matches: row.get(2)?,
+                wins: row.get(3)?,
+                kills: row.get(4)?,
+                deaths: row.get(5)?,
+                last_played: row.get::<_, i64>(6)? as u64,
+            };
+            Ok((row.get(0)?, stats))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn save_stats(&self, players: &BTreeMap<String, PlayerStats>) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM stats", [])?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO stats (username, rating, matches, wins, kills, deaths, last_played)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for (username, stats) in players {
+                insert.execute(params![
+                    username,
+                    stats.rating,
+                    stats.matches,
+                    stats.wins,
+                    stats.kills,
+                    stats.deaths,
+                    stats.last_played as i64,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn load_bans(&self) -> Result<Bans> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT username FROM banned_usernames")?;
+        let usernames = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut statement = connection.prepare("SELECT address FROM banned_addresses")?;
+        let addresses = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|address| {
+                let address = address?;
+                address
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid banned address {address}: {e}"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Bans {
+            usernames,
+            addresses,
+        })
+    }
+
+    fn save_bans(&self, bans: &Bans) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM banned_usernames", [])?;
+        transaction.execute("DELETE FROM banned_addresses", [])?;
+        for username in &bans.usernames {
+            transaction.execute(
+                "INSERT INTO banned_usernames (username) VALUES (?1)",
+                [username],
+            )?;
+        }
+        for address in &bans.addresses {
+            transaction.execute(
+                "INSERT INTO banned_addresses (address) VALUES (?1)",
+                [address.to_string()],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn load_world(&self) -> Result<Option<GameWorld>> {
+        let data: Option<Vec<u8>> = self
+            .connection()
+            .query_row("SELECT data FROM world WHERE id = 0", [], |row| row.get(0))
+            .optional()?;
+        data.map(|data| {
+            GameWorld::from_bytes(&data)
+                .map_err(|e| anyhow!("Invalid world in {}: {e}", self.path.display()))
+        })
+        .transpose()
+    }
+
+    fn save_world(&self, world: &GameWorld) -> Result<()> {
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO world (id, data) VALUES (0, ?1)",
+                [world.to_bytes()?],
+            )
+            .map_err(|e| anyhow!("Failed to save the world to {}: {e}", self.path.display()))?;
+        Ok(())
+    }
+}
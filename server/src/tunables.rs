@@ -0,0 +1,284 @@
+//! Layered resolution of the gameplay [`Tunables`].
+//!
+//! Values start from the built-in defaults, then the game mode's own defaults, can be overridden
+//! by the map being played, and the server configuration has the final say. Every resolved value remembers which layer it came
+//! from so operators can see why the game behaves the way it does.
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use common::tunables::Tunables;
+use toml::{Table, Value};
+
+use crate::cli::ServerConfig;
+
+/// Most simulation steps per second, the timer driving them cannot go much faster
+const MAX_TICK_RATE: f64 = 1000.0;
+
+/// Layers a tunable can be set in, from lowest to highest priority
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Default,
+    Mode,
+    Map,
+    Server,
+}
+impl Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Layer::Default => "default",
+            Layer::Mode => "mode",
+            Layer::Map => "map",
+            Layer::Server => "server",
+        })
+    }
+}
+
+/// Final tunables along with where each value came from
+pub struct ResolvedTunables {
+    pub tunables: Tunables,
+    /// Dotted key of every value, such as `physics.friction`, with its value and layer
+    pub sources: BTreeMap<String, (Value, Layer)>,
+}
+impl ResolvedTunables {
+    /// Resolves the tunables for a server, reading the map file if one was given.
+    pub fn from_config(config: &ServerConfig) -> Result<Self> {
+        let map = match &config.map {
+            Some(path) => map_overrides(path)?,
+            None => Table::new(),
+        };
+        let mut server = Table::new();
+        if let Some(tick_rate) = config.tick_rate {
+            server.insert(String::from("tick_rate"), Value::Float(tick_rate));
+        }
+        for setting in &config.set {
+            insert_setting(&mut server, setting)?;
+        }
+        Self::resolve(&config.mode.tunables(), &map, &server)
+    }
+
+    /// Applies the mode, map and server layers on top of the defaults.
+    pub fn resolve(mode: &Table, map: &Table, server: &Table) -> Result<Self> {
+        let mut merged = Table::try_from(Tunables::default())?;
+        let mut sources = BTreeMap::new();
+        record_defaults(&merged, "", &mut sources);
+
+        overlay(&mut merged, mode, "", Layer::Mode, &mut sources)?;
+        overlay(&mut merged, map, "", Layer::Map, &mut sources)?;
+        overlay(&mut merged, server, "", Layer::Server, &mut sources)?;
+
+        let tunables: Tunables = merged.try_into()?;
+        if !(1.0..=MAX_TICK_RATE).contains(&tunables.tick_rate) {
+            bail!(
+                "The tick rate must be between 1 and {MAX_TICK_RATE}, got {}",
+                tunables.tick_rate
+            );
+        }
+        Ok(Self { tunables, sources })
+    }
+
+    /// One line per tunable with its value and the layer it came from
+    pub fn dump(&self) -> String {
+        self.lines(|_| true).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Lines for the tunables that were changed from their default
+    pub fn overrides(&self) -> impl Iterator<Item = String> {
+        self.lines(|layer| layer != Layer::Default)
+    }
+
+    fn lines(&self, filter: impl Fn(Layer) -> bool) -> impl Iterator<Item = String> {
+        self.sources
+            .iter()
+            .filter(move |(_, (_, layer))| filter(*layer))
+            .map(|(key, (value, layer))| format!("{key} = {} ({layer})", display_value(value)))
+    }
+}
+
+/// Reads the `[tunables]` table of a map file
+fn map_overrides(path: &Path) -> Result<Table> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read map {}: {e}", path.display()))?;
+    let mut map: Table = text.parse()?;
+    match map.remove("tunables") {
+        Some(Value::Table(table)) => Ok(table),
+        Some(_) => bail!("`tunables` in {} must be a table", path.display()),
+        None => Ok(Table::new()),
+    }
+}
+
+/// Parses a `physics.friction=0.5` style override into a nested table
+fn insert_setting(table: &mut Table, setting: &str) -> Result<()> {
+    let (key, value) = setting
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected key=value, got `{setting}`"))?;
+    let value = format!("value = {}", value.trim())
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        // Bare words are taken as strings so quoting is optional on the command line
+        .unwrap_or_else(|| Value::String(value.trim().to_string()));
+
+    let mut parts: Vec<&str> = key.trim().split('.').collect();
+    let last = parts.pop().unwrap();
+    let mut current = table;
+    for part in parts {
+        current = match current
+            .entry(part)
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(inner) => inner,
+            _ => bail!("`{key}` conflicts with another setting"),
+        };
+    }
+    current.insert(last.to_string(), value);
+    Ok(())
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+fn record_defaults(table: &Table, prefix: &str, sources: &mut BTreeMap<String, (Value, Layer)>) {
+    for (key, value) in table {
+        let path = join(prefix, key);
+        match value {
+            Value::Table(inner) => record_defaults(inner, &path, sources),
+            value => {
+                sources.insert(path, (value.clone(), Layer::Default));
+            }
+        }
+    }
+}
+
+/// Writes every value of `layer` over `base`, rejecting keys and types the defaults do not have
+fn overlay(
+    base: &mut Table,
+    layer_table: &Table,
+    prefix: &str,
+    layer: Layer,
+    sources: &mut BTreeMap<String, (Value, Layer)>,
+) -> Result<()> {
+    for (key, value) in layer_table {
+        let path = join(prefix, key);
+        let Some(existing) = base.get_mut(key) else {
+            bail!("Unknown tunable `{path}` in {layer} settings");
+        };
+        match (existing, value) {
+            (Value::Table(inner), Value::Table(value)) => {
+                overlay(inner, value, &path, layer, sources)?;
+            }
+            // Tunables are f32, a NaN or an overflowing value would spread through the simulation
+            (Value::Float(_), Value::Float(float)) if !(*float as f32).is_finite() => {
+                bail!("Tunable `{path}` in {layer} settings must be a finite number, got {float}")
+            }
+            // Whole numbers are accepted where a decimal is expected
+            (existing @ Value::Float(_), Value::Integer(int)) => {
+                *existing = Value::Float(*int as f64);
+                sources.insert(path, (existing.clone(), layer));
+            }
+            (existing, value) if existing.same_type(value) && !value.is_table() => {
+                *existing = value.clone();
+                sources.insert(path, (value.clone(), layer));
+            }
+            (existing, _) => bail!(
+                "Tunable `{path}` in {layer} settings should be a {}",
+                existing.type_str()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Floats come from `f32` fields, so print them at that precision
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::Float(float) => format!("{}", *float as f32),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use test_utils::files::Scratch;
+
+    use super::*;
+
+    fn source(resolved: &ResolvedTunables, key: &str) -> (Value, Layer) {
+        resolved.sources[key].clone()
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let mode = toml::toml! {
+            [physics]
+            friction = 0.1
+            player_collision = true
+        };
+        let map = toml::toml! {
+            [physics]
+            friction = 0.2
+        };
+        let server = toml::toml! {
+            tick_rate = 30
+        };
+        let resolved = ResolvedTunables::resolve(&mode, &map, &server).unwrap();
+        assert_eq!(resolved.tunables.physics.friction, 0.2);
+        assert!(resolved.tunables.physics.player_collision);
+        // Whole numbers are taken where a decimal is expected
+        assert_eq!(resolved.tunables.tick_rate, 30.0);
+        assert_eq!(
+            source(&resolved, "physics.friction"),
+            (Value::Float(0.2), Layer::Map)
+        );
+        assert_eq!(source(&resolved, "physics.player_collision").1, Layer::Mode);
+        assert_eq!(source(&resolved, "tick_rate").1, Layer::Server);
+        assert!(resolved.overrides().all(|line| !line.contains("(default)")));
+    }
+
+    #[test]
+    fn bad_settings_are_refused() {
+        let empty = Table::new();
+        let resolve = |server: Table| ResolvedTunables::resolve(&empty, &empty, &server);
+        let error = resolve(toml::toml! { [physics] bounciness = 1.0 })
+            .err()
+            .unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains("Unknown tunable `physics.bounciness`")
+        );
+        assert!(resolve(toml::toml! { [physics] friction = "high" }).is_err());
+        let mut physics = Table::new();
+        physics.insert(String::from("friction"), Value::Float(f64::NAN));
+        let mut nan = Table::new();
+        nan.insert(String::from("physics"), Value::Table(physics));
+        assert!(resolve(nan).is_err());
+        assert!(resolve(toml::toml! { tick_rate = 5000.0 }).is_err());
+    }
+
+    #[test]
+    fn servers_override_the_map_file() {
+        let scratch = Scratch::new("tunables");
+        let map = scratch.write(
+            "arena.toml",
+            "[tunables.physics]\nfriction = 0.3\nmax_speed = 2.0\n",
+        );
+        let config = ServerConfig::parse_from([
+            "server",
+            "--map",
+            &map.to_string_lossy(),
+            "--set",
+            "physics.friction=0.5",
+        ]);
+        let resolved = ResolvedTunables::from_config(&config).unwrap();
+        assert_eq!(resolved.tunables.physics.friction, 0.5);
+        assert_eq!(resolved.tunables.physics.max_speed, 2.0);
+        assert_eq!(source(&resolved, "physics.friction").1, Layer::Server);
+        assert_eq!(source(&resolved, "physics.max_speed").1, Layer::Map);
+    }
+}
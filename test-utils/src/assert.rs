@@ -0,0 +1,134 @@
+//! Assertions for simulation results and messages, exported at the crate root as macros.
+use std::fmt::Debug;
+
+use common::{
+    message::{Message, frame::Frame},
+    vec::Vec2,
+};
+
+/// Closest two floats are taken to be equal by [`assert_close!`](crate::assert_close) unless the
+/// test gives its own
+pub const EPSILON: f32 = 1e-5;
+
+/// Values compared within a tolerance
+pub trait Approx: Debug {
+    fn distance(&self, other: &Self) -> f64;
+}
+impl Approx for f32 {
+    fn distance(&self, other: &Self) -> f64 {
+        (self - other).abs() as f64
+    }
+}
+impl Approx for f64 {
+    fn distance(&self, other: &Self) -> f64 {
+        (self - other).abs()
+    }
+}
+impl Approx for Vec2 {
+    fn distance(&self, other: &Self) -> f64 {
+        (*self - *other).length() as f64
+    }
+}
+
+/// Asserts two floats or vectors are within an epsilon of each other, [`EPSILON`] by default.
+///
+/// ```
+/// # use common::vec::Vec2;
+/// test_utils::assert_close!(0.1 + 0.2, 0.3f32);
+/// test_utils::assert_close!(Vec2::ONE, Vec2 { x: 1.01, y: 1.0 }, 0.1);
+/// ```
+#[macro_export]
+macro_rules! assert_close {
+    ($actual:expr, $expected:expr $(,)?) => {
+        $crate::assert_close!($actual, $expected, $crate::assert::EPSILON)
+    };
+    ($actual:expr, $expected:expr, $epsilon:expr $(,)?) => {{
+        let (actual, expected) = (&$actual, &$expected);
+        let distance = $crate::assert::Approx::distance(actual, expected);
+        assert!(
+            distance <= $epsilon as f64,
+            "expected {expected:?}, got {actual:?}, {distance} apart"
+        );
+    }};
+}
+
+/// Asserts a message comes out of its frame as it went in, taking the whole frame.
+///
+/// ```
+/// # use common::message::ClientMessage;
+/// test_utils::assert_round_trips!(ClientMessage::Chat(String::from("hi")));
+/// ```
+#[macro_export]
+macro_rules! assert_round_trips {
+    ($msg:expr $(,)?) => {
+        $crate::assert::round_trip(&$msg)
+    };
+}
+
+pub fn round_trip<M: Message + PartialEq + Debug>(msg: &M) {
+    let bytes = msg.encode().expect("message does not encode");
+    match M::decode(&bytes).expect("message does not decode") {
+        Frame::Message(decoded, len) => {
+            assert_eq!(&decoded, msg);
+            assert_eq!(len, bytes.len(), "frame of {msg:?} has trailing bytes");
+        }
+        other => panic!("{msg:?} came back as {other:?}"),
+    }
+}
+
+/// Asserts a value, such as a world after some ticks, prints the same as an expected one. On a
+/// mismatch the first line that differs is shown with its path through the value, rather than
+/// both values in full.
+///
+/// ```
+/// # use common::vec::Vec2;
+/// test_utils::assert_snapshot!(Vec2::ONE, Vec2 { x: 1.0, y: 1.0 });
+/// ```
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($actual:expr, $expected:expr $(,)?) => {
+        $crate::assert::snapshot(&$actual, &$expected)
+    };
+}
+
+pub fn snapshot<T: Debug>(actual: &T, expected: &T) {
+    let actual = format!("{actual:#?}");
+    let expected = format!("{expected:#?}");
+    if actual == expected {
+        return;
+    }
+    let (actual_lines, expected_lines): (Vec<_>, Vec<_>) =
+        (actual.lines().collect(), expected.lines().collect());
+    let line = actual_lines
+        .iter()
+        .zip(&expected_lines)
+        .position(|(a, b)| a != b)
+        .unwrap_or(actual_lines.len().min(expected_lines.len()));
+    // Lines opening a field or item the differing line is nested in, so the message says where
+    let mut path = Vec::new();
+    let indent = |text: &str| text.len() - text.trim_start().len();
+    let mut depth = expected_lines
+        .get(line)
+        .or(actual_lines.get(line))
+        .map_or(0, |text| indent(text));
+    for text in expected_lines[..line.min(expected_lines.len())]
+        .iter()
+        .rev()
+    {
+        if depth == 0 {
+            break;
+        }
+        if indent(text) < depth {
+            path.push(text.trim());
+            depth = indent(text);
+        }
+    }
+    path.reverse();
+    panic!(
+        "snapshots differ at line {}, under {}\n  expected: {}\n    actual: {}",
+        line + 1,
+        path.join(" "),
+        expected_lines.get(line).map_or("<end>", |text| text.trim()),
+        actual_lines.get(line).map_or("<end>", |text| text.trim()),
+    );
+}
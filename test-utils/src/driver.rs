@@ -0,0 +1,158 @@
+//! Clients that follow a script against a running server, for end to end tests.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use common::message::{ClientMessage, ServerMessage};
+//! use test_utils::driver::{ScriptedClient, Step};
+//!
+//! let mut client = ScriptedClient::connect("127.0.0.1:8000").await?;
+//! client.join("tester", "").await?;
+//! client
+//!     .play(&[
+//!         Step::Send(ClientMessage::Chat(String::from("hi"))),
+//!         Step::expect("the chat comes back", |msg| {
+//!             matches!(msg, ServerMessage::ChatBroadcast { .. })
+//!         }),
+//!     ])
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use common::{
+    message::{
+        ClientMessage, ServerMessage,
+        auth::{Handshake, Signer},
+    },
+    version::PROTOCOL_VERSION,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, ToSocketAddrs},
+    time,
+};
+
+/// How long a client waits for a message it expects before the test fails
+pub const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a scripted client does next
+pub enum Step {
+    Send(ClientMessage),
+    /// Reads messages until one matches, failing with the description if none does in time
+    Expect(&'static str, Box<dyn Fn(&ServerMessage) -> bool>),
+    Wait(Duration),
+}
+impl Step {
+    pub fn expect(
+        description: &'static str,
+        matches: impl Fn(&ServerMessage) -> bool + 'static,
+    ) -> Self {
+        Step::Expect(description, Box::new(matches))
+    }
+}
+
+pub struct ScriptedClient {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    /// Signs what is sent once the server agreed on a key when joining
+    signer: Option<Signer>,
+    /// Every message read so far, in order, heartbeats left out
+    pub received: Vec<ServerMessage>,
+}
+impl ScriptedClient {
+    pub async fn connect<T: ToSocketAddrs>(addr: T) -> Result<Self> {
+        Ok(Self {
+            stream: TcpStream::connect(addr).await?,
+            buffer: Vec::new(),
+            signer: None,
+            received: Vec::new(),
+        })
+    }
+
+    /// Says hello with our protocol version and joins as `username` signing its messages,
+    /// returning the id and name the server gave the player
+    pub async fn join(&mut self, username: &str, password: &str) -> Result<(u64, String)> {
+        let handshake = Handshake::new()?;
+        self.send(ClientMessage::Hello(PROTOCOL_VERSION)).await?;
+        self.send(ClientMessage::ProposeSigning(handshake.public_key()))
+            .await?;
+        self.send(ClientMessage::Connect(
+            String::from(username),
+            String::from(password),
+        ))
+        .await?;
+        match self.next().await? {
+            ServerMessage::SigningAgreed(public_key) => {
+                self.signer = Some(Signer::new(handshake.finish(&public_key)?));
+            }
+            other => bail!("Expected the server to agree on signing, got {other:?}"),
+        }
+        match self.next().await? {
+            ServerMessage::ConnectionAccepted { id, username, .. } => Ok((id, username)),
+            other => bail!("Expected to be accepted, got {other:?}"),
+        }
+    }
+
+    pub async fn send(&mut self, msg: ClientMessage) -> Result<()> {
+        let mut frame = msg.encode()?;
+        if let Some(signer) = &mut self.signer {
+            frame = signer.sign(frame)?;
+        }
+        self.stream.write_all(&frame).await?;
+        Ok(())
+    }
+
+    /// Next message that is not a heartbeat, heartbeats being answered on the way
+    pub async fn next(&mut self) -> Result<ServerMessage> {
+        let read = async {
+            loop {
+                match ServerMessage::read_from_stream(&mut self.stream, &mut self.buffer).await? {
+                    Some(ServerMessage::Ping) => self.send(ClientMessage::Pong).await?,
+                    Some(msg) => return Ok(msg),
+                    None => bail!("Server closed the connection"),
+                }
+            }
+        };
+        let msg = time::timeout(EXPECT_TIMEOUT, read)
+            .await
+            .map_err(|_| anyhow!("Nothing came within {} s", EXPECT_TIMEOUT.as_secs()))??;
+        self.received.push(msg.clone());
+        Ok(msg)
+    }
+
+    /// Reads until a message matches, returning it
+    pub async fn expect(
+        &mut self,
+        description: &str,
+        matches: impl Fn(&ServerMessage) -> bool,
+    ) -> Result<ServerMessage> {
+        let wait = async {
+            loop {
+                let msg = self.next().await?;
+                if matches(&msg) {
+                    return Ok::<_, anyhow::Error>(msg);
+                }
+            }
+        };
+        time::timeout(EXPECT_TIMEOUT, wait)
+            .await
+            .map_err(|_| anyhow!("Expected {description}, it never came"))?
+            .map_err(|e| anyhow!("Expected {description}: {e}"))
+    }
+
+    /// Runs the steps in order, stopping at the first that fails
+    pub async fn play(&mut self, script: &[Step]) -> Result<()> {
+        for step in script {
+            match step {
+                Step::Send(msg) => self.send(msg.clone()).await?,
+                Step::Expect(description, matches) => {
+                    self.expect(description, matches).await?;
+                }
+                Step::Wait(duration) => time::sleep(*duration).await,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+//! Scratch directories for tests of code that reads and writes files.
+//!
+//! Each [`Scratch`] is a fresh directory under the system's temporary directory, named after the
+//! test and unique across the tests running at once, and removed with everything in it when
+//! dropped.
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Scratch directories made so far by this process, numbering the next one
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Scratch {
+    path: PathBuf,
+}
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let index = CREATED.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("{name}-{}-{index}", std::process::id()));
+        // Left over by an earlier process that had the same id
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("the temporary directory is writable");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of `name` inside the directory
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Writes `contents` to `name` inside the directory, creating the directories on the way
+    pub fn write(&self, name: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("the scratch directory is writable");
+        }
+        std::fs::write(&path, contents).expect("the scratch directory is writable");
+        path
+    }
+}
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,16 @@
+//! Scaffolding shared by the tests of every crate in the workspace.
+//!
+//! - [`world`] builds worlds and players without spelling out every field
+//! - [`transport`] connects two ends in memory, or over loopback TCP for code that wants a socket
+//! - [`driver`] plays a scripted client against a running server
+//! - [`assert`] holds the assertion macros, exported at the crate root
+//! - [`files`] makes scratch directories for code that reads and writes files
+//!
+//! The server, client and launcher take it as a dev-dependency for their unit tests. Types here
+//! come from `common` as a library, so the unit tests inside `common` itself cannot use them:
+//! those see a second copy of every type. Its tests under `common/tests` can.
+pub mod assert;
+pub mod driver;
+pub mod files;
+pub mod transport;
+pub mod world;
@@ -0,0 +1,78 @@
+//! Connections between two ends of a test without a real network.
+//!
+//! [`FakeTransport`] carries framed messages through memory, both ends reading and writing the
+//! same bytes a socket would. Code written against [`TcpStream`] gets a connected loopback pair
+//! from [`tcp_pair`] instead.
+use anyhow::{Result, bail};
+use common::message::{Message, frame::Frame};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    net::{TcpListener, TcpStream},
+};
+
+/// Bytes either end can have in flight before writing waits for the other to read
+const CAPACITY: usize = 64 * 1024;
+
+/// One end of an in-memory connection
+pub struct FakeTransport {
+    stream: DuplexStream,
+    /// Bytes read that belong to frames not taken yet
+    buffer: Vec<u8>,
+}
+impl FakeTransport {
+    /// Two ends connected to each other, what one sends the other receives
+    pub fn pair() -> (Self, Self) {
+        let (a, b) = tokio::io::duplex(CAPACITY);
+        let end = |stream| Self {
+            stream,
+            buffer: Vec::new(),
+        };
+        (end(a), end(b))
+    }
+
+    pub async fn send<M: Message>(&mut self, msg: &M) -> Result<()> {
+        self.stream.write_all(&msg.encode()?).await?;
+        Ok(())
+    }
+
+    /// Writes raw bytes, for tests of what the other end makes of a broken stream
+    pub async fn send_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream.write_all(bytes).await?;
+        Ok(())
+    }
+
+    /// Next message from the other end, skipping types this side does not know. Returns `None`
+    /// once the other end is dropped.
+    pub async fn recv<M: Message>(&mut self) -> Result<Option<M>> {
+        loop {
+            match M::decode(&self.buffer)? {
+                Frame::Message(msg, len) => {
+                    self.buffer.drain(..len);
+                    return Ok(Some(msg));
+                }
+                Frame::Unknown { len, .. } => {
+                    self.buffer.drain(..len);
+                }
+                Frame::Incomplete => {
+                    let mut chunk = [0u8; 4096];
+                    let n = self.stream.read(&mut chunk).await?;
+                    if n == 0 {
+                        if !self.buffer.is_empty() {
+                            bail!("Closed in the middle of a frame");
+                        }
+                        return Ok(None);
+                    }
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                }
+            }
+        }
+    }
+}
+
+/// Two TCP streams connected to each other over loopback, the connecting end first
+pub async fn tcp_pair() -> Result<(TcpStream, TcpStream)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (connecting, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    Ok((connecting?, accepted?.0))
+}
@@ -0,0 +1,103 @@
+//! Worlds and players for tests, with everything a test does not care about left at a default.
+use common::{
+    color::Color,
+    physics::PhysicsConfig,
+    vec::Vec2,
+    world::{
+        GameWorld,
+        ammo::{Ammo, AmmoConfig},
+        entities::Player,
+        environment::{Attractor, OBJECT_COLOR, Object},
+        rng::GameRng,
+    },
+};
+
+/// Seed of built worlds unless a test picks another, so runs repeat
+pub const SEED: u64 = 0;
+
+/// Player at `pos` standing still, at full health and ammo and without armor
+pub fn player(username: &str, pos: Vec2) -> Player {
+    Player {
+        username: String::from(username),
+        color: Color::WHITE,
+        pos,
+        vel: Vec2::ZERO,
+        last_input_seq: 0,
+        input_ticks: 0,
+        health: 100.0,
+        armor: 0.0,
+        respawn_in: 0.0,
+        sprite: None,
+        ammo: Ammo::full(&AmmoConfig::default()),
+    }
+}
+
+/// Box of the default object color
+pub fn object(pos: Vec2, size: Vec2) -> Object {
+    Object {
+        pos,
+        size,
+        color: OBJECT_COLOR,
+        sprite: None,
+    }
+}
+
+/// Builds a [`GameWorld`] piece by piece, starting from an empty one seeded with [`SEED`]
+pub struct WorldBuilder {
+    world: GameWorld,
+}
+impl WorldBuilder {
+    pub fn new() -> Self {
+        let mut world = GameWorld::new();
+        world.rng = GameRng::seeded(SEED);
+        Self { world }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.world.rng = GameRng::seeded(seed);
+        self
+    }
+
+    pub fn physics(mut self, physics: PhysicsConfig) -> Self {
+        self.world.tunables.physics = physics;
+        self
+    }
+
+    /// Adds a player made by [`player`], see [`WorldBuilder::with_player`] for any other
+    pub fn player(self, id: u64, username: &str, pos: Vec2) -> Self {
+        self.with_player(id, player(username, pos))
+    }
+
+    pub fn with_player(mut self, id: u64, player: Player) -> Self {
+        self.world.entities.players.insert(id, player);
+        self
+    }
+
+    pub fn object(mut self, pos: Vec2, size: Vec2) -> Self {
+        self.world.environment.objects.push(object(pos, size));
+        self
+    }
+
+    pub fn attractor(mut self, pos: Vec2, strength: f32, radius: f32) -> Self {
+        self.world.environment.attractors.push(Attractor {
+            pos,
+            strength,
+            radius,
+        });
+        self
+    }
+
+    pub fn spawn_point(mut self, pos: Vec2) -> Self {
+        self.world.environment.tiles.spawn_points.push(pos);
+        self
+    }
+
+    pub fn build(self) -> GameWorld {
+        self.world
+    }
+}
+impl Default for WorldBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}